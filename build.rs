@@ -4,9 +4,11 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_prost_build::configure()
-        .build_client(false)
         .compile_protos(
-            &["proto/api/v1alpha1/zerotable.proto"],
+            &[
+                "proto/api/v1alpha1/zerotable.proto",
+                "proto/api/v1alpha1/admin.proto",
+            ],
             &["proto"],
         )?;
     Ok(())
@@ -0,0 +1,470 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! gRPC implementation of the Admin service.
+//!
+//! Operator controls like compaction, read-only mode and archiving, served
+//! next to the data plane but only to callers presenting the admin token,
+//! see [`AdminAuth`].
+
+use std::sync::{Arc, RwLock};
+
+use prost::Message;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::Engine;
+use crate::api::v1alpha1::admin_server::Admin;
+use crate::api::v1alpha1::export_documents_response::Item as ExportItem;
+use crate::api::v1alpha1::{
+    ArchiveCollectionRequest, ArchiveCollectionResponse, ArchivedCollection, CompactRequest,
+    Document, ExportDocumentsResponse, GetKeyspaceStatsRequest, GetKeyspaceStatsResponse,
+    KeyspaceStats, ListArchivedCollectionsRequest, ListArchivedCollectionsResponse, PersistRequest,
+    ReloadConfigRequest, RestoreCollectionRequest, RestoreCollectionResponse, SetReadOnlyRequest,
+    SetReadOnlyResponse, VerifyBackupResponse,
+};
+use crate::archive::Stub;
+use crate::export::{ChunkReader, Manifest};
+use crate::keys;
+use crate::name;
+use crate::panic;
+use crate::reload::Reloader;
+use crate::request_id::RequestId;
+use crate::service::{
+    chunk_from_proto, engine_err_to_status, manifest_from_proto, panic_to_status, qualify,
+};
+
+/// A backup is verified by decoding one in this many documents.
+const BACKUP_SAMPLE_INTERVAL: u64 = 100;
+
+/// Metadata key carrying the admin token, as `Bearer <token>`.
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+/// Interceptor rejecting requests without the admin token.
+///
+/// Clones share the token, so replacing it applies to all of them.
+#[derive(Clone)]
+pub struct AdminAuth {
+    token: Arc<RwLock<String>>,
+}
+
+impl AdminAuth {
+    pub fn new(token: impl Into<String>) -> Self {
+        AdminAuth {
+            token: Arc::new(RwLock::new(token.into())),
+        }
+    }
+
+    /// Require `token` from now on.
+    pub fn set_token(&self, token: impl Into<String>) {
+        *self.token.write().expect("admin token lock poisoned") = token.into();
+    }
+
+    /// Let the request through if it carries the token.
+    pub fn check<T>(&self, request: Request<T>) -> Result<Request<T>, Status> {
+        let token = self.token.read().expect("admin token lock poisoned");
+        let presented = request
+            .metadata()
+            .get(AUTHORIZATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+                Ok(request)
+            }
+            Some(_) => Err(Status::permission_denied("invalid admin token")),
+            None => Err(Status::unauthenticated("admin token required")),
+        }
+    }
+}
+
+/// Compare without short-circuiting, so timing does not leak how much of
+/// the token matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Verification of a backup, fed one stream item at a time.
+#[derive(Default)]
+struct BackupCheck {
+    reader: ChunkReader,
+    manifest: Option<Manifest>,
+    chunk_count: u64,
+    document_count: u64,
+    sampled_documents: u64,
+}
+
+impl BackupCheck {
+    /// Check the next item, returning the problem if it is damaged.
+    fn add(&mut self, item: Option<ExportItem>) -> Result<(), String> {
+        if self.manifest.is_some() {
+            return Err("backup continues after its manifest".to_string());
+        }
+        match item {
+            Some(ExportItem::Chunk(chunk)) => {
+                let sequence = chunk.sequence;
+                let documents = self
+                    .reader
+                    .read(&chunk_from_proto(chunk))
+                    .map_err(|e| e.to_string())?;
+                self.chunk_count += 1;
+                for data in documents {
+                    if self.document_count % BACKUP_SAMPLE_INTERVAL == 0 {
+                        let doc = Document::decode(data.as_slice())
+                            .map_err(|e| format!("invalid document in chunk {sequence}: {e}"))?;
+                        name::parse(&doc.name)
+                            .map_err(|e| format!("invalid document in chunk {sequence}: {e}"))?;
+                        self.sampled_documents += 1;
+                    }
+                    self.document_count += 1;
+                }
+            }
+            Some(ExportItem::Manifest(manifest)) => {
+                self.manifest = Some(manifest_from_proto(&manifest));
+            }
+            // recorded along with the chunks, they hold no data
+            Some(ExportItem::Heartbeat(_)) => {}
+            None => return Err("backup holds an empty item".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Report on the backup, `problem` is the first one found if any.
+    fn report(self, problem: Option<String>) -> VerifyBackupResponse {
+        let problem = problem.or_else(|| match &self.manifest {
+            Some(manifest) => self.reader.finish(manifest).err().map(|e| e.to_string()),
+            None => Some("backup ends without a manifest".to_string()),
+        });
+        VerifyBackupResponse {
+            valid: problem.is_none(),
+            problem: problem.unwrap_or_default(),
+            chunk_count: self.chunk_count,
+            document_count: self.document_count,
+            sampled_documents: self.sampled_documents,
+        }
+    }
+}
+
+/// Archived collection `collection`, as stored, with its stub.
+fn archived_collection(collection: &str, stub: Stub) -> ArchivedCollection {
+    let (database_id, collection_id) = keys::unqualify(collection);
+    ArchivedCollection {
+        collection_id: collection_id.to_string(),
+        database_id: database_id.to_string(),
+        document_count: stub.documents,
+        segment_bytes: stub.bytes,
+        archive_time: Some(stub.archived_at.into()),
+    }
+}
+
+#[derive(Clone)]
+pub struct AdminService {
+    engine: Engine,
+    reloader: Option<Reloader>,
+}
+
+impl AdminService {
+    pub fn new(engine: Engine) -> Self {
+        AdminService {
+            engine: engine.acting_as("admin"),
+            reloader: None,
+        }
+    }
+
+    /// Serve ReloadConfig with `reloader`, the call is unimplemented
+    /// otherwise.
+    pub fn with_reloader(mut self, reloader: Reloader) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    async fn handle_compact(&self, request_id: &RequestId) -> Result<Response<()>, Status> {
+        let engine = self.engine.clone();
+        tokio::task::spawn_blocking(move || panic::catch(|| engine.compact()))
+            .await
+            .map_err(|e| Status::internal(format!("task failed: {e}")))?
+            .map_err(|panic| panic_to_status(request_id, panic))?
+            .map_err(engine_err_to_status)?;
+        Ok(Response::new(()))
+    }
+
+    async fn handle_persist(&self, request_id: &RequestId) -> Result<Response<()>, Status> {
+        let engine = self.engine.clone();
+        tokio::task::spawn_blocking(move || panic::catch(|| engine.persist()))
+            .await
+            .map_err(|e| Status::internal(format!("task failed: {e}")))?
+            .map_err(|panic| panic_to_status(request_id, panic))?
+            .map_err(engine_err_to_status)?;
+        Ok(Response::new(()))
+    }
+
+    fn handle_get_keyspace_stats(&self) -> Result<Response<GetKeyspaceStatsResponse>, Status> {
+        let keyspaces = self
+            .engine
+            .keyspace_stats()
+            .into_iter()
+            .map(|stats| KeyspaceStats {
+                name: stats.name.to_string(),
+                approximate_len: stats.approximate_len,
+                disk_space: stats.disk_space,
+            })
+            .collect();
+        Ok(Response::new(GetKeyspaceStatsResponse { keyspaces }))
+    }
+
+    fn handle_set_read_only(
+        &self,
+        request: Request<SetReadOnlyRequest>,
+    ) -> Result<Response<SetReadOnlyResponse>, Status> {
+        let read_only = request.into_inner().read_only;
+        let was_read_only = self.engine.set_read_only(read_only);
+        Ok(Response::new(SetReadOnlyResponse { was_read_only }))
+    }
+
+    async fn handle_verify_backup(
+        &self,
+        request: Request<Streaming<ExportDocumentsResponse>>,
+    ) -> Result<Response<VerifyBackupResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut check = BackupCheck::default();
+        while let Some(message) = stream.message().await? {
+            if let Err(problem) = check.add(message.item) {
+                return Ok(Response::new(check.report(Some(problem))));
+            }
+        }
+        Ok(Response::new(check.report(None)))
+    }
+
+    async fn handle_archive_collection(
+        &self,
+        request: Request<ArchiveCollectionRequest>,
+        request_id: &RequestId,
+    ) -> Result<Response<ArchiveCollectionResponse>, Status> {
+        let req = request.into_inner();
+        let collection = qualify(&req.database_id, &req.collection_id)?;
+        let (engine, archived) = (self.engine.clone(), collection.clone());
+        let stub = tokio::task::spawn_blocking(move || {
+            panic::catch(|| engine.archive_collection(&archived, None))
+        })
+        .await
+        .map_err(|e| Status::internal(format!("task failed: {e}")))?
+        .map_err(|panic| panic_to_status(request_id, panic))?
+        .map_err(engine_err_to_status)?;
+        Ok(Response::new(ArchiveCollectionResponse {
+            collection: stub.map(|stub| archived_collection(&collection, stub)),
+        }))
+    }
+
+    async fn handle_restore_collection(
+        &self,
+        request: Request<RestoreCollectionRequest>,
+        request_id: &RequestId,
+    ) -> Result<Response<RestoreCollectionResponse>, Status> {
+        let req = request.into_inner();
+        let collection = qualify(&req.database_id, &req.collection_id)?;
+        let engine = self.engine.clone();
+        let document_count = tokio::task::spawn_blocking(move || {
+            panic::catch(|| engine.restore_collection(&collection))
+        })
+        .await
+        .map_err(|e| Status::internal(format!("task failed: {e}")))?
+        .map_err(|panic| panic_to_status(request_id, panic))?
+        .map_err(engine_err_to_status)?;
+        Ok(Response::new(RestoreCollectionResponse { document_count }))
+    }
+
+    fn handle_list_archived_collections(
+        &self,
+    ) -> Result<Response<ListArchivedCollectionsResponse>, Status> {
+        let collections = self
+            .engine
+            .archived_collections()
+            .map_err(engine_err_to_status)?
+            .into_iter()
+            .map(|(collection, stub)| archived_collection(&collection, stub))
+            .collect();
+        Ok(Response::new(ListArchivedCollectionsResponse {
+            collections,
+        }))
+    }
+
+    async fn handle_reload_config(&self) -> Result<Response<()>, Status> {
+        let Some(reloader) = self.reloader.clone() else {
+            return Err(Status::unimplemented("config reload is not available"));
+        };
+        tokio::task::spawn_blocking(move || reloader.reload())
+            .await
+            .map_err(|e| Status::internal(format!("task failed: {e}")))?
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        Ok(Response::new(()))
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn compact(&self, request: Request<CompactRequest>) -> Result<Response<()>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("Compact", self.handle_compact(&request_id).await)
+    }
+
+    async fn persist(&self, request: Request<PersistRequest>) -> Result<Response<()>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("Persist", self.handle_persist(&request_id).await)
+    }
+
+    async fn get_keyspace_stats(
+        &self,
+        request: Request<GetKeyspaceStatsRequest>,
+    ) -> Result<Response<GetKeyspaceStatsResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("GetKeyspaceStats", self.handle_get_keyspace_stats())
+    }
+
+    async fn set_read_only(
+        &self,
+        request: Request<SetReadOnlyRequest>,
+    ) -> Result<Response<SetReadOnlyResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("SetReadOnly", self.handle_set_read_only(request))
+    }
+
+    async fn verify_backup(
+        &self,
+        request: Request<Streaming<ExportDocumentsResponse>>,
+    ) -> Result<Response<VerifyBackupResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("VerifyBackup", self.handle_verify_backup(request).await)
+    }
+
+    async fn reload_config(
+        &self,
+        request: Request<ReloadConfigRequest>,
+    ) -> Result<Response<()>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("ReloadConfig", self.handle_reload_config().await)
+    }
+
+    async fn archive_collection(
+        &self,
+        request: Request<ArchiveCollectionRequest>,
+    ) -> Result<Response<ArchiveCollectionResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        let response = self.handle_archive_collection(request, &request_id).await;
+        request_id.finish("ArchiveCollection", response)
+    }
+
+    async fn restore_collection(
+        &self,
+        request: Request<RestoreCollectionRequest>,
+    ) -> Result<Response<RestoreCollectionResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        let response = self.handle_restore_collection(request, &request_id).await;
+        request_id.finish("RestoreCollection", response)
+    }
+
+    async fn list_archived_collections(
+        &self,
+        request: Request<ListArchivedCollectionsRequest>,
+    ) -> Result<Response<ListArchivedCollectionsResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish(
+            "ListArchivedCollections",
+            self.handle_list_archived_collections(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{Chunk, ChunkWriter, Compression};
+    use crate::service::{chunk_item, manifest_item};
+    use std::time::SystemTime;
+    use tonic::Code;
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(value) = authorization {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION_HEADER, value.parse().unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn test_auth() {
+        let auth = AdminAuth::new("s3cret");
+        assert!(auth.check(request(Some("Bearer s3cret"))).is_ok());
+
+        let code = |authorization| auth.check(request(authorization)).unwrap_err().code();
+        assert_eq!(code(Some("Bearer s3cre")), Code::PermissionDenied);
+        assert_eq!(code(Some("Bearer s3cret!")), Code::PermissionDenied);
+        assert_eq!(code(Some("s3cret")), Code::Unauthenticated);
+        assert_eq!(code(None), Code::Unauthenticated);
+
+        auth.clone().set_token("n3w");
+        assert!(auth.check(request(Some("Bearer n3w"))).is_ok());
+        assert_eq!(code(Some("Bearer s3cret")), Code::PermissionDenied);
+    }
+
+    /// A backup of `count` documents, in chunks of a few documents each.
+    fn backup(count: usize) -> (Vec<Chunk>, Manifest) {
+        let mut writer = ChunkWriter::new(64, Compression::Zstd);
+        let mut chunks = Vec::new();
+        for i in 0..count {
+            let doc = Document {
+                name: name::format("users", &format!("user-{i}")),
+                ..Default::default()
+            };
+            chunks.extend(writer.push(&doc.encode_to_vec()).unwrap());
+        }
+        let (last, manifest) = writer.finish().unwrap();
+        chunks.extend(last);
+        (chunks, manifest)
+    }
+
+    fn verify(chunks: Vec<Chunk>, manifest: Option<Manifest>) -> VerifyBackupResponse {
+        let mut check = BackupCheck::default();
+        let items = chunks
+            .into_iter()
+            .map(chunk_item)
+            .chain(manifest.map(|m| manifest_item(m, SystemTime::now())));
+        for item in items {
+            if let Err(problem) = check.add(Some(item)) {
+                return check.report(Some(problem));
+            }
+        }
+        check.report(None)
+    }
+
+    #[test]
+    fn test_verify_backup() {
+        let (chunks, manifest) = backup(250);
+        let chunk_count = chunks.len() as u64;
+        let report = verify(chunks, Some(manifest));
+        assert!(report.valid, "{}", report.problem);
+        assert_eq!(report.chunk_count, chunk_count);
+        assert_eq!(report.document_count, 250);
+        assert_eq!(report.sampled_documents, 3);
+    }
+
+    #[test]
+    fn test_verify_damaged_backup() {
+        let (mut chunks, manifest) = backup(10);
+        chunks[1].payload[0] ^= 1;
+        let report = verify(chunks, Some(manifest.clone()));
+        assert!(!report.valid);
+        assert_eq!(report.problem, "checksum mismatch in chunk 1");
+        assert_eq!(report.chunk_count, 1);
+
+        let (mut chunks, _) = backup(10);
+        chunks.pop();
+        let report = verify(chunks, Some(manifest));
+        assert_eq!(report.problem, "chunks do not match manifest");
+
+        let (chunks, _) = backup(10);
+        let report = verify(chunks, None);
+        assert_eq!(report.problem, "backup ends without a manifest");
+    }
+}
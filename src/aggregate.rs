@@ -0,0 +1,520 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Aggregations (count, sum, avg) over the documents of a collection.
+//!
+//! Sums of ints are exact and fail with [`OutOfRange`] if they do not fit in
+//! an i64, they never wrap or silently turn into a lossy double. A sum with
+//! any double is a double, which fails the same way if it overflows to
+//! infinity while every value was finite.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::api::v1alpha1::aggregation::Operator;
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::{Aggregation, Document, FieldFilter, Value};
+
+/// Maximum number of aggregations in a single query.
+pub const MAX_AGGREGATIONS: usize = 5;
+
+/// Error returned when an aggregation query is malformed.
+#[derive(Debug, PartialEq)]
+pub struct AggregateError(String);
+
+impl fmt::Display for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid aggregation: {}", self.0)
+    }
+}
+
+impl std::error::Error for AggregateError {}
+
+fn invalid(msg: impl Into<String>) -> AggregateError {
+    AggregateError(msg.into())
+}
+
+/// Error returned when an aggregated value cannot be represented.
+#[derive(Debug, PartialEq)]
+pub struct OutOfRange {
+    pub alias: String,
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: sum is out of range", self.alias)
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+/// Whether `doc` matches every equality filter.
+pub fn matches(doc: &Document, filters: &[FieldFilter]) -> bool {
+    filters
+        .iter()
+        .all(|filter| lookup(&doc.fields, &filter.field) == filter.value.as_ref())
+}
+
+/// `filters` with their parameters, like `$city`, replaced by their value
+/// in `parameters`, keyed by name without the `$`. Every parameter must be
+/// used.
+pub fn bind(
+    filters: &[FieldFilter],
+    parameters: &HashMap<String, Value>,
+) -> Result<Vec<FieldFilter>, AggregateError> {
+    let mut unused: HashSet<&str> = parameters.keys().map(String::as_str).collect();
+    let mut bound = Vec::with_capacity(filters.len());
+    for filter in filters {
+        let value = match (&filter.value, filter.parameter.as_str()) {
+            (Some(value), "") => value.clone(),
+            (None, parameter) if !parameter.is_empty() => {
+                let Some(name) = parameter.strip_prefix('$') else {
+                    return Err(invalid(format!(
+                        "{}: parameter '{parameter}' does not start with '$'",
+                        filter.field
+                    )));
+                };
+                let value = parameters
+                    .get(name)
+                    .ok_or_else(|| invalid(format!("no value for parameter '{parameter}'")))?;
+                unused.remove(name);
+                value.clone()
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "{}: filters need either a value or a parameter",
+                    filter.field
+                )));
+            }
+        };
+        bound.push(FieldFilter {
+            field: filter.field.clone(),
+            value: Some(value),
+            parameter: String::new(),
+        });
+    }
+    if let Some(name) = unused.into_iter().min() {
+        return Err(invalid(format!("parameter '${name}' is not used")));
+    }
+    Ok(bound)
+}
+
+/// Find the value at a dot separated field path.
+pub(crate) fn lookup<'a>(fields: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut value = fields.get(segments.next()?)?;
+    for segment in segments {
+        let Some(ValueType::MapValue(map)) = &value.value_type else {
+            return None;
+        };
+        value = map.fields.get(segment)?;
+    }
+    Some(value)
+}
+
+#[derive(Clone)]
+enum Kind {
+    Count,
+    Sum(String),
+    Avg(String),
+}
+
+/// Running sum of the numeric values of a field.
+#[derive(Clone, Default)]
+struct Sum {
+    /// Exact sum of the int values, an i128 cannot overflow adding up to
+    /// 2^64 i64 values.
+    int: i128,
+    /// Sum of the double values.
+    double: f64,
+    /// Whether any value was a double.
+    doubles: bool,
+    /// Whether any value was infinite or NaN.
+    non_finite: bool,
+    values: u64,
+}
+
+impl Sum {
+    fn add(&mut self, value: &Value) {
+        match value.value_type {
+            Some(ValueType::IntValue(i)) => self.int += i128::from(i),
+            Some(ValueType::DoubleValue(d)) => {
+                self.double += d;
+                self.doubles = true;
+                self.non_finite |= !d.is_finite();
+            }
+            _ => return,
+        }
+        self.values += 1;
+    }
+
+    /// Add the values of `other`.
+    fn merge(&mut self, other: &Sum) {
+        self.int += other.int;
+        self.double += other.double;
+        self.doubles |= other.doubles;
+        self.non_finite |= other.non_finite;
+        self.values += other.values;
+    }
+
+    /// Sum of every value as a double, `None` if it overflowed.
+    fn total(&self) -> Option<f64> {
+        let total = self.int as f64 + self.double;
+        (total.is_finite() || self.non_finite).then_some(total)
+    }
+
+    /// The int sum if every value was an int, else the double sum; `None`
+    /// if it overflowed.
+    fn finish(&self) -> Option<ValueType> {
+        if self.doubles {
+            self.total().map(ValueType::DoubleValue)
+        } else {
+            i64::try_from(self.int).ok().map(ValueType::IntValue)
+        }
+    }
+}
+
+/// Accumulates the aggregations of one query, document by document.
+#[derive(Clone)]
+pub struct Aggregator {
+    aggregations: Vec<(String, Kind)>,
+    count: u64,
+    sums: Vec<Sum>,
+}
+
+impl Aggregator {
+    /// Validate the requested aggregations.
+    pub fn new(aggregations: &[Aggregation]) -> Result<Self, AggregateError> {
+        if aggregations.is_empty() {
+            return Err(invalid("at least one aggregation is required"));
+        }
+        if aggregations.len() > MAX_AGGREGATIONS {
+            return Err(invalid(format!(
+                "at most {MAX_AGGREGATIONS} aggregations are allowed"
+            )));
+        }
+
+        let mut parsed: Vec<(String, Kind)> = Vec::with_capacity(aggregations.len());
+        for (i, aggregation) in aggregations.iter().enumerate() {
+            let alias = match aggregation.alias.as_str() {
+                "" => format!("field_{}", i + 1),
+                alias => alias.to_string(),
+            };
+            if parsed.iter().any(|(a, _)| *a == alias) {
+                return Err(invalid(format!("duplicate alias {alias:?}")));
+            }
+            let kind = match &aggregation.operator {
+                Some(Operator::Count(_)) => Kind::Count,
+                Some(Operator::Sum(sum)) if !sum.field.is_empty() => Kind::Sum(sum.field.clone()),
+                Some(Operator::Avg(avg)) if !avg.field.is_empty() => Kind::Avg(avg.field.clone()),
+                Some(_) => return Err(invalid(format!("{alias}: field is required"))),
+                None => return Err(invalid(format!("{alias}: operator is required"))),
+            };
+            parsed.push((alias, kind));
+        }
+
+        Ok(Aggregator {
+            sums: parsed.iter().map(|_| Sum::default()).collect(),
+            aggregations: parsed,
+            count: 0,
+        })
+    }
+
+    /// Alias, operator and field of every aggregation, in request order.
+    pub fn describe(&self) -> impl Iterator<Item = (&str, &'static str, Option<&str>)> {
+        self.aggregations.iter().map(|(alias, kind)| match kind {
+            Kind::Count => (alias.as_str(), "COUNT", None),
+            Kind::Sum(field) => (alias.as_str(), "SUM", Some(field.as_str())),
+            Kind::Avg(field) => (alias.as_str(), "AVG", Some(field.as_str())),
+        })
+    }
+
+    /// Add a matching document.
+    pub fn add(&mut self, doc: &Document) {
+        self.count += 1;
+        for ((_, kind), sum) in self.aggregations.iter().zip(&mut self.sums) {
+            if let Kind::Sum(field) | Kind::Avg(field) = kind
+                && let Some(value) = lookup(&doc.fields, field)
+            {
+                sum.add(value);
+            }
+        }
+    }
+
+    /// Add the documents `other` aggregated, an aggregator of the same
+    /// aggregations over other documents, e.g. another range of the
+    /// collection.
+    pub fn merge(&mut self, other: &Aggregator) {
+        self.count += other.count;
+        for (sum, other) in self.sums.iter_mut().zip(&other.sums) {
+            sum.merge(other);
+        }
+    }
+
+    /// Aggregated values keyed by alias.
+    pub fn finish(self) -> Result<HashMap<String, Value>, OutOfRange> {
+        let count = self.count;
+        self.aggregations
+            .into_iter()
+            .zip(self.sums)
+            .map(|((alias, kind), sum)| {
+                let value = match kind {
+                    Kind::Count => Some(ValueType::IntValue(count as i64)),
+                    Kind::Sum(_) => sum.finish(),
+                    Kind::Avg(_) if sum.values == 0 => Some(ValueType::NullValue(
+                        prost_types::NullValue::NullValue as i32,
+                    )),
+                    Kind::Avg(_) => sum
+                        .total()
+                        .map(|total| ValueType::DoubleValue(total / sum.values as f64)),
+                };
+                match value {
+                    Some(value) => Ok((
+                        alias,
+                        Value {
+                            value_type: Some(value),
+                        },
+                    )),
+                    None => Err(OutOfRange { alias }),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::v1alpha1::MapValue;
+    use crate::api::v1alpha1::aggregation::{Avg, Count, Sum as SumOp};
+
+    fn value(value_type: ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    fn doc(fields: &[(&str, ValueType)]) -> Document {
+        Document {
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), value(v.clone())))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn aggregation(alias: &str, operator: Operator) -> Aggregation {
+        Aggregation {
+            alias: alias.to_string(),
+            operator: Some(operator),
+        }
+    }
+
+    #[test]
+    fn test_count_sum_avg() {
+        let mut aggregator = Aggregator::new(&[
+            aggregation("", Operator::Count(Count {})),
+            aggregation(
+                "total",
+                Operator::Sum(SumOp {
+                    field: "age".to_string(),
+                }),
+            ),
+            aggregation(
+                "mean",
+                Operator::Avg(Avg {
+                    field: "age".to_string(),
+                }),
+            ),
+        ])
+        .unwrap();
+        aggregator.add(&doc(&[("age", ValueType::IntValue(30))]));
+        aggregator.add(&doc(&[("age", ValueType::IntValue(40))]));
+        aggregator.add(&doc(&[("age", ValueType::StringValue("n/a".into()))]));
+
+        let result = aggregator.finish().unwrap();
+        assert_eq!(result["field_1"], value(ValueType::IntValue(3)));
+        assert_eq!(result["total"], value(ValueType::IntValue(70)));
+        assert_eq!(result["mean"], value(ValueType::DoubleValue(35.0)));
+    }
+
+    #[test]
+    fn test_merge() {
+        let aggregations = [
+            aggregation("n", Operator::Count(Count {})),
+            aggregation(
+                "total",
+                Operator::Sum(SumOp {
+                    field: "age".to_string(),
+                }),
+            ),
+        ];
+        let mut first = Aggregator::new(&aggregations).unwrap();
+        first.add(&doc(&[("age", ValueType::IntValue(i64::MAX))]));
+        let mut second = Aggregator::new(&aggregations).unwrap();
+        second.add(&doc(&[("age", ValueType::IntValue(-10))]));
+        second.add(&doc(&[]));
+
+        first.merge(&second);
+        let result = first.finish().unwrap();
+        assert_eq!(result["n"], value(ValueType::IntValue(3)));
+        assert_eq!(result["total"], value(ValueType::IntValue(i64::MAX - 10)));
+    }
+
+    #[test]
+    fn test_sum_overflow_and_doubles() {
+        let sum = |values: &[ValueType]| {
+            let mut aggregator = Aggregator::new(&[aggregation(
+                "s",
+                Operator::Sum(SumOp {
+                    field: "n".to_string(),
+                }),
+            )])
+            .unwrap();
+            for v in values {
+                aggregator.add(&doc(&[("n", v.clone())]));
+            }
+            aggregator
+                .finish()
+                .map(|mut result| result.remove("s").unwrap())
+        };
+
+        let out_of_range = Err(OutOfRange {
+            alias: "s".to_string(),
+        });
+        assert_eq!(
+            sum(&[ValueType::IntValue(i64::MAX), ValueType::IntValue(1)]),
+            out_of_range
+        );
+        assert_eq!(
+            sum(&[
+                ValueType::IntValue(i64::MAX),
+                ValueType::IntValue(1),
+                ValueType::IntValue(-1),
+            ]),
+            Ok(value(ValueType::IntValue(i64::MAX)))
+        );
+        assert_eq!(
+            sum(&[
+                ValueType::DoubleValue(f64::MAX),
+                ValueType::DoubleValue(f64::MAX)
+            ]),
+            out_of_range
+        );
+        assert_eq!(
+            sum(&[ValueType::DoubleValue(f64::INFINITY)]),
+            Ok(value(ValueType::DoubleValue(f64::INFINITY)))
+        );
+        assert_eq!(
+            sum(&[ValueType::IntValue(1), ValueType::DoubleValue(0.5)]),
+            Ok(value(ValueType::DoubleValue(1.5)))
+        );
+        assert_eq!(sum(&[]), Ok(value(ValueType::IntValue(0))));
+    }
+
+    #[test]
+    fn test_avg_of_large_ints() {
+        let mut aggregator = Aggregator::new(&[aggregation(
+            "a",
+            Operator::Avg(Avg {
+                field: "n".to_string(),
+            }),
+        )])
+        .unwrap();
+        aggregator.add(&doc(&[("n", ValueType::IntValue(i64::MAX))]));
+        aggregator.add(&doc(&[("n", ValueType::IntValue(i64::MAX))]));
+
+        assert_eq!(
+            aggregator.finish().unwrap()["a"],
+            value(ValueType::DoubleValue(i64::MAX as f64))
+        );
+    }
+
+    #[test]
+    fn test_avg_without_values_is_null() {
+        let mut aggregator = Aggregator::new(&[aggregation(
+            "a",
+            Operator::Avg(Avg {
+                field: "missing".to_string(),
+            }),
+        )])
+        .unwrap();
+        aggregator.add(&doc(&[]));
+
+        assert_eq!(
+            aggregator.finish().unwrap()["a"],
+            value(ValueType::NullValue(
+                prost_types::NullValue::NullValue as i32
+            ))
+        );
+    }
+
+    #[test]
+    fn test_invalid_aggregations() {
+        assert!(Aggregator::new(&[]).is_err());
+        assert!(
+            Aggregator::new(&[
+                aggregation("x", Operator::Count(Count {})),
+                aggregation("x", Operator::Count(Count {})),
+            ])
+            .is_err()
+        );
+        assert!(Aggregator::new(&[aggregation("x", Operator::Sum(SumOp::default()))]).is_err());
+    }
+
+    #[test]
+    fn test_matches_nested_field() {
+        let mut address = HashMap::new();
+        address.insert(
+            "city".to_string(),
+            value(ValueType::StringValue("Rome".into())),
+        );
+        let doc = doc(&[("address", ValueType::MapValue(MapValue { fields: address }))]);
+
+        let filter = |field: &str, city: &str| FieldFilter {
+            field: field.to_string(),
+            value: Some(value(ValueType::StringValue(city.into()))),
+            ..Default::default()
+        };
+        assert!(matches(&doc, &[filter("address.city", "Rome")]));
+        assert!(!matches(&doc, &[filter("address.city", "Milan")]));
+        assert!(!matches(&doc, &[filter("address.zip", "Rome")]));
+    }
+
+    #[test]
+    fn test_bind() {
+        let rome = value(ValueType::StringValue("Rome".into()));
+        let filter = |parameter: &str| FieldFilter {
+            field: "city".to_string(),
+            value: None,
+            parameter: parameter.to_string(),
+        };
+        let mut parameters = HashMap::new();
+        parameters.insert("city".to_string(), rome.clone());
+
+        let bound = bind(&[filter("$city")], &parameters).unwrap();
+        assert_eq!(bound[0].value, Some(rome.clone()));
+        assert!(bound[0].parameter.is_empty());
+        let literal = FieldFilter {
+            value: Some(rome),
+            ..filter("")
+        };
+        assert_eq!(
+            bind(&[literal.clone()], &HashMap::new()).unwrap(),
+            [literal.clone()]
+        );
+
+        // unknown, unused and malformed parameters
+        assert!(bind(&[filter("$town")], &parameters).is_err());
+        assert!(bind(&[literal.clone()], &parameters).is_err());
+        assert!(bind(&[filter("city")], &parameters).is_err());
+        assert!(bind(&[filter("")], &HashMap::new()).is_err());
+        let both = FieldFilter {
+            parameter: "$city".to_string(),
+            ..literal
+        };
+        assert!(bind(&[both], &parameters).is_err());
+    }
+}
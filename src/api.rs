@@ -0,0 +1,9 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Types generated from the protobuf definitions.
+
+pub mod v1alpha1 {
+    tonic::include_proto!("api.v1alpha1");
+}
@@ -0,0 +1,286 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Archival tier for cold collections.
+//!
+//! Archiving a collection moves all of its documents out of the primary
+//! keyspace into a single segment written to an [`ArchiveStore`], and leaves
+//! a [`Stub`] in its place saying which segment holds them. Segments keep the
+//! storage records as they are, so sequence numbers and write times survive a
+//! round trip through the archive.
+//!
+//! Segment layout: `{crc32: u32 BE}` of the rest, then zstd compressed
+//! `{key_len: u32 BE}{key}{record_len: u32 BE}{record}` repeated in key
+//! order.
+//!
+//! The only store is [`DirStore`], a directory of segment files; an object
+//! storage bucket mounted as a file system (s3fs, gcsfuse, ...) makes it an
+//! object storage tier.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Zstd level used for segments, written once and read rarely.
+const ZSTD_LEVEL: i32 = 9;
+
+/// Storage of archived segments, by name.
+pub trait ArchiveStore: Send + Sync {
+    /// Store `data` under `name`, replacing any previous segment.
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()>;
+
+    /// The segment stored under `name`.
+    fn get(&self, name: &str) -> io::Result<Vec<u8>>;
+
+    /// Delete the segment stored under `name`, if any.
+    fn delete(&self, name: &str) -> io::Result<()>;
+}
+
+/// Segments stored as files of a directory.
+#[derive(Debug, Clone)]
+pub struct DirStore {
+    dir: PathBuf,
+}
+
+impl DirStore {
+    /// Store in `dir`, created if it does not exist.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(DirStore { dir })
+    }
+}
+
+impl ArchiveStore for DirStore {
+    fn put(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        // written aside first so a crash never leaves half a segment
+        let tmp = self.dir.join(format!("{name}.tmp"));
+        fs::write(&tmp, data)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(&tmp, self.dir.join(name))
+    }
+
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.dir.join(name))
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Errors of the archival tier.
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// No archive store is configured.
+    Disabled,
+    /// The store failed to read or write a segment.
+    Io(io::Error),
+    /// A segment does not match its checksum or is malformed.
+    Corrupted(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Disabled => write!(f, "no archive store is configured"),
+            ArchiveError::Io(e) => write!(f, "archive store error: {e}"),
+            ArchiveError::Corrupted(segment) => write!(f, "corrupted segment {segment}"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+/// Pack `(key, record)` entries, in key order, into a segment.
+pub fn encode_segment(entries: &[(Vec<u8>, Vec<u8>)]) -> io::Result<Vec<u8>> {
+    let mut writer = SegmentWriter::new()?;
+    for (key, record) in entries {
+        writer.push(key, record)?;
+    }
+    writer.finish()
+}
+
+/// Segment packed entry by entry, compressed as it goes, so the entries
+/// need not all be held at once like [`encode_segment`] does.
+pub struct SegmentWriter {
+    encoder: zstd::stream::write::Encoder<'static, Vec<u8>>,
+}
+
+impl SegmentWriter {
+    pub fn new() -> io::Result<Self> {
+        Ok(SegmentWriter {
+            encoder: zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL)?,
+        })
+    }
+
+    /// Append the entry of `key`, which must sort after the keys pushed
+    /// before.
+    pub fn push(&mut self, key: &[u8], record: &[u8]) -> io::Result<()> {
+        self.encoder.write_all(&(key.len() as u32).to_be_bytes())?;
+        self.encoder.write_all(key)?;
+        self.encoder
+            .write_all(&(record.len() as u32).to_be_bytes())?;
+        self.encoder.write_all(record)
+    }
+
+    /// The segment of the entries pushed.
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        let compressed = self.encoder.finish()?;
+        let mut segment = Vec::with_capacity(4 + compressed.len());
+        segment.extend_from_slice(&crc32fast::hash(&compressed).to_be_bytes());
+        segment.extend_from_slice(&compressed);
+        Ok(segment)
+    }
+}
+
+/// Verify and unpack the `(key, record)` entries of segment `name`.
+pub fn decode_segment(name: &str, segment: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ArchiveError> {
+    let corrupted = || ArchiveError::Corrupted(name.to_string());
+    let (checksum, compressed) = segment.split_first_chunk::<4>().ok_or_else(corrupted)?;
+    if crc32fast::hash(compressed) != u32::from_be_bytes(*checksum) {
+        return Err(corrupted());
+    }
+    let raw = zstd::decode_all(compressed).map_err(|_| corrupted())?;
+
+    let mut entries = Vec::new();
+    let mut rest = raw.as_slice();
+    while !rest.is_empty() {
+        let key = take(&mut rest).ok_or_else(corrupted)?;
+        let record = take(&mut rest).ok_or_else(corrupted)?;
+        entries.push((key.to_vec(), record.to_vec()));
+    }
+    Ok(entries)
+}
+
+/// Split a length prefixed field off the front of `rest`.
+fn take<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let (len, tail) = rest.split_first_chunk::<4>()?;
+    let len = u32::from_be_bytes(*len) as usize;
+    if tail.len() < len {
+        return None;
+    }
+    let (field, tail) = tail.split_at(len);
+    *rest = tail;
+    Some(field)
+}
+
+/// What is left of an archived collection in the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stub {
+    /// Name of the segment in the archive store.
+    pub segment: String,
+    /// Number of documents in the segment.
+    pub documents: u64,
+    /// Size of the segment in bytes.
+    pub bytes: u64,
+    /// When the collection was archived, millisecond precision.
+    pub archived_at: SystemTime,
+}
+
+impl Stub {
+    /// Layout: `{documents: u64 BE}{bytes: u64 BE}`, when it was archived in
+    /// milliseconds since the epoch as `u64 BE`, then the segment name.
+    pub fn encode(&self) -> Vec<u8> {
+        let archived_at = self
+            .archived_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut bytes = Vec::with_capacity(24 + self.segment.len());
+        bytes.extend_from_slice(&self.documents.to_be_bytes());
+        bytes.extend_from_slice(&self.bytes.to_be_bytes());
+        bytes.extend_from_slice(&archived_at.to_be_bytes());
+        bytes.extend_from_slice(self.segment.as_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (documents, rest) = bytes.split_first_chunk::<8>()?;
+        let (size, rest) = rest.split_first_chunk::<8>()?;
+        let (archived_at, segment) = rest.split_first_chunk::<8>()?;
+        Some(Stub {
+            segment: String::from_utf8(segment.to_vec()).ok()?,
+            documents: u64::from_be_bytes(*documents),
+            bytes: u64::from_be_bytes(*size),
+            archived_at: SystemTime::UNIX_EPOCH
+                + Duration::from_millis(u64::from_be_bytes(*archived_at)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![
+            (b"users/alice".to_vec(), b"record a".to_vec()),
+            (b"users/bob".to_vec(), Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn test_segment_roundtrip() {
+        let segment = encode_segment(&entries()).unwrap();
+        assert_eq!(decode_segment("s", &segment).unwrap(), entries());
+        let empty = encode_segment(&[]).unwrap();
+        assert!(decode_segment("s", &empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupted_segment() {
+        let mut segment = encode_segment(&entries()).unwrap();
+        *segment.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            decode_segment("s", &segment),
+            Err(ArchiveError::Corrupted(name)) if name == "s"
+        ));
+        assert!(decode_segment("s", &[1, 2]).is_err());
+
+        // valid checksum, truncated entries
+        let compressed = zstd::encode_all(&[0, 0, 0, 9, b'k'][..], ZSTD_LEVEL).unwrap();
+        let mut segment = crc32fast::hash(&compressed).to_be_bytes().to_vec();
+        segment.extend_from_slice(&compressed);
+        assert!(decode_segment("s", &segment).is_err());
+    }
+
+    #[test]
+    fn test_stub_roundtrip() {
+        let stub = Stub {
+            segment: "0190-a.segment".to_string(),
+            documents: 3,
+            bytes: 120,
+            archived_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000),
+        };
+        assert_eq!(Stub::decode(&stub.encode()), Some(stub));
+        assert_eq!(Stub::decode(&[0; 10]), None);
+    }
+
+    #[test]
+    fn test_dir_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DirStore::new(dir.path().join("archive")).unwrap();
+        store.put("a.segment", b"one").unwrap();
+        store.put("a.segment", b"two").unwrap();
+        assert_eq!(store.get("a.segment").unwrap(), b"two");
+        store.delete("a.segment").unwrap();
+        store.delete("a.segment").unwrap();
+        assert_eq!(
+            store.get("a.segment").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
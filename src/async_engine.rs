@@ -0,0 +1,258 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Engine work for async callers.
+//!
+//! Engine calls block on disk I/O and locks, so they must not run on the
+//! runtime threads. An [`AsyncEngine`] runs them on a fixed pool of worker
+//! threads of its own rather than on the blocking pool of the runtime,
+//! which spawns a thread per call up to hundreds: at most as many calls as
+//! there are workers run at once, the others wait in a bounded queue. Once
+//! the queue is full, callers wait for a free slot before queueing, so a
+//! burst of requests is held back in the handlers, where their deadlines
+//! still apply.
+//!
+//! Long-running work, like operations and export streams, stays on the
+//! blocking pool of the runtime, it would hold a worker for minutes; the
+//! service bounds how much of it runs at once.
+//!
+//! Before the engine closes, [`AsyncEngine::drain`] waits for the calls
+//! queued or running to finish.
+
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{mpsc, oneshot, watch};
+
+use crate::engine::{Durability, Engine};
+use crate::panic::{self, Panic};
+
+/// Number of worker threads, engine calls running at once.
+pub const DEFAULT_WORKERS: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+
+/// Number of engine calls waiting for a worker before callers wait too.
+pub const DEFAULT_QUEUE_DEPTH: NonZeroUsize = NonZeroUsize::new(1024).unwrap();
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Engine handle whose calls run on a worker pool, shared by clones. The
+/// workers stop once every handle is dropped.
+#[derive(Clone)]
+pub struct AsyncEngine {
+    engine: Engine,
+    jobs: mpsc::Sender<Job>,
+    /// Number of calls queued or running.
+    pending: Arc<watch::Sender<usize>>,
+}
+
+/// Counts a call as pending until dropped, along with the call once it ran.
+struct Pending(Arc<watch::Sender<usize>>);
+
+impl Pending {
+    fn new(pending: &Arc<watch::Sender<usize>>) -> Self {
+        pending.send_modify(|pending| *pending += 1);
+        Pending(pending.clone())
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.send_modify(|pending| *pending -= 1);
+    }
+}
+
+impl AsyncEngine {
+    /// Run the calls of `engine` on `workers` threads, with up to
+    /// `queue_depth` calls waiting for one.
+    pub fn new(engine: Engine, workers: NonZeroUsize, queue_depth: NonZeroUsize) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>(queue_depth.get());
+        let queue = Arc::new(Mutex::new(queue));
+        for i in 0..workers.get() {
+            let queue = queue.clone();
+            std::thread::Builder::new()
+                .name(format!("zerotable-engine-{i}"))
+                .spawn(move || {
+                    loop {
+                        // released before the job runs, for the next worker
+                        let job = queue
+                            .lock()
+                            .expect("engine queue lock poisoned")
+                            .blocking_recv();
+                        match job {
+                            Some(job) => job(),
+                            None => break,
+                        }
+                    }
+                })
+                .expect("engine worker thread spawns");
+        }
+        AsyncEngine {
+            engine,
+            jobs,
+            pending: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    /// The engine, for calls that do not block, like reading its settings,
+    /// and for blocking code.
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Handle on the same workers whose writes are attributed to `actor`,
+    /// see [`Engine::acting_as`].
+    pub fn acting_as(&self, actor: &str) -> AsyncEngine {
+        AsyncEngine {
+            engine: self.engine.acting_as(actor),
+            jobs: self.jobs.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// Handle on the same workers whose writes reach the disk as
+    /// `durability` says, see [`Engine::with_durability`].
+    pub fn with_durability(&self, durability: Durability) -> AsyncEngine {
+        AsyncEngine {
+            engine: self.engine.with_durability(durability),
+            jobs: self.jobs.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// Run `work` on a worker, waiting for a free slot of the queue first if
+    /// it is full. Returns the panic of `work` if it panics.
+    ///
+    /// Dropping the future before `work` is queued drops `work`, dropping it
+    /// after does not stop `work`, a deadline it checks does.
+    pub async fn run<T, F>(&self, work: F) -> Result<T, Panic>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Engine) -> T + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        let (done, result) = oneshot::channel();
+        let pending = Pending::new(&self.pending);
+        let job: Job = Box::new(move || {
+            let _pending = pending;
+            // the caller may be gone already
+            let _ = done.send(panic::catch(|| work(&engine)));
+        });
+        self.jobs
+            .send(job)
+            .await
+            .expect("engine workers outlive their handles");
+        result.await.expect("engine workers run every job")
+    }
+
+    /// Wait for every call queued or running to finish, those made
+    /// meanwhile included. Calls waiting for a free slot of the queue are
+    /// waited for too.
+    pub async fn drain(&self) {
+        let mut pending = self.pending.subscribe();
+        // the sender is held by `self`, it cannot be dropped meanwhile
+        let _ = pending.wait_for(|&pending| pending == 0).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    fn test_engine(workers: usize, queue_depth: usize) -> AsyncEngine {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Engine::open(dir.path()).unwrap();
+        AsyncEngine::new(
+            engine,
+            NonZeroUsize::new(workers).unwrap(),
+            NonZeroUsize::new(queue_depth).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let engine = test_engine(2, 4).acting_as("alice");
+        let sequence = engine
+            .run(|engine| engine.create_document("users", "a", b"alice"))
+            .await
+            .unwrap()
+            .unwrap();
+        let document = engine
+            .run(|engine| engine.get_document("users", "a"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(document.sequence, sequence);
+        assert_eq!(document.data, b"alice");
+
+        let panic = engine.run::<(), _>(|_| panic!("boom")).await.unwrap_err();
+        assert_eq!(panic.message, "boom");
+        // the worker survives
+        assert!(
+            engine
+                .run(|engine| engine.get_document("users", "a"))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_is_bounded() {
+        let engine = test_engine(2, 1);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let engine = engine.clone();
+                let (running, most) = (running.clone(), most.clone());
+                tokio::spawn(async move {
+                    engine
+                        .run(move |_| {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            most.fetch_max(now, Ordering::SeqCst);
+                            std::thread::sleep(Duration::from_millis(10));
+                            running.fetch_sub(1, Ordering::SeqCst);
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert!(most.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let engine = test_engine(1, 4);
+        let done = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let (engine, done) = (engine.clone(), done.clone());
+                tokio::spawn(async move {
+                    engine
+                        .run(move |_| {
+                            std::thread::sleep(Duration::from_millis(10));
+                            done.fetch_add(1, Ordering::SeqCst);
+                        })
+                        .await
+                })
+            })
+            .collect();
+        // every call is queued or running by now
+        while *engine.pending.borrow() < 4 {
+            tokio::task::yield_now().await;
+        }
+        engine.drain().await;
+        assert_eq!(done.load(Ordering::SeqCst), 4);
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        // nothing pending
+        engine.drain().await;
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Audit log entries recording every document mutation.
+//!
+//! Entries are keyed by `{Tag::Changelog}{sequence: u64 BE}{index: u32 BE}`,
+//! the commit sequence number of the write and the position of the change
+//! within it, so the log reads back in write order.
+//!
+//! Entry format: `{version: u8}{time_millis: u64 BE}{action: u8}
+//! {collection_sequence: u64 BE}`, then the actor, collection ID and document
+//! ID each as `{len: u16 BE}{bytes}`, then the document payload after the
+//! change, empty for deletes. Version 1 entries lack the collection sequence.
+//!
+//! Version 3 entries are laid out like version 2 ones, their document
+//! payload sealed, see [`cipher`](crate::cipher), bound to the key and the
+//! fields before it.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::cipher::{self, CipherError, KeyProvider};
+use crate::keys::Tag;
+
+/// Current entry format version.
+const FORMAT_VERSION: u8 = 2;
+
+/// Version of the entries whose document payload is sealed, see
+/// [`encode_sealed`].
+const SEALED_VERSION: u8 = 3;
+
+/// Size of an entry key.
+pub const KEY_LEN: usize = 1 + 8 + 4;
+
+/// Errors that can occur while decoding an audit entry.
+#[derive(Debug, PartialEq)]
+pub enum AuditError {
+    Truncated,
+    /// The key is not tagged as an audit entry key.
+    NotAnEntryKey,
+    UnknownVersion(u8),
+    UnknownAction(u8),
+    InvalidUtf8,
+    /// The sealed document payload could not be opened.
+    Cipher(CipherError),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Truncated => write!(f, "audit entry truncated"),
+            AuditError::NotAnEntryKey => write!(f, "not an audit entry key"),
+            AuditError::UnknownVersion(v) => write!(f, "unknown audit entry version {v}"),
+            AuditError::UnknownAction(a) => write!(f, "unknown audit action {a}"),
+            AuditError::InvalidUtf8 => write!(f, "audit entry holds invalid UTF-8"),
+            AuditError::Cipher(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<CipherError> for AuditError {
+    fn from(e: CipherError) -> Self {
+        AuditError::Cipher(e)
+    }
+}
+
+/// Kind of change made to a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    /// The document was replaced. Also recorded by unchecked imports, which
+    /// do not know whether the document existed before.
+    Replace,
+    Delete,
+}
+
+impl Action {
+    fn to_byte(self) -> u8 {
+        match self {
+            Action::Create => 1,
+            Action::Replace => 2,
+            Action::Delete => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, AuditError> {
+        match byte {
+            1 => Ok(Action::Create),
+            2 => Ok(Action::Replace),
+            3 => Ok(Action::Delete),
+            _ => Err(AuditError::UnknownAction(byte)),
+        }
+    }
+}
+
+/// One change to one document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Commit sequence number of the write.
+    pub sequence: u64,
+    /// Position of the change among those of the same write.
+    pub index: u32,
+    /// Wall-clock time of the write, millisecond precision.
+    pub time: SystemTime,
+    /// Who made the change, e.g. the client address.
+    pub actor: String,
+    pub action: Action,
+    /// Mutation number of the write in the collection, 0 for entries written
+    /// before collections were numbered.
+    pub collection_sequence: u64,
+    pub collection_id: String,
+    pub doc_id: String,
+    /// Document payload after the change, empty for deletes.
+    pub data: Vec<u8>,
+}
+
+/// Key of the entry for change `index` of the write with `sequence`.
+pub fn key(sequence: u64, index: u32) -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    key[0] = Tag::Changelog as u8;
+    key[1..9].copy_from_slice(&sequence.to_be_bytes());
+    key[9..].copy_from_slice(&index.to_be_bytes());
+    key
+}
+
+/// Encode an entry, returns its key and value.
+///
+/// Collection and document IDs are at most 1500 bytes, longer actors are
+/// truncated.
+pub fn encode(entry: &AuditEntry) -> ([u8; KEY_LEN], Vec<u8>) {
+    let millis = entry
+        .time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let mut end = entry.actor.len().min(usize::from(u16::MAX));
+    while !entry.actor.is_char_boundary(end) {
+        end -= 1;
+    }
+    let actor = &entry.actor[..end];
+
+    let mut value = Vec::with_capacity(
+        1 + 8 + 1 + 8 + 6 + actor.len() + entry.collection_id.len() + entry.doc_id.len(),
+    );
+    value.push(FORMAT_VERSION);
+    value.extend_from_slice(&millis.to_be_bytes());
+    value.push(entry.action.to_byte());
+    value.extend_from_slice(&entry.collection_sequence.to_be_bytes());
+    for part in [actor, &entry.collection_id, &entry.doc_id] {
+        value.extend_from_slice(&(part.len() as u16).to_be_bytes());
+        value.extend_from_slice(part.as_bytes());
+    }
+    value.extend_from_slice(&entry.data);
+    (key(entry.sequence, entry.index), value)
+}
+
+/// Encode an entry like [`encode`], its document payload sealed under the
+/// current key of `keys`.
+pub fn encode_sealed(
+    entry: &AuditEntry,
+    keys: &dyn KeyProvider,
+) -> Result<([u8; KEY_LEN], Vec<u8>), CipherError> {
+    let (key, mut value) = encode(entry);
+    value[0] = SEALED_VERSION;
+    let fields = value.len() - entry.data.len();
+    let sealed = cipher::seal(keys, &[&key[..], &value[..fields]].concat(), &entry.data)?;
+    value.truncate(fields);
+    value.extend_from_slice(&sealed);
+    Ok((key, value))
+}
+
+/// Decode an entry from its key and value. Fails on sealed entries, see
+/// [`decode_with`].
+pub fn decode(key: &[u8], value: &[u8]) -> Result<AuditEntry, AuditError> {
+    decode_with(key, value, None)
+}
+
+/// Decode an entry like [`decode`], opening sealed document payloads with
+/// `keys`.
+pub fn decode_with(
+    entry_key: &[u8],
+    value: &[u8],
+    keys: Option<&dyn KeyProvider>,
+) -> Result<AuditEntry, AuditError> {
+    let key = match entry_key.split_first() {
+        Some((&tag, rest)) if tag == Tag::Changelog as u8 => rest,
+        _ => return Err(AuditError::NotAnEntryKey),
+    };
+    let (sequence, index) = key.split_first_chunk::<8>().ok_or(AuditError::Truncated)?;
+    let index: [u8; 4] = index.try_into().map_err(|_| AuditError::Truncated)?;
+
+    let (&version, rest) = value.split_first().ok_or(AuditError::Truncated)?;
+    if !(1..=SEALED_VERSION).contains(&version) {
+        return Err(AuditError::UnknownVersion(version));
+    }
+    let (millis, rest) = rest.split_first_chunk::<8>().ok_or(AuditError::Truncated)?;
+    let (&action, mut rest) = rest.split_first().ok_or(AuditError::Truncated)?;
+    let mut collection_sequence = 0;
+    if version >= 2 {
+        let (sequence, tail) = rest.split_first_chunk::<8>().ok_or(AuditError::Truncated)?;
+        collection_sequence = u64::from_be_bytes(*sequence);
+        rest = tail;
+    }
+    let mut parts = Vec::with_capacity(3);
+    for _ in 0..3 {
+        let (len, tail) = rest.split_first_chunk::<2>().ok_or(AuditError::Truncated)?;
+        let len = usize::from(u16::from_be_bytes(*len));
+        if tail.len() < len {
+            return Err(AuditError::Truncated);
+        }
+        let (part, tail) = tail.split_at(len);
+        let part = std::str::from_utf8(part).map_err(|_| AuditError::InvalidUtf8)?;
+        parts.push(part.to_string());
+        rest = tail;
+    }
+    let [actor, collection_id, doc_id] = <[String; 3]>::try_from(parts).expect("three parts");
+    let data = if version == SEALED_VERSION {
+        let keys = keys.ok_or(CipherError::NoKeys)?;
+        let fields = &value[..value.len() - rest.len()];
+        cipher::open(keys, &[entry_key, fields].concat(), rest)?
+    } else {
+        rest.to_vec()
+    };
+
+    Ok(AuditEntry {
+        sequence: u64::from_be_bytes(*sequence),
+        index: u32::from_be_bytes(index),
+        time: SystemTime::UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(*millis)),
+        actor,
+        action: Action::from_byte(action)?,
+        collection_sequence,
+        collection_id,
+        doc_id,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::StaticKeys;
+
+    fn entry() -> AuditEntry {
+        AuditEntry {
+            sequence: 42,
+            index: 3,
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            actor: "10.0.0.1".to_string(),
+            action: Action::Replace,
+            collection_sequence: 7,
+            collection_id: "users".to_string(),
+            doc_id: "a/b".to_string(),
+            data: b"payload".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let entry = entry();
+        let (key, value) = encode(&entry);
+        assert_eq!(decode(&key, &value), Ok(entry));
+    }
+
+    #[test]
+    fn test_sealed() {
+        let entry = entry();
+        let keys = StaticKeys::new([(1, [1; cipher::KEY_LEN])]).unwrap();
+        let (key, value) = encode_sealed(&entry, &keys).unwrap();
+        assert_eq!(value[0], SEALED_VERSION);
+        assert!(!value.windows(7).any(|w| w == b"payload"));
+        assert_eq!(decode_with(&key, &value, Some(&keys)), Ok(entry));
+
+        assert_eq!(
+            decode(&key, &value),
+            Err(AuditError::Cipher(CipherError::NoKeys))
+        );
+        // bound to its key
+        let other = super::key(43, 3);
+        assert_eq!(
+            decode_with(&other, &value, Some(&keys)),
+            Err(AuditError::Cipher(CipherError::Invalid))
+        );
+    }
+
+    #[test]
+    fn test_decode_version_1() {
+        let entry = entry();
+        let (key, mut value) = encode(&entry);
+        value[0] = 1;
+        value.drain(10..18);
+        let decoded = decode(&key, &value).unwrap();
+        assert_eq!(decoded.collection_sequence, 0);
+        assert_eq!(decoded.doc_id, entry.doc_id);
+        assert_eq!(decoded.data, entry.data);
+    }
+
+    #[test]
+    fn test_keys_follow_write_order() {
+        assert!(key(1, u32::MAX) < key(2, 0));
+        assert!(key(2, 0) < key(2, 1));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let (key, value) = encode(&entry());
+        assert_eq!(decode(&key, &value[..12]), Err(AuditError::Truncated));
+        assert_eq!(decode(&key[..9], &value), Err(AuditError::Truncated));
+        assert_eq!(decode(&key[1..], &value), Err(AuditError::NotAnEntryKey));
+
+        let mut bad = value.clone();
+        bad[0] = 9;
+        assert_eq!(decode(&key, &bad), Err(AuditError::UnknownVersion(9)));
+        let mut bad = value;
+        bad[9] = 0;
+        assert_eq!(decode(&key, &bad), Err(AuditError::UnknownAction(0)));
+    }
+}
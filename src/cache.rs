@@ -0,0 +1,206 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Cache of hot documents.
+//!
+//! Point reads, see [`Engine::get_document`](crate::Engine::get_document),
+//! are served from a size-bounded cache of the documents read last, skipping
+//! the LSM tree, the least recently read are evicted first. A committed
+//! write of a document drops it from the cache.
+//!
+//! Documents found missing are cached as such, so probing again and again
+//! for documents that do not exist skips the LSM tree too. Creating one is a
+//! write like any other and drops the entry.
+//!
+//! A read racing with a write could fill the cache with the version the
+//! write replaced, so reads only fill it if no write committed since they
+//! started, see [`DocumentCache::generation`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::engine::StoredDocument;
+
+/// Bytes an entry is counted for on top of its key and payload.
+const ENTRY_OVERHEAD: u64 = 96;
+
+/// Size-bounded LRU cache of documents by key, shared by the handles of an
+/// engine.
+pub struct DocumentCache {
+    capacity: u64,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Vec<u8>, Entry>,
+    /// Keys by last read, least recent first.
+    order: BTreeMap<u64, Vec<u8>>,
+    tick: u64,
+    bytes: u64,
+    /// Bumped by every invalidation.
+    generation: u64,
+}
+
+struct Entry {
+    /// `None` if the document does not exist.
+    document: Option<StoredDocument>,
+    tick: u64,
+}
+
+impl Entry {
+    /// Bytes the entry at `key` is counted for.
+    fn size(key: &[u8], document: Option<&StoredDocument>) -> u64 {
+        let data = document.map_or(0, |document| document.data.len());
+        key.len() as u64 + data as u64 + ENTRY_OVERHEAD
+    }
+}
+
+impl DocumentCache {
+    /// Cache of up to `capacity` bytes.
+    pub fn new(capacity: u64) -> Self {
+        DocumentCache {
+            capacity,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Document at `key` if cached, `Some(None)` if it is cached as
+    /// missing.
+    pub fn get(&self, key: &[u8]) -> Option<Option<StoredDocument>> {
+        let mut inner = self.lock();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(key)?;
+        let last = std::mem::replace(&mut entry.tick, tick);
+        let document = entry.document.clone();
+        let key = inner.order.remove(&last).expect("cached keys are ordered");
+        inner.order.insert(tick, key);
+        Some(document)
+    }
+
+    /// Generation of the cache, to take before reading a document to
+    /// [`insert`](Self::insert).
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Cache `document`, read at `key` since the cache was at `generation`,
+    /// `None` if missing, unless a document was invalidated since. Evicts
+    /// the least recently read documents to make room.
+    pub fn insert(&self, generation: u64, key: &[u8], document: Option<&StoredDocument>) {
+        let size = Entry::size(key, document);
+        let mut inner = self.lock();
+        if inner.generation != generation || size > self.capacity {
+            return;
+        }
+        inner.remove(key);
+        while inner.bytes + size > self.capacity {
+            let (_, oldest) = inner.order.pop_first().expect("the cache is not empty");
+            inner.remove(&oldest);
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.order.insert(tick, key.to_vec());
+        inner.bytes += size;
+        inner.entries.insert(
+            key.to_vec(),
+            Entry {
+                document: document.cloned(),
+                tick,
+            },
+        );
+    }
+
+    /// Drop the document at `key`, written since it was cached.
+    pub fn invalidate(&self, key: &[u8]) {
+        let mut inner = self.lock();
+        inner.generation += 1;
+        inner.remove(key);
+    }
+
+    /// Bytes cached.
+    pub fn bytes(&self) -> u64 {
+        self.lock().bytes
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("document cache lock poisoned")
+    }
+}
+
+impl Inner {
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.bytes -= Entry::size(key, entry.document.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::now_millis;
+
+    fn document(data: &[u8]) -> StoredDocument {
+        StoredDocument {
+            data: data.to_vec(),
+            sequence: 1,
+            size: data.len(),
+            write_time: now_millis(),
+        }
+    }
+
+    #[test]
+    fn test_lru() {
+        // room for two entries
+        let cache = DocumentCache::new(2 * (1 + 4 + ENTRY_OVERHEAD));
+        let generation = cache.generation();
+        cache.insert(generation, b"a", Some(&document(b"aaaa")));
+        cache.insert(generation, b"b", Some(&document(b"bbbb")));
+        assert_eq!(cache.get(b"a").unwrap().unwrap().data, b"aaaa");
+
+        // a was read last, so b is evicted
+        cache.insert(generation, b"c", Some(&document(b"cccc")));
+        assert_eq!(cache.get(b"b"), None);
+        assert!(cache.get(b"a").is_some());
+        assert!(cache.get(b"c").is_some());
+        assert_eq!(cache.bytes(), 2 * (1 + 4 + ENTRY_OVERHEAD));
+
+        // too large for the cache
+        cache.insert(generation, b"d", Some(&document(&[0; 1024])));
+        assert_eq!(cache.get(b"d"), None);
+    }
+
+    #[test]
+    fn test_missing() {
+        let cache = DocumentCache::new(1024);
+        cache.insert(cache.generation(), b"a", None);
+        assert_eq!(cache.get(b"a"), Some(None));
+        assert_eq!(cache.bytes(), 1 + ENTRY_OVERHEAD);
+
+        // created since
+        cache.invalidate(b"a");
+        assert_eq!(cache.get(b"a"), None);
+        cache.insert(cache.generation(), b"a", Some(&document(b"new")));
+        assert_eq!(cache.get(b"a").unwrap().unwrap().data, b"new");
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let cache = DocumentCache::new(1024);
+        let generation = cache.generation();
+        cache.insert(generation, b"a", Some(&document(b"old")));
+        cache.invalidate(b"a");
+        assert_eq!(cache.get(b"a"), None);
+        assert_eq!(cache.bytes(), 0);
+
+        // read before the write, the version it replaced is not cached
+        cache.insert(generation, b"a", Some(&document(b"old")));
+        assert_eq!(cache.get(b"a"), None);
+        cache.insert(cache.generation(), b"a", Some(&document(b"new")));
+        assert_eq!(cache.get(b"a").unwrap().unwrap().data, b"new");
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Encryption at rest of document payloads.
+//!
+//! Payloads are sealed with XChaCha20-Poly1305 under 256-bit keys handed out
+//! by a [`KeyProvider`], each under a random nonce and bound to the header of
+//! its record, so a sealed payload does not open in another record.
+//!
+//! Every key has an ID, stored with what it sealed. New payloads are sealed
+//! under the current key and older ones open with theirs, so rotating keys
+//! rewrites nothing: a document is sealed under the current key the next
+//! time it is written, and a retired key must stay with the provider until
+//! no record, past revision, archived segment or audit log entry uses it
+//! anymore.
+//!
+//! Document keys are not encrypted, listings and range scans rely on their
+//! order.
+//!
+//! Sealed layout: `{key_id: u32 BE}{nonce: 24 bytes}{ciphertext and tag}`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+/// Length of a key in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Length of a nonce in bytes, large enough to be drawn at random.
+const NONCE_LEN: usize = 24;
+
+/// Errors of sealing and opening payloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CipherError {
+    /// The provider has no key with this ID.
+    UnknownKey(u32),
+    /// The provider could not hand out a key, e.g. its KMS is unreachable.
+    Provider(String),
+    /// A sealed payload is truncated or does not authenticate.
+    Invalid,
+    /// A payload is sealed and no key provider is configured.
+    NoKeys,
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CipherError::UnknownKey(id) => write!(f, "no encryption key {id}"),
+            CipherError::Provider(e) => write!(f, "encryption key provider error: {e}"),
+            CipherError::Invalid => write!(f, "sealed payload does not authenticate"),
+            CipherError::NoKeys => write!(f, "payload is encrypted, no key is configured"),
+        }
+    }
+}
+
+impl std::error::Error for CipherError {}
+
+/// Source of the keys payloads are sealed with, e.g. backed by a KMS.
+pub trait KeyProvider: Send + Sync {
+    /// ID of the key new payloads are sealed under.
+    fn current_key_id(&self) -> Result<u32, CipherError>;
+
+    /// Key `id`.
+    fn key(&self, id: u32) -> Result<[u8; KEY_LEN], CipherError>;
+}
+
+/// Keys held in memory, the one with the largest ID is current.
+#[derive(Clone, PartialEq, Eq)]
+pub struct StaticKeys {
+    keys: BTreeMap<u32, [u8; KEY_LEN]>,
+}
+
+impl StaticKeys {
+    /// Keys by ID, `None` if there are none.
+    pub fn new(keys: impl IntoIterator<Item = (u32, [u8; KEY_LEN])>) -> Option<Self> {
+        let keys: BTreeMap<_, _> = keys.into_iter().collect();
+        (!keys.is_empty()).then_some(StaticKeys { keys })
+    }
+
+    /// Parse comma separated `id:key` pairs, keys in standard base64, like
+    /// `1:8J+Ri...,2:q83v...`. `None` if malformed or empty.
+    pub fn parse(keys: &str) -> Option<Self> {
+        let mut parsed = Vec::new();
+        for pair in keys.split(',') {
+            let (id, key) = pair.trim().split_once(':')?;
+            let key = BASE64.decode(key).ok()?;
+            parsed.push((id.parse().ok()?, key.try_into().ok()?));
+        }
+        Self::new(parsed)
+    }
+}
+
+impl fmt::Debug for StaticKeys {
+    /// Key IDs only, keys stay out of logs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticKeys")
+            .field("ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeyProvider for StaticKeys {
+    fn current_key_id(&self) -> Result<u32, CipherError> {
+        let (id, _) = self.keys.last_key_value().expect("there is a key");
+        Ok(*id)
+    }
+
+    fn key(&self, id: u32) -> Result<[u8; KEY_LEN], CipherError> {
+        self.keys
+            .get(&id)
+            .copied()
+            .ok_or(CipherError::UnknownKey(id))
+    }
+}
+
+/// Seal `plaintext` under the current key of `keys`, bound to `aad`.
+pub fn seal(keys: &dyn KeyProvider, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+    let id = keys.current_key_id()?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&keys.key(id)?));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| CipherError::Invalid)?;
+
+    let mut sealed = Vec::with_capacity(4 + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&id.to_be_bytes());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Open a payload sealed by [`seal`] with the same `aad`.
+pub fn open(keys: &dyn KeyProvider, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, CipherError> {
+    let (id, rest) = sealed
+        .split_first_chunk::<4>()
+        .ok_or(CipherError::Invalid)?;
+    let (nonce, ciphertext) = rest
+        .split_first_chunk::<NONCE_LEN>()
+        .ok_or(CipherError::Invalid)?;
+    let key = keys.key(u32::from_be_bytes(*id))?;
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    XChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| CipherError::Invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> StaticKeys {
+        StaticKeys::new([(1, [1; KEY_LEN]), (2, [2; KEY_LEN])]).unwrap()
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let keys = keys();
+        let sealed = seal(&keys, b"header", b"payload").unwrap();
+        assert_eq!(&sealed[..4], 2u32.to_be_bytes());
+        assert_eq!(open(&keys, b"header", &sealed).unwrap(), b"payload");
+        // nonces are random
+        assert_ne!(seal(&keys, b"header", b"payload").unwrap(), sealed);
+
+        assert_eq!(open(&keys, b"other", &sealed), Err(CipherError::Invalid));
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(open(&keys, b"header", &tampered), Err(CipherError::Invalid));
+        assert_eq!(open(&keys, b"header", &[0; 8]), Err(CipherError::Invalid));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let old = StaticKeys::new([(1, [1; KEY_LEN])]).unwrap();
+        let sealed = seal(&old, b"", b"payload").unwrap();
+        // retired keys still open what they sealed
+        assert_eq!(open(&keys(), b"", &sealed).unwrap(), b"payload");
+        let rotated = StaticKeys::new([(2, [2; KEY_LEN])]).unwrap();
+        assert_eq!(
+            open(&rotated, b"", &sealed),
+            Err(CipherError::UnknownKey(1))
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let key = BASE64.encode([7; KEY_LEN]);
+        let keys = StaticKeys::parse(&format!("3:{key}, 10:{key}")).unwrap();
+        assert_eq!(keys.current_key_id(), Ok(10));
+        assert_eq!(keys.key(3), Ok([7; KEY_LEN]));
+        assert_eq!(format!("{keys:?}"), "StaticKeys { ids: [3, 10] }");
+
+        assert_eq!(StaticKeys::parse(""), None);
+        assert_eq!(StaticKeys::parse("1"), None);
+        assert_eq!(StaticKeys::parse(&format!("x:{key}")), None);
+        assert_eq!(StaticKeys::parse("1:c2hvcnQ="), None);
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Wall clock used for commit timestamps and generated IDs.
+//!
+//! Timestamps have millisecond precision and never move backwards within a
+//! process: if the source jumps back, e.g. after an NTP step, the last
+//! timestamp is reused until the source catches up. Such regressions are
+//! logged and counted.
+
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use uuid::timestamp::context::ContextV7;
+use uuid::{Timestamp, Uuid};
+
+use crate::id::extract_timestamp;
+
+/// Where the current time comes from.
+pub trait ClockSource: Send + Sync + 'static {
+    fn now(&self) -> SystemTime;
+}
+
+/// The operating system wall clock.
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Monotonic millisecond clock on top of a [`ClockSource`].
+pub struct Clock {
+    source: Box<dyn ClockSource>,
+    last_millis: AtomicU64,
+    regressions: AtomicU64,
+    uuid_context: Mutex<ContextV7>,
+}
+
+static GLOBAL: OnceLock<Clock> = OnceLock::new();
+
+/// The process wide clock, reading the system clock unless [`install`] was
+/// called first.
+pub fn global() -> &'static Clock {
+    GLOBAL.get_or_init(|| Clock::new(SystemClock))
+}
+
+/// Replace the process wide clock, must be called before it is first used.
+///
+/// Gives the clock back if the global clock is already in use.
+pub fn install(clock: Clock) -> Result<(), Clock> {
+    GLOBAL.set(clock)
+}
+
+impl Clock {
+    pub fn new(source: impl ClockSource) -> Self {
+        Clock {
+            source: Box::new(source),
+            last_millis: AtomicU64::new(0),
+            regressions: AtomicU64::new(0),
+            uuid_context: Mutex::new(ContextV7::new()),
+        }
+    }
+
+    /// Current time truncated to millisecond precision, never earlier than a
+    /// previously returned time.
+    pub fn now_millis(&self) -> SystemTime {
+        let millis = self
+            .source
+            .now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let last = self.last_millis.fetch_max(millis, Ordering::Relaxed);
+        if millis >= last {
+            return SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+        }
+
+        // log the first regression, then less and less often
+        let regressions = self.regressions.fetch_add(1, Ordering::Relaxed) + 1;
+        if regressions.is_power_of_two() {
+            tracing::warn!(
+                behind_ms = last - millis,
+                regressions,
+                "clock moved backwards, holding at the last timestamp"
+            );
+        }
+        SystemTime::UNIX_EPOCH + Duration::from_millis(last)
+    }
+
+    /// Generate a new UUID v7 and extract its embedded timestamp.
+    ///
+    /// IDs generated by the same clock are strictly increasing.
+    pub fn generate_uuid_v7(&self) -> (Uuid, SystemTime) {
+        let now = self
+            .now_millis()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let ts = Timestamp::from_unix(&self.uuid_context, now.as_secs(), now.subsec_nanos());
+        let uuid = Uuid::new_v7(ts);
+        (uuid, extract_timestamp(&uuid))
+    }
+
+    /// Number of times the source was seen going backwards.
+    pub fn regressions(&self) -> u64 {
+        self.regressions.load(Ordering::Relaxed)
+    }
+
+    /// Render the clock metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_clock_regressions_total Times the wall clock moved backwards."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_clock_regressions_total counter");
+        let _ = writeln!(
+            out,
+            "zerotable_clock_regressions_total {}",
+            self.regressions()
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Clock source returning a time set by the test, in milliseconds.
+    #[derive(Clone, Default)]
+    struct ManualClock(Arc<AtomicU64>);
+
+    impl ManualClock {
+        fn set(&self, millis: u64) {
+            self.0.store(millis, Ordering::Relaxed);
+        }
+    }
+
+    impl ClockSource for ManualClock {
+        fn now(&self) -> SystemTime {
+            SystemTime::UNIX_EPOCH + Duration::from_millis(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    fn millis(time: SystemTime) -> u128 {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    }
+
+    #[test]
+    fn test_never_goes_backwards() {
+        let source = ManualClock::default();
+        let clock = Clock::new(source.clone());
+
+        source.set(1_000);
+        assert_eq!(millis(clock.now_millis()), 1_000);
+
+        source.set(400);
+        assert_eq!(millis(clock.now_millis()), 1_000);
+        assert_eq!(clock.regressions(), 1);
+
+        source.set(1_500);
+        assert_eq!(millis(clock.now_millis()), 1_500);
+        assert_eq!(clock.regressions(), 1);
+    }
+
+    #[test]
+    fn test_uuids_increase_across_regression() {
+        let source = ManualClock::default();
+        let clock = Clock::new(source.clone());
+
+        source.set(1_700_000_000_000);
+        let (first, _) = clock.generate_uuid_v7();
+        source.set(1_600_000_000_000);
+        let (second, ts) = clock.generate_uuid_v7();
+
+        assert!(second > first);
+        assert_eq!(millis(ts), 1_700_000_000_000);
+    }
+}
@@ -0,0 +1,1162 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Server configuration.
+//!
+//! Every setting has a default and can be overridden by a `zerotable.toml`
+//! file, then by a `ZEROTABLE_*` environment variable, then by a command
+//! line flag, see [`ServerConfig::load`].
+//!
+//! The file and the flags name settings after the variables: `data_dir =
+//! "/var/lib/zerotable"` in the file and `--data-dir /var/lib/zerotable` on
+//! the command line both set `ZEROTABLE_DATA_DIR`.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tonic::codec::CompressionEncoding;
+use tracing_subscriber::EnvFilter;
+
+use crate::async_engine::{DEFAULT_QUEUE_DEPTH, DEFAULT_WORKERS};
+use crate::cipher::StaticKeys;
+use crate::engine::{DEFAULT_SOFT_DELETE_RETENTION, Durability};
+use crate::keys;
+use crate::memory::DEFAULT_MEMORY_BUDGET;
+use crate::record::{self, ValueCompression};
+
+/// Configuration file read when no other is given, if it exists.
+const DEFAULT_CONFIG_FILE: &str = "zerotable.toml";
+/// Prefix of the environment variables, and of the setting keys.
+const ENV_PREFIX: &str = "ZEROTABLE_";
+
+const DEFAULT_ADDR: &str = "[::1]:50051";
+const DEFAULT_DATA_DIR: &str = ".zerotable_data";
+
+/// Same default as tonic: 4 MiB.
+const DEFAULT_MAX_DECODING_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+const DEFAULT_MAX_ENCODING_MESSAGE_SIZE: usize = usize::MAX;
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RETRY_BUDGET: Duration = Duration::from_millis(100);
+/// Largest encoded document accepted by default: 1 MiB, like Firestore.
+pub const DEFAULT_MAX_DOCUMENT_SIZE: usize = 1024 * 1024;
+/// How long an idempotency key is remembered by default: one day.
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long a streaming client may stop reading by default before it is
+/// dropped.
+pub const DEFAULT_STREAM_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+/// Number of operations and exports running at once by default.
+pub const DEFAULT_LONG_RUNNING_TASKS: NonZeroUsize = NonZeroUsize::new(8).unwrap();
+/// Fraction of the requests mirrored by default once a mirror is set: all.
+const DEFAULT_MIRROR_FRACTION: f64 = 1.0;
+/// How often the storage is synced to disk by default with periodic
+/// durability.
+const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Errors that can occur while loading the configuration.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    Invalid {
+        key: &'static str,
+        value: String,
+    },
+    /// Neither a TCP address nor a unix socket is configured.
+    NoListener,
+    /// The configuration file could not be read or parsed.
+    File {
+        path: PathBuf,
+        message: String,
+    },
+    /// The configuration file or a flag names a setting that does not exist.
+    UnknownSetting(String),
+    /// A command line argument is not a `--name value` flag.
+    InvalidFlag(String),
+    /// Collections are to be archived, but there is nowhere to put them.
+    NoArchiveDir,
+    /// Only one of the TLS certificate and key is configured.
+    IncompleteTls,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Invalid { key, value } => write!(f, "invalid value for {key}: {value:?}"),
+            ConfigError::NoListener => {
+                write!(
+                    f,
+                    "at least one of ZEROTABLE_ADDR or ZEROTABLE_UNIX_SOCKET is required"
+                )
+            }
+            ConfigError::File { path, message } => {
+                write!(f, "invalid config file {}: {message}", path.display())
+            }
+            ConfigError::UnknownSetting(key) => write!(f, "unknown setting {key}"),
+            ConfigError::InvalidFlag(arg) => write!(f, "invalid flag {arg:?}"),
+            ConfigError::NoArchiveDir => {
+                write!(
+                    f,
+                    "ZEROTABLE_ARCHIVE_IDLE_DAYS requires ZEROTABLE_ARCHIVE_DIR"
+                )
+            }
+            ConfigError::IncompleteTls => {
+                write!(
+                    f,
+                    "ZEROTABLE_TLS_CERT and ZEROTABLE_TLS_KEY must be set together"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// TCP address the gRPC server listens on, `None` to disable TCP.
+    pub addr: Option<SocketAddr>,
+    /// Unix domain socket the gRPC server listens on.
+    pub unix_socket: Option<PathBuf>,
+    /// TCP address of the HTTP/JSON gateway, disabled if `None`.
+    pub rest_addr: Option<SocketAddr>,
+    /// Directory holding the database files.
+    pub data_dir: PathBuf,
+    /// Compression applied to responses when the client accepts it.
+    /// Compressed requests are always accepted.
+    pub compression: Option<CompressionEncoding>,
+    /// Largest request message accepted, in bytes.
+    pub max_decoding_message_size: usize,
+    /// Largest response message sent, in bytes.
+    pub max_encoding_message_size: usize,
+    /// Largest encoded document accepted by writes, in bytes.
+    pub max_document_size: usize,
+    /// Maximum number of in-flight requests per connection.
+    pub concurrency_limit: Option<usize>,
+    /// Maximum number of concurrent HTTP/2 streams per connection.
+    pub max_concurrent_streams: Option<u32>,
+    /// TCP keepalive interval for accepted connections.
+    pub tcp_keepalive: Option<Duration>,
+    /// How long in-flight requests may run after a shutdown signal, and then
+    /// how long the writes still under way may take before closing.
+    pub shutdown_timeout: Duration,
+    /// Accept grpc-web requests from browsers, over HTTP/1.1 as well.
+    pub grpc_web: bool,
+    /// Maximum time a request may spend retrying conflicting transactions.
+    pub retry_budget: Duration,
+    /// Read requests per second allowed per client, unlimited if `None`.
+    pub read_rate_limit: Option<NonZeroU32>,
+    /// Write requests per second allowed per client, unlimited if `None`.
+    pub write_rate_limit: Option<NonZeroU32>,
+    /// How long retried creates with the same idempotency key are deduplicated.
+    pub idempotency_ttl: Duration,
+    /// Record every document mutation in the audit log.
+    pub audit_log: bool,
+    /// How long replaced and deleted document revisions are kept for reads
+    /// as of a past time, none are if `None`.
+    pub history_retention: Option<Duration>,
+    /// How long documents soft deleted are kept before they are purged.
+    pub soft_delete_retention: Duration,
+    /// Token required by the Admin service, which is disabled if `None`.
+    pub admin_token: Option<String>,
+    /// PEM certificate chain the gRPC TCP listener serves TLS with, it
+    /// serves plaintext if `None`. The unix socket and the HTTP/JSON gateway
+    /// always serve plaintext, put the gateway behind a TLS proxy.
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of `tls_cert`, set along with it.
+    pub tls_key: Option<PathBuf>,
+    /// OTLP/gRPC collector receiving traces, tracing is disabled if `None`.
+    pub otlp_endpoint: Option<String>,
+    /// Format of the log lines.
+    pub log_format: LogFormat,
+    /// Directives filtering the logs, e.g. `info,zerotable=debug`, those of
+    /// `RUST_LOG` are used if `None`.
+    pub log_filter: Option<String>,
+    /// Memory the storage engine may use for memtables and caches, in bytes.
+    pub memory_budget: u64,
+    /// Bytes of documents, found or missing, cached for point reads, on top
+    /// of the memory budget, none are cached if 0.
+    pub document_cache: u64,
+    /// Queries running at least this long are logged with their plan, none
+    /// are if `None`.
+    pub slow_query_threshold: Option<Duration>,
+    /// Requests taking at least this long are logged, none are if `None`.
+    pub slow_request_threshold: Option<Duration>,
+    /// Databases whose collections all refuse queries no index serves.
+    pub strict_databases: Vec<String>,
+    /// How long a client may stop reading a response stream before it is
+    /// dropped, releasing the snapshot the stream reads from.
+    pub stream_stall_timeout: Duration,
+    /// Directory archived collections are moved to, archiving is off if
+    /// `None`.
+    pub archive_dir: Option<PathBuf>,
+    /// Restore an archived collection when it is read, rather than reading
+    /// its archived documents where they are.
+    pub archive_rehydrate: bool,
+    /// Collections not written for this long are archived, none are on
+    /// their own if `None`.
+    pub archive_idle_after: Option<Duration>,
+    /// Directory exports and imports run as operations write and read their
+    /// files in, those are refused if `None`.
+    pub export_dir: Option<PathBuf>,
+    /// Secondary server requests are mirrored to, e.g.
+    /// `http://10.0.0.2:50051`, none are if `None`.
+    pub mirror_endpoint: Option<String>,
+    /// Fraction of the requests mirrored, in `(0, 1]`.
+    pub mirror_fraction: f64,
+    /// Mirror reads as well as writes.
+    pub mirror_reads: bool,
+    /// When writes reach the disk, unless a request asks otherwise.
+    pub durability: Durability,
+    /// How often the storage is synced to disk in the background, never on
+    /// its own if `None`. Set by periodic durability only.
+    pub sync_interval: Option<Duration>,
+    /// How long the first of the synced writes committed about the same time
+    /// waits for more to join before syncing.
+    ///
+    /// Only writes with sync durability, the server's or asked for with the
+    /// `x-durability` header, are grouped. Buffered writes, periodic
+    /// durability's included, do not sync on commit: they are neither
+    /// batched nor delayed by the window, which does nothing for a server
+    /// whose clients never ask for sync.
+    pub group_commit_window: Duration,
+    /// How the payloads of the documents written are stored.
+    pub value_compression: ValueCompression,
+    /// Keys the payloads of the documents written are sealed with, stored
+    /// in the clear if `None`.
+    pub encryption_keys: Option<StaticKeys>,
+    /// Number of threads engine calls of requests run on.
+    pub engine_workers: NonZeroUsize,
+    /// Number of engine calls waiting for a worker before requests wait to
+    /// queue theirs.
+    pub engine_queue_depth: NonZeroUsize,
+    /// Number of operations and exports running at once. Operations started
+    /// beyond it wait, exports are refused.
+    pub long_running_tasks: NonZeroUsize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: Some(DEFAULT_ADDR.parse().expect("default address is valid")),
+            unix_socket: None,
+            rest_addr: None,
+            data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+            compression: None,
+            max_decoding_message_size: DEFAULT_MAX_DECODING_MESSAGE_SIZE,
+            max_encoding_message_size: DEFAULT_MAX_ENCODING_MESSAGE_SIZE,
+            max_document_size: DEFAULT_MAX_DOCUMENT_SIZE,
+            concurrency_limit: None,
+            max_concurrent_streams: None,
+            tcp_keepalive: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            grpc_web: false,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            read_rate_limit: None,
+            write_rate_limit: None,
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            audit_log: false,
+            history_retention: None,
+            soft_delete_retention: DEFAULT_SOFT_DELETE_RETENTION,
+            admin_token: None,
+            tls_cert: None,
+            tls_key: None,
+            otlp_endpoint: None,
+            log_format: LogFormat::default(),
+            log_filter: None,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            document_cache: 0,
+            slow_query_threshold: None,
+            slow_request_threshold: None,
+            strict_databases: Vec::new(),
+            stream_stall_timeout: DEFAULT_STREAM_STALL_TIMEOUT,
+            archive_dir: None,
+            archive_rehydrate: false,
+            archive_idle_after: None,
+            export_dir: None,
+            mirror_endpoint: None,
+            mirror_fraction: DEFAULT_MIRROR_FRACTION,
+            mirror_reads: false,
+            durability: Durability::Buffered,
+            sync_interval: None,
+            group_commit_window: Duration::ZERO,
+            value_compression: ValueCompression::None,
+            encryption_keys: None,
+            engine_workers: DEFAULT_WORKERS,
+            engine_queue_depth: DEFAULT_QUEUE_DEPTH,
+            long_running_tasks: DEFAULT_LONG_RUNNING_TASKS,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Load the configuration from the process environment.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Load the configuration from a file, the process environment and the
+    /// command line flags in `args`, which override each other in that
+    /// order.
+    ///
+    /// The file is the one given by `--config`, else by `ZEROTABLE_CONFIG`,
+    /// else `zerotable.toml` in the working directory if it exists.
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut flags = Settings::from_args(args)?;
+        let path = flags
+            .0
+            .remove("ZEROTABLE_CONFIG")
+            .or_else(|| std::env::var("ZEROTABLE_CONFIG").ok())
+            .map(PathBuf::from)
+            .or_else(|| {
+                let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+                default.exists().then_some(default)
+            });
+        let file = match path {
+            Some(path) => Settings::read(&path)?,
+            None => Settings::default(),
+        };
+        Self::from_settings(&file, |key| std::env::var(key).ok(), &flags)
+    }
+
+    /// Load the configuration from `file`, overridden by variables resolved
+    /// by `lookup`, overridden by `flags`.
+    ///
+    /// Fails if `file` or `flags` hold a setting that does not exist.
+    pub fn from_settings(
+        file: &Settings,
+        lookup: impl Fn(&str) -> Option<String>,
+        flags: &Settings,
+    ) -> Result<Self, ConfigError> {
+        let known = RefCell::new(HashSet::new());
+        let config = Self::from_lookup(|key| {
+            known.borrow_mut().insert(key.to_string());
+            flags
+                .get(key)
+                .or_else(|| lookup(key))
+                .or_else(|| file.get(key))
+        })?;
+
+        let known = known.into_inner();
+        let mut unknown: Vec<_> = file.0.keys().chain(flags.0.keys()).collect();
+        unknown.retain(|key| !known.contains(*key));
+        unknown.sort();
+        match unknown.first() {
+            Some(key) => Err(ConfigError::UnknownSetting(key.to_string())),
+            None => Ok(config),
+        }
+    }
+
+    /// Load the configuration using `lookup` to resolve variables.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut config = ServerConfig::default();
+
+        if let Some(value) = lookup("ZEROTABLE_ADDR") {
+            config.addr = match value.as_str() {
+                "" | "none" => None,
+                _ => Some(parse("ZEROTABLE_ADDR", value)?),
+            };
+        }
+        if let Some(value) = lookup("ZEROTABLE_UNIX_SOCKET") {
+            config.unix_socket = (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+        if let Some(value) = lookup("ZEROTABLE_REST_ADDR") {
+            config.rest_addr = match value.as_str() {
+                "" | "none" => None,
+                _ => Some(parse("ZEROTABLE_REST_ADDR", value)?),
+            };
+        }
+        if let Some(value) = lookup("ZEROTABLE_DATA_DIR") {
+            config.data_dir = PathBuf::from(value);
+        }
+        if let Some(value) = lookup("ZEROTABLE_COMPRESSION") {
+            config.compression = parse_compression("ZEROTABLE_COMPRESSION", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_MAX_DECODING_MESSAGE_SIZE") {
+            config.max_decoding_message_size = parse("ZEROTABLE_MAX_DECODING_MESSAGE_SIZE", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_MAX_ENCODING_MESSAGE_SIZE") {
+            config.max_encoding_message_size = parse("ZEROTABLE_MAX_ENCODING_MESSAGE_SIZE", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_MAX_DOCUMENT_SIZE") {
+            config.max_document_size = parse_document_size("ZEROTABLE_MAX_DOCUMENT_SIZE", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_CONCURRENCY_LIMIT") {
+            config.concurrency_limit = Some(parse("ZEROTABLE_CONCURRENCY_LIMIT", value)?);
+        }
+        if let Some(value) = lookup("ZEROTABLE_MAX_CONCURRENT_STREAMS") {
+            config.max_concurrent_streams = Some(parse("ZEROTABLE_MAX_CONCURRENT_STREAMS", value)?);
+        }
+        if let Some(value) = lookup("ZEROTABLE_TCP_KEEPALIVE_SECS") {
+            let secs = parse("ZEROTABLE_TCP_KEEPALIVE_SECS", value)?;
+            config.tcp_keepalive = Some(Duration::from_secs(secs));
+        }
+        if let Some(value) = lookup("ZEROTABLE_SHUTDOWN_TIMEOUT_SECS") {
+            let secs = parse("ZEROTABLE_SHUTDOWN_TIMEOUT_SECS", value)?;
+            config.shutdown_timeout = Duration::from_secs(secs);
+        }
+        if let Some(value) = lookup("ZEROTABLE_GRPC_WEB") {
+            config.grpc_web = parse("ZEROTABLE_GRPC_WEB", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_RETRY_BUDGET_MS") {
+            let millis = parse("ZEROTABLE_RETRY_BUDGET_MS", value)?;
+            config.retry_budget = Duration::from_millis(millis);
+        }
+        if let Some(value) = lookup("ZEROTABLE_SLOW_QUERY_MS") {
+            let millis = parse("ZEROTABLE_SLOW_QUERY_MS", value)?;
+            config.slow_query_threshold = Some(Duration::from_millis(millis));
+        }
+        if let Some(value) = lookup("ZEROTABLE_SLOW_REQUEST_MS") {
+            let millis = parse("ZEROTABLE_SLOW_REQUEST_MS", value)?;
+            config.slow_request_threshold = Some(Duration::from_millis(millis));
+        }
+        if let Some(value) = lookup("ZEROTABLE_STREAM_STALL_TIMEOUT_SECS") {
+            let secs = parse("ZEROTABLE_STREAM_STALL_TIMEOUT_SECS", value)?;
+            config.stream_stall_timeout = Duration::from_secs(secs);
+        }
+        if let Some(value) = lookup("ZEROTABLE_STRICT_DATABASES") {
+            config.strict_databases = parse_databases("ZEROTABLE_STRICT_DATABASES", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_READ_RATE_LIMIT") {
+            config.read_rate_limit = Some(parse("ZEROTABLE_READ_RATE_LIMIT", value)?);
+        }
+        if let Some(value) = lookup("ZEROTABLE_WRITE_RATE_LIMIT") {
+            config.write_rate_limit = Some(parse("ZEROTABLE_WRITE_RATE_LIMIT", value)?);
+        }
+        if let Some(value) = lookup("ZEROTABLE_IDEMPOTENCY_TTL_SECS") {
+            let secs = parse("ZEROTABLE_IDEMPOTENCY_TTL_SECS", value)?;
+            config.idempotency_ttl = Duration::from_secs(secs);
+        }
+        if let Some(value) = lookup("ZEROTABLE_AUDIT_LOG") {
+            config.audit_log = parse("ZEROTABLE_AUDIT_LOG", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_HISTORY_RETENTION_SECS") {
+            config.history_retention = match value.as_str() {
+                "" | "none" => None,
+                _ => {
+                    let secs = parse("ZEROTABLE_HISTORY_RETENTION_SECS", value)?;
+                    Some(Duration::from_secs(secs))
+                }
+            };
+        }
+        if let Some(value) = lookup("ZEROTABLE_SOFT_DELETE_RETENTION_SECS") {
+            let secs = parse("ZEROTABLE_SOFT_DELETE_RETENTION_SECS", value)?;
+            config.soft_delete_retention = Duration::from_secs(secs);
+        }
+        if let Some(value) = lookup("ZEROTABLE_ADMIN_TOKEN") {
+            config.admin_token = (!value.is_empty()).then_some(value);
+        }
+        if let Some(value) = lookup("ZEROTABLE_TLS_CERT") {
+            config.tls_cert = (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+        if let Some(value) = lookup("ZEROTABLE_TLS_KEY") {
+            config.tls_key = (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+        if let Some(value) = lookup("ZEROTABLE_OTLP_ENDPOINT") {
+            config.otlp_endpoint = (!value.is_empty()).then_some(value);
+        }
+        if let Some(value) = lookup("ZEROTABLE_LOG_FORMAT") {
+            config.log_format = parse_log_format("ZEROTABLE_LOG_FORMAT", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_LOG_FILTER") {
+            config.log_filter = parse_log_filter("ZEROTABLE_LOG_FILTER", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_MEMORY_BUDGET_MB") {
+            let mib: u64 = parse("ZEROTABLE_MEMORY_BUDGET_MB", value)?;
+            config.memory_budget = mib.saturating_mul(1024 * 1024);
+        }
+        if let Some(value) = lookup("ZEROTABLE_DOCUMENT_CACHE_MB") {
+            let mib: u64 = parse("ZEROTABLE_DOCUMENT_CACHE_MB", value)?;
+            config.document_cache = mib.saturating_mul(1024 * 1024);
+        }
+        if let Some(value) = lookup("ZEROTABLE_ARCHIVE_DIR") {
+            config.archive_dir = (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+        if let Some(value) = lookup("ZEROTABLE_ARCHIVE_REHYDRATE") {
+            config.archive_rehydrate = parse("ZEROTABLE_ARCHIVE_REHYDRATE", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_ARCHIVE_IDLE_DAYS") {
+            config.archive_idle_after = match value.as_str() {
+                "" | "none" => None,
+                _ => {
+                    let days: u64 = parse("ZEROTABLE_ARCHIVE_IDLE_DAYS", value)?;
+                    Some(Duration::from_secs(days.saturating_mul(24 * 60 * 60)))
+                }
+            };
+        }
+        if let Some(value) = lookup("ZEROTABLE_EXPORT_DIR") {
+            config.export_dir = (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+        if let Some(value) = lookup("ZEROTABLE_MIRROR_ENDPOINT") {
+            config.mirror_endpoint = (!value.is_empty()).then_some(value);
+        }
+        if let Some(value) = lookup("ZEROTABLE_MIRROR_FRACTION") {
+            let fraction: f64 = parse("ZEROTABLE_MIRROR_FRACTION", value.clone())?;
+            if fraction.is_nan() || fraction <= 0.0 || fraction > 1.0 {
+                return Err(ConfigError::Invalid {
+                    key: "ZEROTABLE_MIRROR_FRACTION",
+                    value,
+                });
+            }
+            config.mirror_fraction = fraction;
+        }
+        if let Some(value) = lookup("ZEROTABLE_MIRROR_READS") {
+            config.mirror_reads = parse("ZEROTABLE_MIRROR_READS", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_DURABILITY") {
+            (config.durability, config.sync_interval) =
+                parse_durability("ZEROTABLE_DURABILITY", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_SYNC_INTERVAL_MS") {
+            let millis = parse("ZEROTABLE_SYNC_INTERVAL_MS", value.clone())?;
+            if millis == 0 {
+                return Err(ConfigError::Invalid {
+                    key: "ZEROTABLE_SYNC_INTERVAL_MS",
+                    value,
+                });
+            }
+            // only periodic durability syncs on an interval
+            if let Some(interval) = &mut config.sync_interval {
+                *interval = Duration::from_millis(millis);
+            }
+        }
+        if let Some(value) = lookup("ZEROTABLE_GROUP_COMMIT_WINDOW_US") {
+            let micros = parse("ZEROTABLE_GROUP_COMMIT_WINDOW_US", value)?;
+            config.group_commit_window = Duration::from_micros(micros);
+        }
+        if let Some(value) = lookup("ZEROTABLE_VALUE_COMPRESSION") {
+            config.value_compression =
+                parse_value_compression("ZEROTABLE_VALUE_COMPRESSION", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_ENCRYPTION_KEYS") {
+            config.encryption_keys = parse_encryption_keys("ZEROTABLE_ENCRYPTION_KEYS", &value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_ENGINE_WORKERS") {
+            config.engine_workers = parse("ZEROTABLE_ENGINE_WORKERS", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_ENGINE_QUEUE_DEPTH") {
+            config.engine_queue_depth = parse("ZEROTABLE_ENGINE_QUEUE_DEPTH", value)?;
+        }
+        if let Some(value) = lookup("ZEROTABLE_LONG_RUNNING_TASKS") {
+            config.long_running_tasks = parse("ZEROTABLE_LONG_RUNNING_TASKS", value)?;
+        }
+
+        if config.addr.is_none() && config.unix_socket.is_none() {
+            return Err(ConfigError::NoListener);
+        }
+        if config.archive_idle_after.is_some() && config.archive_dir.is_none() {
+            return Err(ConfigError::NoArchiveDir);
+        }
+        if config.tls_cert.is_some() != config.tls_key.is_some() {
+            return Err(ConfigError::IncompleteTls);
+        }
+        Ok(config)
+    }
+}
+
+/// Settings from a configuration file or command line flags, keyed by the
+/// environment variable they stand for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings(HashMap<String, String>);
+
+impl Settings {
+    fn get(&self, key: &str) -> Option<String> {
+        self.0.get(key).cloned()
+    }
+
+    /// Read the TOML configuration file at `path`.
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let file_error = |message: String| ConfigError::File {
+            path: path.to_path_buf(),
+            message,
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+        Self::parse_toml(&text).map_err(file_error)
+    }
+
+    /// Parse a TOML document of top-level `name = value` settings.
+    pub fn parse_toml(text: &str) -> Result<Self, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut settings = HashMap::new();
+        for (name, value) in table {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => return Err(format!("{name} must be a string, number or boolean")),
+            };
+            settings.insert(setting_key(&name), value);
+        }
+        Ok(Settings(settings))
+    }
+
+    /// Parse command line flags, each `--name value` or `--name=value`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut args = args.into_iter();
+        let mut settings = HashMap::new();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--").filter(|flag| !flag.is_empty()) else {
+                return Err(ConfigError::InvalidFlag(arg));
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                None => match args.next() {
+                    Some(value) => (flag, value),
+                    None => return Err(ConfigError::InvalidFlag(arg)),
+                },
+            };
+            settings.insert(setting_key(name), value);
+        }
+        Ok(Settings(settings))
+    }
+}
+
+/// Environment variable of the setting `name`, e.g. `ZEROTABLE_DATA_DIR` for
+/// `data_dir` or `data-dir`.
+fn setting_key(name: &str) -> String {
+    format!(
+        "{ENV_PREFIX}{}",
+        name.replace('-', "_").to_ascii_uppercase()
+    )
+}
+
+fn parse<T: std::str::FromStr>(key: &'static str, value: String) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Invalid { key, value })
+}
+
+fn parse_compression(
+    key: &'static str,
+    value: String,
+) -> Result<Option<CompressionEncoding>, ConfigError> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" | "" => Ok(None),
+        "gzip" => Ok(Some(CompressionEncoding::Gzip)),
+        "zstd" => Ok(Some(CompressionEncoding::Zstd)),
+        _ => Err(ConfigError::Invalid { key, value }),
+    }
+}
+
+/// `buffered`, `periodic` or `sync`, as the durability of the writes and
+/// how often the storage is synced to disk in the background.
+fn parse_durability(
+    key: &'static str,
+    value: String,
+) -> Result<(Durability, Option<Duration>), ConfigError> {
+    match value.to_ascii_lowercase().as_str() {
+        "buffered" | "" => Ok((Durability::Buffered, None)),
+        "periodic" => Ok((Durability::Buffered, Some(DEFAULT_SYNC_INTERVAL))),
+        "sync" => Ok((Durability::Sync, None)),
+        _ => Err(ConfigError::Invalid { key, value }),
+    }
+}
+
+/// `none` or `zstd`, as the compression of the stored document payloads.
+fn parse_value_compression(
+    key: &'static str,
+    value: String,
+) -> Result<ValueCompression, ConfigError> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" | "" => Ok(ValueCompression::None),
+        "zstd" => Ok(ValueCompression::Zstd),
+        _ => Err(ConfigError::Invalid { key, value }),
+    }
+}
+
+/// Comma separated `id:key` pairs, see [`StaticKeys::parse`], none if
+/// empty. The value is left out of the error, it holds keys.
+fn parse_encryption_keys(
+    key: &'static str,
+    value: &str,
+) -> Result<Option<StaticKeys>, ConfigError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match StaticKeys::parse(value) {
+        Some(keys) => Ok(Some(keys)),
+        None => Err(ConfigError::Invalid {
+            key,
+            value: "<redacted>".to_string(),
+        }),
+    }
+}
+
+fn parse_log_format(key: &'static str, value: String) -> Result<LogFormat, ConfigError> {
+    match value.to_ascii_lowercase().as_str() {
+        "text" | "" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => Err(ConfigError::Invalid { key, value }),
+    }
+}
+
+fn parse_log_filter(key: &'static str, value: String) -> Result<Option<String>, ConfigError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match EnvFilter::try_new(&value) {
+        Ok(_) => Ok(Some(value)),
+        Err(_) => Err(ConfigError::Invalid { key, value }),
+    }
+}
+
+/// A document size in bytes, no larger than storage can hold.
+fn parse_document_size(key: &'static str, value: String) -> Result<usize, ConfigError> {
+    match value.parse() {
+        Ok(size) if (1..=record::MAX_PAYLOAD_LEN).contains(&size) => Ok(size),
+        _ => Err(ConfigError::Invalid { key, value }),
+    }
+}
+
+/// Comma separated database IDs, `(default)` for the default database.
+fn parse_databases(key: &'static str, value: String) -> Result<Vec<String>, ConfigError> {
+    let mut databases = Vec::new();
+    for database_id in value.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        if keys::validate_database(database_id).is_err() {
+            return Err(ConfigError::Invalid { key, value });
+        }
+        databases.push(database_id.to_string());
+    }
+    Ok(databases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::KEY_LEN;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<ServerConfig, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        ServerConfig::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(load(&[]).unwrap(), ServerConfig::default());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config = load(&[
+            ("ZEROTABLE_ADDR", "127.0.0.1:9000"),
+            ("ZEROTABLE_DATA_DIR", "/var/lib/zerotable"),
+            ("ZEROTABLE_COMPRESSION", "zstd"),
+            ("ZEROTABLE_GRPC_WEB", "true"),
+            ("ZEROTABLE_REST_ADDR", "127.0.0.1:8080"),
+            ("ZEROTABLE_AUDIT_LOG", "true"),
+            ("ZEROTABLE_ADMIN_TOKEN", "s3cret"),
+            ("ZEROTABLE_OTLP_ENDPOINT", "http://localhost:4317"),
+            ("ZEROTABLE_LOG_FORMAT", "JSON"),
+            ("ZEROTABLE_LOG_FILTER", "warn,zerotable=debug"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.addr, Some("127.0.0.1:9000".parse().unwrap()));
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/zerotable"));
+        assert_eq!(config.compression, Some(CompressionEncoding::Zstd));
+        assert!(config.grpc_web);
+        assert_eq!(config.rest_addr, Some("127.0.0.1:8080".parse().unwrap()));
+        assert!(config.audit_log);
+        assert_eq!(config.admin_token.as_deref(), Some("s3cret"));
+        assert_eq!(
+            config.otlp_endpoint.as_deref(),
+            Some("http://localhost:4317")
+        );
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_filter.as_deref(), Some("warn,zerotable=debug"));
+    }
+
+    #[test]
+    fn test_unix_socket_only() {
+        let config = load(&[
+            ("ZEROTABLE_ADDR", "none"),
+            ("ZEROTABLE_UNIX_SOCKET", "/run/zerotable.sock"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.addr, None);
+        assert_eq!(
+            config.unix_socket,
+            Some(PathBuf::from("/run/zerotable.sock"))
+        );
+    }
+
+    #[test]
+    fn test_no_listener() {
+        assert_eq!(
+            load(&[("ZEROTABLE_ADDR", "none")]).unwrap_err(),
+            ConfigError::NoListener
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = load(&[
+            ("ZEROTABLE_MAX_DECODING_MESSAGE_SIZE", "1024"),
+            ("ZEROTABLE_MAX_DOCUMENT_SIZE", "2048"),
+            ("ZEROTABLE_CONCURRENCY_LIMIT", "32"),
+            ("ZEROTABLE_TCP_KEEPALIVE_SECS", "60"),
+            ("ZEROTABLE_SHUTDOWN_TIMEOUT_SECS", "5"),
+            ("ZEROTABLE_RETRY_BUDGET_MS", "0"),
+            ("ZEROTABLE_SLOW_QUERY_MS", "250"),
+            ("ZEROTABLE_SLOW_REQUEST_MS", "1000"),
+            ("ZEROTABLE_STREAM_STALL_TIMEOUT_SECS", "10"),
+            ("ZEROTABLE_IDEMPOTENCY_TTL_SECS", "3600"),
+            ("ZEROTABLE_MEMORY_BUDGET_MB", "64"),
+            ("ZEROTABLE_DOCUMENT_CACHE_MB", "16"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.max_decoding_message_size, 1024);
+        assert_eq!(config.max_encoding_message_size, usize::MAX);
+        assert_eq!(config.max_document_size, 2048);
+        assert_eq!(config.concurrency_limit, Some(32));
+        assert_eq!(config.max_concurrent_streams, None);
+        assert_eq!(config.tcp_keepalive, Some(Duration::from_secs(60)));
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(5));
+        assert_eq!(config.retry_budget, Duration::ZERO);
+        assert_eq!(
+            config.slow_query_threshold,
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(config.slow_request_threshold, Some(Duration::from_secs(1)));
+        assert_eq!(config.stream_stall_timeout, Duration::from_secs(10));
+        assert_eq!(config.idempotency_ttl, Duration::from_secs(3600));
+        assert_eq!(config.memory_budget, 64 * 1024 * 1024);
+        assert_eq!(config.document_cache, 16 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_invalid_limit() {
+        assert!(load(&[("ZEROTABLE_CONCURRENCY_LIMIT", "-1")]).is_err());
+        assert!(load(&[("ZEROTABLE_MAX_DOCUMENT_SIZE", "0")]).is_err());
+        assert!(load(&[("ZEROTABLE_MAX_DOCUMENT_SIZE", "8589934592")]).is_err());
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let config = load(&[("ZEROTABLE_WRITE_RATE_LIMIT", "50")]).unwrap();
+        assert_eq!(config.read_rate_limit, None);
+        assert_eq!(config.write_rate_limit, NonZeroU32::new(50));
+
+        assert!(load(&[("ZEROTABLE_READ_RATE_LIMIT", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_compression() {
+        let err = load(&[("ZEROTABLE_COMPRESSION", "brotli")]).unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::Invalid {
+                key: "ZEROTABLE_COMPRESSION",
+                value: "brotli".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_file() {
+        let file = Settings::parse_toml(
+            r#"
+            addr = "127.0.0.1:9000"
+            data_dir = "/var/lib/zerotable"
+            memory_budget_mb = 512
+            audit_log = true
+            "#,
+        )
+        .unwrap();
+        let config = ServerConfig::from_settings(&file, |_| None, &Settings::default()).unwrap();
+
+        assert_eq!(config.addr, Some("127.0.0.1:9000".parse().unwrap()));
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/zerotable"));
+        assert_eq!(config.memory_budget, 512 * 1024 * 1024);
+        assert!(config.audit_log);
+
+        assert!(Settings::parse_toml("addr = [1]").is_err());
+        assert!(Settings::parse_toml("addr = ").is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let file = Settings::parse_toml(
+            r#"
+            data_dir = "/from/file"
+            admin_token = "file"
+            audit_log = true
+            "#,
+        )
+        .unwrap();
+        let env = |key: &str| match key {
+            "ZEROTABLE_DATA_DIR" => Some("/from/env".to_string()),
+            "ZEROTABLE_ADMIN_TOKEN" => Some("env".to_string()),
+            _ => None,
+        };
+        let flags = Settings::from_args(["--data-dir=/from/flag".to_string()]).unwrap();
+        let config = ServerConfig::from_settings(&file, env, &flags).unwrap();
+
+        assert_eq!(config.data_dir, PathBuf::from("/from/flag"));
+        assert_eq!(config.admin_token.as_deref(), Some("env"));
+        assert!(config.audit_log);
+    }
+
+    #[test]
+    fn test_flags() {
+        let args = ["--addr", "none", "--unix-socket", "/run/zerotable.sock"];
+        let flags = Settings::from_args(args.map(String::from)).unwrap();
+        let config = ServerConfig::from_settings(&Settings::default(), |_| None, &flags).unwrap();
+        assert_eq!(config.addr, None);
+        assert_eq!(
+            config.unix_socket,
+            Some(PathBuf::from("/run/zerotable.sock"))
+        );
+
+        let invalid = |args: &[&str]| {
+            Settings::from_args(args.iter().map(|arg| arg.to_string())).unwrap_err()
+        };
+        assert_eq!(
+            invalid(&["addr"]),
+            ConfigError::InvalidFlag("addr".to_string())
+        );
+        assert_eq!(
+            invalid(&["--addr"]),
+            ConfigError::InvalidFlag("--addr".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_setting() {
+        let file = Settings::parse_toml("data_dri = \"/tmp\"").unwrap();
+        let err = ServerConfig::from_settings(&file, |_| None, &Settings::default()).unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::UnknownSetting("ZEROTABLE_DATA_DRI".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_log_filter() {
+        assert!(load(&[("ZEROTABLE_LOG_FILTER", "zerotable=loud")]).is_err());
+    }
+
+    #[test]
+    fn test_strict_databases() {
+        let config = load(&[("ZEROTABLE_STRICT_DATABASES", "(default), acme,")]).unwrap();
+        assert_eq!(config.strict_databases, ["(default)", "acme"]);
+        assert!(load(&[("ZEROTABLE_STRICT_DATABASES", "Acme")]).is_err());
+    }
+
+    #[test]
+    fn test_tls() {
+        let config = load(&[]).unwrap();
+        assert_eq!((config.tls_cert, config.tls_key), (None, None));
+        let config = load(&[
+            ("ZEROTABLE_TLS_CERT", "/etc/zerotable/cert.pem"),
+            ("ZEROTABLE_TLS_KEY", "/etc/zerotable/key.pem"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.tls_cert,
+            Some(PathBuf::from("/etc/zerotable/cert.pem"))
+        );
+        assert_eq!(
+            config.tls_key,
+            Some(PathBuf::from("/etc/zerotable/key.pem"))
+        );
+        assert_eq!(
+            load(&[("ZEROTABLE_TLS_CERT", "/etc/zerotable/cert.pem")]).unwrap_err(),
+            ConfigError::IncompleteTls
+        );
+    }
+
+    #[test]
+    fn test_archive() {
+        let config = load(&[
+            ("ZEROTABLE_ARCHIVE_DIR", "/mnt/archive"),
+            ("ZEROTABLE_ARCHIVE_REHYDRATE", "true"),
+            ("ZEROTABLE_ARCHIVE_IDLE_DAYS", "30"),
+        ])
+        .unwrap();
+        assert_eq!(config.archive_dir, Some(PathBuf::from("/mnt/archive")));
+        assert!(config.archive_rehydrate);
+        assert_eq!(
+            config.archive_idle_after,
+            Some(Duration::from_secs(30 * 24 * 60 * 60))
+        );
+
+        assert_eq!(
+            load(&[("ZEROTABLE_ARCHIVE_IDLE_DAYS", "30")]).unwrap_err(),
+            ConfigError::NoArchiveDir
+        );
+        assert!(load(&[("ZEROTABLE_ARCHIVE_IDLE_DAYS", "soon")]).is_err());
+    }
+
+    #[test]
+    fn test_history_retention() {
+        assert_eq!(load(&[]).unwrap().history_retention, None);
+        let config = load(&[("ZEROTABLE_HISTORY_RETENTION_SECS", "3600")]).unwrap();
+        assert_eq!(config.history_retention, Some(Duration::from_secs(3600)));
+        let config = load(&[("ZEROTABLE_HISTORY_RETENTION_SECS", "none")]).unwrap();
+        assert_eq!(config.history_retention, None);
+        assert!(load(&[("ZEROTABLE_HISTORY_RETENTION_SECS", "1h")]).is_err());
+    }
+
+    #[test]
+    fn test_soft_delete_retention() {
+        assert_eq!(
+            load(&[]).unwrap().soft_delete_retention,
+            DEFAULT_SOFT_DELETE_RETENTION
+        );
+        let config = load(&[("ZEROTABLE_SOFT_DELETE_RETENTION_SECS", "60")]).unwrap();
+        assert_eq!(config.soft_delete_retention, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_export_dir() {
+        assert_eq!(load(&[]).unwrap().export_dir, None);
+        let config = load(&[("ZEROTABLE_EXPORT_DIR", "/var/lib/zerotable/exports")]).unwrap();
+        assert_eq!(
+            config.export_dir,
+            Some(PathBuf::from("/var/lib/zerotable/exports"))
+        );
+        assert_eq!(
+            load(&[("ZEROTABLE_EXPORT_DIR", "")]).unwrap().export_dir,
+            None
+        );
+    }
+
+    #[test]
+    fn test_mirror() {
+        let config = load(&[]).unwrap();
+        assert_eq!(config.mirror_endpoint, None);
+        assert_eq!(config.mirror_fraction, 1.0);
+        assert!(!config.mirror_reads);
+
+        let config = load(&[
+            ("ZEROTABLE_MIRROR_ENDPOINT", "http://10.0.0.2:50051"),
+            ("ZEROTABLE_MIRROR_FRACTION", "0.05"),
+            ("ZEROTABLE_MIRROR_READS", "true"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.mirror_endpoint.as_deref(),
+            Some("http://10.0.0.2:50051")
+        );
+        assert_eq!(config.mirror_fraction, 0.05);
+        assert!(config.mirror_reads);
+
+        for fraction in ["0", "1.5", "-0.1", "NaN", "half"] {
+            assert!(load(&[("ZEROTABLE_MIRROR_FRACTION", fraction)]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_durability() {
+        let config = load(&[]).unwrap();
+        assert_eq!(
+            (config.durability, config.sync_interval),
+            (Durability::Buffered, None)
+        );
+
+        let config = load(&[("ZEROTABLE_DURABILITY", "SYNC")]).unwrap();
+        assert_eq!(
+            (config.durability, config.sync_interval),
+            (Durability::Sync, None)
+        );
+        assert_eq!(config.group_commit_window, Duration::ZERO);
+        let config = load(&[
+            ("ZEROTABLE_DURABILITY", "sync"),
+            ("ZEROTABLE_GROUP_COMMIT_WINDOW_US", "200"),
+        ])
+        .unwrap();
+        assert_eq!(config.group_commit_window, Duration::from_micros(200));
+
+        let config = load(&[("ZEROTABLE_DURABILITY", "periodic")]).unwrap();
+        assert_eq!(config.sync_interval, Some(DEFAULT_SYNC_INTERVAL));
+        let config = load(&[
+            ("ZEROTABLE_DURABILITY", "periodic"),
+            ("ZEROTABLE_SYNC_INTERVAL_MS", "250"),
+        ])
+        .unwrap();
+        assert_eq!(
+            (config.durability, config.sync_interval),
+            (Durability::Buffered, Some(Duration::from_millis(250)))
+        );
+
+        assert!(load(&[("ZEROTABLE_DURABILITY", "fsync")]).is_err());
+        assert!(load(&[("ZEROTABLE_SYNC_INTERVAL_MS", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_value_compression() {
+        assert_eq!(load(&[]).unwrap().value_compression, ValueCompression::None);
+        let config = load(&[("ZEROTABLE_VALUE_COMPRESSION", "ZSTD")]).unwrap();
+        assert_eq!(config.value_compression, ValueCompression::Zstd);
+        assert!(load(&[("ZEROTABLE_VALUE_COMPRESSION", "lz4")]).is_err());
+    }
+
+    #[test]
+    fn test_engine_workers() {
+        let config = load(&[]).unwrap();
+        assert_eq!(config.engine_workers, DEFAULT_WORKERS);
+        assert_eq!(config.engine_queue_depth, DEFAULT_QUEUE_DEPTH);
+        let config = load(&[
+            ("ZEROTABLE_ENGINE_WORKERS", "8"),
+            ("ZEROTABLE_ENGINE_QUEUE_DEPTH", "64"),
+        ])
+        .unwrap();
+        assert_eq!(config.engine_workers.get(), 8);
+        assert_eq!(config.engine_queue_depth.get(), 64);
+        assert!(load(&[("ZEROTABLE_ENGINE_WORKERS", "0")]).is_err());
+        assert!(load(&[("ZEROTABLE_ENGINE_QUEUE_DEPTH", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_long_running_tasks() {
+        let config = load(&[]).unwrap();
+        assert_eq!(config.long_running_tasks, DEFAULT_LONG_RUNNING_TASKS);
+        let config = load(&[("ZEROTABLE_LONG_RUNNING_TASKS", "2")]).unwrap();
+        assert_eq!(config.long_running_tasks.get(), 2);
+        assert!(load(&[("ZEROTABLE_LONG_RUNNING_TASKS", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_encryption_keys() {
+        assert_eq!(load(&[]).unwrap().encryption_keys, None);
+        let key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+        let keys = format!("1:{key}");
+        let config = load(&[("ZEROTABLE_ENCRYPTION_KEYS", keys.as_str())]).unwrap();
+        assert_eq!(config.encryption_keys, StaticKeys::new([(1, [1; KEY_LEN])]));
+
+        // keys stay out of errors
+        assert_eq!(
+            load(&[("ZEROTABLE_ENCRYPTION_KEYS", "1:c2hvcnQ=")]),
+            Err(ConfigError::Invalid {
+                key: "ZEROTABLE_ENCRYPTION_KEYS",
+                value: "<redacted>".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_addr() {
+        assert!(load(&[("ZEROTABLE_ADDR", "not an address")]).is_err());
+    }
+}
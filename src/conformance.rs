@@ -0,0 +1,715 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! API conformance suite.
+//!
+//! Exercises the documented semantics of the gRPC API, error codes included,
+//! against any endpoint, so that forks and alternative backends can check
+//! they behave the same. Run it with `zerotable conformance [endpoint]`.
+//!
+//! Every run works in a fresh collection named `conformance-{uuid}`, of the
+//! default database and of the `conformance` one, which is left behind.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use tokio::task::JoinSet;
+use tonic::transport::Channel;
+use tonic::{Code, Response, Status};
+use tonic_types::StatusExt;
+
+use crate::api::v1alpha1::batch_get_result::Result as BatchResult;
+use crate::api::v1alpha1::bulk_write_request::Operation as BulkOperation;
+use crate::api::v1alpha1::field_transform::TransformType;
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::zerotable_client::ZerotableClient;
+use crate::api::v1alpha1::{
+    BatchGetDocumentsRequest, BulkWriteRequest, ChildDocument, CopyDocumentRequest,
+    CreateDocumentRequest, CreateDocumentTreeRequest, DeleteDocumentRequest, DeleteWhereRequest,
+    Document, DocumentExistsRequest, DocumentExistsResponse, DocumentMask, FieldFilter,
+    FieldTransform, GetDocumentRequest, ListDocumentsRequest, MoveDocumentRequest,
+    TransformDocumentRequest, UpdateDocumentRequest, UpdateWhereRequest, Value,
+};
+use crate::generate_uuid_v7;
+
+/// Endpoint checked when none is given.
+pub const DEFAULT_ENDPOINT: &str = "http://[::1]:50051";
+
+/// Increments run at once by the increments check.
+const CONCURRENT_INCREMENTS: i64 = 8;
+
+/// Updates of disjoint fields run at once by the disjoint updates check.
+const CONCURRENT_UPDATES: i64 = 8;
+
+/// Database other than the default one the checks write to.
+const DATABASE: &str = "conformance";
+
+/// Outcome of every check of a run, in order.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub results: Vec<(&'static str, Result<(), String>)>,
+}
+
+impl Report {
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, result) in &self.results {
+            match result {
+                Ok(()) => writeln!(f, "ok    {name}")?,
+                Err(reason) => writeln!(f, "FAIL  {name}: {reason}")?,
+            }
+        }
+        let failed = self.results.iter().filter(|(_, r)| r.is_err()).count();
+        write!(f, "{} passed, {failed} failed", self.results.len() - failed)
+    }
+}
+
+/// Run every check against the server at `endpoint`.
+pub async fn run(endpoint: String) -> Result<Report, tonic::transport::Error> {
+    let client = ZerotableClient::connect(endpoint).await?;
+    let suite = Suite {
+        client,
+        collection: format!("conformance-{}", generate_uuid_v7().0),
+    };
+
+    let results = vec![
+        ("create then get", suite.create_then_get().await),
+        ("create existing fails", suite.create_existing().await),
+        ("get missing fails", suite.get_missing().await),
+        ("malformed name is rejected", suite.malformed_name().await),
+        ("delete", suite.delete().await),
+        ("batch get keeps request order", suite.batch_get().await),
+        ("document ids with slashes", suite.slash_in_id().await),
+        ("copy and move", suite.copy_and_move().await),
+        ("document exists", suite.document_exists().await),
+        ("databases are isolated", suite.databases().await),
+        ("document trees are atomic", suite.document_tree().await),
+        ("concurrent increments add up", suite.increments().await),
+        ("list documents in order", suite.list_documents().await),
+        ("disjoint updates merge", suite.disjoint_updates().await),
+        ("update and delete where", suite.bulk_writes().await),
+        ("bulk write reports every write", suite.bulk_write().await),
+    ];
+    Ok(Report { results })
+}
+
+type Check = Result<(), String>;
+
+struct Suite {
+    client: ZerotableClient<Channel>,
+    collection: String,
+}
+
+impl Suite {
+    fn name(&self, doc_id: &str) -> String {
+        format!("{}/{doc_id}", self.collection)
+    }
+
+    async fn create(&self, doc_id: &str, n: i64) -> Result<Document, Status> {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "n".to_string(),
+            Value {
+                value_type: Some(ValueType::IntValue(n)),
+            },
+        );
+        let request = CreateDocumentRequest {
+            collection_id: self.collection.clone(),
+            document_id: doc_id.to_string(),
+            document: Some(Document {
+                fields,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.client
+            .clone()
+            .create_document(request)
+            .await
+            .map(Response::into_inner)
+    }
+
+    async fn get(&self, name: &str) -> Result<Document, Status> {
+        let request = GetDocumentRequest {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        self.client
+            .clone()
+            .get_document(request)
+            .await
+            .map(Response::into_inner)
+    }
+
+    fn database_name(&self, database_id: &str, doc_id: &str) -> String {
+        format!(
+            "databases/{database_id}/collections/{}/documents/{doc_id}",
+            self.collection
+        )
+    }
+
+    async fn exists(&self, doc_id: &str) -> Result<DocumentExistsResponse, Status> {
+        let request = DocumentExistsRequest {
+            name: self.name(doc_id),
+        };
+        self.client
+            .clone()
+            .document_exists(request)
+            .await
+            .map(Response::into_inner)
+    }
+
+    async fn create_then_get(&self) -> Check {
+        let created = self.create("a", 1).await.map_err(unexpected)?;
+        ensure(created.name == self.name("a"), "created name")?;
+        ensure(created.create_time.is_some(), "create_time is set")?;
+        ensure(created.create_time == created.update_time, "times match")?;
+
+        let got = self.get(&self.name("a")).await.map_err(unexpected)?;
+        ensure(got == created, "get returns the created document")
+    }
+
+    async fn create_existing(&self) -> Check {
+        self.create("b", 1).await.map_err(unexpected)?;
+        expect_code(self.create("b", 2).await, Code::AlreadyExists)?;
+        let got = self.get(&self.name("b")).await.map_err(unexpected)?;
+        let n = got.fields.get("n").and_then(|v| v.value_type.clone());
+        ensure(n == Some(ValueType::IntValue(1)), "document is unchanged")
+    }
+
+    async fn get_missing(&self) -> Check {
+        expect_code(self.get(&self.name("missing")).await, Code::NotFound)
+    }
+
+    async fn malformed_name(&self) -> Check {
+        let status = match self.get("no-slash").await {
+            Ok(_) => return Err("expected INVALID_ARGUMENT, got a document".to_string()),
+            Err(status) => status,
+        };
+        expect_code::<()>(Err(status.clone()), Code::InvalidArgument)?;
+        let bad_request = status
+            .get_details_bad_request()
+            .ok_or("missing BadRequest details")?;
+        ensure(
+            bad_request
+                .field_violations
+                .iter()
+                .any(|v| v.field == "name"),
+            "BadRequest points at the name field",
+        )
+    }
+
+    async fn delete(&self) -> Check {
+        self.create("c", 1).await.map_err(unexpected)?;
+        let delete = || async {
+            let request = DeleteDocumentRequest {
+                name: self.name("c"),
+                return_previous: true,
+            };
+            self.client.clone().delete_document(request).await
+        };
+        let deleted = delete().await.map_err(unexpected)?.into_inner();
+        let n = deleted
+            .previous_document
+            .and_then(|doc| doc.fields.get("n")?.value_type.clone());
+        ensure(
+            n == Some(ValueType::IntValue(1)),
+            "the deleted document is returned",
+        )?;
+        expect_code(self.get(&self.name("c")).await, Code::NotFound)?;
+        expect_code(delete().await, Code::NotFound)
+    }
+
+    async fn batch_get(&self) -> Check {
+        self.create("d", 1).await.map_err(unexpected)?;
+        let names = vec![self.name("d"), self.name("missing"), self.name("d")];
+        let response = self
+            .client
+            .clone()
+            .batch_get_documents(BatchGetDocumentsRequest {
+                names: names.clone(),
+            })
+            .await
+            .map_err(unexpected)?
+            .into_inner();
+
+        ensure(response.results.len() == 3, "one result per name")?;
+        for (name, result) in names.iter().zip(&response.results) {
+            let ok = match &result.result {
+                Some(BatchResult::Found(doc)) => doc.name == *name,
+                Some(BatchResult::Missing(missing)) => {
+                    missing == name && *name == self.name("missing")
+                }
+                None => false,
+            };
+            ensure(ok, "results follow the request order")?;
+        }
+        Ok(())
+    }
+
+    async fn slash_in_id(&self) -> Check {
+        let created = self.create("e/f", 1).await.map_err(unexpected)?;
+        ensure(
+            created.name == self.name("e%2Ff"),
+            "slash is percent-encoded",
+        )?;
+        self.get(&created.name).await.map_err(unexpected)?;
+        Ok(())
+    }
+
+    async fn copy_and_move(&self) -> Check {
+        self.create("g", 1).await.map_err(unexpected)?;
+        let copy = || async {
+            let request = CopyDocumentRequest {
+                name: self.name("g"),
+                destination: self.name("h"),
+                preserve_timestamps: true,
+            };
+            self.client.clone().copy_document(request).await
+        };
+        let copied = copy().await.map_err(unexpected)?.into_inner();
+        ensure(copied.name == self.name("h"), "copy has the new name")?;
+        expect_code(copy().await, Code::AlreadyExists)?;
+
+        self.client
+            .clone()
+            .move_document(MoveDocumentRequest {
+                name: self.name("g"),
+                destination: self.name("i"),
+            })
+            .await
+            .map_err(unexpected)?;
+        expect_code(self.get(&self.name("g")).await, Code::NotFound)?;
+        self.get(&self.name("i")).await.map_err(unexpected)?;
+        Ok(())
+    }
+
+    async fn document_exists(&self) -> Check {
+        self.create("j", 1).await.map_err(unexpected)?;
+        let found = self.exists("j").await.map_err(unexpected)?;
+        ensure(
+            found.exists && found.update_time.is_some(),
+            "existing document",
+        )?;
+        let missing = self.exists("missing").await.map_err(unexpected)?;
+        ensure(
+            !missing.exists && missing.update_time.is_none(),
+            "missing document",
+        )
+    }
+
+    async fn databases(&self) -> Check {
+        self.create("k", 1).await.map_err(unexpected)?;
+        let other = self.database_name(DATABASE, "k");
+        expect_code(self.get(&other).await, Code::NotFound)?;
+
+        let request = CreateDocumentRequest {
+            collection_id: self.collection.clone(),
+            document_id: "k".to_string(),
+            document: Some(Document::default()),
+            database_id: DATABASE.to_string(),
+        };
+        let created = self
+            .client
+            .clone()
+            .create_document(request)
+            .await
+            .map_err(unexpected)?
+            .into_inner();
+        ensure(created.name == other, "name carries the database")?;
+        let got = self.get(&other).await.map_err(unexpected)?;
+        ensure(got.fields.is_empty(), "each database has its own document")?;
+
+        let got = self
+            .get(&self.database_name("(default)", "k"))
+            .await
+            .map_err(unexpected)?;
+        ensure(
+            got.name == self.name("k"),
+            "the default database has a long name too",
+        )
+    }
+
+    async fn document_tree(&self) -> Check {
+        let parent = |doc_id: &str| CreateDocumentRequest {
+            collection_id: self.collection.clone(),
+            document_id: doc_id.to_string(),
+            document: Some(Document::default()),
+            ..Default::default()
+        };
+        let child = |doc_id: &str| ChildDocument {
+            collection_id: self.collection.clone(),
+            document_id: doc_id.to_string(),
+            document: Some(Document::default()),
+        };
+
+        let request = CreateDocumentTreeRequest {
+            parent: Some(parent("tree")),
+            children: vec![child("tree-1"), child("tree-2")],
+        };
+        let created = self
+            .client
+            .clone()
+            .create_document_tree(request)
+            .await
+            .map_err(unexpected)?
+            .into_inner();
+        let names: Vec<_> = created
+            .children
+            .iter()
+            .map(|doc| doc.name.as_str())
+            .collect();
+        ensure(
+            names == [self.name("tree-1"), self.name("tree-2")],
+            "children are returned in request order",
+        )?;
+        self.get(&self.name("tree-2")).await.map_err(unexpected)?;
+
+        let request = CreateDocumentTreeRequest {
+            parent: Some(parent("tree-3")),
+            children: vec![child("tree-2")],
+        };
+        let result = self.client.clone().create_document_tree(request).await;
+        expect_code(result, Code::AlreadyExists)?;
+        expect_code(self.get(&self.name("tree-3")).await, Code::NotFound)
+    }
+
+    async fn increments(&self) -> Check {
+        self.create("counter", 0).await.map_err(unexpected)?;
+        let increment = TransformDocumentRequest {
+            name: self.name("counter"),
+            transforms: vec![FieldTransform {
+                field_path: "n".to_string(),
+                transform_type: Some(TransformType::Increment(Value {
+                    value_type: Some(ValueType::IntValue(1)),
+                })),
+            }],
+        };
+
+        let mut increments = JoinSet::new();
+        for _ in 0..CONCURRENT_INCREMENTS {
+            let mut client = self.client.clone();
+            let request = increment.clone();
+            increments.spawn(async move { client.transform_document(request).await });
+        }
+        while let Some(result) = increments.join_next().await {
+            result.map_err(|e| e.to_string())?.map_err(unexpected)?;
+        }
+        let doc = self.get(&self.name("counter")).await.map_err(unexpected)?;
+        let n = doc.fields.get("n").and_then(|n| n.value_type.clone());
+        ensure(
+            n == Some(ValueType::IntValue(CONCURRENT_INCREMENTS)),
+            "no increment is lost",
+        )?;
+
+        let request = TransformDocumentRequest {
+            name: self.name("no-counter"),
+            ..increment
+        };
+        let result = self.client.clone().transform_document(request).await;
+        expect_code(result, Code::NotFound)
+    }
+
+    async fn list_documents(&self) -> Check {
+        // a collection of its own, the other checks leave documents behind
+        let collection_id = format!("{}-list", self.collection);
+        for (doc_id, n) in [("a", 3), ("b", 1), ("c", 2)] {
+            let mut fields = HashMap::new();
+            fields.insert(
+                "n".to_string(),
+                Value {
+                    value_type: Some(ValueType::IntValue(n)),
+                },
+            );
+            let request = CreateDocumentRequest {
+                collection_id: collection_id.clone(),
+                document_id: doc_id.to_string(),
+                document: Some(Document {
+                    fields,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            self.client
+                .clone()
+                .create_document(request)
+                .await
+                .map_err(unexpected)?;
+        }
+
+        let mut ids = Vec::new();
+        let mut request = ListDocumentsRequest {
+            collection_id: collection_id.clone(),
+            page_size: 2,
+            order_by: "n desc".to_string(),
+            ..Default::default()
+        };
+        // more pages than needed, in case the token never runs out
+        for _ in 0..3 {
+            let page = self
+                .client
+                .clone()
+                .list_documents(request.clone())
+                .await
+                .map_err(unexpected)?
+                .into_inner();
+            ensure(page.documents.len() <= 2, "pages hold at most page_size")?;
+            for doc in &page.documents {
+                ids.push(doc.name.rsplit('/').next().unwrap_or_default().to_string());
+            }
+            if page.next_page_token.is_empty() {
+                break;
+            }
+            request.page_token = page.next_page_token;
+        }
+        ensure(ids == ["a", "c", "b"], "documents are ordered by order_by")?;
+
+        let request = ListDocumentsRequest {
+            collection_id,
+            page_token: request.page_token,
+            ..Default::default()
+        };
+        let result = self.client.clone().list_documents(request).await;
+        expect_code(result, Code::InvalidArgument)
+    }
+
+    async fn disjoint_updates(&self) -> Check {
+        let created = self.create("wide", 0).await.map_err(unexpected)?;
+
+        let mut updates = JoinSet::new();
+        for i in 0..CONCURRENT_UPDATES {
+            let field = format!("f{i}");
+            let mut fields = HashMap::new();
+            fields.insert(
+                field.clone(),
+                Value {
+                    value_type: Some(ValueType::IntValue(i)),
+                },
+            );
+            let request = UpdateDocumentRequest {
+                document: Some(Document {
+                    name: created.name.clone(),
+                    fields,
+                    ..Default::default()
+                }),
+                update_mask: Some(DocumentMask {
+                    field_paths: vec![field],
+                }),
+                merge_disjoint_fields: true,
+                ..Default::default()
+            };
+            let mut client = self.client.clone();
+            updates.spawn(async move { client.update_document(request).await });
+        }
+        while let Some(result) = updates.join_next().await {
+            result.map_err(|e| e.to_string())?.map_err(unexpected)?;
+        }
+        let doc = self.get(&created.name).await.map_err(unexpected)?;
+        ensure(
+            (0..CONCURRENT_UPDATES).all(|i| doc.fields.contains_key(&format!("f{i}"))),
+            "no update is lost",
+        )?;
+        ensure(
+            doc.fields.contains_key("n"),
+            "fields outside the masks are kept",
+        )?;
+
+        let request = UpdateDocumentRequest {
+            document: Some(Document {
+                name: created.name,
+                ..Default::default()
+            }),
+            merge_disjoint_fields: true,
+            ..Default::default()
+        };
+        let result = self.client.clone().update_document(request).await;
+        expect_code(result, Code::InvalidArgument)
+    }
+
+    async fn bulk_writes(&self) -> Check {
+        // a collection of its own, the other checks leave documents behind
+        let collection_id = format!("{}-bulk", self.collection);
+        let string = |s: &str| Value {
+            value_type: Some(ValueType::StringValue(s.to_string())),
+        };
+        for (doc_id, city) in [("a", "paris"), ("b", "rome"), ("c", "paris")] {
+            let mut fields = HashMap::new();
+            fields.insert("city".to_string(), string(city));
+            let request = CreateDocumentRequest {
+                collection_id: collection_id.clone(),
+                document_id: doc_id.to_string(),
+                document: Some(Document {
+                    fields,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            self.client
+                .clone()
+                .create_document(request)
+                .await
+                .map_err(unexpected)?;
+        }
+        let in_paris = vec![FieldFilter {
+            field: "city".to_string(),
+            value: Some(string("paris")),
+            ..Default::default()
+        }];
+
+        let mut fields = HashMap::new();
+        fields.insert("country".to_string(), string("fr"));
+        let request = UpdateWhereRequest {
+            collection_id: collection_id.clone(),
+            filters: in_paris.clone(),
+            update: Some(Document {
+                fields,
+                ..Default::default()
+            }),
+            update_mask: Some(DocumentMask {
+                field_paths: vec!["country".to_string()],
+            }),
+            ..Default::default()
+        };
+        let response = self.client.clone().update_where(request).await;
+        let updated = response.map_err(unexpected)?.into_inner().updated;
+        ensure(updated == 2, "UpdateWhere counts the documents it updates")?;
+        let doc = self
+            .get(&format!("{collection_id}/c"))
+            .await
+            .map_err(unexpected)?;
+        ensure(
+            doc.fields.get("country") == Some(&string("fr")),
+            "matching documents are updated",
+        )?;
+
+        let request = DeleteWhereRequest {
+            collection_id: collection_id.clone(),
+            filters: in_paris,
+            ..Default::default()
+        };
+        let response = self.client.clone().delete_where(request).await;
+        let deleted = response.map_err(unexpected)?.into_inner().deleted;
+        ensure(deleted == 2, "DeleteWhere counts the documents it deletes")?;
+        let result = self.get(&format!("{collection_id}/a")).await;
+        expect_code(result, Code::NotFound)?;
+        let doc = self
+            .get(&format!("{collection_id}/b"))
+            .await
+            .map_err(unexpected)?;
+        ensure(
+            !doc.fields.contains_key("country"),
+            "other documents are left alone",
+        )
+    }
+
+    async fn bulk_write(&self) -> Check {
+        let doc = |doc_id: &str| Document {
+            name: self.name(doc_id),
+            ..Default::default()
+        };
+        let writes = [
+            BulkOperation::Create(doc("bulk-a")),
+            BulkOperation::Create(doc("bulk-a")),
+            BulkOperation::Update(doc("bulk-missing")),
+            BulkOperation::Set(doc("bulk-b")),
+            BulkOperation::Delete(self.name("bulk-a")),
+        ];
+        let requests: Vec<_> = writes
+            .into_iter()
+            .map(|operation| BulkWriteRequest {
+                operation: Some(operation),
+                ..Default::default()
+            })
+            .collect();
+        let response = self
+            .client
+            .clone()
+            .bulk_write(tokio_stream::iter(requests))
+            .await;
+        let mut responses = response.map_err(unexpected)?.into_inner();
+        let mut results = Vec::new();
+        while let Some(response) = responses.message().await.map_err(unexpected)? {
+            results.extend(response.results);
+        }
+
+        let codes: Vec<_> = results.iter().map(|result| result.code).collect();
+        let expected = [
+            Code::Ok,
+            Code::AlreadyExists,
+            Code::NotFound,
+            Code::Ok,
+            Code::Ok,
+        ];
+        ensure(
+            codes == expected.map(|code| code as i32),
+            "every write gets its own result",
+        )?;
+        ensure(
+            results.iter().zip(0..).all(|(result, i)| result.index == i),
+            "results are in stream order",
+        )?;
+        let result = self.get(&self.name("bulk-a")).await;
+        expect_code(result, Code::NotFound)?;
+        self.get(&self.name("bulk-b")).await.map_err(unexpected)?;
+        Ok(())
+    }
+}
+
+fn ensure(condition: bool, what: &str) -> Check {
+    if condition {
+        Ok(())
+    } else {
+        Err(format!("check failed: {what}"))
+    }
+}
+
+fn expect_code<T>(result: Result<T, Status>, code: Code) -> Check {
+    match result {
+        Ok(_) => Err(format!("expected {code:?}, got OK")),
+        Err(status) if status.code() == code => Ok(()),
+        Err(status) => Err(format!(
+            "expected {code:?}, got {:?}: {}",
+            status.code(),
+            status.message()
+        )),
+    }
+}
+
+fn unexpected(status: Status) -> String {
+    format!("unexpected {:?}: {}", status.code(), status.message())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+    use crate::api::v1alpha1::zerotable_server::ZerotableServer;
+    use crate::async_engine::{AsyncEngine, DEFAULT_QUEUE_DEPTH, DEFAULT_WORKERS};
+    use crate::service::ZerotableService;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    #[tokio::test]
+    async fn test_suite_passes_against_this_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Engine::open(dir.path()).unwrap();
+        let engine = AsyncEngine::new(engine, DEFAULT_WORKERS, DEFAULT_QUEUE_DEPTH);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(ZerotableServer::new(ZerotableService::new(engine)))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let report = run(format!("http://{addr}")).await.unwrap();
+        assert!(report.passed(), "{report}");
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Transaction contention metrics.
+//!
+//! Optimistic transactions that hit a conflict are retried within a per
+//! request time budget. These counters record how often that happens and how
+//! much latency it adds, and are rendered in the Prometheus text format.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde_json::json;
+
+/// Upper bounds of the retries-per-request buckets.
+const RETRY_BUCKETS: [u64; 6] = [0, 1, 2, 4, 8, 16];
+
+/// Upper bounds of the time-spent-retrying buckets, in milliseconds.
+const RETRY_TIME_BUCKETS_MS: [u64; 9] = [1, 5, 10, 25, 50, 100, 250, 500, 1000];
+
+/// Fixed bucket histogram, the last bucket counts values above every bound.
+pub(crate) struct Histogram<const N: usize> {
+    bounds: [u64; N],
+    buckets: [AtomicU64; N],
+    overflow: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    pub(crate) fn new(bounds: [u64; N]) -> Self {
+        Histogram {
+            bounds,
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            overflow: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn observe(&self, value: u64) {
+        match self.bounds.iter().position(|&bound| value <= bound) {
+            Some(i) => self.buckets[i].fetch_add(1, Ordering::Relaxed),
+            None => self.overflow.fetch_add(1, Ordering::Relaxed),
+        };
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        render_header(out, name, help);
+        self.render_series(out, name, "");
+    }
+
+    /// Write the samples of the series of `name` with `labels`, e.g.
+    /// `method="GetDocument"`, below a header written by [`render_header`].
+    pub(crate) fn render_series(&self, out: &mut String, name: &str, labels: &str) {
+        let (bucket_labels, labels) = match labels {
+            "" => (String::new(), String::new()),
+            labels => (format!("{labels},"), format!("{{{labels}}}")),
+        };
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{bucket_labels}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        cumulative += self.overflow.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{bucket_labels}le=\"+Inf\"}} {cumulative}"
+        );
+        let _ = writeln!(
+            out,
+            "{name}_sum{labels} {}",
+            self.sum.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(out, "{name}_count{labels} {cumulative}");
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let mut buckets: Vec<_> = self
+            .bounds
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, bucket)| json!({ "le": bound, "count": bucket.load(Ordering::Relaxed) }))
+            .collect();
+        buckets.push(json!({ "le": "+Inf", "count": self.overflow.load(Ordering::Relaxed) }));
+        json!({
+            "buckets": buckets,
+            "sum": self.sum.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Write the HELP and TYPE lines of the histogram `name`.
+pub(crate) fn render_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} histogram");
+}
+
+/// Contention counters shared by every clone of the service.
+pub struct ContentionMetrics {
+    conflicts: AtomicU64,
+    exhausted: AtomicU64,
+    retries: Histogram<{ RETRY_BUCKETS.len() }>,
+    retry_time_ms: Histogram<{ RETRY_TIME_BUCKETS_MS.len() }>,
+}
+
+impl Default for ContentionMetrics {
+    fn default() -> Self {
+        ContentionMetrics {
+            conflicts: AtomicU64::new(0),
+            exhausted: AtomicU64::new(0),
+            retries: Histogram::new(RETRY_BUCKETS),
+            retry_time_ms: Histogram::new(RETRY_TIME_BUCKETS_MS),
+        }
+    }
+}
+
+impl ContentionMetrics {
+    /// Record a transaction that completed after `retries` retries.
+    ///
+    /// `retry_time` is the time spent from the first conflict to completion,
+    /// `exhausted` is set if it gave up because the retry budget ran out.
+    pub fn record(&self, retries: u32, retry_time: Duration, exhausted: bool) {
+        self.conflicts
+            .fetch_add(u64::from(retries) + u64::from(exhausted), Ordering::Relaxed);
+        if exhausted {
+            self.exhausted.fetch_add(1, Ordering::Relaxed);
+        }
+        self.retries.observe(u64::from(retries));
+        if retries > 0 || exhausted {
+            let millis = u64::try_from(retry_time.as_millis()).unwrap_or(u64::MAX);
+            self.retry_time_ms.observe(millis);
+        }
+    }
+
+    /// Total number of transaction conflicts seen.
+    pub fn conflicts(&self) -> u64 {
+        self.conflicts.load(Ordering::Relaxed)
+    }
+
+    /// Point in time copy of the metrics, for stats dumps.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "conflicts": self.conflicts(),
+            "retry_budget_exhausted": self.exhausted.load(Ordering::Relaxed),
+            "retries_per_request": self.retries.to_json(),
+            "retry_milliseconds": self.retry_time_ms.to_json(),
+        })
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_transaction_conflicts_total Optimistic transaction conflicts."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_transaction_conflicts_total counter");
+        let _ = writeln!(
+            out,
+            "zerotable_transaction_conflicts_total {}",
+            self.conflicts()
+        );
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_retry_budget_exhausted_total Requests that ran out of retry budget."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_retry_budget_exhausted_total counter");
+        let _ = writeln!(
+            out,
+            "zerotable_retry_budget_exhausted_total {}",
+            self.exhausted.load(Ordering::Relaxed)
+        );
+        self.retries.render(
+            &mut out,
+            "zerotable_transaction_retries",
+            "Transaction retries per request.",
+        );
+        self.retry_time_ms.render(
+            &mut out,
+            "zerotable_transaction_retry_milliseconds",
+            "Time spent retrying conflicting transactions.",
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let metrics = ContentionMetrics::default();
+        metrics.record(0, Duration::ZERO, false);
+        metrics.record(3, Duration::from_millis(7), false);
+        metrics.record(5, Duration::from_millis(100), true);
+
+        assert_eq!(metrics.conflicts(), 9);
+
+        let text = metrics.render();
+        assert!(text.contains("zerotable_retry_budget_exhausted_total 1\n"));
+        assert!(text.contains("zerotable_transaction_retries_bucket{le=\"0\"} 1\n"));
+        assert!(text.contains("zerotable_transaction_retries_bucket{le=\"4\"} 2\n"));
+        assert!(text.contains("zerotable_transaction_retries_count 3\n"));
+        assert!(text.contains("zerotable_transaction_retry_milliseconds_count 2\n"));
+        assert!(text.contains("zerotable_transaction_retry_milliseconds_sum 107\n"));
+
+        let json = metrics.to_json();
+        assert_eq!(json["retry_budget_exhausted"], 1);
+        assert_eq!(json["retry_milliseconds"]["sum"], 107);
+    }
+
+    #[test]
+    fn test_histogram_overflow() {
+        let histogram = Histogram::new([1, 2]);
+        histogram.observe(10);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "h", "help");
+        assert!(out.contains("h_bucket{le=\"2\"} 0\n"));
+        assert!(out.contains("h_bucket{le=\"+Inf\"} 1\n"));
+    }
+
+    #[test]
+    fn test_histogram_labels() {
+        let histogram = Histogram::new([1]);
+        histogram.observe(1);
+
+        let mut out = String::new();
+        histogram.render_series(&mut out, "h", "method=\"Get\"");
+        assert!(out.contains("h_bucket{method=\"Get\",le=\"1\"} 1\n"));
+        assert!(out.contains("h_sum{method=\"Get\"} 1\n"));
+        assert!(out.contains("h_count{method=\"Get\"} 1\n"));
+    }
+}
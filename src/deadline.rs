@@ -0,0 +1,176 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Request deadlines and cancellation.
+//!
+//! A [`Deadline`] is derived from the client's `grpc-timeout` and handed to
+//! blocking engine work, which checks it between units of work and stops
+//! early once the deadline passes or the RPC is dropped.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Status};
+
+/// Metadata key carrying the client timeout.
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// Point in time after which work on behalf of a request should stop.
+#[derive(Debug, Clone, Default)]
+pub struct Deadline {
+    at: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    /// A deadline that never expires unless cancelled.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// A deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline {
+            at: Instant::now().checked_add(timeout),
+            cancelled: Arc::default(),
+        }
+    }
+
+    /// Build a deadline from the `grpc-timeout` metadata, if present and valid.
+    pub fn from_metadata(metadata: &MetadataMap) -> Self {
+        metadata
+            .get(GRPC_TIMEOUT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_grpc_timeout)
+            .map(Deadline::after)
+            .unwrap_or_default()
+    }
+
+    /// Get the deadline assigned by [`intercept`].
+    ///
+    /// Falls back to reading the metadata if the interceptor is not installed.
+    pub fn of<T>(request: &Request<T>) -> Self {
+        match request.extensions().get::<Deadline>() {
+            Some(deadline) => deadline.clone(),
+            None => Deadline::from_metadata(request.metadata()),
+        }
+    }
+
+    /// Time left before the deadline, `None` if there is no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Stop the work immediately, regardless of the time left.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the work should stop.
+    pub fn is_expired(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Cancel the deadline when the returned guard is dropped, unless it is
+    /// disarmed first.
+    pub fn cancel_on_drop(&self) -> CancelGuard {
+        CancelGuard(Some(self.clone()))
+    }
+}
+
+/// Cancels a [`Deadline`] when dropped.
+pub struct CancelGuard(Option<Deadline>);
+
+impl CancelGuard {
+    /// Drop the guard without cancelling the deadline, e.g. once the work
+    /// it stops finished and more work of the same request may follow.
+    pub fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(deadline) = &self.0 {
+            deadline.cancel();
+        }
+    }
+}
+
+/// Parse a `grpc-timeout` value: up to 8 digits followed by a unit.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = digits.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+/// Interceptor assigning a [`Deadline`] to every incoming request.
+pub fn intercept(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let deadline = Deadline::from_metadata(request.metadata());
+    request.extensions_mut().insert(deadline);
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_grpc_timeout() {
+        assert_eq!(parse_grpc_timeout("5S"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_grpc_timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_grpc_timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_grpc_timeout("S"), None);
+        assert_eq!(parse_grpc_timeout("5x"), None);
+        assert_eq!(parse_grpc_timeout("123456789S"), None);
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(GRPC_TIMEOUT_HEADER, "10S".parse().unwrap());
+
+        let remaining = Deadline::from_metadata(&metadata).remaining().unwrap();
+        assert!(remaining <= Duration::from_secs(10));
+        assert!(remaining > Duration::from_secs(9));
+    }
+
+    #[test]
+    fn test_no_deadline() {
+        let deadline = Deadline::from_metadata(&MetadataMap::new());
+        assert_eq!(deadline.remaining(), None);
+        assert!(!deadline.is_expired());
+    }
+
+    #[test]
+    fn test_expired() {
+        assert!(Deadline::after(Duration::ZERO).is_expired());
+    }
+
+    #[test]
+    fn test_cancel_on_drop() {
+        let deadline = Deadline::none();
+        drop(deadline.cancel_on_drop());
+        assert!(deadline.is_expired());
+
+        let deadline = Deadline::none();
+        deadline.cancel_on_drop().disarm();
+        assert!(!deadline.is_expired());
+    }
+}
@@ -2,12 +2,93 @@
 // Use of this source code is governed by the Apache 2.0 license that can be
 // found in the LICENSE file.
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fmt;
+use std::ops::{Bound, ControlFlow};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use fjall::{KeyspaceCreateOptions, OptimisticTxDatabase, OptimisticTxKeyspace, Readable};
+use fjall::{
+    KeyspaceCreateOptions, OptimisticTxDatabase, OptimisticTxKeyspace, PersistMode, Readable,
+};
 
-use crate::keys::{self, KeyError};
+use crate::archive::{self, ArchiveError, ArchiveStore, SegmentWriter, Stub};
+use crate::audit::{self, Action, AuditEntry, AuditError};
+use crate::cache::DocumentCache;
+use crate::cipher::{CipherError, KeyProvider};
+use crate::deadline::Deadline;
+use crate::group_commit::GroupSync;
+use crate::id::{generate_uuid_v7, now_millis};
+use crate::keys::{self, KeyError, Tag};
+use crate::memory::{DEFAULT_MEMORY_BUDGET, MemoryBudget, MemoryTracker};
+use crate::merge::MergeBy;
+use crate::record::{self, RecordError, RecordHeader, ValueCompression};
+use crate::stats::{CollectionStats, Counters, DocumentSize, StatsTracker};
+
+/// Key in the meta keyspace holding the upper bound of leased sequence numbers,
+/// tagged as a system key.
+const SEQUENCE_LEASE_KEY: &[u8] = b"\x03sequence_lease";
+
+/// How many sequence numbers are leased from disk at once.
+const SEQUENCE_LEASE_SIZE: u64 = 1024;
+
+/// Namespace of collection counters in the meta keyspace.
+const STATS_NAMESPACE: &str = "stats";
+
+/// Namespace of collection configs in the meta keyspace.
+const CONFIG_NAMESPACE: &str = "config";
+
+/// Namespace of per-collection mutation counters in the meta keyspace.
+const COLLECTION_SEQUENCE_NAMESPACE: &str = "collection_sequence";
+
+/// Namespace of counters in the meta keyspace, see
+/// [`Engine::increment_counter`].
+const COUNTER_NAMESPACE: &str = "counter";
+
+/// Namespace of the stubs of archived collections in the meta keyspace.
+const ARCHIVE_NAMESPACE: &str = "archive";
+
+/// Namespace of the metadata records of the 'collections' keyspace.
+const COLLECTION_NAMESPACE: &str = "collection";
+
+/// Namespace of the progress of a key upgrade in the meta keyspace, the last
+/// old key upgraded by keyspace name, see [`upgrade_keys`].
+const KEY_UPGRADE_NAMESPACE: &str = "key_upgrade";
+
+/// Key in the meta keyspace present only after a clean shutdown, tagged as a
+/// system key.
+const CLEAN_SHUTDOWN_KEY: &[u8] = b"\x03clean_shutdown";
+
+/// Key in the meta keyspace holding the version of the key layout, see
+/// [`keys::FORMAT_VERSION`]. Absent from databases written by version 0.
+const KEY_FORMAT_KEY: &[u8] = b"\x03key_format";
+
+/// Documents deleted per transaction by [`Engine::drop_collection`].
+pub const DROP_BATCH_SIZE: usize = 1000;
+
+/// Keys upgraded per transaction by [`upgrade_keys`].
+const UPGRADE_BATCH_SIZE: usize = 1000;
+
+/// Times [`Engine::run_transaction`] runs a transaction whose commit
+/// conflicts before it gives up.
+pub const TRANSACTION_ATTEMPTS: u32 = 5;
+
+/// How long soft deleted documents are kept before [`Engine::purge_deleted`]
+/// drops them, unless set otherwise.
+pub const DEFAULT_SOFT_DELETE_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Namespace of import job progress entries in the operations keyspace.
+const IMPORT_OPERATION: &str = "import";
+
+/// Namespace of idempotency keys in the operations keyspace.
+const IDEMPOTENCY_OPERATION: &str = "idempotency";
+
+/// Namespace of the multi-step job journal in the operations keyspace.
+const JOB_OPERATION: &str = "job";
 
 /// Errors returned by Engine operations.
 #[derive(Debug)]
@@ -20,9 +101,40 @@ pub enum EngineError {
     InvalidKey(KeyError),
     /// Storage-level error from fjall.
     Storage(fjall::Error),
+    /// A stored record could not be decoded.
+    Corrupted(RecordError),
     /// Transaction conflict.
     /// At commit time there might be a conflict, the user in this case needs to retry the transaction!
     TransactionConflict,
+    /// An import chunk arrived ahead of the next expected sequence number.
+    OutOfSequence { expected: u64, got: u64 },
+    /// The request deadline passed or the request was cancelled.
+    DeadlineExceeded,
+    /// The collection is write locked.
+    WriteLocked(String),
+    /// An audit log entry could not be decoded.
+    CorruptedAudit(AuditError),
+    /// The database is in read-only mode.
+    ReadOnly,
+    /// The database was written with a newer key layout than supported.
+    UnsupportedKeyFormat(u8),
+    /// A document is larger than storage can hold.
+    DocumentTooLarge { size: usize, max: usize },
+    /// The collection is archived, see [`Engine::archive_collection`].
+    Archived(String),
+    /// A segment could not be written to or read from the archive.
+    Archive(ArchiveError),
+    /// The document is not at the version a conditional write expects,
+    /// holds the header of the document as it is.
+    StaleDocument(RecordHeader),
+    /// A read as of a past time reaches further back than the history kept,
+    /// see [`Engine::with_history`].
+    HistoryUnavailable,
+    /// An increment would take a counter past the range of an `i64`.
+    CounterOverflow,
+    /// A payload could not be sealed or opened, see
+    /// [`Engine::with_encryption`].
+    Encryption(CipherError),
 }
 
 impl fmt::Display for EngineError {
@@ -32,7 +144,35 @@ impl fmt::Display for EngineError {
             EngineError::NotFound => write!(f, "document not found"),
             EngineError::InvalidKey(e) => write!(f, "invalid key: {e}"),
             EngineError::Storage(e) => write!(f, "storage error: {e}"),
+            EngineError::Corrupted(e) => write!(f, "corrupted record: {e}"),
             EngineError::TransactionConflict => write!(f, "transaction conflict"),
+            EngineError::OutOfSequence { expected, got } => {
+                write!(f, "expected chunk {expected}, got {got}")
+            }
+            EngineError::DeadlineExceeded => write!(f, "deadline exceeded"),
+            EngineError::WriteLocked(collection_id) => {
+                write!(f, "collection {collection_id} is write locked")
+            }
+            EngineError::CorruptedAudit(e) => write!(f, "corrupted audit entry: {e}"),
+            EngineError::ReadOnly => write!(f, "database is read-only"),
+            EngineError::UnsupportedKeyFormat(version) => {
+                write!(f, "key format version {version} is not supported")
+            }
+            EngineError::DocumentTooLarge { size, max } => {
+                write!(f, "document is {size} bytes, at most {max} are allowed")
+            }
+            EngineError::Archived(collection_id) => {
+                write!(f, "collection {collection_id} is archived")
+            }
+            EngineError::Archive(e) => write!(f, "{e}"),
+            EngineError::StaleDocument(_) => {
+                write!(f, "document was written since the expected version")
+            }
+            EngineError::HistoryUnavailable => {
+                write!(f, "no document history is kept that far back")
+            }
+            EngineError::CounterOverflow => write!(f, "counter would overflow"),
+            EngineError::Encryption(e) => write!(f, "encryption error: {e}"),
         }
     }
 }
@@ -51,156 +191,6282 @@ impl From<fjall::Error> for EngineError {
     }
 }
 
+impl From<RecordError> for EngineError {
+    fn from(e: RecordError) -> Self {
+        match e {
+            RecordError::Cipher(e) => EngineError::Encryption(e),
+            e => EngineError::Corrupted(e),
+        }
+    }
+}
+
+impl From<AuditError> for EngineError {
+    fn from(e: AuditError) -> Self {
+        match e {
+            AuditError::Cipher(e) => EngineError::Encryption(e),
+            e => EngineError::CorruptedAudit(e),
+        }
+    }
+}
+
+impl From<ArchiveError> for EngineError {
+    fn from(e: ArchiveError) -> Self {
+        EngineError::Archive(e)
+    }
+}
+
+/// A document as read from storage, together with its record metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredDocument {
+    /// The encoded document payload.
+    pub data: Vec<u8>,
+    /// Commit sequence number of the write that produced this version.
+    pub sequence: u64,
+    /// Size of the payload in bytes.
+    pub size: usize,
+    /// Time of the write that produced this version.
+    pub write_time: SystemTime,
+}
+
+/// What to do when an imported document already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Keep the existing document.
+    #[default]
+    Skip,
+    /// Replace the existing document.
+    Overwrite,
+    /// Reject the whole chunk.
+    Fail,
+    /// Replace the existing document without reading it first.
+    ///
+    /// Fastest for bulk loads, but the collection counters can no longer
+    /// tell new documents from replaced ones and become estimates.
+    Unchecked,
+}
+
+/// A document to be written by an import.
+#[derive(Debug, Clone)]
+pub struct ImportDocument {
+    pub collection_id: String,
+    pub doc_id: String,
+    pub data: Vec<u8>,
+}
+
+/// A change made by a step of a multi-step job.
+#[derive(Debug, Clone)]
+pub enum JobWrite {
+    /// Create or replace a document.
+    Put {
+        collection_id: String,
+        doc_id: String,
+        data: Vec<u8>,
+    },
+    /// Remove a document if it exists.
+    Delete {
+        collection_id: String,
+        doc_id: String,
+    },
+}
+
+/// Version of a document a conditional write expects to replace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// Last written by the commit with this sequence number, see
+    /// [`RecordHeader::sequence`].
+    Sequence(u64),
+    /// Last written at this time, millisecond precision. Two writes in the
+    /// same millisecond are not told apart.
+    UpdateTime(SystemTime),
+}
+
+impl Version {
+    fn matches(&self, header: &RecordHeader) -> bool {
+        match *self {
+            Version::Sequence(sequence) => header.sequence == sequence,
+            Version::UpdateTime(time) => header.write_time == time,
+        }
+    }
+}
+
+/// What [`Engine::rewrite_range`] does with a document it read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rewrite {
+    /// Leave the document as is.
+    Keep,
+    /// Replace the payload of the document.
+    Replace(Vec<u8>),
+    /// Delete the document.
+    Delete,
+}
+
+/// Outcome of one batch of [`Engine::rewrite_range`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RewriteProgress {
+    /// Documents replaced or deleted by the batch.
+    pub changed: u64,
+    /// ID of the document the next batch starts at, `None` once the end of
+    /// the collection was reached.
+    pub next: Option<String>,
+}
+
+/// Outcome of one batch of [`Engine::delete_expired`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpiryProgress {
+    /// Documents deleted by the batch.
+    pub deleted: usize,
+    /// Expiry entry the next batch starts at, `None` once no entry due was
+    /// left unread.
+    pub next: Option<Vec<u8>>,
+}
+
+/// Reads and writes of a transaction run by [`Engine::run_transaction`].
+///
+/// Reads see the latest committed documents and the writes of the
+/// transaction before them; writes are buffered until the commit, which
+/// fails with [`EngineError::TransactionConflict`] if a document read was
+/// written since.
+pub struct Transaction<'a> {
+    engine: &'a Engine,
+    /// Record of every document read by key, `None` if it did not exist.
+    reads: HashMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Collection ID, document ID, key and new payload, `None` to delete, in
+    /// order.
+    writes: Vec<(String, String, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Transaction<'_> {
+    /// Payload of a document, `None` if it does not exist.
+    pub fn get(
+        &mut self,
+        collection_id: &str,
+        doc_id: &str,
+    ) -> Result<Option<Vec<u8>>, EngineError> {
+        let key = keys::encode(collection_id, doc_id)?;
+        if let Some((.., data)) = self.writes.iter().rev().find(|write| write.2 == key) {
+            return Ok(data.clone());
+        }
+        let value = match self.reads.get(&key) {
+            Some(value) => value.clone(),
+            None => {
+                let mut value = self.engine.primary.get(&key)?;
+                if value.is_none() {
+                    // restores an archived collection or fails
+                    self.engine.ensure_local([collection_id])?;
+                    value = self.engine.primary.get(&key)?;
+                }
+                let value = value.map(|value| value.to_vec());
+                self.reads.insert(key, value.clone());
+                value
+            }
+        };
+        match value {
+            Some(value) => Ok(Some(self.engine.decode_record(&value)?.1.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    /// Create or replace a document.
+    pub fn set(
+        &mut self,
+        collection_id: &str,
+        doc_id: &str,
+        data: Vec<u8>,
+    ) -> Result<(), EngineError> {
+        let key = keys::encode(collection_id, doc_id)?;
+        check_document_size(data.len())?;
+        self.writes.push((
+            collection_id.to_string(),
+            doc_id.to_string(),
+            key,
+            Some(data),
+        ));
+        Ok(())
+    }
+
+    /// Delete a document, if it exists.
+    pub fn delete(&mut self, collection_id: &str, doc_id: &str) -> Result<(), EngineError> {
+        let key = keys::encode(collection_id, doc_id)?;
+        self.writes
+            .push((collection_id.to_string(), doc_id.to_string(), key, None));
+        Ok(())
+    }
+}
+
+/// One write of [`Engine::apply_batch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    /// Create a document, failing with [`EngineError::AlreadyExists`] if it
+    /// exists.
+    Create {
+        collection_id: String,
+        doc_id: String,
+        data: Vec<u8>,
+    },
+    /// Replace the payload of a document, failing with
+    /// [`EngineError::NotFound`] if it does not exist.
+    Update {
+        collection_id: String,
+        doc_id: String,
+        data: Vec<u8>,
+    },
+    /// Create or replace a document.
+    Set {
+        collection_id: String,
+        doc_id: String,
+        data: Vec<u8>,
+    },
+    /// Delete a document, if it exists.
+    Delete {
+        collection_id: String,
+        doc_id: String,
+    },
+}
+
+/// A consistent view of the database, see [`Engine::snapshot`].
+///
+/// Documents of archived collections are read from their segment when
+/// asked for, not as of the snapshot, and listing them fails with
+/// [`EngineError::Archived`].
+pub struct Snapshot<R> {
+    engine: Engine,
+    rtx: R,
+}
+
+impl<R: Readable> Snapshot<R> {
+    /// Like [`Engine::get_document`], as of the snapshot.
+    pub fn get_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> Result<StoredDocument, EngineError> {
+        let mut results = self.get_many(&[(collection, doc_id)])?;
+        results.pop().expect("one result per document")
+    }
+
+    /// Like [`Engine::get_many`], as of the snapshot.
+    pub fn get_many(
+        &self,
+        documents: &[(&str, &str)],
+    ) -> Result<Vec<Result<StoredDocument, EngineError>>, EngineError> {
+        self.engine.get_many_in(&self.rtx, documents)
+    }
+
+    /// Like [`Engine::list_documents`], as of the snapshot.
+    pub fn list_documents(
+        &self,
+        collection_id: &str,
+        after: Option<&str>,
+        descending: bool,
+        limit: usize,
+        deadline: &Deadline,
+    ) -> Result<Vec<(String, StoredDocument)>, EngineError> {
+        // restoring it would write past the snapshot
+        if self
+            .engine
+            .archive_stub(&self.rtx, collection_id)?
+            .is_some()
+        {
+            return Err(EngineError::Archived(collection_id.to_string()));
+        }
+        self.engine
+            .list_documents_in(&self.rtx, collection_id, after, descending, limit, deadline)
+    }
+}
+
+/// Per-collection safety switches, cleared only by an admin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectionConfig {
+    /// Refuse bulk deletes of the collection.
+    pub delete_protection: bool,
+    /// Refuse every write to the collection.
+    pub write_lock: bool,
+    /// Refuse queries whose filters no index serves.
+    pub strict_queries: bool,
+    /// Keep deleted documents aside, to be restored or purged, see
+    /// [`Engine::undelete_document`].
+    pub soft_delete: bool,
+}
+
+impl CollectionConfig {
+    fn encode(&self) -> [u8; 1] {
+        [u8::from(self.delete_protection)
+            | u8::from(self.write_lock) << 1
+            | u8::from(self.strict_queries) << 2
+            | u8::from(self.soft_delete) << 3]
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let flags = bytes.first().copied().unwrap_or(0);
+        CollectionConfig {
+            delete_protection: flags & 1 != 0,
+            write_lock: flags & 2 != 0,
+            strict_queries: flags & 4 != 0,
+            soft_delete: flags & 8 != 0,
+        }
+    }
+}
+
+/// What is recorded of a collection, updated in the same transaction as its
+/// documents, see [`Engine::collection_metadata`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollectionMetadata {
+    /// When the collection was first written, millisecond precision. For a
+    /// collection written before records were kept, its first write since.
+    pub created_at: SystemTime,
+    pub document_count: u64,
+    /// Approximate total size of the document payloads in bytes.
+    pub size_bytes: u64,
+}
+
+impl CollectionMetadata {
+    /// Layout: when it was created in milliseconds since the epoch, then
+    /// the document count and the size, each as `u64 BE`.
+    fn encode(&self) -> [u8; 24] {
+        let created_at = self
+            .created_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut bytes = [0; 24];
+        bytes[..8].copy_from_slice(&created_at.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.document_count.to_be_bytes());
+        bytes[16..].copy_from_slice(&self.size_bytes.to_be_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (created_at, rest) = bytes.split_first_chunk::<8>()?;
+        let (document_count, rest) = rest.split_first_chunk::<8>()?;
+        let (size_bytes, _) = rest.split_first_chunk::<8>()?;
+        Some(CollectionMetadata {
+            created_at: SystemTime::UNIX_EPOCH
+                + Duration::from_millis(u64::from_be_bytes(*created_at)),
+            document_count: u64::from_be_bytes(*document_count),
+            size_bytes: u64::from_be_bytes(*size_bytes),
+        })
+    }
+}
+
+/// Size of one keyspace of the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceStats {
+    pub name: &'static str,
+    /// Approximate number of entries, including ones not yet compacted away.
+    pub approximate_len: u64,
+    /// Size on disk in bytes.
+    pub disk_space: u64,
+}
+
+/// Last step committed by a multi-step job.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JobCheckpoint {
+    /// Number of the next step the job expects.
+    pub next_step: u64,
+    /// Job specific state saved with the last step, e.g. a resume key.
+    pub state: Vec<u8>,
+}
+
+/// Outcome of applying an import chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportProgress {
+    /// Sequence number of the next chunk the job expects.
+    pub next_sequence: u64,
+    /// Documents written by this chunk.
+    pub written: u64,
+    /// Documents left untouched because they already existed.
+    pub skipped: u64,
+}
+
+/// Allocates monotonically increasing commit sequence numbers.
+///
+/// Numbers are leased from the meta keyspace in blocks, so a restart skips
+/// ahead instead of reusing numbers. Gaps are expected.
+struct Sequencer {
+    state: Mutex<SequencerState>,
+}
+
+struct SequencerState {
+    next: u64,
+    limit: u64,
+}
+
+impl Sequencer {
+    fn open(db: &OptimisticTxDatabase, meta: &OptimisticTxKeyspace) -> fjall::Result<Self> {
+        let limit = match db.read_tx().get(meta, SEQUENCE_LEASE_KEY)? {
+            Some(bytes) => decode_u64(&bytes),
+            None => 0,
+        };
+        Ok(Sequencer {
+            state: Mutex::new(SequencerState {
+                next: limit + 1,
+                limit,
+            }),
+        })
+    }
+
+    fn next(
+        &self,
+        db: &OptimisticTxDatabase,
+        meta: &OptimisticTxKeyspace,
+    ) -> Result<u64, EngineError> {
+        let mut state = self.state.lock().expect("sequencer lock poisoned");
+
+        if state.next > state.limit {
+            let limit = state.limit + SEQUENCE_LEASE_SIZE;
+            let mut wtx = db.write_tx()?;
+            wtx.insert(meta, SEQUENCE_LEASE_KEY, limit.to_be_bytes());
+            wtx.commit()?
+                .map_err(|_| EngineError::TransactionConflict)?;
+            state.limit = limit;
+        }
+
+        let seq = state.next;
+        state.next += 1;
+        Ok(seq)
+    }
+}
+
+/// When committed document writes reach the disk, see
+/// [`Engine::with_durability`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// A write returns once committed to the journal, which is synced to disk
+    /// by [`Engine::sync`], [`Engine::persist`] or the storage on its own: a
+    /// crash of the machine may lose the latest writes.
+    #[default]
+    Buffered,
+    /// A write returns once synced to disk, sharing the sync with the writes
+    /// committed about the same time, see [`group_commit`](crate::group_commit).
+    Sync,
+}
+
+/// Tuning of the storage, see [`Engine::open_with_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineOptions {
+    /// Memory in bytes shared by memtables and the block cache, see
+    /// [`MemoryBudget::split`].
+    pub memory_budget: u64,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Engine {
     // NOTE: should we add a trait to abstract away fjall?
     db: OptimisticTxDatabase,
     primary: OptimisticTxKeyspace,
+    meta: OptimisticTxKeyspace,
+    operations: OptimisticTxKeyspace,
+    audit: OptimisticTxKeyspace,
+    history: OptimisticTxKeyspace,
+    collections: OptimisticTxKeyspace,
+    sequencer: Arc<Sequencer>,
+    /// Syncs of the writes with [`Durability::Sync`], shared by all handles.
+    syncs: Arc<GroupSync>,
+    stats: Arc<StatsTracker>,
+    /// Record every document mutation in the audit keyspace.
+    audit_log: bool,
+    /// How long replaced and deleted revisions are kept in the history
+    /// keyspace, none are if `None`.
+    history_retention: Option<Duration>,
+    /// How long soft deleted documents are kept.
+    soft_delete_retention: Duration,
+    /// Who the mutations made through this handle are attributed to.
+    actor: Arc<str>,
+    /// Refuse every document write, shared by all handles.
+    read_only: Arc<AtomicBool>,
+    memory: Arc<MemoryTracker>,
+    /// Where archived collections go, none if archiving is off.
+    archive: Option<Arc<dyn ArchiveStore>>,
+    /// Restore an archived collection when it is read rather than reading
+    /// its segment.
+    rehydrate: bool,
+    /// When writes made through this handle reach the disk.
+    durability: Durability,
+    /// When a document expires, read from its payload, none do if `None`.
+    expiry: Option<fn(&[u8]) -> Option<SystemTime>>,
+    /// How the payloads written through this handle are stored.
+    compression: ValueCompression,
+    /// Keys payloads are sealed with, stored in the clear if `None`.
+    keys: Option<Arc<dyn KeyProvider>>,
+    /// Documents read last, shared by all handles, none are cached if
+    /// `None`.
+    cache: Option<Arc<DocumentCache>>,
 }
 
 impl Engine {
     /// Open an optimistictx database, creating it if it does not exists.
-    /// 
-    /// Open also a 'primary' keyspace, creating it if it does not exists.
-    pub fn open(path: impl AsRef<Path>) -> fjall::Result<Self> {
-        let db = OptimisticTxDatabase::builder(path).open()?;
+    ///
+    /// Open also a 'primary' keyspace for documents, a 'meta' keyspace for
+    /// engine bookkeeping, an 'operations' keyspace for long running jobs, an
+    /// 'audit' keyspace for the audit log, a 'history' keyspace for past
+    /// revisions of documents and a 'collections' keyspace for collection
+    /// metadata, creating them if they do not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        Self::open_with_options(path, EngineOptions::default())
+    }
+
+    /// Open like [`Engine::open`], tuned by `options`.
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        options: EngineOptions,
+    ) -> Result<Self, EngineError> {
+        let budget = MemoryBudget::split(options.memory_budget);
+        let db = OptimisticTxDatabase::builder(path)
+            .cache_size(budget.block_cache)
+            .open()?;
 
         // NOTE: For now we define a single keyspace where we insert all the things.
         // NOTE: Later maybe we can create another keyspace for indexes.
         let primary = db.keyspace("primary", KeyspaceCreateOptions::default)?;
+        let meta = db.keyspace("meta", KeyspaceCreateOptions::default)?;
+        let operations = db.keyspace("operations", KeyspaceCreateOptions::default)?;
+        let audit = db.keyspace("audit", KeyspaceCreateOptions::default)?;
+        // written with tagged keys from the start, nothing to upgrade
+        let history = db.keyspace("history", KeyspaceCreateOptions::default)?;
+        let collections = db.keyspace("collections", KeyspaceCreateOptions::default)?;
+        upgrade_keys(
+            &db,
+            &meta,
+            [
+                ("primary", &primary, Tag::Document),
+                ("meta", &meta, Tag::System),
+                ("operations", &operations, Tag::System),
+                ("audit", &audit, Tag::Changelog),
+            ],
+        )?;
+        let sequencer = Arc::new(Sequencer::open(&db, &meta)?);
+        let stats = Arc::new(load_stats(&db, &primary, &meta)?);
 
-        Ok(Engine { db, primary })
+        Ok(Engine {
+            db,
+            primary,
+            meta,
+            operations,
+            audit,
+            history,
+            collections,
+            sequencer,
+            syncs: Arc::default(),
+            stats,
+            audit_log: false,
+            history_retention: None,
+            soft_delete_retention: DEFAULT_SOFT_DELETE_RETENTION,
+            actor: Arc::from(""),
+            read_only: Arc::default(),
+            memory: Arc::new(MemoryTracker::new(budget)),
+            archive: None,
+            rehydrate: false,
+            durability: Durability::Buffered,
+            expiry: None,
+            compression: ValueCompression::None,
+            keys: None,
+            cache: None,
+        })
     }
 
-    /// Create a document. Fails if the document already exists.
-    pub fn create_document(
-        &self,
-        collection_id: &str,
-        doc_id: &str,
-        data: &[u8],
-    ) -> Result<(), EngineError> {
-        let key = keys::encode(collection_id, doc_id)?;
-
-        let mut wtx = self.db.write_tx()?;
+    /// Record every document mutation in the audit log, in the same
+    /// transaction as the mutation. Off by default.
+    pub fn with_audit_log(mut self, enabled: bool) -> Self {
+        self.audit_log = enabled;
+        self
+    }
 
-        // Check if document already exists (within a transaction)
-        if wtx.get(&self.primary, &key)?.is_some() {
-            return Err(EngineError::AlreadyExists);
-        }
+    /// Whether mutations are recorded in the audit log.
+    pub fn audit_log_enabled(&self) -> bool {
+        self.audit_log
+    }
 
-        wtx.insert(&self.primary, &key, data);
+    /// Keep the revisions documents are replaced or deleted by for
+    /// `retention`, in the same transaction as the write, so documents can
+    /// be read as of a time within the window, see
+    /// [`Engine::get_document_as_of`]. Off by default, as when `None`.
+    ///
+    /// Imports with [`ConflictPolicy::Unchecked`] overwrite documents
+    /// without keeping their revisions.
+    pub fn with_history(mut self, retention: Option<Duration>) -> Self {
+        self.history_retention = retention;
+        self
+    }
 
-        wtx.commit()?
-            .map_err(|_| EngineError::TransactionConflict)?; // we discard the Conflict error of fjall because it doesn't add something meaningful
+    /// How long replaced and deleted revisions are kept, `None` if they are
+    /// not.
+    pub fn history_retention(&self) -> Option<Duration> {
+        self.history_retention
+    }
 
-        // TODO: Durability options to investigate:
-        // - User configurable persist mode (like MongoDB write concern)
-        // - Background worker for periodic fsync (configurable intervals?)
-        // - Per-operation persist with PersistMode::SyncAll for strict durability
-        Ok(())
+    /// Keep the documents deleted from collections with
+    /// [`CollectionConfig::soft_delete`] on for `retention`,
+    /// [`DEFAULT_SOFT_DELETE_RETENTION`] by default.
+    pub fn with_soft_delete_retention(mut self, retention: Duration) -> Self {
+        self.soft_delete_retention = retention;
+        self
     }
 
-    /// Get a document by collection ID and document ID.
-    pub fn get_document(&self, collection: &str, doc_id: &str) -> Result<Vec<u8>, EngineError> {
-        let key = keys::encode(collection, doc_id)?;
+    pub fn soft_delete_retention(&self) -> Duration {
+        self.soft_delete_retention
+    }
 
-        match self.primary.get(&key)? {
-            Some(value) => Ok(value.to_vec()),
-            None => Err(EngineError::NotFound),
-        }
+    /// Expire documents at the time `expiry_of` reads from their payload,
+    /// `None` if they do not expire, see [`Engine::delete_expired`].
+    ///
+    /// Every write indexes the expiry of the payload it writes in the same
+    /// transaction; documents written before it was set never expire.
+    pub fn with_expiry(mut self, expiry_of: fn(&[u8]) -> Option<SystemTime>) -> Self {
+        self.expiry = Some(expiry_of);
+        self
     }
 
-    /// Delete a document. Fails if the document does not exist.
-    pub fn delete_document(&self, collection: &str, doc_id: &str) -> Result<(), EngineError> {
-        let key = keys::encode(collection, doc_id)?;
+    /// Archive collections to `store`, see [`Engine::archive_collection`].
+    /// With `rehydrate`, reading an archived collection restores it first.
+    pub fn with_archive(mut self, store: Arc<dyn ArchiveStore>, rehydrate: bool) -> Self {
+        self.archive = Some(store);
+        self.rehydrate = rehydrate;
+        self
+    }
 
-        let mut wtx = self.db.write_tx()?;
+    /// Whether collections can be archived.
+    pub fn archive_enabled(&self) -> bool {
+        self.archive.is_some()
+    }
 
-        // Check if document exists (within a transaction)
-        if wtx.get(&self.primary, &key)?.is_none() {
-            return Err(EngineError::NotFound);
-        }
+    /// Store the payloads of the documents written from now on compressed as
+    /// `compression` says, as is by default. Records written either way are
+    /// read alike, see [`record`](crate::record).
+    pub fn with_value_compression(mut self, compression: ValueCompression) -> Self {
+        self.compression = compression;
+        self
+    }
 
-        wtx.remove(&self.primary, &key);
+    /// Seal the payloads of the documents written from now on under the
+    /// current key of `keys`, see [`cipher`](crate::cipher).
+    ///
+    /// Payloads written before, in the clear or under a retired key, are
+    /// read as they are and sealed under the current key when their document
+    /// is next written, so `keys` must keep every key still in use. The
+    /// payloads of the audit log entries and idempotency records written from
+    /// now on are sealed too, and never sealed again, their keys are in use
+    /// as long as they are kept. Document keys are stored as they are.
+    pub fn with_encryption(mut self, keys: Arc<dyn KeyProvider>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
 
-        wtx.commit()?
-            .map_err(|_| EngineError::TransactionConflict)?;
-        Ok(())
+    /// Serve [`Engine::get_document`] from a cache of up to `capacity` bytes
+    /// of the documents read last, and [`Engine::document_header`] too for
+    /// those missing, see [`cache`](crate::cache). None are cached if
+    /// `capacity` is 0.
+    pub fn with_document_cache(mut self, capacity: u64) -> Self {
+        self.cache = (capacity > 0).then(|| Arc::new(DocumentCache::new(capacity)));
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Storage record of `data`, compressed and sealed as configured.
+    fn encode_record(&self, header: &RecordHeader, data: &[u8]) -> Result<Vec<u8>, EngineError> {
+        Ok(match &self.keys {
+            Some(keys) => record::encode_sealed(header, data, self.compression, keys.as_ref())?,
+            None => record::encode_with(header, data, self.compression),
+        })
+    }
 
-    fn test_engine() -> Engine {
-        let dir = tempfile::tempdir().unwrap();
-        Engine::open(dir.path()).unwrap()
+    /// Header and payload of the storage record `value`, opened if sealed.
+    fn decode_record<'a>(
+        &self,
+        value: &'a [u8],
+    ) -> Result<(RecordHeader, Cow<'a, [u8]>), EngineError> {
+        Ok(record::decode_with(value, self.keys.as_deref())?)
     }
 
-    #[test]
-    fn test_open() {
-        let dir = tempfile::tempdir().unwrap();
-        let engine = Engine::open(dir.path()).unwrap();
-        let rtx = engine.db.read_tx();
-        assert!(rtx.is_empty(&engine.primary).unwrap());
+    /// Document stored in the record `value`.
+    fn stored_document(&self, value: &[u8]) -> Result<StoredDocument, EngineError> {
+        let (header, payload) = self.decode_record(value)?;
+        Ok(StoredDocument {
+            data: payload.to_vec(),
+            sequence: header.sequence,
+            size: payload.len(),
+            write_time: header.write_time,
+        })
     }
 
-    #[test]
-    fn test_create_and_get() {
-        let engine = test_engine();
-        let data = b"hello world";
+    /// Have the first of the writes with [`Durability::Sync`] committed about
+    /// the same time wait `window` for more to join before syncing, see
+    /// [`group_commit`](crate::group_commit). They do not wait by default.
+    /// Writes with [`Durability::Buffered`] do not sync, they are not grouped.
+    pub fn with_group_commit_window(mut self, window: Duration) -> Self {
+        self.syncs = Arc::new(GroupSync::new(window));
+        self
+    }
 
-        engine.create_document("users", "doc1", data).unwrap();
-        let result = engine.get_document("users", "doc1").unwrap();
+    /// Handle on the same database whose writes reach the disk as
+    /// `durability` says, [`Durability::Buffered`] by default.
+    pub fn with_durability(&self, durability: Durability) -> Engine {
+        Engine {
+            durability,
+            ..self.clone()
+        }
+    }
 
-        assert_eq!(result, data);
+    pub fn durability(&self) -> Durability {
+        self.durability
     }
 
-    #[test]
-    fn test_create_already_exists() {
-        let engine = test_engine();
+    /// Handle on the same database attributing mutations to `actor` in the
+    /// audit log.
+    pub fn acting_as(&self, actor: &str) -> Engine {
+        Engine {
+            actor: Arc::from(actor),
+            ..self.clone()
+        }
+    }
 
-        engine.create_document("users", "doc1", b"data").unwrap();
-        let err = engine
-            .create_document("users", "doc1", b"other")
-            .unwrap_err();
+    /// Header of a new storage record stamped with a fresh sequence number.
+    fn new_header(&self) -> Result<RecordHeader, EngineError> {
+        Ok(RecordHeader {
+            sequence: self.sequencer.next(&self.db, &self.meta)?,
+            write_time: now_millis(),
+        })
+    }
 
-        assert!(matches!(err, EngineError::AlreadyExists));
+    /// Audit log key and entry of change `index` of the write with `header`,
+    /// made to `document` as mutation `collection_sequence` of its collection.
+    /// The payload is sealed like the documents are.
+    fn audit_entry(
+        &self,
+        header: &RecordHeader,
+        index: u32,
+        action: Action,
+        (collection_id, doc_id): (&str, &str),
+        collection_sequence: u64,
+        data: &[u8],
+    ) -> Result<([u8; audit::KEY_LEN], Vec<u8>), EngineError> {
+        let entry = AuditEntry {
+            sequence: header.sequence,
+            index,
+            time: header.write_time,
+            actor: self.actor.to_string(),
+            action,
+            collection_id: collection_id.to_string(),
+            doc_id: doc_id.to_string(),
+            collection_sequence,
+            data: data.to_vec(),
+        };
+        Ok(match &self.keys {
+            Some(keys) => {
+                audit::encode_sealed(&entry, keys.as_ref()).map_err(EngineError::Encryption)?
+            }
+            None => audit::encode(&entry),
+        })
     }
 
-    #[test]
-    fn test_get_not_found() {
-        let engine = test_engine();
-        let err = engine.get_document("users", "missing").unwrap_err();
+    /// History key and entry keeping `old`, the record of the document at
+    /// `key` replaced or deleted at `superseded_at`, `None` if no history is
+    /// kept.
+    fn history_entry(
+        &self,
+        key: &[u8],
+        old: &[u8],
+        superseded_at: SystemTime,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        if self.history_retention.is_none() {
+            return Ok(None);
+        }
+        let header = record::decode_header(old)?;
+        Ok(Some((
+            keys::history(key, header.sequence),
+            encode_history_entry(superseded_at, old),
+        )))
+    }
 
-        assert!(matches!(err, EngineError::NotFound));
+    /// Key and entry keeping `old`, the record of the document at `key` of
+    /// `collection_id` deleted at `deleted_at`, aside, `None` unless the
+    /// collection soft deletes.
+    ///
+    /// The config is read through `tx`, so turning soft deletes on or off
+    /// conflicts with the delete.
+    fn tombstone_entry(
+        &self,
+        tx: &impl Readable,
+        collection_id: &str,
+        key: &[u8],
+        old: &[u8],
+        deleted_at: SystemTime,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let config_key = keys::system(CONFIG_NAMESPACE, collection_id)?;
+        let soft_delete = tx
+            .get(&self.meta, &config_key)?
+            .is_some_and(|bytes| CollectionConfig::decode(&bytes).soft_delete);
+        if !soft_delete {
+            return Ok(None);
+        }
+        Ok(Some((
+            keys::deleted(key),
+            encode_history_entry(deleted_at, old),
+        )))
     }
 
-    #[test]
-    fn test_delete() {
-        let engine = test_engine();
+    /// Metadata entry of collection `collection_id` once a write changes its
+    /// document count and size by `(documents, bytes)`, read through `tx` so
+    /// concurrent writes to the collection conflict.
+    ///
+    /// A collection without a record starts from its counters, see
+    /// [`Engine::collection_stats`].
+    fn metadata_entry(
+        &self,
+        tx: &impl Readable,
+        collection_id: &str,
+        (documents, bytes): (i64, i64),
+    ) -> Result<(Vec<u8>, [u8; 24]), EngineError> {
+        let key = keys::system(COLLECTION_NAMESPACE, collection_id)?;
+        let record = tx.get(&self.collections, &key)?;
+        let metadata = match record.and_then(|record| CollectionMetadata::decode(&record)) {
+            Some(metadata) => metadata,
+            None => {
+                let stats = self.stats.get(collection_id);
+                CollectionMetadata {
+                    created_at: now_millis(),
+                    document_count: stats.document_count,
+                    size_bytes: stats.size_bytes,
+                }
+            }
+        };
+        let metadata = CollectionMetadata {
+            document_count: metadata.document_count.saturating_add_signed(documents),
+            size_bytes: metadata.size_bytes.saturating_add_signed(bytes),
+            ..metadata
+        };
+        Ok((key, metadata.encode()))
+    }
 
-        engine.create_document("users", "doc1", b"data").unwrap();
-        engine.delete_document("users", "doc1").unwrap();
+    /// Expiry entry of the document at `key` written with payload `data`,
+    /// `None` if it does not expire.
+    ///
+    /// Entries are not removed when the document changes, the reaper skips
+    /// those the document no longer matches, see [`Engine::delete_expired`].
+    fn expiry_entry(&self, key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+        let expire_at = (self.expiry?)(data)?;
+        let millis = expire_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Some(keys::ttl(millis, key))
+    }
 
-        let err = engine.get_document("users", "doc1").unwrap_err();
-        assert!(matches!(err, EngineError::NotFound));
+    /// Next mutation number of `collection_id` and the meta key to store it
+    /// under.
+    ///
+    /// The counter is read through `tx`, so concurrent writes to the same
+    /// collection conflict and the numbers follow the commit order.
+    fn next_collection_sequence(
+        &self,
+        tx: &impl Readable,
+        collection_id: &str,
+    ) -> Result<(Vec<u8>, u64), EngineError> {
+        let key = keys::system(COLLECTION_SEQUENCE_NAMESPACE, collection_id)?;
+        let current = tx.get(&self.meta, &key)?.map_or(0, |v| decode_u64(&v));
+        Ok((key, current + 1))
     }
 
-    #[test]
-    fn test_delete_not_found() {
-        let engine = test_engine();
-        let err = engine.delete_document("users", "missing").unwrap_err();
+    /// Like [`next_collection_sequence`], for writes changing several
+    /// documents. Every collection gets one number per write, remembered in
+    /// `sequences` for the caller to store once the write is complete.
+    ///
+    /// [`next_collection_sequence`]: Engine::next_collection_sequence
+    fn write_collection_sequence<'a>(
+        &self,
+        tx: &impl Readable,
+        sequences: &mut HashMap<&'a str, (Vec<u8>, u64)>,
+        collection_id: &'a str,
+    ) -> Result<u64, EngineError> {
+        if let Some((_, sequence)) = sequences.get(collection_id) {
+            return Ok(*sequence);
+        }
+        let (key, sequence) = self.next_collection_sequence(tx, collection_id)?;
+        sequences.insert(collection_id, (key, sequence));
+        Ok(sequence)
+    }
 
-        assert!(matches!(err, EngineError::NotFound));
+    /// Number of the last mutation committed to a collection, 0 if none.
+    ///
+    /// Every write assigns the next number to each collection it changes,
+    /// giving clients a total order of the changes of a collection that does
+    /// not depend on clocks.
+    pub fn collection_sequence(&self, collection_id: &str) -> Result<u64, EngineError> {
+        let key = keys::system(COLLECTION_SEQUENCE_NAMESPACE, collection_id)?;
+        let current = self.db.read_tx().get(&self.meta, &key)?;
+        Ok(current.map_or(0, |v| decode_u64(&v)))
     }
 
-    #[test]
-    fn test_create_invalid_key() {
-        let engine = test_engine();
-        let err = engine.create_document("", "doc1", b"data").unwrap_err();
+    /// Create a document. Fails if the document already exists.
+    ///
+    /// Returns the mutation number of the write in the collection, see
+    /// [`Engine::collection_sequence`].
+    #[tracing::instrument(skip(self, data), fields(bytes = data.len()))]
+    pub fn create_document(
+        &self,
+        collection_id: &str,
+        doc_id: &str,
+        data: &[u8],
+    ) -> Result<u64, EngineError> {
+        let key = keys::encode(collection_id, doc_id)?;
+        check_document_size(data.len())?;
+        let header = self.new_header()?;
 
-        assert!(matches!(err, EngineError::InvalidKey(KeyError::EmptyId)));
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection_id])?;
+
+        // Check if document already exists (within a transaction)
+        if wtx.get(&self.primary, &key)?.is_some() {
+            return Err(EngineError::AlreadyExists);
+        }
+
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection_id)?;
+        wtx.insert(&self.primary, &key, self.encode_record(&header, data)?);
+        if let Some(ttl_key) = self.expiry_entry(&key, data) {
+            wtx.insert(&self.primary, ttl_key, []);
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        if self.audit_log {
+            let (audit_key, entry) = self.audit_entry(
+                &header,
+                0,
+                Action::Create,
+                (collection_id, doc_id),
+                sequence,
+                data,
+            )?;
+            wtx.insert(&self.audit, audit_key, entry);
+        }
+        let (metadata_key, metadata) =
+            self.metadata_entry(&wtx, collection_id, (1, data.len() as i64))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?; // we discard the Conflict error of fjall because it doesn't add something meaningful
+        self.stats.record(collection_id, 1, data.len() as i64);
+        self.document_written(collection_id, doc_id, Some(data.len() as u64));
+        self.after_commit(key.len() + data.len())?;
+        Ok(sequence)
+    }
+
+    /// Create documents, possibly of several collections, in a single
+    /// transaction. Fails, creating none, if any of them exists or two of
+    /// them have the same name.
+    ///
+    /// `documents` are collection ID, document ID and payload. Returns the
+    /// mutation number of the write in the collection of each document, see
+    /// [`Engine::collection_sequence`].
+    #[tracing::instrument(skip(self, documents), fields(documents = documents.len()))]
+    pub fn create_documents(
+        &self,
+        documents: &[(&str, &str, &[u8])],
+    ) -> Result<Vec<u64>, EngineError> {
+        let doc_keys = documents
+            .iter()
+            .map(|(collection_id, doc_id, _)| keys::encode(collection_id, doc_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (_, _, data) in documents {
+            check_document_size(data.len())?;
+        }
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(
+            &wtx,
+            documents.iter().map(|(collection_id, _, _)| *collection_id),
+        )?;
+
+        let mut collection_sequences = HashMap::new();
+        let mut deltas: HashMap<&str, (i64, i64)> = HashMap::new();
+        let mut sequences = Vec::with_capacity(documents.len());
+        for (index, (&(collection_id, doc_id, data), key)) in
+            documents.iter().zip(&doc_keys).enumerate()
+        {
+            // the transaction reads its own writes, so duplicates exist too
+            if wtx.get(&self.primary, key)?.is_some() {
+                return Err(EngineError::AlreadyExists);
+            }
+            wtx.insert(&self.primary, key, self.encode_record(&header, data)?);
+            if let Some(ttl_key) = self.expiry_entry(key, data) {
+                wtx.insert(&self.primary, ttl_key, []);
+            }
+            let sequence =
+                self.write_collection_sequence(&wtx, &mut collection_sequences, collection_id)?;
+            if self.audit_log {
+                let (audit_key, entry) = self.audit_entry(
+                    &header,
+                    index as u32,
+                    Action::Create,
+                    (collection_id, doc_id),
+                    sequence,
+                    data,
+                )?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+            let delta = deltas.entry(collection_id).or_default();
+            delta.0 += 1;
+            delta.1 += data.len() as i64;
+            sequences.push(sequence);
+        }
+        for (sequence_key, sequence) in collection_sequences.into_values() {
+            wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        }
+        for (collection_id, &delta) in &deltas {
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        for (collection_id, (count, bytes)) in deltas {
+            self.stats.record(collection_id, count, bytes);
+        }
+        let mut written = 0;
+        for ((collection_id, doc_id, data), key) in documents.iter().zip(&doc_keys) {
+            self.document_written(collection_id, doc_id, Some(data.len() as u64));
+            written += key.len() + data.len();
+        }
+        self.after_commit(written)?;
+        Ok(sequences)
+    }
+
+    /// Create a document at most once per idempotency key.
+    ///
+    /// The first call with `idempotency_key` creates the document and keeps
+    /// its payload until `expires_at`. Until then, retries return that payload
+    /// instead of failing with [`EngineError::AlreadyExists`] or creating a
+    /// second document. Returns the payload of the document created for the
+    /// key, with the mutation number of the write in the collection unless
+    /// the call was a retry.
+    ///
+    /// Idempotency keys are scoped by collection: the same key sent for
+    /// another collection, or another database, creates its own document.
+    #[tracing::instrument(skip(self, data, idempotency_key, expires_at), fields(bytes = data.len()))]
+    pub fn create_document_once(
+        &self,
+        collection_id: &str,
+        doc_id: &str,
+        data: &[u8],
+        idempotency_key: &str,
+        expires_at: SystemTime,
+    ) -> Result<(Vec<u8>, Option<u64>), EngineError> {
+        let token_key = keys::scoped_system(IDEMPOTENCY_OPERATION, collection_id, idempotency_key)?;
+        let key = keys::encode(collection_id, doc_id)?;
+        check_document_size(data.len())?;
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection_id])?;
+
+        if let Some(entry) = wtx.get(&self.operations, &token_key)? {
+            match decode_idempotency_entry(&entry) {
+                Some((expiry, record)) if expiry > now_millis() => {
+                    let (_, payload) = self.decode_record(record)?;
+                    return Ok((payload.into_owned(), None));
+                }
+                _ => {} // expired, the key can be used again
+            }
+        }
+        if wtx.get(&self.primary, &key)?.is_some() {
+            return Err(EngineError::AlreadyExists);
+        }
+
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection_id)?;
+        let record = self.encode_record(&header, data)?;
+        wtx.insert(&self.primary, &key, &record);
+        if let Some(ttl_key) = self.expiry_entry(&key, data) {
+            wtx.insert(&self.primary, ttl_key, []);
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        wtx.insert(
+            &self.operations,
+            &token_key,
+            encode_idempotency_entry(expires_at, &record),
+        );
+        if self.audit_log {
+            let (audit_key, entry) = self.audit_entry(
+                &header,
+                0,
+                Action::Create,
+                (collection_id, doc_id),
+                sequence,
+                data,
+            )?;
+            wtx.insert(&self.audit, audit_key, entry);
+        }
+        let (metadata_key, metadata) =
+            self.metadata_entry(&wtx, collection_id, (1, data.len() as i64))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.stats.record(collection_id, 1, data.len() as i64);
+        self.document_written(collection_id, doc_id, Some(data.len() as u64));
+        self.after_commit(key.len() + data.len())?;
+        Ok((data.to_vec(), Some(sequence)))
+    }
+
+    /// Drop the idempotency keys expired at `now`, up to `limit` in a single
+    /// transaction. Returns how many were dropped, fewer than `limit` once
+    /// none are left.
+    pub fn purge_idempotency_keys(
+        &self,
+        now: SystemTime,
+        limit: usize,
+    ) -> Result<usize, EngineError> {
+        let prefix = keys::system_prefix(IDEMPOTENCY_OPERATION);
+
+        let mut wtx = self.db.write_tx()?;
+        let mut expired = Vec::new();
+        for guard in wtx.prefix(&self.operations, &prefix) {
+            if expired.len() == limit {
+                break;
+            }
+            let (key, value) = guard.into_inner()?;
+            match decode_idempotency_entry(&value) {
+                Some((expiry, _)) if expiry > now => {}
+                _ => expired.push(key),
+            }
+        }
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        for key in &expired {
+            wtx.remove(&self.operations, key);
+        }
+
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        Ok(expired.len())
+    }
+
+    /// Drop the revisions replaced or deleted longer than the history
+    /// retention before `now`, up to `limit` in a single transaction.
+    /// Returns how many were dropped, fewer than `limit` once none are left.
+    pub fn prune_history(&self, now: SystemTime, limit: usize) -> Result<usize, EngineError> {
+        let Some(retention) = self.history_retention else {
+            return Ok(0);
+        };
+        let oldest = now.checked_sub(retention).unwrap_or(SystemTime::UNIX_EPOCH);
+
+        // revisions are never rewritten, removing them unread cannot race
+        // a write
+        let rtx = self.db.read_tx();
+        let mut expired = Vec::new();
+        for guard in rtx.prefix(&self.history, [Tag::History as u8]) {
+            if expired.len() == limit {
+                break;
+            }
+            let (key, entry) = guard.into_inner()?;
+            if decode_history_entry(&entry)?.0 < oldest {
+                expired.push(key);
+            }
+        }
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        let mut wtx = self.db.write_tx()?;
+        for key in &expired {
+            wtx.remove(&self.history, key);
+        }
+
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        Ok(expired.len())
+    }
+
+    /// Drop the documents soft deleted longer than the soft delete retention
+    /// before `now`, up to `limit` in a single transaction. Returns how many
+    /// were dropped, fewer than `limit` once none are left.
+    pub fn purge_deleted(&self, now: SystemTime, limit: usize) -> Result<usize, EngineError> {
+        let oldest = now
+            .checked_sub(self.soft_delete_retention)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        // read in the transaction, a document deleted again meanwhile makes
+        // it conflict rather than lose its fresh deletion
+        let mut wtx = self.db.write_tx()?;
+        let mut expired = Vec::new();
+        for guard in wtx.prefix(&self.primary, [Tag::Deleted as u8]) {
+            if expired.len() == limit {
+                break;
+            }
+            let (key, entry) = guard.into_inner()?;
+            if decode_history_entry(&entry)?.0 < oldest {
+                expired.push(key);
+            }
+        }
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        for key in &expired {
+            wtx.remove(&self.primary, key);
+        }
+
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        Ok(expired.len())
+    }
+
+    /// Delete the documents whose expiry passed at `now`, reading up to
+    /// `limit` expiry entries from `start` (inclusive) in a single
+    /// transaction, see [`Engine::with_expiry`].
+    ///
+    /// Documents are deleted like [`Engine::delete_document`] does. Those
+    /// of write locked or archived collections are left until the collection
+    /// is writable again; their entries count toward `limit` all the same,
+    /// so a batch never reads more than `limit` entries and the next one
+    /// starts past them.
+    pub fn delete_expired(
+        &self,
+        now: SystemTime,
+        start: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<ExpiryProgress, EngineError> {
+        let mut progress = ExpiryProgress::default();
+        if self.expiry.is_none() {
+            return Ok(progress);
+        }
+        let millis = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let lower = start.map_or_else(|| vec![Tag::Ttl as u8], <[u8]>::to_vec);
+        let upper = keys::ttl_prefix(millis.saturating_add(1));
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        // expiry entry and document of the entries due
+        let mut due = Vec::new();
+        // whether each collection met can be written
+        let mut checked = HashMap::new();
+        let mut read = 0;
+        for guard in wtx.range(&self.primary, lower..upper) {
+            let (ttl_key, _) = guard.into_inner()?;
+            if read == limit {
+                progress.next = Some(ttl_key.to_vec());
+                break;
+            }
+            read += 1;
+            let Some((_, collection_id, doc_id)) = keys::decode_ttl(&ttl_key) else {
+                continue;
+            };
+            let writable = match checked.get(collection_id) {
+                Some(&writable) => writable,
+                None => {
+                    let writable = match self.ensure_writable(&wtx, [collection_id]) {
+                        Ok(()) => true,
+                        Err(EngineError::WriteLocked(_) | EngineError::Archived(_)) => false,
+                        Err(e) => return Err(e),
+                    };
+                    checked.insert(collection_id.to_string(), writable);
+                    writable
+                }
+            };
+            if writable {
+                due.push((
+                    ttl_key.to_vec(),
+                    collection_id.to_string(),
+                    doc_id.to_string(),
+                ));
+            }
+        }
+        if due.is_empty() {
+            return Ok(progress);
+        }
+
+        let mut collection_sequences = HashMap::new();
+        let mut deltas: HashMap<&str, (i64, i64)> = HashMap::new();
+        let mut changes = Vec::new();
+        let mut written = 0;
+        for (ttl_key, collection_id, doc_id) in &due {
+            let key = keys::encode(collection_id, doc_id)?;
+            wtx.remove(&self.primary, ttl_key.as_slice());
+            let Some(old) = wtx.get(&self.primary, &key)? else {
+                continue;
+            };
+            let (_, old_payload) = self.decode_record(&old)?;
+            // written since with another expiry, or none
+            if self.expiry_entry(&key, &old_payload).as_ref() != Some(ttl_key) {
+                continue;
+            }
+
+            wtx.remove(&self.primary, &key);
+            if let Some((history_key, entry)) = self.history_entry(&key, &old, header.write_time)? {
+                wtx.insert(&self.history, history_key, entry);
+            }
+            if let Some((deleted_key, entry)) =
+                self.tombstone_entry(&wtx, collection_id, &key, &old, header.write_time)?
+            {
+                wtx.insert(&self.primary, deleted_key, entry);
+            }
+            let sequence =
+                self.write_collection_sequence(&wtx, &mut collection_sequences, collection_id)?;
+            if self.audit_log {
+                let (audit_key, entry) = self.audit_entry(
+                    &header,
+                    changes.len() as u32,
+                    Action::Delete,
+                    (collection_id, doc_id),
+                    sequence,
+                    &[],
+                )?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+            let delta = deltas.entry(collection_id).or_default();
+            delta.0 -= 1;
+            delta.1 -= old_payload.len() as i64;
+            written += key.len();
+            changes.push((collection_id, doc_id));
+        }
+        for (sequence_key, sequence) in collection_sequences.into_values() {
+            wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        }
+        for (collection_id, &delta) in &deltas {
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        for (collection_id, (count, bytes)) in deltas {
+            self.stats.record(collection_id, count, bytes);
+        }
+        for (collection_id, doc_id) in &changes {
+            self.document_written(collection_id, doc_id, None);
+        }
+        self.after_commit(written)?;
+        progress.deleted = changes.len();
+        Ok(progress)
+    }
+
+    /// Fail a read as of `time` if the history does not reach back to it.
+    fn check_history(&self, time: SystemTime) -> Result<(), EngineError> {
+        let kept = self.history_retention.is_some_and(|retention| {
+            now_millis()
+                .checked_sub(retention)
+                .is_none_or(|oldest| time >= oldest)
+        });
+        if !kept {
+            return Err(EngineError::HistoryUnavailable);
+        }
+        Ok(())
+    }
+
+    /// Pin a consistent view of the database for reads spanning several
+    /// calls, released when the snapshot is dropped.
+    ///
+    /// A snapshot holds back the cleanup of the versions it sees, so it is
+    /// meant to be short-lived.
+    pub fn snapshot(&self) -> Snapshot<impl Readable> {
+        Snapshot {
+            engine: self.clone(),
+            rtx: self.db.read_tx(),
+        }
+    }
+
+    /// Get a document by collection ID and document ID.
+    #[tracing::instrument(skip(self))]
+    pub fn get_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> Result<StoredDocument, EngineError> {
+        let key = keys::encode(collection, doc_id)?;
+        // taken before the read, see DocumentCache::generation
+        let generation = match &self.cache {
+            Some(cache) => match cache.get(&key) {
+                Some(document) => return document.ok_or(EngineError::NotFound),
+                None => cache.generation(),
+            },
+            None => 0,
+        };
+
+        match self.primary.get(&key)? {
+            Some(value) => {
+                let document = self.stored_document(&value)?;
+                if let Some(cache) = &self.cache {
+                    cache.insert(generation, &key, Some(&document));
+                }
+                Ok(document)
+            }
+            None => match self.read_archived(collection, &key)? {
+                Some(value) => self.stored_document(&value),
+                None => {
+                    if let Some(cache) = &self.cache {
+                        cache.insert(generation, &key, None);
+                    }
+                    Err(EngineError::NotFound)
+                }
+            },
+        }
+    }
+
+    /// Record header of a document, without copying its payload.
+    ///
+    /// Returns `None` if the document does not exist.
+    #[tracing::instrument(skip(self))]
+    pub fn document_header(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> Result<Option<RecordHeader>, EngineError> {
+        let key = keys::encode(collection, doc_id)?;
+        // documents missing are cached, see Engine::get_document
+        let generation = match &self.cache {
+            Some(cache) => match cache.get(&key) {
+                Some(document) => {
+                    return Ok(document.map(|document| RecordHeader {
+                        sequence: document.sequence,
+                        write_time: document.write_time,
+                    }));
+                }
+                None => cache.generation(),
+            },
+            None => 0,
+        };
+
+        match self.primary.get(&key)? {
+            Some(value) => Ok(Some(record::decode_header(&value)?)),
+            None => match self.read_archived(collection, &key)? {
+                Some(value) => Ok(Some(record::decode_header(&value)?)),
+                None => {
+                    if let Some(cache) = &self.cache {
+                        cache.insert(generation, &key, None);
+                    }
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    /// Get a document as it was at `time`, its latest revision written by
+    /// then unless it was deleted since.
+    ///
+    /// Fails with [`EngineError::HistoryUnavailable`] unless `time` is within
+    /// the history retention window, see [`Engine::with_history`]. Archived
+    /// collections are read once restored.
+    #[tracing::instrument(skip(self))]
+    pub fn get_document_as_of(
+        &self,
+        collection: &str,
+        doc_id: &str,
+        time: SystemTime,
+    ) -> Result<StoredDocument, EngineError> {
+        self.check_history(time)?;
+        let key = keys::encode(collection, doc_id)?;
+        self.ensure_local([collection])?;
+
+        let rtx = self.db.read_tx();
+        if let Some(value) = rtx.get(&self.primary, &key)? {
+            let document = self.stored_document(&value)?;
+            if document.write_time <= time {
+                return Ok(document);
+            }
+        }
+        // latest first
+        let prefix = keys::document_history_prefix(&key);
+        for guard in rtx.prefix(&self.history, &prefix).rev() {
+            let (_, entry) = guard.into_inner()?;
+            let (superseded_at, record) = decode_history_entry(&entry)?;
+            if superseded_at <= time {
+                // deleted by then
+                break;
+            }
+            let document = self.stored_document(record)?;
+            if document.write_time <= time {
+                return Ok(document);
+            }
+        }
+        Err(EngineError::NotFound)
+    }
+
+    /// Get several documents, possibly from different collections.
+    ///
+    /// All lookups read the same snapshot, documents of archived collections
+    /// are read after it, reading the segment of each collection once.
+    /// Results are returned in the order of `documents`, each with its own
+    /// outcome, so a missing document or an invalid key does not fail the
+    /// others.
+    #[tracing::instrument(skip_all, fields(documents = documents.len()))]
+    pub fn get_many(
+        &self,
+        documents: &[(&str, &str)],
+    ) -> Result<Vec<Result<StoredDocument, EngineError>>, EngineError> {
+        self.get_many_in(&self.db.read_tx(), documents)
+    }
+
+    /// [`get_many`](Self::get_many) reading `rtx`.
+    fn get_many_in(
+        &self,
+        rtx: &impl Readable,
+        documents: &[(&str, &str)],
+    ) -> Result<Vec<Result<StoredDocument, EngineError>>, EngineError> {
+        let mut results = Vec::with_capacity(documents.len());
+        // index and key of the documents missing from the snapshot, by
+        // collection
+        let mut missing: HashMap<&str, Vec<(usize, Vec<u8>)>> = HashMap::new();
+        for (i, (collection, doc_id)) in documents.iter().enumerate() {
+            let result = match keys::encode(collection, doc_id) {
+                Ok(key) => match rtx.get(&self.primary, &key)? {
+                    Some(value) => self.stored_document(&value),
+                    None => {
+                        missing.entry(*collection).or_default().push((i, key));
+                        Err(EngineError::NotFound)
+                    }
+                },
+                Err(e) => Err(e.into()),
+            };
+            results.push(result);
+        }
+
+        for (collection, missing) in missing {
+            // missing as of `rtx` unless archived then
+            if self.archive_stub(rtx, collection)?.is_none() {
+                continue;
+            }
+            let lookups: Vec<_> = missing.iter().map(|(_, key)| key.as_slice()).collect();
+            match self.read_archived_many(collection, &lookups) {
+                Ok(records) => {
+                    for ((i, _), record) in missing.iter().zip(records) {
+                        if let Some(value) = record {
+                            results[*i] = self.stored_document(&value);
+                        }
+                    }
+                }
+                // errors are not Clone, each document gets its own
+                Err(_) => {
+                    for (i, key) in &missing {
+                        results[*i] = match self.read_archived(collection, key) {
+                            Ok(Some(value)) => self.stored_document(&value),
+                            Ok(None) => Err(EngineError::NotFound),
+                            Err(e) => Err(e),
+                        };
+                    }
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Visit the documents of several collections as one stream ordered by
+    /// document ID.
+    ///
+    /// Documents sharing an ID come in the order of `collections`. Only one
+    /// document per collection is buffered at a time. Stops early when `visit`
+    /// breaks and fails once `deadline` expires.
+    #[tracing::instrument(skip(self, deadline, visit))]
+    pub fn scan_merged(
+        &self,
+        collections: &[&str],
+        deadline: &Deadline,
+        mut visit: impl FnMut(&str, &str, StoredDocument) -> ControlFlow<()>,
+    ) -> Result<(), EngineError> {
+        let prefixes = collections
+            .iter()
+            .map(|collection_id| keys::collection_prefix(collection_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.ensure_local(collections.iter().copied())?;
+
+        let rtx = self.db.read_tx();
+        let sources = prefixes
+            .iter()
+            .map(|prefix| {
+                rtx.prefix(&self.primary, prefix)
+                    .map(|guard| guard.into_inner())
+            })
+            .collect();
+        let merged = MergeBy::new(sources, |(key, _)| {
+            keys::decode(key).map(|(_, doc_id)| doc_id.to_string())
+        });
+
+        for entry in merged {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = entry?;
+            let Some((collection_id, doc_id)) = keys::decode(&key) else {
+                continue;
+            };
+            let document = self.stored_document(&value)?;
+            if visit(collection_id, doc_id, document).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Visit the documents of `collections`, or of every collection if it is
+    /// empty, from one consistent snapshot in (collection ID, document ID)
+    /// order.
+    ///
+    /// Stops early when `visit` breaks and fails once `deadline` expires.
+    /// Archived collections are restored first or fail the scan, see
+    /// [`Engine::with_archive`].
+    #[tracing::instrument(skip(self, deadline, visit))]
+    pub fn scan_snapshot(
+        &self,
+        collections: &[&str],
+        deadline: &Deadline,
+        mut visit: impl FnMut(&str, &str, StoredDocument) -> ControlFlow<()>,
+    ) -> Result<(), EngineError> {
+        let mut prefixes = collections
+            .iter()
+            .map(|collection_id| keys::collection_prefix(collection_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        if prefixes.is_empty() {
+            // every key starts with the empty prefix
+            prefixes.push(Vec::new());
+            let archived = self.archived_collections()?;
+            self.ensure_local(archived.iter().map(|(collection_id, _)| &collection_id[..]))?;
+        } else {
+            self.ensure_local(collections.iter().copied())?;
+        }
+        prefixes.sort();
+        prefixes.dedup();
+
+        let rtx = self.db.read_tx();
+        let entries = prefixes
+            .iter()
+            .flat_map(|prefix| rtx.prefix(&self.primary, prefix));
+        for guard in entries {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = guard.into_inner()?;
+            let Some((collection_id, doc_id)) = keys::decode(&key) else {
+                continue;
+            };
+            let document = self.stored_document(&value)?;
+            if visit(collection_id, doc_id, document).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Split a collection into up to `partitions` ranges of roughly equal
+    /// document count, for scanning it from several workers in parallel.
+    ///
+    /// Returns the document IDs where each range after the first starts, in
+    /// order; the first range starts at the beginning of the collection and
+    /// the last one ends at its end. Sizes are based on the collection
+    /// counters, so small or freshly recovered collections may get fewer
+    /// ranges than requested.
+    pub fn partition_collection(
+        &self,
+        collection_id: &str,
+        partitions: usize,
+        deadline: &Deadline,
+    ) -> Result<Vec<String>, EngineError> {
+        let prefix = keys::collection_prefix(collection_id)?;
+        self.ensure_local([collection_id])?;
+        let documents = self.stats.get(collection_id).document_count as usize;
+        if partitions < 2 || documents < 2 {
+            return Ok(Vec::new());
+        }
+        let step = documents.div_ceil(partitions);
+
+        let rtx = self.db.read_tx();
+        let mut starts = Vec::with_capacity(partitions - 1);
+        for (i, guard) in rtx.prefix(&self.primary, &prefix).enumerate() {
+            if starts.len() == partitions - 1 {
+                break;
+            }
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            if i == 0 || i % step != 0 {
+                continue;
+            }
+            let (key, _) = guard.into_inner()?;
+            if let Some((_, doc_id)) = keys::decode(&key) {
+                starts.push(doc_id.to_string());
+            }
+        }
+        Ok(starts)
+    }
+
+    /// Visit the documents of a collection from `start` (inclusive) to `end`
+    /// (exclusive) in document ID order, unbounded where `None`.
+    ///
+    /// Meant for scanning the ranges returned by [`partition_collection`].
+    ///
+    /// [`partition_collection`]: Engine::partition_collection
+    #[tracing::instrument(skip(self, deadline, visit))]
+    pub fn scan_range(
+        &self,
+        collection_id: &str,
+        start: Option<&str>,
+        end: Option<&str>,
+        deadline: &Deadline,
+        mut visit: impl FnMut(&str, StoredDocument) -> ControlFlow<()>,
+    ) -> Result<(), EngineError> {
+        let prefix = keys::collection_prefix(collection_id)?;
+        let lower = match start {
+            Some(doc_id) => keys::encode(collection_id, doc_id)?,
+            None => prefix.clone(),
+        };
+        let upper = match end {
+            Some(doc_id) => keys::encode(collection_id, doc_id)?,
+            None => {
+                // first key past the collection prefix
+                let mut upper = prefix;
+                *upper.last_mut().expect("prefix ends with a separator") += 1;
+                upper
+            }
+        };
+        self.ensure_local([collection_id])?;
+
+        let rtx = self.db.read_tx();
+        for guard in rtx.range(&self.primary, lower..upper) {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = guard.into_inner()?;
+            let Some((_, doc_id)) = keys::decode(&key) else {
+                continue;
+            };
+            let document = self.stored_document(&value)?;
+            if visit(doc_id, document).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Visit the documents of a collection as they were at `time`, see
+    /// [`Engine::get_document_as_of`], in document ID order.
+    ///
+    /// The revisions current at `time` are gathered first, the scan holds
+    /// those of the documents replaced or deleted since in memory.
+    #[tracing::instrument(skip(self, deadline, visit))]
+    pub fn scan_as_of(
+        &self,
+        collection_id: &str,
+        time: SystemTime,
+        deadline: &Deadline,
+        mut visit: impl FnMut(&str, StoredDocument) -> ControlFlow<()>,
+    ) -> Result<(), EngineError> {
+        self.check_history(time)?;
+        let prefix = keys::collection_prefix(collection_id)?;
+        let history_prefix = keys::history_prefix(collection_id)?;
+        self.ensure_local([collection_id])?;
+
+        let rtx = self.db.read_tx();
+        // revisions current at `time` replaced or deleted since, at most one
+        // per document, in document ID order
+        let mut past: Vec<(String, StoredDocument)> = Vec::new();
+        for guard in rtx.prefix(&self.history, &history_prefix) {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, entry) = guard.into_inner()?;
+            let Some((_, doc_id, _)) = keys::decode_history(&key) else {
+                continue;
+            };
+            let (superseded_at, record) = decode_history_entry(&entry)?;
+            let document = self.stored_document(record)?;
+            if document.write_time > time || superseded_at <= time {
+                continue;
+            }
+            match past.last_mut() {
+                Some((last, current)) if last.as_str() == doc_id => *current = document,
+                _ => past.push((doc_id.to_string(), document)),
+            }
+        }
+
+        let mut past = past.into_iter().peekable();
+        for guard in rtx.prefix(&self.primary, &prefix) {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = guard.into_inner()?;
+            let Some((_, doc_id)) = keys::decode(&key) else {
+                continue;
+            };
+            while let Some((past_id, document)) =
+                past.next_if(|(past_id, _)| past_id.as_str() < doc_id)
+            {
+                if visit(&past_id, document).is_break() {
+                    return Ok(());
+                }
+            }
+            let revision = past.next_if(|(past_id, _)| past_id == doc_id);
+            let document = self.stored_document(&value)?;
+            let document = match revision {
+                _ if document.write_time <= time => document,
+                Some((_, revision)) => revision,
+                // created since
+                None => continue,
+            };
+            if visit(doc_id, document).is_break() {
+                return Ok(());
+            }
+        }
+        for (doc_id, document) in past {
+            if visit(&doc_id, document).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` documents of a collection, with their IDs, in document ID
+    /// order, or reverse order if `descending`, from its beginning or after
+    /// the document `after`.
+    ///
+    /// A page of a listing: the ID of the last document returned is the cursor
+    /// of the next page. The cursor document need not exist anymore.
+    /// Descending listings read backwards from the cursor, the latest
+    /// documents of a collection with version 7 UUID IDs cost no more than
+    /// the first ones.
+    #[tracing::instrument(skip(self, deadline))]
+    pub fn list_documents(
+        &self,
+        collection_id: &str,
+        after: Option<&str>,
+        descending: bool,
+        limit: usize,
+        deadline: &Deadline,
+    ) -> Result<Vec<(String, StoredDocument)>, EngineError> {
+        self.ensure_local([collection_id])?;
+        let rtx = self.db.read_tx();
+        self.list_documents_in(&rtx, collection_id, after, descending, limit, deadline)
+    }
+
+    /// [`list_documents`](Self::list_documents) reading `rtx`, which must
+    /// not see the collection archived.
+    fn list_documents_in(
+        &self,
+        rtx: &impl Readable,
+        collection_id: &str,
+        after: Option<&str>,
+        descending: bool,
+        limit: usize,
+        deadline: &Deadline,
+    ) -> Result<Vec<(String, StoredDocument)>, EngineError> {
+        let prefix = keys::collection_prefix(collection_id)?;
+        // first key past the collection prefix
+        let mut end = prefix.clone();
+        *end.last_mut().expect("prefix ends with a separator") += 1;
+        let (lower, upper) = match after {
+            Some(doc_id) if descending => (prefix, keys::encode(collection_id, doc_id)?),
+            Some(doc_id) => (keys::encode(collection_id, doc_id)?, end),
+            None => (prefix, end),
+        };
+
+        let range = rtx.range(&self.primary, lower..upper);
+        let entries: Box<dyn Iterator<Item = _>> = match descending {
+            true => Box::new(range.rev()),
+            false => Box::new(range),
+        };
+        let mut documents = Vec::with_capacity(limit.min(1024));
+        for guard in entries {
+            if documents.len() == limit {
+                break;
+            }
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = guard.into_inner()?;
+            let Some((_, doc_id)) = keys::decode(&key) else {
+                continue;
+            };
+            if Some(doc_id) == after {
+                continue;
+            }
+            documents.push((doc_id.to_string(), self.stored_document(&value)?));
+        }
+        Ok(documents)
+    }
+
+    /// Copy a document to a new name, possibly in another collection, in a
+    /// single transaction.
+    ///
+    /// `rewrite` builds the payload of the copy from the source payload, e.g.
+    /// to update the name embedded in it; nothing is written if it returns
+    /// `None`. Fails if the source does not exist or the destination does.
+    /// Returns the payload of the copy and the mutation number of the write in
+    /// the destination collection.
+    pub fn copy_document(
+        &self,
+        from: (&str, &str),
+        to: (&str, &str),
+        rewrite: impl FnOnce(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<Option<(Vec<u8>, u64)>, EngineError> {
+        self.relocate_document(from, to, rewrite, false)
+    }
+
+    /// Move a document to a new name, possibly in another collection, in a
+    /// single transaction.
+    ///
+    /// Like [`copy_document`], but the source is deleted in the same
+    /// transaction. A move to another collection is a mutation of both.
+    ///
+    /// [`copy_document`]: Engine::copy_document
+    pub fn move_document(
+        &self,
+        from: (&str, &str),
+        to: (&str, &str),
+        rewrite: impl FnOnce(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<Option<(Vec<u8>, u64)>, EngineError> {
+        self.relocate_document(from, to, rewrite, true)
+    }
+
+    #[tracing::instrument(skip(self, rewrite))]
+    fn relocate_document(
+        &self,
+        from: (&str, &str),
+        to: (&str, &str),
+        rewrite: impl FnOnce(&[u8]) -> Option<Vec<u8>>,
+        remove_source: bool,
+    ) -> Result<Option<(Vec<u8>, u64)>, EngineError> {
+        let from_key = keys::encode(from.0, from.1)?;
+        let to_key = keys::encode(to.0, to.1)?;
+        self.ensure_local([from.0])?;
+
+        let mut wtx = self.db.write_tx()?;
+        if remove_source {
+            self.ensure_writable(&wtx, [from.0, to.0])?;
+        } else {
+            self.ensure_writable(&wtx, [to.0])?;
+        }
+
+        let Some(source) = wtx.get(&self.primary, &from_key)? else {
+            return Err(EngineError::NotFound);
+        };
+        if wtx.get(&self.primary, &to_key)?.is_some() {
+            return Err(EngineError::AlreadyExists);
+        }
+        let (_, payload) = self.decode_record(&source)?;
+        let Some(data) = rewrite(&payload) else {
+            return Ok(None);
+        };
+        check_document_size(data.len())?;
+
+        let header = self.new_header()?;
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, to.0)?;
+        let mut source_sequence = sequence;
+        wtx.insert(&self.primary, &to_key, self.encode_record(&header, &data)?);
+        if let Some(ttl_key) = self.expiry_entry(&to_key, &data) {
+            wtx.insert(&self.primary, ttl_key, []);
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        if remove_source {
+            wtx.remove(&self.primary, &from_key);
+            if let Some((history_key, entry)) =
+                self.history_entry(&from_key, &source, header.write_time)?
+            {
+                wtx.insert(&self.history, history_key, entry);
+            }
+            if let Some((deleted_key, entry)) =
+                self.tombstone_entry(&wtx, from.0, &from_key, &source, header.write_time)?
+            {
+                wtx.insert(&self.primary, deleted_key, entry);
+            }
+            if from.0 != to.0 {
+                let (sequence_key, sequence) = self.next_collection_sequence(&wtx, from.0)?;
+                wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+                source_sequence = sequence;
+            }
+        }
+        if self.audit_log {
+            let (audit_key, entry) =
+                self.audit_entry(&header, 0, Action::Create, to, sequence, &data)?;
+            wtx.insert(&self.audit, audit_key, entry);
+            if remove_source {
+                let (audit_key, entry) =
+                    self.audit_entry(&header, 1, Action::Delete, from, source_sequence, &[])?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+        }
+        if remove_source {
+            let delta = (-1, -(payload.len() as i64));
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, from.0, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+        let (metadata_key, metadata) = self.metadata_entry(&wtx, to.0, (1, data.len() as i64))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        if remove_source {
+            self.stats.record(from.0, -1, -(payload.len() as i64));
+            self.document_written(from.0, from.1, None);
+        }
+        self.stats.record(to.0, 1, data.len() as i64);
+        self.document_written(to.0, to.1, Some(data.len() as u64));
+        self.after_commit(to_key.len() + data.len() + from_key.len())?;
+        Ok(Some((data, sequence)))
+    }
+
+    /// Replace a document by a function of its current payload, in a single
+    /// transaction.
+    ///
+    /// `rewrite` builds the new payload from the current one; nothing is
+    /// written if it returns `None`. Fails if the document does not exist,
+    /// and with [`EngineError::TransactionConflict`] if it was written
+    /// concurrently, in which case running the update again rewrites the
+    /// newer payload. Returns the new document as stored, with the sequence
+    /// number clients compare and swap on, see [`Version::Sequence`], and the
+    /// mutation number of the write in the collection.
+    pub fn update_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+        rewrite: impl FnOnce(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<Option<(StoredDocument, u64)>, EngineError> {
+        self.update_document_if(collection, doc_id, None, rewrite)
+    }
+
+    /// Like [`update_document`](Self::update_document), but when `expected`
+    /// is given only replaces the document at that version, failing with
+    /// [`EngineError::StaleDocument`] otherwise: a compare-and-swap. The
+    /// version is checked in the transaction, a write between the check and
+    /// the commit fails it with a conflict.
+    #[tracing::instrument(skip(self, rewrite))]
+    pub fn update_document_if(
+        &self,
+        collection: &str,
+        doc_id: &str,
+        expected: Option<Version>,
+        rewrite: impl FnOnce(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<Option<(StoredDocument, u64)>, EngineError> {
+        let key = keys::encode(collection, doc_id)?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection])?;
+
+        let Some(old) = wtx.get(&self.primary, &key)? else {
+            return Err(EngineError::NotFound);
+        };
+        let (old_header, old_payload) = self.decode_record(&old)?;
+        if let Some(expected) = expected
+            && !expected.matches(&old_header)
+        {
+            return Err(EngineError::StaleDocument(old_header));
+        }
+        let Some(data) = rewrite(&old_payload) else {
+            return Ok(None);
+        };
+        check_document_size(data.len())?;
+        let size_delta = data.len() as i64 - old_payload.len() as i64;
+
+        let header = self.new_header()?;
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection)?;
+        wtx.insert(&self.primary, &key, self.encode_record(&header, &data)?);
+        if let Some(ttl_key) = self.expiry_entry(&key, &data) {
+            wtx.insert(&self.primary, ttl_key, []);
+        }
+        if let Some((history_key, entry)) = self.history_entry(&key, &old, header.write_time)? {
+            wtx.insert(&self.history, history_key, entry);
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        if self.audit_log {
+            let (audit_key, entry) = self.audit_entry(
+                &header,
+                0,
+                Action::Replace,
+                (collection, doc_id),
+                sequence,
+                &data,
+            )?;
+            wtx.insert(&self.audit, audit_key, entry);
+        }
+        let (metadata_key, metadata) = self.metadata_entry(&wtx, collection, (0, size_delta))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.stats.record(collection, 0, size_delta);
+        self.document_written(collection, doc_id, Some(data.len() as u64));
+        self.after_commit(key.len() + data.len())?;
+        let stored = StoredDocument {
+            size: data.len(),
+            data,
+            sequence: header.sequence,
+            write_time: header.write_time,
+        };
+        Ok(Some((stored, sequence)))
+    }
+
+    /// Write a document whether or not it exists.
+    ///
+    /// Returns whether the document was created or replaced, and the mutation
+    /// number of the write in the collection, see
+    /// [`Engine::collection_sequence`].
+    #[tracing::instrument(skip(self, data), fields(bytes = data.len()))]
+    pub fn set_document(
+        &self,
+        collection_id: &str,
+        doc_id: &str,
+        data: &[u8],
+    ) -> Result<(Action, u64), EngineError> {
+        let key = keys::encode(collection_id, doc_id)?;
+        check_document_size(data.len())?;
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection_id])?;
+
+        // read in the transaction, so a concurrent create or delete conflicts
+        // instead of skewing the collection counters
+        let old = wtx.get(&self.primary, &key)?;
+        let old_len = match &old {
+            Some(old) => Some(self.decode_record(old)?.1.len()),
+            None => None,
+        };
+        let action = match old_len {
+            Some(_) => Action::Replace,
+            None => Action::Create,
+        };
+
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection_id)?;
+        wtx.insert(&self.primary, &key, self.encode_record(&header, data)?);
+        if let Some(ttl_key) = self.expiry_entry(&key, data) {
+            wtx.insert(&self.primary, ttl_key, []);
+        }
+        if let Some(old) = &old
+            && let Some((history_key, entry)) = self.history_entry(&key, old, header.write_time)?
+        {
+            wtx.insert(&self.history, history_key, entry);
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        if self.audit_log {
+            let (audit_key, entry) =
+                self.audit_entry(&header, 0, action, (collection_id, doc_id), sequence, data)?;
+            wtx.insert(&self.audit, audit_key, entry);
+        }
+        let delta = (
+            i64::from(old_len.is_none()),
+            data.len() as i64 - old_len.unwrap_or_default() as i64,
+        );
+        let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.stats.record(
+            collection_id,
+            i64::from(old_len.is_none()),
+            data.len() as i64 - old_len.unwrap_or_default() as i64,
+        );
+        self.document_written(collection_id, doc_id, Some(data.len() as u64));
+        self.after_commit(key.len() + data.len())?;
+        Ok((action, sequence))
+    }
+
+    /// Rewrite up to `limit` documents of a collection, from `start`
+    /// (inclusive) in document ID order, in a single transaction.
+    ///
+    /// `rewrite` is handed the ID and payload of every document read and
+    /// says what to do with it; an error stops the batch, writing nothing.
+    /// Documents are read in the transaction, so the batch fails with
+    /// [`EngineError::TransactionConflict`] if one of them is written
+    /// concurrently, and running it again rewrites the newer payload. Meant
+    /// for bulk updates and deletes run batch by batch.
+    #[tracing::instrument(skip(self, deadline, rewrite))]
+    pub fn rewrite_range<E>(
+        &self,
+        collection_id: &str,
+        start: Option<&str>,
+        limit: usize,
+        deadline: &Deadline,
+        mut rewrite: impl FnMut(&str, &[u8]) -> Result<Rewrite, E>,
+    ) -> Result<Result<RewriteProgress, E>, EngineError> {
+        let prefix = keys::collection_prefix(collection_id)?;
+        let lower = match start {
+            Some(doc_id) => keys::encode(collection_id, doc_id)?,
+            None => prefix.clone(),
+        };
+        // first key past the collection prefix
+        let mut upper = prefix;
+        *upper.last_mut().expect("prefix ends with a separator") += 1;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection_id])?;
+
+        let mut progress = RewriteProgress::default();
+        // key, document ID, old record and new payload, `None` for a delete
+        let mut changes = Vec::new();
+        let mut read = 0;
+        for guard in wtx.range(&self.primary, lower..upper) {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = guard.into_inner()?;
+            let Some((_, doc_id)) = keys::decode(&key) else {
+                continue;
+            };
+            if read == limit {
+                progress.next = Some(doc_id.to_string());
+                break;
+            }
+            read += 1;
+            let (_, payload) = self.decode_record(&value)?;
+            let data = match rewrite(doc_id, &payload) {
+                Ok(Rewrite::Keep) => continue,
+                Ok(Rewrite::Replace(data)) => Some(data),
+                Ok(Rewrite::Delete) => None,
+                Err(e) => return Ok(Err(e)),
+            };
+            changes.push((key.to_vec(), doc_id.to_string(), value.clone(), data));
+        }
+        if changes.is_empty() {
+            return Ok(Ok(progress));
+        }
+
+        let header = self.new_header()?;
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection_id)?;
+        let (mut documents, mut bytes, mut written) = (0, 0, 0);
+        for (index, (key, doc_id, old, data)) in changes.iter().enumerate() {
+            let old_len = self.decode_record(old)?.1.len() as i64;
+            let action = match data {
+                Some(data) => {
+                    check_document_size(data.len())?;
+                    wtx.insert(&self.primary, key, self.encode_record(&header, data)?);
+                    if let Some(ttl_key) = self.expiry_entry(key, data) {
+                        wtx.insert(&self.primary, ttl_key, []);
+                    }
+                    bytes += data.len() as i64 - old_len;
+                    written += key.len() + data.len();
+                    Action::Replace
+                }
+                None => {
+                    wtx.remove(&self.primary, key);
+                    if let Some((deleted_key, entry)) =
+                        self.tombstone_entry(&wtx, collection_id, key, old, header.write_time)?
+                    {
+                        wtx.insert(&self.primary, deleted_key, entry);
+                    }
+                    documents -= 1;
+                    bytes -= old_len;
+                    written += key.len();
+                    Action::Delete
+                }
+            };
+            if let Some((history_key, entry)) = self.history_entry(key, old, header.write_time)? {
+                wtx.insert(&self.history, history_key, entry);
+            }
+            if self.audit_log {
+                let (audit_key, entry) = self.audit_entry(
+                    &header,
+                    index as u32,
+                    action,
+                    (collection_id, doc_id),
+                    sequence,
+                    data.as_deref().unwrap_or_default(),
+                )?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        let (metadata_key, metadata) =
+            self.metadata_entry(&wtx, collection_id, (documents, bytes))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.stats.record(collection_id, documents, bytes);
+        for (_, doc_id, _, data) in &changes {
+            let size = data.as_ref().map(|data| data.len() as u64);
+            self.document_written(collection_id, doc_id, size);
+        }
+        self.after_commit(written)?;
+        progress.changed = changes.len() as u64;
+        Ok(Ok(progress))
+    }
+
+    /// Delete every document of a collection, [`DROP_BATCH_SIZE`] at a time
+    /// in document ID order, then its metadata record. Returns the number
+    /// of documents deleted.
+    ///
+    /// Every batch is a transaction of its own, see [`Engine::rewrite_range`],
+    /// retried on conflicts, and `progress` is handed the number of
+    /// documents it deleted. Deletes are recorded in the history, the soft
+    /// delete tombstones and the audit log like any other. A failure, or
+    /// `deadline` expiring, leaves the batches before it committed: dropping
+    /// the collection again finishes the job. Documents written while the
+    /// collection is dropped are deleted as well.
+    pub fn drop_collection(
+        &self,
+        collection_id: &str,
+        deadline: &Deadline,
+        mut progress: impl FnMut(u64),
+    ) -> Result<u64, EngineError> {
+        let prefix = keys::collection_prefix(collection_id)?;
+        let metadata_key = keys::system(COLLECTION_NAMESPACE, collection_id)?;
+        let mut deleted = 0;
+        loop {
+            let mut attempts = 1;
+            let batch = loop {
+                let batch =
+                    self.rewrite_range(collection_id, None, DROP_BATCH_SIZE, deadline, |_, _| {
+                        Ok::<_, Infallible>(Rewrite::Delete)
+                    });
+                match batch {
+                    Err(EngineError::TransactionConflict) if attempts < TRANSACTION_ATTEMPTS => {
+                        attempts += 1;
+                    }
+                    batch => break batch?,
+                }
+            };
+            let Ok(batch) = batch;
+            deleted += batch.changed;
+            progress(batch.changed);
+            if batch.next.is_some() {
+                continue;
+            }
+
+            let mut wtx = self.db.write_tx()?;
+            self.ensure_writable(&wtx, [collection_id])?;
+            // read through `wtx`, so a document written since the last batch
+            // conflicts and gets deleted by the next one
+            if wtx.prefix(&self.primary, &prefix).next().is_some() {
+                continue;
+            }
+            wtx.remove(&self.collections, &metadata_key);
+            match wtx.commit()? {
+                Ok(()) => return Ok(deleted),
+                Err(_) if deadline.is_expired() => return Err(EngineError::DeadlineExceeded),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Write documents, possibly of several collections, given by
+    /// collection ID and document ID, in a single transaction.
+    ///
+    /// `write` is handed the index of every write and the current payload
+    /// of its document, `None` if it does not exist, and says what to do
+    /// with it; writes see the changes of the ones before them. A write
+    /// failing with an error changes nothing, the others are still made.
+    /// Returns the outcome of every write, in order: the mutation number it
+    /// got in its collection, see [`Engine::collection_sequence`], `None` if
+    /// nothing was written.
+    #[tracing::instrument(skip(self, writes, write), fields(writes = writes.len()))]
+    pub fn write_batch<E>(
+        &self,
+        writes: &[(&str, &str)],
+        mut write: impl FnMut(usize, Option<&[u8]>) -> Result<Rewrite, E>,
+    ) -> Result<Vec<Result<Option<u64>, E>>, EngineError> {
+        let doc_keys = writes
+            .iter()
+            .map(|(collection_id, doc_id)| keys::encode(collection_id, doc_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, writes.iter().map(|(collection_id, _)| *collection_id))?;
+
+        let mut collection_sequences = HashMap::new();
+        let mut deltas: HashMap<&str, (i64, i64)> = HashMap::new();
+        // document and new payload size, `None` once deleted
+        let mut changes = Vec::new();
+        let mut written = 0;
+        let mut outcomes = Vec::with_capacity(writes.len());
+        for (index, (&(collection_id, doc_id), key)) in writes.iter().zip(&doc_keys).enumerate() {
+            // the transaction reads its own writes
+            let old = wtx.get(&self.primary, key)?;
+            let payload = match &old {
+                Some(value) => Some(self.decode_record(value)?.1),
+                None => None,
+            };
+            let current = payload.as_deref();
+            let delta = deltas.entry(collection_id).or_default();
+            let (action, data) = match (write(index, current), current) {
+                (Err(e), _) => {
+                    outcomes.push(Err(e));
+                    continue;
+                }
+                (Ok(Rewrite::Keep), _) | (Ok(Rewrite::Delete), None) => {
+                    outcomes.push(Ok(None));
+                    continue;
+                }
+                (Ok(Rewrite::Replace(data)), current) => {
+                    check_document_size(data.len())?;
+                    wtx.insert(&self.primary, key, self.encode_record(&header, &data)?);
+                    if let Some(ttl_key) = self.expiry_entry(key, &data) {
+                        wtx.insert(&self.primary, ttl_key, []);
+                    }
+                    delta.0 += i64::from(current.is_none());
+                    delta.1 += data.len() as i64 - current.map_or(0, |old| old.len() as i64);
+                    written += key.len() + data.len();
+                    let action = match current {
+                        Some(_) => Action::Replace,
+                        None => Action::Create,
+                    };
+                    (action, Some(data))
+                }
+                (Ok(Rewrite::Delete), Some(current)) => {
+                    wtx.remove(&self.primary, key);
+                    if let Some(old) = &old
+                        && let Some((deleted_key, entry)) =
+                            self.tombstone_entry(&wtx, collection_id, key, old, header.write_time)?
+                    {
+                        wtx.insert(&self.primary, deleted_key, entry);
+                    }
+                    delta.0 -= 1;
+                    delta.1 -= current.len() as i64;
+                    written += key.len();
+                    (Action::Delete, None)
+                }
+            };
+            if let Some(old) = &old
+                && let Some((history_key, entry)) =
+                    self.history_entry(key, old, header.write_time)?
+            {
+                wtx.insert(&self.history, history_key, entry);
+            }
+            let sequence =
+                self.write_collection_sequence(&wtx, &mut collection_sequences, collection_id)?;
+            if self.audit_log {
+                let (audit_key, entry) = self.audit_entry(
+                    &header,
+                    index as u32,
+                    action,
+                    (collection_id, doc_id),
+                    sequence,
+                    data.as_deref().unwrap_or_default(),
+                )?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+            changes.push((collection_id, doc_id, data.map(|data| data.len() as u64)));
+            outcomes.push(Ok(Some(sequence)));
+        }
+        if changes.is_empty() {
+            return Ok(outcomes);
+        }
+        for (sequence_key, sequence) in collection_sequences.into_values() {
+            wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        }
+        for (collection_id, &delta) in &deltas {
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        for (collection_id, (count, bytes)) in deltas {
+            self.stats.record(collection_id, count, bytes);
+        }
+        for (collection_id, doc_id, size) in changes {
+            self.document_written(collection_id, doc_id, size);
+        }
+        self.after_commit(written)?;
+        Ok(outcomes)
+    }
+
+    /// Run `work` in a transaction and commit it.
+    ///
+    /// A transaction whose commit conflicts with a concurrent write is run
+    /// again, after a short backoff, up to [`TRANSACTION_ATTEMPTS`] times and
+    /// while `deadline` allows, so `work` must have no effect outside of the
+    /// transaction. Returns what the last run returned; when `work` fails,
+    /// nothing is written.
+    #[tracing::instrument(skip_all)]
+    pub fn run_transaction<T>(
+        &self,
+        deadline: &Deadline,
+        mut work: impl FnMut(&mut Transaction<'_>) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        let mut attempts = 1;
+        loop {
+            let mut tx = Transaction {
+                engine: self,
+                reads: HashMap::new(),
+                writes: Vec::new(),
+            };
+            let result = work(&mut tx).and_then(|value| {
+                self.commit_transaction(tx)?;
+                Ok(value)
+            });
+            match result {
+                Err(EngineError::TransactionConflict)
+                    if attempts < TRANSACTION_ATTEMPTS && !deadline.is_expired() =>
+                {
+                    std::thread::sleep(Duration::from_millis(1 << attempts));
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Apply `mutations`, possibly of several collections, in a single
+    /// transaction: all of them, or none if any of them fails. Mutations
+    /// see the changes of the ones before them.
+    ///
+    /// The batch is run like [`Engine::run_transaction`] runs a transaction,
+    /// reading the documents whose existence it checks, so a concurrent
+    /// write to one of them makes it run again.
+    #[tracing::instrument(skip_all, fields(mutations = mutations.len()))]
+    pub fn apply_batch(&self, mutations: Vec<Mutation>) -> Result<(), EngineError> {
+        self.run_transaction(&Deadline::none(), |tx| {
+            for mutation in &mutations {
+                match mutation {
+                    Mutation::Create {
+                        collection_id,
+                        doc_id,
+                        data,
+                    } => {
+                        if tx.get(collection_id, doc_id)?.is_some() {
+                            return Err(EngineError::AlreadyExists);
+                        }
+                        tx.set(collection_id, doc_id, data.clone())?;
+                    }
+                    Mutation::Update {
+                        collection_id,
+                        doc_id,
+                        data,
+                    } => {
+                        if tx.get(collection_id, doc_id)?.is_none() {
+                            return Err(EngineError::NotFound);
+                        }
+                        tx.set(collection_id, doc_id, data.clone())?;
+                    }
+                    Mutation::Set {
+                        collection_id,
+                        doc_id,
+                        data,
+                    } => tx.set(collection_id, doc_id, data.clone())?,
+                    Mutation::Delete {
+                        collection_id,
+                        doc_id,
+                    } => tx.delete(collection_id, doc_id)?,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Check that the documents read by `tx` are as they were and apply its
+    /// writes, in a single transaction.
+    fn commit_transaction(&self, tx: Transaction<'_>) -> Result<(), EngineError> {
+        let mut wtx = self.db.write_tx()?;
+        // every write gets a new sequence number, equal records are the same
+        // version; reading them through `wtx` also makes a write racing the
+        // commit conflict
+        for (key, read) in &tx.reads {
+            if wtx.get(&self.primary, key)?.as_deref() != read.as_deref() {
+                return Err(EngineError::TransactionConflict);
+            }
+        }
+        if tx.writes.is_empty() {
+            return Ok(());
+        }
+        self.ensure_writable(&wtx, tx.writes.iter().map(|write| write.0.as_str()))?;
+
+        let header = self.new_header()?;
+        let mut collection_sequences = HashMap::new();
+        let mut deltas: HashMap<&str, (i64, i64)> = HashMap::new();
+        // document and new payload size, `None` once deleted
+        let mut changes = Vec::new();
+        let mut written = 0;
+        for (index, (collection_id, doc_id, key, data)) in tx.writes.iter().enumerate() {
+            // the transaction reads its own writes
+            let old = wtx.get(&self.primary, key)?;
+            let payload = match &old {
+                Some(value) => Some(self.decode_record(value)?.1),
+                None => None,
+            };
+            let current = payload.as_deref();
+            let delta = deltas.entry(collection_id).or_default();
+            let action = match (data, current) {
+                (Some(data), current) => {
+                    wtx.insert(&self.primary, key, self.encode_record(&header, data)?);
+                    if let Some(ttl_key) = self.expiry_entry(key, data) {
+                        wtx.insert(&self.primary, ttl_key, []);
+                    }
+                    delta.0 += i64::from(current.is_none());
+                    delta.1 += data.len() as i64 - current.map_or(0, |old| old.len() as i64);
+                    written += key.len() + data.len();
+                    match current {
+                        Some(_) => Action::Replace,
+                        None => Action::Create,
+                    }
+                }
+                (None, Some(current)) => {
+                    wtx.remove(&self.primary, key);
+                    if let Some(old) = &old
+                        && let Some((deleted_key, entry)) =
+                            self.tombstone_entry(&wtx, collection_id, key, old, header.write_time)?
+                    {
+                        wtx.insert(&self.primary, deleted_key, entry);
+                    }
+                    delta.0 -= 1;
+                    delta.1 -= current.len() as i64;
+                    written += key.len();
+                    Action::Delete
+                }
+                (None, None) => continue,
+            };
+            if let Some(old) = &old
+                && let Some((history_key, entry)) =
+                    self.history_entry(key, old, header.write_time)?
+            {
+                wtx.insert(&self.history, history_key, entry);
+            }
+            let sequence =
+                self.write_collection_sequence(&wtx, &mut collection_sequences, collection_id)?;
+            if self.audit_log {
+                let (audit_key, entry) = self.audit_entry(
+                    &header,
+                    index as u32,
+                    action,
+                    (collection_id.as_str(), doc_id.as_str()),
+                    sequence,
+                    data.as_deref().unwrap_or_default(),
+                )?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+            changes.push((
+                collection_id,
+                doc_id,
+                data.as_ref().map(|data| data.len() as u64),
+            ));
+        }
+        for (sequence_key, sequence) in collection_sequences.into_values() {
+            wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        }
+        for (collection_id, &delta) in &deltas {
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        for (collection_id, (count, bytes)) in deltas {
+            self.stats.record(collection_id, count, bytes);
+        }
+        for (collection_id, doc_id, size) in changes {
+            self.document_written(collection_id, doc_id, size);
+        }
+        self.after_commit(written)?;
+        Ok(())
+    }
+
+    /// Delete a document. Fails if the document does not exist.
+    ///
+    /// Returns the payload of the document as read in the transaction that
+    /// deleted it, and the mutation number of the write in the collection.
+    #[tracing::instrument(skip(self))]
+    pub fn delete_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> Result<(Vec<u8>, u64), EngineError> {
+        let key = keys::encode(collection, doc_id)?;
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection])?;
+
+        // Check if document exists (within a transaction)
+        let Some(old) = wtx.get(&self.primary, &key)? else {
+            return Err(EngineError::NotFound);
+        };
+        let (_, old_payload) = self.decode_record(&old)?;
+        let old_size = old_payload.len() as i64;
+
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection)?;
+        wtx.remove(&self.primary, &key);
+        if let Some((history_key, entry)) = self.history_entry(&key, &old, header.write_time)? {
+            wtx.insert(&self.history, history_key, entry);
+        }
+        if let Some((deleted_key, entry)) =
+            self.tombstone_entry(&wtx, collection, &key, &old, header.write_time)?
+        {
+            wtx.insert(&self.primary, deleted_key, entry);
+        }
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        if self.audit_log {
+            let (audit_key, entry) = self.audit_entry(
+                &header,
+                0,
+                Action::Delete,
+                (collection, doc_id),
+                sequence,
+                &[],
+            )?;
+            wtx.insert(&self.audit, audit_key, entry);
+        }
+        let (metadata_key, metadata) = self.metadata_entry(&wtx, collection, (-1, -old_size))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.stats.record(collection, -1, -old_size);
+        self.document_written(collection, doc_id, None);
+        self.after_commit(key.len())?;
+        Ok((old_payload.to_vec(), sequence))
+    }
+
+    /// A soft deleted document and when it was deleted, see
+    /// [`CollectionConfig::soft_delete`].
+    ///
+    /// Fails with [`EngineError::NotFound`] if the document was not soft
+    /// deleted or has been purged since.
+    #[tracing::instrument(skip(self))]
+    pub fn get_deleted_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> Result<(StoredDocument, SystemTime), EngineError> {
+        let key = keys::deleted(&keys::encode(collection, doc_id)?);
+
+        let Some(entry) = self.primary.get(&key)? else {
+            return Err(EngineError::NotFound);
+        };
+        let (deleted_at, record) = decode_history_entry(&entry)?;
+        Ok((self.stored_document(record)?, deleted_at))
+    }
+
+    /// Restore a soft deleted document as it was when deleted, in a new
+    /// write. Returns the restored document and the mutation number of the
+    /// write in the collection.
+    ///
+    /// Fails with [`EngineError::NotFound`] if the document was not soft
+    /// deleted or has been purged since, and with
+    /// [`EngineError::AlreadyExists`] if it was created again.
+    #[tracing::instrument(skip(self))]
+    pub fn undelete_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> Result<(StoredDocument, u64), EngineError> {
+        let key = keys::encode(collection, doc_id)?;
+        let deleted_key = keys::deleted(&key);
+        let header = self.new_header()?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection])?;
+
+        let Some(entry) = wtx.get(&self.primary, &deleted_key)? else {
+            return Err(EngineError::NotFound);
+        };
+        if wtx.get(&self.primary, &key)?.is_some() {
+            return Err(EngineError::AlreadyExists);
+        }
+        let (_, old) = decode_history_entry(&entry)?;
+        let (_, data) = self.decode_record(old)?;
+        let value = self.encode_record(&header, &data)?;
+
+        let (sequence_key, sequence) = self.next_collection_sequence(&wtx, collection)?;
+        wtx.insert(&self.primary, &key, value.as_slice());
+        if let Some(ttl_key) = self.expiry_entry(&key, data) {
+            wtx.insert(&self.primary, ttl_key, []);
+        }
+        wtx.remove(&self.primary, &deleted_key);
+        wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        if self.audit_log {
+            let (audit_key, entry) = self.audit_entry(
+                &header,
+                0,
+                Action::Create,
+                (collection, doc_id),
+                sequence,
+                data,
+            )?;
+            wtx.insert(&self.audit, audit_key, entry);
+        }
+        let (metadata_key, metadata) =
+            self.metadata_entry(&wtx, collection, (1, data.len() as i64))?;
+        wtx.insert(&self.collections, metadata_key, metadata);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.stats.record(collection, 1, data.len() as i64);
+        self.document_written(collection, doc_id, Some(data.len() as u64));
+        self.after_commit(key.len() + data.len())?;
+        Ok((self.stored_document(&value)?, sequence))
+    }
+
+    /// Drop a soft deleted document for good, before the retention is up.
+    ///
+    /// Fails with [`EngineError::NotFound`] if the document was not soft
+    /// deleted or has been purged already.
+    #[tracing::instrument(skip(self))]
+    pub fn purge_document(&self, collection: &str, doc_id: &str) -> Result<(), EngineError> {
+        let key = keys::deleted(&keys::encode(collection, doc_id)?);
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection])?;
+        if wtx.get(&self.primary, &key)?.is_none() {
+            return Err(EngineError::NotFound);
+        }
+        wtx.remove(&self.primary, &key);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.after_commit(key.len())?;
+        Ok(())
+    }
+
+    /// Value of a counter, 0 if it was never incremented.
+    pub fn counter(&self, counter_id: &str) -> Result<i64, EngineError> {
+        let key = keys::system(COUNTER_NAMESPACE, counter_id)?;
+        let current = self.db.read_tx().get(&self.meta, &key)?;
+        Ok(current.map_or(0, |v| decode_u64(&v) as i64))
+    }
+
+    /// Add `delta` to a counter, starting from 0, and return its new value.
+    ///
+    /// The counter is read and written in one transaction; one conflicting
+    /// with a concurrent increment is run again, like
+    /// [`Engine::run_transaction`] does, so concurrent increments all count.
+    /// Fails with [`EngineError::CounterOverflow`] if the value would leave
+    /// the range of an `i64`, leaving it as it is.
+    #[tracing::instrument(skip(self, deadline))]
+    pub fn increment_counter(
+        &self,
+        counter_id: &str,
+        delta: i64,
+        deadline: &Deadline,
+    ) -> Result<i64, EngineError> {
+        let key = keys::system(COUNTER_NAMESPACE, counter_id)?;
+        let mut attempts = 1;
+        loop {
+            match self.try_increment_counter(&key, delta) {
+                Err(EngineError::TransactionConflict)
+                    if attempts < TRANSACTION_ATTEMPTS && !deadline.is_expired() =>
+                {
+                    std::thread::sleep(Duration::from_millis(1 << attempts));
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// One attempt of [`increment_counter`](Self::increment_counter).
+    fn try_increment_counter(&self, key: &[u8], delta: i64) -> Result<i64, EngineError> {
+        if self.is_read_only() {
+            return Err(EngineError::ReadOnly);
+        }
+        let mut wtx = self.db.write_tx()?;
+        // read through `wtx`, so concurrent increments conflict
+        let current = wtx
+            .get(&self.meta, key)?
+            .map_or(0, |v| decode_u64(&v) as i64);
+        let value = current
+            .checked_add(delta)
+            .ok_or(EngineError::CounterOverflow)?;
+        wtx.insert(&self.meta, key, value.to_be_bytes());
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        self.after_commit(key.len() + 8)?;
+        Ok(value)
+    }
+
+    /// Approximate document count and size of a collection.
+    ///
+    /// Served from maintained counters and the size of the primary keyspace,
+    /// never from a scan: fjall does not size key ranges, so the disk size
+    /// of a collection is its payloads' share of the keyspace's disk space.
+    pub fn collection_stats(&self, collection_id: &str) -> Result<CollectionStats, EngineError> {
+        keys::collection_prefix(collection_id)?;
+        let mut stats = self.stats.get(collection_id);
+        stats.disk_bytes = disk_share(
+            self.primary.inner().disk_space(),
+            stats.size_bytes,
+            self.stats.total_bytes(),
+        );
+        Ok(stats)
+    }
+
+    /// Metadata record of a collection, kept in the same transaction as its
+    /// writes. Fails with [`EngineError::NotFound`] if it was never written.
+    pub fn collection_metadata(
+        &self,
+        collection_id: &str,
+    ) -> Result<CollectionMetadata, EngineError> {
+        let key = keys::system(COLLECTION_NAMESPACE, collection_id)?;
+        self.db
+            .read_tx()
+            .get(&self.collections, &key)?
+            .and_then(|record| CollectionMetadata::decode(&record))
+            .ok_or(EngineError::NotFound)
+    }
+
+    /// Largest documents of a collection written since the engine was
+    /// opened, by decreasing size, see [`LARGEST_DOCUMENTS`].
+    ///
+    /// [`LARGEST_DOCUMENTS`]: crate::stats::LARGEST_DOCUMENTS
+    pub fn largest_documents(&self, collection_id: &str) -> Result<Vec<DocumentSize>, EngineError> {
+        keys::collection_prefix(collection_id)?;
+        Ok(self.stats.largest(collection_id))
+    }
+
+    /// Approximate size of every collection that ever held a document,
+    /// sorted by collection ID.
+    pub fn all_collection_stats(&self) -> Vec<(String, CollectionStats)> {
+        let disk_space = self.primary.inner().disk_space();
+        let total_bytes = self.stats.total_bytes();
+        let mut all: Vec<_> = self
+            .stats
+            .snapshot()
+            .into_iter()
+            .map(|(collection_id, _)| {
+                let mut stats = self.stats.get(&collection_id);
+                stats.disk_bytes = disk_share(disk_space, stats.size_bytes, total_bytes);
+                (collection_id, stats)
+            })
+            .collect();
+        all.sort_by(|a, b| a.0.cmp(&b.0));
+        all
+    }
+
+    /// Safety switches of a collection, all off unless set.
+    pub fn collection_config(&self, collection_id: &str) -> Result<CollectionConfig, EngineError> {
+        let key = keys::system(CONFIG_NAMESPACE, collection_id)?;
+        Ok(match self.db.read_tx().get(&self.meta, &key)? {
+            Some(bytes) => CollectionConfig::decode(&bytes),
+            None => CollectionConfig::default(),
+        })
+    }
+
+    /// Replace the safety switches of a collection.
+    ///
+    /// Writes in flight when a write lock is set either commit before it or
+    /// conflict and see the lock on retry.
+    pub fn set_collection_config(
+        &self,
+        collection_id: &str,
+        config: CollectionConfig,
+    ) -> Result<(), EngineError> {
+        let key = keys::system(CONFIG_NAMESPACE, collection_id)?;
+
+        let mut wtx = self.db.write_tx()?;
+        if config == CollectionConfig::default() {
+            wtx.remove(&self.meta, &key);
+        } else {
+            wtx.insert(&self.meta, &key, config.encode());
+        }
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        Ok(())
+    }
+
+    /// Fail if the database is read-only or any of `collections` is write
+    /// locked.
+    ///
+    /// The configs are read through `tx` so that a lock set concurrently
+    /// makes the write transaction conflict.
+    fn ensure_writable<'a>(
+        &self,
+        tx: &impl Readable,
+        collections: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), EngineError> {
+        if self.is_read_only() {
+            return Err(EngineError::ReadOnly);
+        }
+        let mut checked: Vec<&str> = Vec::new();
+        for collection_id in collections {
+            if checked.contains(&collection_id) {
+                continue;
+            }
+            checked.push(collection_id);
+            let key = keys::system(CONFIG_NAMESPACE, collection_id)?;
+            if let Some(bytes) = tx.get(&self.meta, &key)?
+                && CollectionConfig::decode(&bytes).write_lock
+            {
+                return Err(EngineError::WriteLocked(collection_id.to_string()));
+            }
+            // read through `tx`, so archiving conflicts with concurrent writes
+            if self.archive_stub(tx, collection_id)?.is_some() {
+                return Err(EngineError::Archived(collection_id.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether document writes are refused.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
+    /// Turn read-only mode on or off, returns the previous mode.
+    ///
+    /// Writes that already passed the check may still commit. The mode is
+    /// kept in memory only and is off after a restart.
+    pub fn set_read_only(&self, read_only: bool) -> bool {
+        self.read_only.swap(read_only, Ordering::SeqCst)
+    }
+
+    fn archive_store(&self) -> Result<&dyn ArchiveStore, EngineError> {
+        match &self.archive {
+            Some(store) => Ok(store.as_ref()),
+            None => Err(ArchiveError::Disabled.into()),
+        }
+    }
+
+    /// Stub of `collection_id`, `None` unless it is archived.
+    fn archive_stub(
+        &self,
+        tx: &impl Readable,
+        collection_id: &str,
+    ) -> Result<Option<Stub>, EngineError> {
+        let key = keys::system(ARCHIVE_NAMESPACE, collection_id)?;
+        Ok(tx
+            .get(&self.meta, &key)?
+            .and_then(|bytes| Stub::decode(&bytes)))
+    }
+
+    /// Archived collections and their stubs, sorted by collection ID.
+    pub fn archived_collections(&self) -> Result<Vec<(String, Stub)>, EngineError> {
+        let prefix = keys::system_prefix(ARCHIVE_NAMESPACE);
+        let mut archived = Vec::new();
+        for guard in self.db.read_tx().prefix(&self.meta, &prefix) {
+            let (key, value) = guard.into_inner()?;
+            if let (Some((_, collection_id)), Some(stub)) =
+                (keys::decode_system(&key), Stub::decode(&value))
+            {
+                archived.push((collection_id.to_string(), stub));
+            }
+        }
+        Ok(archived)
+    }
+
+    /// Move the documents of a collection to one segment of the archive
+    /// store, leaving a stub in their place.
+    ///
+    /// With `written_before`, only if every document was written before
+    /// then. Returns `None`, leaving the collection as is, if it is empty or
+    /// was written since. An archived collection keeps its stats, refuses
+    /// writes until restored with [`Engine::restore_collection`] and is read
+    /// from its segment, see [`Engine::with_archive`]. Fails with
+    /// [`EngineError::Archived`] if it already is.
+    ///
+    /// The segment is packed from a snapshot and uploaded before the write
+    /// transaction, which only drops the documents if the collection was not
+    /// written since the snapshot, so writes are not held up by the upload.
+    pub fn archive_collection(
+        &self,
+        collection_id: &str,
+        written_before: Option<SystemTime>,
+    ) -> Result<Option<Stub>, EngineError> {
+        let store = self.archive_store()?;
+        let prefix = keys::collection_prefix(collection_id)?;
+
+        let rtx = self.db.read_tx();
+        self.ensure_writable(&rtx, [collection_id])?;
+        let (_, next_sequence) = self.next_collection_sequence(&rtx, collection_id)?;
+        let mut writer = SegmentWriter::new().map_err(ArchiveError::from)?;
+        let mut doc_keys = Vec::new();
+        for guard in rtx.prefix(&self.primary, &prefix) {
+            let (key, value) = guard.into_inner()?;
+            let header = record::decode_header(&value)?;
+            if written_before.is_some_and(|cutoff| header.write_time >= cutoff) {
+                return Ok(None);
+            }
+            writer.push(&key, &value).map_err(ArchiveError::from)?;
+            doc_keys.push(key.to_vec());
+        }
+        drop(rtx);
+        if doc_keys.is_empty() {
+            return Ok(None);
+        }
+
+        let segment = writer.finish().map_err(ArchiveError::from)?;
+        let stub = Stub {
+            segment: format!("{}.segment", generate_uuid_v7().0),
+            documents: doc_keys.len() as u64,
+            bytes: segment.len() as u64,
+            archived_at: now_millis(),
+        };
+        store
+            .put(&stub.segment, &segment)
+            .map_err(ArchiveError::from)?;
+        drop(segment);
+
+        match self.commit_archive(collection_id, next_sequence, &doc_keys, &stub) {
+            Ok(true) => {}
+            committed => {
+                // nothing points to the segment
+                delete_segment(store, &stub.segment);
+                return committed.map(|_| None);
+            }
+        }
+        tracing::info!(
+            collection_id,
+            segment = %stub.segment,
+            documents = stub.documents,
+            bytes = stub.bytes,
+            "archived collection"
+        );
+        Ok(Some(stub))
+    }
+
+    /// Replace the documents under `doc_keys` of `collection_id` by `stub`,
+    /// unless the next write to the collection would no longer take the
+    /// mutation number `next_sequence`, in which case it returns false and
+    /// writes nothing.
+    fn commit_archive(
+        &self,
+        collection_id: &str,
+        next_sequence: u64,
+        doc_keys: &[Vec<u8>],
+        stub: &Stub,
+    ) -> Result<bool, EngineError> {
+        let stub_key = keys::system(ARCHIVE_NAMESPACE, collection_id)?;
+
+        let mut wtx = self.db.write_tx()?;
+        self.ensure_writable(&wtx, [collection_id])?;
+        // read through `wtx`, so a write committed meanwhile conflicts
+        if self.next_collection_sequence(&wtx, collection_id)?.1 != next_sequence {
+            return Ok(false);
+        }
+        for key in doc_keys {
+            wtx.remove(&self.primary, key);
+        }
+        wtx.insert(&self.meta, stub_key, stub.encode());
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        Ok(true)
+    }
+
+    /// Archive every collection none of whose documents was written since
+    /// `written_before`, see [`Engine::archive_collection`].
+    ///
+    /// Collections written or locked meanwhile are left for the next run.
+    /// Returns the collections archived.
+    pub fn archive_idle_collections(
+        &self,
+        written_before: SystemTime,
+    ) -> Result<Vec<(String, Stub)>, EngineError> {
+        let archived = self.archived_collections()?;
+        let mut done = Vec::new();
+        for (collection_id, _) in self.all_collection_stats() {
+            if archived
+                .iter()
+                .any(|(archived, _)| *archived == collection_id)
+            {
+                continue;
+            }
+            match self.archive_collection(&collection_id, Some(written_before)) {
+                Ok(Some(stub)) => done.push((collection_id, stub)),
+                Ok(None) | Err(EngineError::TransactionConflict | EngineError::WriteLocked(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(done)
+    }
+
+    /// Bring the documents of an archived collection back from its segment
+    /// and drop its stub. Returns how many documents were restored, 0 if the
+    /// collection is not archived.
+    pub fn restore_collection(&self, collection_id: &str) -> Result<u64, EngineError> {
+        let stub_key = keys::system(ARCHIVE_NAMESPACE, collection_id)?;
+
+        let mut wtx = self.db.write_tx()?;
+        let Some(stub) = self.archive_stub(&wtx, collection_id)? else {
+            return Ok(0);
+        };
+        let store = self.archive_store()?;
+        let segment = store.get(&stub.segment).map_err(ArchiveError::from)?;
+        let mut written = 0;
+        for (key, value) in archive::decode_segment(&stub.segment, &segment)? {
+            written += key.len() + value.len();
+            wtx.insert(&self.primary, key, value);
+        }
+        wtx.remove(&self.meta, stub_key);
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        delete_segment(store, &stub.segment);
+        self.after_commit(written)?;
+        tracing::info!(
+            collection_id,
+            documents = stub.documents,
+            "restored collection"
+        );
+        Ok(stub.documents)
+    }
+
+    /// Record under `key` of a document missing from the primary keyspace,
+    /// read from the segment of its collection if it is archived.
+    ///
+    /// With rehydration on, the collection is restored and the record read
+    /// from the primary keyspace again.
+    fn read_archived(&self, collection: &str, key: &[u8]) -> Result<Option<Vec<u8>>, EngineError> {
+        Ok(self.read_archived_many(collection, &[key])?.pop().flatten())
+    }
+
+    /// Records under `keys` of documents of `collection`, like
+    /// [`read_archived`](Self::read_archived) reading its segment once.
+    fn read_archived_many(
+        &self,
+        collection: &str,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, EngineError> {
+        let from_primary = || -> Result<_, EngineError> {
+            keys.iter()
+                .map(|key| Ok(self.primary.get(key)?.map(|value| value.to_vec())))
+                .collect()
+        };
+        let Some(stub) = self.archive_stub(&self.db.read_tx(), collection)? else {
+            // the collection may have been restored since the caller's read
+            return from_primary();
+        };
+        if self.rehydrate {
+            self.rehydrate_collection(collection)?;
+            return from_primary();
+        }
+        let segment = self
+            .archive_store()?
+            .get(&stub.segment)
+            .map_err(ArchiveError::from)?;
+        let entries = archive::decode_segment(&stub.segment, &segment)?;
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let i = entries
+                    .binary_search_by(|(entry_key, _)| entry_key.as_slice().cmp(key))
+                    .ok()?;
+                Some(entries[i].1.clone())
+            })
+            .collect())
+    }
+
+    /// Restore an archived collection about to be read, a concurrent restore
+    /// is as good.
+    fn rehydrate_collection(&self, collection_id: &str) -> Result<(), EngineError> {
+        match self.restore_collection(collection_id) {
+            Ok(_) | Err(EngineError::TransactionConflict) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Make sure the documents of `collections` are in the primary keyspace
+    /// before scanning them, scans do not read segments. Archived ones are
+    /// restored with rehydration on and fail with [`EngineError::Archived`]
+    /// otherwise.
+    fn ensure_local<'a>(
+        &self,
+        collections: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), EngineError> {
+        let rtx = self.db.read_tx();
+        for collection_id in collections {
+            if self.archive_stub(&rtx, collection_id)?.is_none() {
+                continue;
+            }
+            if !self.rehydrate {
+                return Err(EngineError::Archived(collection_id.to_string()));
+            }
+            self.rehydrate_collection(collection_id)?;
+        }
+        Ok(())
+    }
+
+    fn keyspaces(&self) -> [(&'static str, &OptimisticTxKeyspace); 6] {
+        [
+            ("primary", &self.primary),
+            ("meta", &self.meta),
+            ("operations", &self.operations),
+            ("audit", &self.audit),
+            ("history", &self.history),
+            ("collections", &self.collections),
+        ]
+    }
+
+    /// Memory budget and memtable usage of the storage.
+    pub fn memory(&self) -> &MemoryTracker {
+        &self.memory
+    }
+
+    /// Account for the committed write of document `doc_id`, `size` bytes
+    /// long or deleted if `None`, and drop it from the cache.
+    fn document_written(&self, collection_id: &str, doc_id: &str, size: Option<u64>) {
+        self.stats.record_document(collection_id, doc_id, size);
+        if let Some(cache) = &self.cache
+            && let Ok(key) = keys::encode(collection_id, doc_id)
+        {
+            cache.invalidate(&key);
+        }
+    }
+
+    /// Account for `bytes` of committed document writes, flushing the
+    /// memtables once they approach their share of the memory budget, and
+    /// sync them to disk, along with concurrent writes, with
+    /// [`Durability::Sync`].
+    fn after_commit(&self, bytes: usize) -> Result<(), EngineError> {
+        if self.memory.record_write(bytes as u64) {
+            for (name, keyspace) in self.keyspaces() {
+                // the sealed memtable is written out by the flush workers
+                if let Err(e) = keyspace.inner().rotate_memtable() {
+                    tracing::warn!(keyspace = name, error = %e, "memtable flush failed");
+                }
+            }
+            self.memory.flushed();
+        }
+        if self.durability == Durability::Sync {
+            tracing::info_span!("sync")
+                .in_scope(|| self.syncs.sync(|| self.db.persist(PersistMode::SyncAll)))?;
+        }
+        Ok(())
+    }
+
+    /// Size of every keyspace.
+    pub fn keyspace_stats(&self) -> Vec<KeyspaceStats> {
+        self.keyspaces()
+            .into_iter()
+            .map(|(name, keyspace)| KeyspaceStats {
+                name,
+                approximate_len: keyspace.inner().approximate_len() as u64,
+                disk_space: keyspace.inner().disk_space(),
+            })
+            .collect()
+    }
+
+    /// Run a major compaction of every keyspace, dropping overwritten and
+    /// deleted entries. Blocks until done.
+    pub fn compact(&self) -> Result<(), EngineError> {
+        for (_, keyspace) in self.keyspaces() {
+            keyspace.inner().major_compact()?;
+        }
+        Ok(())
+    }
+
+    /// Flush all buffered writes to disk and fsync them, without
+    /// checkpointing the collection counters like [`persist`](Self::persist).
+    /// Meant to be run periodically with [`Durability::Buffered`].
+    pub fn sync(&self) -> Result<(), EngineError> {
+        self.db.persist(PersistMode::SyncAll)?;
+        Ok(())
+    }
+
+    /// Flush all buffered writes to disk and fsync them, with the collection
+    /// counters, whatever the [`Durability`] of the writes.
+    pub fn persist(&self) -> Result<(), EngineError> {
+        self.checkpoint_stats(false)?;
+        self.db.persist(PersistMode::SyncAll)?;
+        Ok(())
+    }
+
+    /// Refuse writes from now on, see [`Engine::set_read_only`], persist
+    /// everything and mark the database as cleanly shut down.
+    ///
+    /// Call this once no more writes are in flight. The database is not
+    /// marked clean if a write commits while the counters are written, they
+    /// miss it.
+    pub fn close(&self) -> Result<(), EngineError> {
+        self.set_read_only(true);
+        self.checkpoint_stats(true)?;
+        self.db.persist(PersistMode::SyncAll)?;
+        Ok(())
+    }
+
+    /// Write the collection counters to the meta keyspace.
+    fn checkpoint_stats(&self, clean_shutdown: bool) -> Result<(), EngineError> {
+        let changes = self.stats.changes();
+        let mut wtx = self.db.write_tx()?;
+        for (collection_id, counters) in self.stats.snapshot() {
+            let key = keys::system(STATS_NAMESPACE, &collection_id)?;
+            wtx.insert(&self.meta, key, counters.encode());
+        }
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+
+        // estimates must not be trusted as exact on the next open, nor
+        // counters missing a write committed since they were read
+        if clean_shutdown && self.stats.is_exact() {
+            if self.stats.changes() != changes {
+                tracing::warn!("documents written while closing, counters left as estimates");
+                return Ok(());
+            }
+            let mut wtx = self.db.write_tx()?;
+            wtx.insert(&self.meta, CLEAN_SHUTDOWN_KEY, []);
+            wtx.commit()?
+                .map_err(|_| EngineError::TransactionConflict)?;
+        }
+        Ok(())
+    }
+
+    /// Sequence number of the next chunk expected by an import job.
+    ///
+    /// Returns 0 for a job that has not applied any chunk yet.
+    pub fn import_progress(&self, job_id: &str) -> Result<u64, EngineError> {
+        let key = keys::system(IMPORT_OPERATION, job_id)?;
+        let next = self.db.read_tx().get(&self.operations, &key)?;
+        Ok(next.map(|v| decode_u64(&v)).unwrap_or(0))
+    }
+
+    /// Apply one chunk of an import job atomically.
+    ///
+    /// The job's progress is committed in the same transaction as the
+    /// documents, so re-sending an already applied chunk is a no-op and a
+    /// client can resume an interrupted import from [`Engine::import_progress`].
+    /// Nothing is written if `deadline` expires before the chunk is applied.
+    #[tracing::instrument(skip(self, documents, deadline), fields(documents = documents.len()))]
+    pub fn apply_import_chunk(
+        &self,
+        job_id: &str,
+        sequence: u64,
+        documents: &[ImportDocument],
+        policy: ConflictPolicy,
+        deadline: &Deadline,
+    ) -> Result<ImportProgress, EngineError> {
+        let progress_key = keys::system(IMPORT_OPERATION, job_id)?;
+        let doc_keys = documents
+            .iter()
+            .map(|d| keys::encode(&d.collection_id, &d.doc_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        for doc in documents {
+            check_document_size(doc.data.len())?;
+        }
+
+        let mut wtx = self.db.write_tx()?;
+
+        let expected = wtx
+            .get(&self.operations, &progress_key)?
+            .map(|v| decode_u64(&v))
+            .unwrap_or(0);
+        if sequence < expected {
+            // Already applied, the client is replaying after a disconnect.
+            return Ok(ImportProgress {
+                next_sequence: expected,
+                ..Default::default()
+            });
+        }
+        if sequence > expected {
+            return Err(EngineError::OutOfSequence {
+                expected,
+                got: sequence,
+            });
+        }
+        self.ensure_writable(&wtx, documents.iter().map(|d| d.collection_id.as_str()))?;
+
+        let header = RecordHeader {
+            sequence: self.sequencer.next(&self.db, &self.meta)?,
+            write_time: now_millis(),
+        };
+        let mut progress = ImportProgress {
+            next_sequence: sequence + 1,
+            ..Default::default()
+        };
+        let mut deltas: HashMap<&str, (i64, i64)> = HashMap::new();
+        let mut collection_sequences = HashMap::new();
+        let mut written = Vec::with_capacity(documents.len());
+
+        for (doc, key) in documents.iter().zip(&doc_keys) {
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let delta = deltas.entry(&doc.collection_id).or_default();
+            let old = match policy {
+                ConflictPolicy::Unchecked => None,
+                _ => wtx.get(&self.primary, key)?,
+            };
+            let action = match (&old, policy) {
+                (None, ConflictPolicy::Unchecked) | (Some(_), _) => Action::Replace,
+                (None, _) => Action::Create,
+            };
+            if let Some(old) = old {
+                match policy {
+                    ConflictPolicy::Skip => {
+                        progress.skipped += 1;
+                        continue;
+                    }
+                    ConflictPolicy::Fail => return Err(EngineError::AlreadyExists),
+                    ConflictPolicy::Overwrite | ConflictPolicy::Unchecked => {
+                        let (_, old_payload) = self.decode_record(&old)?;
+                        delta.0 -= 1;
+                        delta.1 -= old_payload.len() as i64;
+                        if let Some((history_key, entry)) =
+                            self.history_entry(key, &old, header.write_time)?
+                        {
+                            wtx.insert(&self.history, history_key, entry);
+                        }
+                    }
+                }
+            }
+            wtx.insert(&self.primary, key, self.encode_record(&header, &doc.data)?);
+            if let Some(ttl_key) = self.expiry_entry(key, &doc.data) {
+                wtx.insert(&self.primary, ttl_key, []);
+            }
+            let collection_sequence = self.write_collection_sequence(
+                &wtx,
+                &mut collection_sequences,
+                &doc.collection_id,
+            )?;
+            if self.audit_log {
+                let (audit_key, entry) = self.audit_entry(
+                    &header,
+                    progress.written as u32,
+                    action,
+                    (&doc.collection_id, &doc.doc_id),
+                    collection_sequence,
+                    &doc.data,
+                )?;
+                wtx.insert(&self.audit, audit_key, entry);
+            }
+            delta.0 += 1;
+            delta.1 += doc.data.len() as i64;
+            progress.written += 1;
+            written.push(doc);
+        }
+        for (sequence_key, sequence) in collection_sequences.into_values() {
+            wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        }
+
+        wtx.insert(
+            &self.operations,
+            &progress_key,
+            progress.next_sequence.to_be_bytes(),
+        );
+        for (collection_id, &delta) in &deltas {
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        for (collection_id, (documents, bytes)) in deltas {
+            self.stats.record(collection_id, documents, bytes);
+        }
+        for doc in written {
+            let size = Some(doc.data.len() as u64);
+            self.document_written(&doc.collection_id, &doc.doc_id, size);
+        }
+        if policy == ConflictPolicy::Unchecked && progress.written > 0 {
+            self.stats.mark_estimate();
+        }
+        self.after_commit(
+            documents
+                .iter()
+                .zip(&doc_keys)
+                .map(|(doc, key)| key.len() + doc.data.len())
+                .sum(),
+        )?;
+        Ok(progress)
+    }
+
+    /// Checkpoint of a multi-step job, `None` if it never committed a step.
+    pub fn job_checkpoint(
+        &self,
+        kind: &str,
+        job_id: &str,
+    ) -> Result<Option<JobCheckpoint>, EngineError> {
+        let key = job_key(kind, job_id)?;
+        let entry = self.db.read_tx().get(&self.operations, &key)?;
+        Ok(entry.map(|v| decode_job_checkpoint(&v)))
+    }
+
+    /// Commit one step of a multi-step job together with its data changes.
+    ///
+    /// The journal entry and `writes` are committed atomically, so after a
+    /// crash the job resumes from [`Engine::job_checkpoint`] without losing or
+    /// repeating work. Re-running an already committed step is a no-op and
+    /// returns false.
+    #[tracing::instrument(skip(self, state, writes), fields(writes = writes.len()))]
+    pub fn commit_job_step(
+        &self,
+        kind: &str,
+        job_id: &str,
+        step: u64,
+        state: &[u8],
+        writes: &[JobWrite],
+    ) -> Result<bool, EngineError> {
+        let job_key = job_key(kind, job_id)?;
+        let doc_keys = writes
+            .iter()
+            .map(|write| match write {
+                JobWrite::Put {
+                    collection_id,
+                    doc_id,
+                    ..
+                }
+                | JobWrite::Delete {
+                    collection_id,
+                    doc_id,
+                } => keys::encode(collection_id, doc_id),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for write in writes {
+            if let JobWrite::Put { data, .. } = write {
+                check_document_size(data.len())?;
+            }
+        }
+
+        let mut wtx = self.db.write_tx()?;
+
+        let expected = wtx
+            .get(&self.operations, &job_key)?
+            .map(|v| decode_job_checkpoint(&v).next_step)
+            .unwrap_or(0);
+        if step < expected {
+            return Ok(false);
+        }
+        if step > expected {
+            return Err(EngineError::OutOfSequence {
+                expected,
+                got: step,
+            });
+        }
+        self.ensure_writable(
+            &wtx,
+            writes.iter().map(|write| match write {
+                JobWrite::Put { collection_id, .. } | JobWrite::Delete { collection_id, .. } => {
+                    collection_id.as_str()
+                }
+            }),
+        )?;
+
+        let header = RecordHeader {
+            sequence: self.sequencer.next(&self.db, &self.meta)?,
+            write_time: now_millis(),
+        };
+        let mut deltas: HashMap<&str, (i64, i64)> = HashMap::new();
+        let mut collection_sequences = HashMap::new();
+        let mut changes = 0;
+        for (write, key) in writes.iter().zip(&doc_keys) {
+            let old = wtx.get(&self.primary, key)?;
+            let old_len = match &old {
+                Some(old) => Some(self.decode_record(old)?.1.len() as i64),
+                None => None,
+            };
+            if let Some(old) = &old
+                && let Some((history_key, entry)) =
+                    self.history_entry(key, old, header.write_time)?
+            {
+                wtx.insert(&self.history, history_key, entry);
+            }
+            match write {
+                JobWrite::Put {
+                    collection_id,
+                    doc_id,
+                    data,
+                } => {
+                    wtx.insert(&self.primary, key, self.encode_record(&header, data)?);
+                    if let Some(ttl_key) = self.expiry_entry(key, data) {
+                        wtx.insert(&self.primary, ttl_key, []);
+                    }
+                    let collection_sequence = self.write_collection_sequence(
+                        &wtx,
+                        &mut collection_sequences,
+                        collection_id,
+                    )?;
+                    if self.audit_log {
+                        let action = match old_len {
+                            Some(_) => Action::Replace,
+                            None => Action::Create,
+                        };
+                        let (audit_key, entry) = self.audit_entry(
+                            &header,
+                            changes,
+                            action,
+                            (collection_id, doc_id),
+                            collection_sequence,
+                            data,
+                        )?;
+                        wtx.insert(&self.audit, audit_key, entry);
+                    }
+                    changes += 1;
+                    let delta = deltas.entry(collection_id).or_default();
+                    delta.0 += i64::from(old_len.is_none());
+                    delta.1 += data.len() as i64 - old_len.unwrap_or(0);
+                }
+                JobWrite::Delete {
+                    collection_id,
+                    doc_id,
+                } => {
+                    let (Some(old), Some(old_len)) = (&old, old_len) else {
+                        continue;
+                    };
+                    wtx.remove(&self.primary, key);
+                    if let Some((deleted_key, entry)) =
+                        self.tombstone_entry(&wtx, collection_id, key, old, header.write_time)?
+                    {
+                        wtx.insert(&self.primary, deleted_key, entry);
+                    }
+                    let collection_sequence = self.write_collection_sequence(
+                        &wtx,
+                        &mut collection_sequences,
+                        collection_id,
+                    )?;
+                    if self.audit_log {
+                        let (audit_key, entry) = self.audit_entry(
+                            &header,
+                            changes,
+                            Action::Delete,
+                            (collection_id, doc_id),
+                            collection_sequence,
+                            &[],
+                        )?;
+                        wtx.insert(&self.audit, audit_key, entry);
+                    }
+                    changes += 1;
+                    let delta = deltas.entry(collection_id).or_default();
+                    delta.0 -= 1;
+                    delta.1 -= old_len;
+                }
+            }
+        }
+        for (sequence_key, sequence) in collection_sequences.into_values() {
+            wtx.insert(&self.meta, sequence_key, sequence.to_be_bytes());
+        }
+
+        let checkpoint = JobCheckpoint {
+            next_step: step + 1,
+            state: state.to_vec(),
+        };
+        wtx.insert(
+            &self.operations,
+            &job_key,
+            encode_job_checkpoint(&checkpoint),
+        );
+        for (collection_id, &delta) in &deltas {
+            let (metadata_key, metadata) = self.metadata_entry(&wtx, collection_id, delta)?;
+            wtx.insert(&self.collections, metadata_key, metadata);
+        }
+
+        tracing::info_span!("commit")
+            .in_scope(|| wtx.commit())?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        for (collection_id, (documents, bytes)) in deltas {
+            self.stats.record(collection_id, documents, bytes);
+        }
+        for write in writes {
+            match write {
+                JobWrite::Put {
+                    collection_id,
+                    doc_id,
+                    data,
+                } => {
+                    let size = Some(data.len() as u64);
+                    self.document_written(collection_id, doc_id, size);
+                }
+                JobWrite::Delete {
+                    collection_id,
+                    doc_id,
+                } => self.document_written(collection_id, doc_id, None),
+            }
+        }
+        self.after_commit(
+            writes
+                .iter()
+                .zip(&doc_keys)
+                .map(|(write, key)| match write {
+                    JobWrite::Put { data, .. } => key.len() + data.len(),
+                    JobWrite::Delete { .. } => key.len(),
+                })
+                .sum(),
+        )?;
+        Ok(true)
+    }
+
+    /// Audit log entries in write order, starting after the entry with the
+    /// `after` (sequence, index) position, or from the beginning if `None`.
+    ///
+    /// Only entries of the collections `include` accepts are returned. Stops
+    /// after `limit` entries and fails once `deadline` expires.
+    #[tracing::instrument(skip(self, include, deadline))]
+    pub fn audit_entries(
+        &self,
+        after: Option<(u64, u32)>,
+        include: impl Fn(&str) -> bool,
+        limit: usize,
+        deadline: &Deadline,
+    ) -> Result<Vec<AuditEntry>, EngineError> {
+        let start = match after {
+            Some((sequence, index)) => Bound::Excluded(audit::key(sequence, index).to_vec()),
+            None => Bound::Unbounded,
+        };
+
+        let rtx = self.db.read_tx();
+        let mut entries = Vec::new();
+        for guard in rtx.range(&self.audit, (start, Bound::Unbounded)) {
+            if entries.len() == limit {
+                break;
+            }
+            if deadline.is_expired() {
+                return Err(EngineError::DeadlineExceeded);
+            }
+            let (key, value) = guard.into_inner()?;
+            let entry = audit::decode_with(&key, &value, self.keys.as_deref())?;
+            if include(&entry.collection_id) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Forget a finished multi-step job.
+    pub fn clear_job(&self, kind: &str, job_id: &str) -> Result<(), EngineError> {
+        let key = job_key(kind, job_id)?;
+        let mut wtx = self.db.write_tx()?;
+        wtx.remove(&self.operations, &key);
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        Ok(())
+    }
+}
+
+/// Bring the keys of the database to the current layout, `keyspaces` lists
+/// the name and the tag of the keys of every keyspace.
+///
+/// Version 0 keys lack the tag byte, it is added to every key in batches of
+/// [`UPGRADE_BATCH_SIZE`], in key order. Each batch records the last key it
+/// upgraded, so an interrupted upgrade resumes where it stopped on the next
+/// open, and the layout version is written once every key is upgraded.
+/// Fails on a database written with a newer layout.
+fn upgrade_keys(
+    db: &OptimisticTxDatabase,
+    meta: &OptimisticTxKeyspace,
+    keyspaces: [(&str, &OptimisticTxKeyspace, Tag); 4],
+) -> Result<(), EngineError> {
+    let rtx = db.read_tx();
+    let version = match rtx.get(meta, KEY_FORMAT_KEY)? {
+        Some(value) => value.first().copied().unwrap_or_default(),
+        None => 0,
+    };
+    if version > keys::FORMAT_VERSION {
+        return Err(EngineError::UnsupportedKeyFormat(version));
+    }
+    if version == keys::FORMAT_VERSION {
+        return Ok(());
+    }
+
+    // old keys starting with the tag of their keyspace cannot be told apart
+    // from upgraded ones, they are upgraded first, in one transaction along
+    // with the progress of every keyspace. Tagged, they start with the tag
+    // twice, never equal to a key upgraded later.
+    let (first, ..) = keyspaces[0];
+    if rtx
+        .get(meta, keys::system(KEY_UPGRADE_NAMESPACE, first)?)?
+        .is_none()
+    {
+        let mut entries = Vec::new();
+        for (_, keyspace, tag) in keyspaces {
+            for guard in rtx.prefix(keyspace, [tag as u8]) {
+                let (key, value) = guard.into_inner()?;
+                entries.push((keyspace, tag, key, value));
+            }
+        }
+        // every old key is removed before the new ones are written, a tagged
+        // key may equal an old key of another record
+        let mut wtx = db.write_tx()?;
+        for (keyspace, _, key, _) in &entries {
+            wtx.remove(keyspace, key);
+        }
+        for (keyspace, tag, key, value) in entries {
+            wtx.insert(keyspace, tagged(tag, &key), value);
+        }
+        for (name, ..) in keyspaces {
+            wtx.insert(
+                meta,
+                keys::system(KEY_UPGRADE_NAMESPACE, name)?,
+                Vec::<u8>::new(),
+            );
+        }
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+    }
+
+    // the keys left to upgrade are those past the last one upgraded, but
+    // for the tagged ones
+    for (name, keyspace, tag) in keyspaces {
+        let progress = keys::system(KEY_UPGRADE_NAMESPACE, name)?;
+        loop {
+            let rtx = db.read_tx();
+            let last = rtx
+                .get(meta, &progress)?
+                .map(|value| value.to_vec())
+                .unwrap_or_default();
+            let below = (last.as_slice() < [tag as u8].as_slice()).then(|| {
+                rtx.range(
+                    keyspace,
+                    (
+                        Bound::Excluded(last.clone()),
+                        Bound::Excluded(vec![tag as u8]),
+                    ),
+                )
+            });
+            let above = if last.as_slice() > [tag as u8 + 1].as_slice() {
+                Bound::Excluded(last.clone())
+            } else {
+                Bound::Included(vec![tag as u8 + 1])
+            };
+            let mut batch = Vec::new();
+            for guard in below
+                .into_iter()
+                .flatten()
+                .chain(rtx.range(keyspace, (above, Bound::Unbounded)))
+            {
+                if batch.len() == UPGRADE_BATCH_SIZE {
+                    break;
+                }
+                batch.push(guard.into_inner()?);
+            }
+            let Some((last, _)) = batch.last() else {
+                break;
+            };
+
+            let mut wtx = db.write_tx()?;
+            wtx.insert(meta, &progress, last.clone());
+            for (key, value) in batch {
+                wtx.remove(keyspace, &key);
+                wtx.insert(keyspace, tagged(tag, &key), value);
+            }
+            wtx.commit()?
+                .map_err(|_| EngineError::TransactionConflict)?;
+        }
+    }
+
+    let mut wtx = db.write_tx()?;
+    for (name, ..) in keyspaces {
+        wtx.remove(meta, keys::system(KEY_UPGRADE_NAMESPACE, name)?);
+    }
+    wtx.insert(meta, KEY_FORMAT_KEY, [keys::FORMAT_VERSION]);
+    wtx.commit()?
+        .map_err(|_| EngineError::TransactionConflict)?;
+    db.persist(PersistMode::SyncAll)?;
+    Ok(())
+}
+
+/// Version 0 `key` tagged with `tag`.
+fn tagged(tag: Tag, key: &[u8]) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(1 + key.len());
+    tagged.push(tag as u8);
+    tagged.extend_from_slice(key);
+    tagged
+}
+
+/// Load the collection counters checkpointed in the meta keyspace.
+///
+/// The counters are exact only for an empty database or after a clean
+/// shutdown. The clean shutdown marker is consumed so that a crash during this
+/// run is detected on the next open.
+fn load_stats(
+    db: &OptimisticTxDatabase,
+    primary: &OptimisticTxKeyspace,
+    meta: &OptimisticTxKeyspace,
+) -> Result<StatsTracker, EngineError> {
+    let rtx = db.read_tx();
+    let prefix = keys::system_prefix(STATS_NAMESPACE);
+
+    let mut counters = HashMap::new();
+    for guard in rtx.prefix(meta, &prefix) {
+        let (key, value) = guard.into_inner()?;
+        if let (Some((_, collection_id)), Some(c)) =
+            (keys::decode_system(&key), Counters::decode(&value))
+        {
+            counters.insert(collection_id.to_string(), c);
+        }
+    }
+
+    let clean_shutdown = rtx.get(meta, CLEAN_SHUTDOWN_KEY)?.is_some();
+    let exact = clean_shutdown || rtx.is_empty(primary)?;
+
+    if clean_shutdown {
+        let mut wtx = db.write_tx()?;
+        wtx.remove(meta, CLEAN_SHUTDOWN_KEY);
+        wtx.commit()?
+            .map_err(|_| EngineError::TransactionConflict)?;
+        db.persist(PersistMode::SyncAll)?;
+    }
+
+    Ok(StatsTracker::new(counters, exact))
+}
+
+/// Journal key of a job, jobs of different kinds may share an ID.
+fn job_key(kind: &str, job_id: &str) -> Result<Vec<u8>, KeyError> {
+    keys::system(JOB_OPERATION, &format!("{kind}.{job_id}"))
+}
+
+/// Journal entry layout: the next step number, then the job state.
+fn encode_job_checkpoint(checkpoint: &JobCheckpoint) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(8 + checkpoint.state.len());
+    entry.extend_from_slice(&checkpoint.next_step.to_be_bytes());
+    entry.extend_from_slice(&checkpoint.state);
+    entry
+}
+
+fn decode_job_checkpoint(entry: &[u8]) -> JobCheckpoint {
+    match entry.split_first_chunk::<8>() {
+        Some((step, state)) => JobCheckpoint {
+            next_step: u64::from_be_bytes(*step),
+            state: state.to_vec(),
+        },
+        None => JobCheckpoint::default(),
+    }
+}
+
+/// Idempotency entry layout: expiry in milliseconds since the epoch, then
+/// the record of the created document, sealed like the document itself.
+fn encode_idempotency_entry(expires_at: SystemTime, record: &[u8]) -> Vec<u8> {
+    let millis = expires_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let mut entry = Vec::with_capacity(8 + record.len());
+    entry.extend_from_slice(&millis.to_be_bytes());
+    entry.extend_from_slice(record);
+    entry
+}
+
+fn decode_idempotency_entry(entry: &[u8]) -> Option<(SystemTime, &[u8])> {
+    let (millis, record) = entry.split_first_chunk::<8>()?;
+    let expiry = SystemTime::UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(*millis));
+    Some((expiry, record))
+}
+
+/// History entry layout: when the revision was replaced or deleted, in
+/// milliseconds since the epoch as `u64 BE`, then its record.
+fn encode_history_entry(superseded_at: SystemTime, record: &[u8]) -> Vec<u8> {
+    let millis = superseded_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let mut entry = Vec::with_capacity(8 + record.len());
+    entry.extend_from_slice(&millis.to_be_bytes());
+    entry.extend_from_slice(record);
+    entry
+}
+
+fn decode_history_entry(entry: &[u8]) -> Result<(SystemTime, &[u8]), RecordError> {
+    let (millis, record) = entry
+        .split_first_chunk::<8>()
+        .ok_or(RecordError::Truncated { len: entry.len() })?;
+    let superseded_at = SystemTime::UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(*millis));
+    Ok((superseded_at, record))
+}
+
+/// Delete a segment nothing points to anymore, failing only leaks it.
+fn delete_segment(store: &dyn ArchiveStore, segment: &str) {
+    if let Err(e) = store.delete(segment) {
+        tracing::warn!(segment, error = %e, "failed to delete archive segment");
+    }
+}
+
+/// Fail if a document of `size` bytes is larger than a record can carry,
+/// before it reaches storage.
+fn check_document_size(size: usize) -> Result<(), EngineError> {
+    if size > record::MAX_PAYLOAD_LEN {
+        return Err(EngineError::DocumentTooLarge {
+            size,
+            max: record::MAX_PAYLOAD_LEN,
+        });
+    }
+    Ok(())
+}
+
+/// Share of `disk_space` taken by `bytes` of the `total_bytes` of payloads.
+fn disk_share(disk_space: u64, bytes: u64, total_bytes: u64) -> u64 {
+    if total_bytes == 0 {
+        return 0;
+    }
+    (u128::from(disk_space) * u128::from(bytes.min(total_bytes)) / u128::from(total_bytes)) as u64
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::DirStore;
+    use crate::cipher::{KEY_LEN, StaticKeys};
+
+    fn test_engine() -> Engine {
+        let dir = tempfile::tempdir().unwrap();
+        Engine::open(dir.path()).unwrap()
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Engine::open(dir.path()).unwrap();
+        let rtx = engine.db.read_tx();
+        assert!(rtx.is_empty(&engine.primary).unwrap());
+    }
+
+    #[test]
+    fn test_memory_budget_flushes_memtables() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = EngineOptions {
+            memory_budget: 64 * 1024,
+        };
+        let engine = Engine::open_with_options(dir.path(), options).unwrap();
+        assert_eq!(engine.memory().budget(), MemoryBudget::split(64 * 1024));
+
+        let data = [7; 1024];
+        for i in 0..32 {
+            engine
+                .create_document("users", &format!("user-{i}"), &data)
+                .unwrap();
+        }
+        assert!(
+            engine
+                .memory()
+                .render()
+                .contains("zerotable_memtable_flushes_total 2\n")
+        );
+        assert!(engine.memory().memtable_bytes() < 16 * 1024);
+        let doc = engine.get_document("users", "user-0").unwrap();
+        assert_eq!(doc.data, data);
+    }
+
+    #[test]
+    fn test_upgrade_untagged_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            // write the database as version 0 did
+            let engine = Engine::open(dir.path()).unwrap();
+            let header = RecordHeader {
+                sequence: 1,
+                write_time: SystemTime::UNIX_EPOCH,
+            };
+            let config = CollectionConfig {
+                write_lock: true,
+                ..Default::default()
+            };
+            let mut wtx = engine.db.write_tx().unwrap();
+            wtx.remove(&engine.meta, KEY_FORMAT_KEY);
+            wtx.insert(
+                &engine.primary,
+                b"users\x00a".as_slice(),
+                record::encode(&header, b"alice"),
+            );
+            // starting with the tag of document keys
+            wtx.insert(
+                &engine.primary,
+                b"\x01x\x00a".as_slice(),
+                record::encode(&header, b"x"),
+            );
+            wtx.insert(&engine.meta, b"config\x00users".as_slice(), config.encode());
+            wtx.insert(
+                &engine.meta,
+                b"sequence_lease".as_slice(),
+                5000u64.to_be_bytes(),
+            );
+            wtx.commit().unwrap().unwrap();
+        }
+
+        let engine = Engine::open(dir.path()).unwrap();
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice");
+        assert_eq!(engine.get_document("\x01x", "a").unwrap().data, b"x");
+        assert!(engine.collection_config("users").unwrap().write_lock);
+        engine.create_document("orders", "a", b"1").unwrap();
+        assert!(engine.get_document("orders", "a").unwrap().sequence > 5000);
+
+        let rtx = engine.db.read_tx();
+        for guard in rtx.range(
+            &engine.primary,
+            (Bound::<Vec<u8>>::Unbounded, Bound::Unbounded),
+        ) {
+            let (key, _) = guard.into_inner().unwrap();
+            assert_eq!(Tag::of(&key), Some(Tag::Document));
+        }
+    }
+
+    #[test]
+    fn test_resume_key_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            // interrupted after upgrading the first document
+            let engine = Engine::open(dir.path()).unwrap();
+            let header = RecordHeader {
+                sequence: 1,
+                write_time: SystemTime::UNIX_EPOCH,
+            };
+            let mut wtx = engine.db.write_tx().unwrap();
+            wtx.remove(&engine.meta, KEY_FORMAT_KEY);
+            wtx.insert(
+                &engine.primary,
+                b"\x01users\x00a".as_slice(),
+                record::encode(&header, b"alice"),
+            );
+            wtx.insert(
+                &engine.primary,
+                b"users\x00b".as_slice(),
+                record::encode(&header, b"bob"),
+            );
+            for name in ["primary", "meta", "operations", "audit"] {
+                wtx.insert(
+                    &engine.meta,
+                    keys::system(KEY_UPGRADE_NAMESPACE, name).unwrap(),
+                    Vec::<u8>::new(),
+                );
+            }
+            wtx.insert(
+                &engine.meta,
+                keys::system(KEY_UPGRADE_NAMESPACE, "primary").unwrap(),
+                b"users\x00a".as_slice(),
+            );
+            wtx.commit().unwrap().unwrap();
+        }
+
+        let engine = Engine::open(dir.path()).unwrap();
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice");
+        assert_eq!(engine.get_document("users", "b").unwrap().data, b"bob");
+
+        let rtx = engine.db.read_tx();
+        let progress = keys::system_prefix(KEY_UPGRADE_NAMESPACE);
+        assert_eq!(rtx.prefix(&engine.meta, &progress).count(), 0);
+        let keys: Vec<_> = rtx
+            .range(
+                &engine.primary,
+                (Bound::<Vec<u8>>::Unbounded, Bound::Unbounded),
+            )
+            .map(|guard| guard.into_inner().unwrap().0.to_vec())
+            .collect();
+        assert_eq!(keys, [&b"\x01users\x00a"[..], &b"\x01users\x00b"[..]]);
+    }
+
+    #[test]
+    fn test_newer_key_format() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let engine = Engine::open(dir.path()).unwrap();
+            let mut wtx = engine.db.write_tx().unwrap();
+            wtx.insert(&engine.meta, KEY_FORMAT_KEY, [keys::FORMAT_VERSION + 1]);
+            wtx.commit().unwrap().unwrap();
+        }
+        assert!(matches!(
+            Engine::open(dir.path()),
+            Err(EngineError::UnsupportedKeyFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_and_get() {
+        let engine = test_engine();
+        let data = b"hello world";
+
+        engine.create_document("users", "doc1", data).unwrap();
+        let result = engine.get_document("users", "doc1").unwrap();
+
+        assert_eq!(result.data, data);
+        assert_eq!(result.size, data.len());
+    }
+
+    #[test]
+    fn test_sequence_increases() {
+        let engine = test_engine();
+
+        engine.create_document("users", "doc1", b"a").unwrap();
+        engine.create_document("users", "doc2", b"b").unwrap();
+
+        let first = engine.get_document("users", "doc1").unwrap();
+        let second = engine.get_document("users", "doc2").unwrap();
+        assert!(second.sequence > first.sequence);
+    }
+
+    #[test]
+    fn test_sequence_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let before = {
+            let engine = Engine::open(dir.path()).unwrap();
+            engine.create_document("users", "doc1", b"a").unwrap();
+            engine.get_document("users", "doc1").unwrap().sequence
+        };
+
+        let engine = Engine::open(dir.path()).unwrap();
+        engine.create_document("users", "doc2", b"b").unwrap();
+        let after = engine.get_document("users", "doc2").unwrap().sequence;
+
+        assert!(after > before);
+    }
+
+    #[test]
+    fn test_create_already_exists() {
+        let engine = test_engine();
+
+        engine.create_document("users", "doc1", b"data").unwrap();
+        let err = engine
+            .create_document("users", "doc1", b"other")
+            .unwrap_err();
+
+        assert!(matches!(err, EngineError::AlreadyExists));
+    }
+
+    #[test]
+    fn test_get_not_found() {
+        let engine = test_engine();
+        let err = engine.get_document("users", "missing").unwrap_err();
+
+        assert!(matches!(err, EngineError::NotFound));
+    }
+
+    #[test]
+    fn test_delete() {
+        let engine = test_engine();
+
+        engine.create_document("users", "doc1", b"data").unwrap();
+        let (previous, _) = engine.delete_document("users", "doc1").unwrap();
+        assert_eq!(previous, b"data");
+
+        let err = engine.get_document("users", "doc1").unwrap_err();
+        assert!(matches!(err, EngineError::NotFound));
+    }
+
+    #[test]
+    fn test_rewrite_range() {
+        let engine = test_engine();
+        for (doc_id, data) in [("a", b"1"), ("b", b"2"), ("c", b"3"), ("d", b"4")] {
+            engine.create_document("users", doc_id, data).unwrap();
+        }
+        let rewrite = |doc_id: &str, data: &[u8]| -> Result<Rewrite, ()> {
+            Ok(match doc_id {
+                "a" => Rewrite::Delete,
+                "b" => Rewrite::Keep,
+                _ => Rewrite::Replace([data, data].concat()),
+            })
+        };
+
+        let progress = engine
+            .rewrite_range("users", None, 3, &Deadline::none(), rewrite)
+            .unwrap()
+            .unwrap();
+        assert_eq!(progress.changed, 2);
+        assert_eq!(progress.next.as_deref(), Some("d"));
+        assert!(engine.get_document("users", "a").is_err());
+        assert_eq!(engine.get_document("users", "b").unwrap().data, b"2");
+        assert_eq!(engine.get_document("users", "c").unwrap().data, b"33");
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 3);
+
+        let progress = engine
+            .rewrite_range("users", Some("d"), 3, &Deadline::none(), rewrite)
+            .unwrap()
+            .unwrap();
+        assert_eq!(progress.next, None);
+        assert_eq!(engine.get_document("users", "d").unwrap().data, b"44");
+
+        let failed = engine
+            .rewrite_range("users", None, 3, &Deadline::none(), |_, _| Err("stop"))
+            .unwrap();
+        assert_eq!(failed, Err("stop"));
+        assert_eq!(engine.get_document("users", "c").unwrap().data, b"33");
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"1").unwrap();
+        engine.create_document("users", "b", b"2").unwrap();
+
+        let writes = [
+            ("users", "a", Rewrite::Replace(b"11".to_vec())),
+            ("users", "b", Rewrite::Delete),
+            ("users", "c", Rewrite::Replace(b"3".to_vec())),
+            ("orders", "o1", Rewrite::Replace(b"order".to_vec())),
+            ("users", "c", Rewrite::Keep),
+            ("users", "d", Rewrite::Delete),
+            ("users", "e", Rewrite::Replace(b"5".to_vec())),
+        ];
+        let documents: Vec<_> = writes
+            .iter()
+            .map(|(collection_id, doc_id, _)| (*collection_id, *doc_id))
+            .collect();
+        let outcomes = engine
+            .write_batch(&documents, |index, current| match (index, current) {
+                // created by a write before it
+                (4, Some(b"3")) => Ok(Rewrite::Keep),
+                (4, _) => Err("c is missing"),
+                (5, None) => Err("d is missing"),
+                (6, _) => Err("refused"),
+                _ => Ok(writes[index].2.clone()),
+            })
+            .unwrap();
+        assert_eq!(
+            outcomes,
+            [
+                Ok(Some(3)),
+                Ok(Some(3)),
+                Ok(Some(3)),
+                Ok(Some(1)),
+                Ok(None),
+                Err("d is missing"),
+                Err("refused"),
+            ]
+        );
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"11");
+        assert!(engine.get_document("users", "b").is_err());
+        assert_eq!(engine.get_document("users", "c").unwrap().data, b"3");
+        assert!(engine.get_document("users", "e").is_err());
+        assert_eq!(engine.get_document("orders", "o1").unwrap().data, b"order");
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!((stats.document_count, stats.size_bytes), (2, 3));
+    }
+
+    #[test]
+    fn test_delete_not_found() {
+        let engine = test_engine();
+        let err = engine.delete_document("users", "missing").unwrap_err();
+
+        assert!(matches!(err, EngineError::NotFound));
+    }
+
+    #[test]
+    fn test_persist() {
+        let engine = test_engine();
+        engine.create_document("users", "doc1", b"data").unwrap();
+        engine.persist().unwrap();
+    }
+
+    #[test]
+    fn test_collection_stats() {
+        let engine = test_engine();
+
+        engine.create_document("users", "doc1", b"12345").unwrap();
+        engine.create_document("users", "doc2", b"123").unwrap();
+        engine.create_document("orders", "doc1", b"1").unwrap();
+        engine.delete_document("users", "doc2").unwrap();
+
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!(stats.document_count, 1);
+        assert_eq!(stats.size_bytes, 5);
+        assert!(stats.exact);
+
+        let orders = engine.collection_stats("orders").unwrap();
+        let disk_space = engine.keyspace_stats()[0].disk_space;
+        assert!(stats.disk_bytes + orders.disk_bytes <= disk_space);
+        assert!(stats.disk_bytes >= orders.disk_bytes);
+        assert_eq!(disk_share(1000, 1, 4), 250);
+        assert_eq!(disk_share(1000, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_largest_documents() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"12345").unwrap();
+        engine.create_document("users", "b", b"123").unwrap();
+        engine.create_document("users", "c", b"1234").unwrap();
+        engine.delete_document("users", "c").unwrap();
+        engine
+            .move_document(("users", "a"), ("archive", "a"), |d| Some(d.to_vec()))
+            .unwrap();
+
+        let largest = engine.largest_documents("users").unwrap();
+        assert_eq!(
+            largest,
+            [DocumentSize {
+                doc_id: "b".to_string(),
+                size_bytes: 3
+            }]
+        );
+        let archive = engine.largest_documents("archive").unwrap();
+        assert_eq!(archive[0].size_bytes, 5);
+        assert!(engine.largest_documents("").is_err());
+    }
+
+    #[test]
+    fn test_collection_stats_after_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let engine = Engine::open(dir.path()).unwrap();
+            engine.create_document("users", "doc1", b"12345").unwrap();
+            engine.close().unwrap();
+            assert!(matches!(
+                engine.create_document("users", "doc2", b"1"),
+                Err(EngineError::ReadOnly)
+            ));
+        }
+        let engine = Engine::open(dir.path()).unwrap();
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!(stats.document_count, 1);
+        assert!(stats.exact);
+        drop(engine);
+
+        // no clean shutdown this time
+        let engine = Engine::open(dir.path()).unwrap();
+        assert!(!engine.collection_stats("users").unwrap().exact);
+    }
+
+    #[test]
+    fn test_create_invalid_key() {
+        let engine = test_engine();
+        let err = engine.create_document("", "doc1", b"data").unwrap_err();
+
+        assert!(matches!(err, EngineError::InvalidKey(KeyError::EmptyId)));
+    }
+
+    #[test]
+    fn test_create_documents() {
+        let engine = test_engine();
+        engine.create_document("orders", "o0", b"0").unwrap();
+
+        let sequences = engine
+            .create_documents(&[
+                ("orders", "o1", b"order".as_slice()),
+                ("order_items", "o1-1", b"item".as_slice()),
+                ("order_items", "o1-2", b"item".as_slice()),
+            ])
+            .unwrap();
+        assert_eq!(sequences, [2, 1, 1]);
+        assert_eq!(
+            engine.get_document("order_items", "o1-2").unwrap().data,
+            b"item"
+        );
+        assert_eq!(
+            engine
+                .collection_stats("order_items")
+                .unwrap()
+                .document_count,
+            2
+        );
+
+        // nothing is written if one of them exists, or is named twice
+        for other in ["o1", "o2"] {
+            let documents = [
+                ("orders", "o2", b"2".as_slice()),
+                ("orders", other, b"2".as_slice()),
+            ];
+            assert!(matches!(
+                engine.create_documents(&documents),
+                Err(EngineError::AlreadyExists)
+            ));
+            assert!(matches!(
+                engine.get_document("orders", "o2"),
+                Err(EngineError::NotFound)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_document_size() {
+        assert!(check_document_size(record::MAX_PAYLOAD_LEN).is_ok());
+        assert!(matches!(
+            check_document_size(record::MAX_PAYLOAD_LEN + 1),
+            Err(EngineError::DocumentTooLarge { .. })
+        ));
+    }
+
+    fn import_doc(doc_id: &str, data: &[u8]) -> ImportDocument {
+        ImportDocument {
+            collection_id: "users".to_string(),
+            doc_id: doc_id.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_import_chunks_in_order() {
+        let engine = test_engine();
+
+        let progress = engine
+            .apply_import_chunk(
+                "job",
+                0,
+                &[import_doc("a", b"1")],
+                ConflictPolicy::Fail,
+                &Deadline::none(),
+            )
+            .unwrap();
+        assert_eq!(progress.next_sequence, 1);
+        assert_eq!(progress.written, 1);
+
+        engine
+            .apply_import_chunk(
+                "job",
+                1,
+                &[import_doc("b", b"2")],
+                ConflictPolicy::Fail,
+                &Deadline::none(),
+            )
+            .unwrap();
+
+        assert_eq!(engine.import_progress("job").unwrap(), 2);
+        assert_eq!(engine.get_document("users", "b").unwrap().data, b"2");
+    }
+
+    #[test]
+    fn test_import_replayed_chunk_is_noop() {
+        let engine = test_engine();
+        let chunk = [import_doc("a", b"1")];
+
+        engine
+            .apply_import_chunk("job", 0, &chunk, ConflictPolicy::Fail, &Deadline::none())
+            .unwrap();
+        let progress = engine
+            .apply_import_chunk("job", 0, &chunk, ConflictPolicy::Fail, &Deadline::none())
+            .unwrap();
+
+        assert_eq!(progress.next_sequence, 1);
+        assert_eq!(progress.written, 0);
+    }
+
+    #[test]
+    fn test_import_gap_rejected() {
+        let engine = test_engine();
+        let err = engine
+            .apply_import_chunk(
+                "job",
+                3,
+                &[import_doc("a", b"1")],
+                ConflictPolicy::Fail,
+                &Deadline::none(),
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            EngineError::OutOfSequence {
+                expected: 0,
+                got: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn test_import_conflict_policies() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"old").unwrap();
+
+        let progress = engine
+            .apply_import_chunk(
+                "skip",
+                0,
+                &[import_doc("a", b"new")],
+                ConflictPolicy::Skip,
+                &Deadline::none(),
+            )
+            .unwrap();
+        assert_eq!(progress.skipped, 1);
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"old");
+
+        let err = engine
+            .apply_import_chunk(
+                "fail",
+                0,
+                &[import_doc("a", b"new")],
+                ConflictPolicy::Fail,
+                &Deadline::none(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, EngineError::AlreadyExists));
+        assert_eq!(engine.import_progress("fail").unwrap(), 0);
+
+        engine
+            .apply_import_chunk(
+                "over",
+                0,
+                &[import_doc("a", b"new")],
+                ConflictPolicy::Overwrite,
+                &Deadline::none(),
+            )
+            .unwrap();
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"new");
+    }
+
+    #[test]
+    fn test_import_stops_at_deadline() {
+        let engine = test_engine();
+        let deadline = Deadline::none();
+        deadline.cancel();
+
+        let err = engine
+            .apply_import_chunk(
+                "job",
+                0,
+                &[import_doc("a", b"1")],
+                ConflictPolicy::Fail,
+                &deadline,
+            )
+            .unwrap_err();
+        assert!(matches!(err, EngineError::DeadlineExceeded));
+        assert_eq!(engine.import_progress("job").unwrap(), 0);
+        assert!(matches!(
+            engine.get_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_get_many() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"1").unwrap();
+        engine.create_document("orders", "b", b"2").unwrap();
+
+        let results = engine
+            .get_many(&[
+                ("orders", "b"),
+                ("users", "missing"),
+                ("users", ""),
+                ("users", "a"),
+            ])
+            .unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().data, b"2");
+        assert!(matches!(results[1], Err(EngineError::NotFound)));
+        assert!(matches!(results[2], Err(EngineError::InvalidKey(_))));
+        assert_eq!(results[3].as_ref().unwrap().data, b"1");
+    }
+
+    #[test]
+    fn test_create_document_once() {
+        let engine = test_engine();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+
+        let first = engine
+            .create_document_once("users", "a", b"first", "token", expires_at)
+            .unwrap();
+        // a retry with a fresh id still gets the original document back
+        let retry = engine
+            .create_document_once("users", "b", b"retry", "token", expires_at)
+            .unwrap();
+
+        assert_eq!(first, (b"first".to_vec(), Some(1)));
+        assert_eq!(retry, (b"first".to_vec(), None));
+        assert!(matches!(
+            engine.get_document("users", "b"),
+            Err(EngineError::NotFound)
+        ));
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 1);
+    }
+
+    #[test]
+    fn test_idempotency_keys_per_database() {
+        let engine = test_engine();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+
+        let acme = engine
+            .create_document_once("acme/users", "a", b"acme", "token", expires_at)
+            .unwrap();
+        let beta = engine
+            .create_document_once("beta/users", "a", b"beta", "token", expires_at)
+            .unwrap();
+        assert_eq!(acme, (b"acme".to_vec(), Some(1)));
+        assert_eq!(beta, (b"beta".to_vec(), Some(1)));
+        assert_eq!(
+            engine.get_document("beta/users", "a").unwrap().data,
+            b"beta"
+        );
+
+        // another collection of the same database is kept apart too
+        let orders = engine
+            .create_document_once("acme/orders", "a", b"order", "token", expires_at)
+            .unwrap();
+        assert_eq!(orders, (b"order".to_vec(), Some(1)));
+    }
+
+    #[test]
+    fn test_expired_idempotency_key() {
+        let engine = test_engine();
+        let expired = SystemTime::now() - Duration::from_secs(1);
+
+        engine
+            .create_document_once("users", "a", b"1", "token", expired)
+            .unwrap();
+        let second = engine
+            .create_document_once("users", "b", b"2", "token", expired)
+            .unwrap();
+        assert_eq!(second.0, b"2");
+
+        engine
+            .create_document_once("orders", "a", b"1", "token", expired)
+            .unwrap();
+        let now = SystemTime::now();
+        assert_eq!(engine.purge_idempotency_keys(now, 1).unwrap(), 1);
+        assert_eq!(engine.purge_idempotency_keys(now, 10).unwrap(), 1);
+        assert_eq!(engine.purge_idempotency_keys(now, 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_scan_merged() {
+        let engine = test_engine();
+        engine.create_document("a", "2", b"a2").unwrap();
+        engine.create_document("a", "4", b"a4").unwrap();
+        engine.create_document("b", "1", b"b1").unwrap();
+        engine.create_document("b", "2", b"b2").unwrap();
+        engine.create_document("c", "3", b"c3").unwrap();
+
+        let mut seen = Vec::new();
+        engine
+            .scan_merged(
+                &["a", "b"],
+                &Deadline::none(),
+                |collection_id, doc_id, _| {
+                    seen.push(format!("{collection_id}/{doc_id}"));
+                    ControlFlow::Continue(())
+                },
+            )
+            .unwrap();
+        assert_eq!(seen, ["b/1", "a/2", "b/2", "a/4"]);
+
+        let mut first = None;
+        engine
+            .scan_merged(&["a", "b"], &Deadline::none(), |_, _, doc| {
+                first = Some(doc.data);
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!(first.unwrap(), b"b1");
+    }
+
+    fn put(doc_id: &str, data: &[u8]) -> JobWrite {
+        JobWrite::Put {
+            collection_id: "users".to_string(),
+            doc_id: doc_id.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_job_steps() {
+        let engine = test_engine();
+        assert_eq!(engine.job_checkpoint("backfill", "j").unwrap(), None);
+
+        assert!(
+            engine
+                .commit_job_step("backfill", "j", 0, b"a", &[put("a", b"1"), put("b", b"2")])
+                .unwrap()
+        );
+        let delete = JobWrite::Delete {
+            collection_id: "users".to_string(),
+            doc_id: "a".to_string(),
+        };
+        assert!(
+            engine
+                .commit_job_step("backfill", "j", 1, b"b", &[delete])
+                .unwrap()
+        );
+
+        assert_eq!(
+            engine.job_checkpoint("backfill", "j").unwrap(),
+            Some(JobCheckpoint {
+                next_step: 2,
+                state: b"b".to_vec(),
+            })
+        );
+        assert!(matches!(
+            engine.get_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!(stats.document_count, 1);
+        assert_eq!(stats.size_bytes, 1);
+
+        // another kind of job with the same id has its own journal
+        assert_eq!(engine.job_checkpoint("purge", "j").unwrap(), None);
+
+        engine.clear_job("backfill", "j").unwrap();
+        assert_eq!(engine.job_checkpoint("backfill", "j").unwrap(), None);
+    }
+
+    #[test]
+    fn test_job_step_replay_and_gap() {
+        let engine = test_engine();
+        engine
+            .commit_job_step("purge", "j", 0, b"", &[put("a", b"1")])
+            .unwrap();
+
+        assert!(
+            !engine
+                .commit_job_step("purge", "j", 0, b"", &[put("a", b"other")])
+                .unwrap()
+        );
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"1");
+
+        let err = engine
+            .commit_job_step("purge", "j", 5, b"", &[])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            EngineError::OutOfSequence {
+                expected: 1,
+                got: 5
+            }
+        ));
+    }
+
+    #[test]
+    fn test_all_collection_stats() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"1").unwrap();
+        engine.create_document("orders", "a", b"22").unwrap();
+
+        let all = engine.all_collection_stats();
+        let ids: Vec<_> = all.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["orders", "users"]);
+        assert_eq!(all[0].1.size_bytes, 2);
+    }
+
+    #[test]
+    fn test_databases_are_isolated() {
+        let engine = test_engine();
+        let acme = keys::qualify("acme", "users").unwrap();
+        engine.create_document("users", "a", b"1").unwrap();
+        engine.create_document(&acme, "a", b"22").unwrap();
+
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"1");
+        assert_eq!(engine.get_document(&acme, "a").unwrap().data, b"22");
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 1);
+        assert_eq!(engine.collection_stats(&acme).unwrap().size_bytes, 2);
+
+        let mut seen = Vec::new();
+        engine
+            .scan_merged(&["users"], &Deadline::none(), |collection_id, _, _| {
+                seen.push(collection_id.to_string());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(seen, ["users"]);
+
+        engine.delete_document(&acme, "a").unwrap();
+        assert!(engine.get_document("users", "a").is_ok());
+    }
+
+    #[test]
+    fn test_partition_collection() {
+        let engine = test_engine();
+        for i in 0..10 {
+            engine
+                .create_document("users", &format!("doc{i}"), b"x")
+                .unwrap();
+        }
+        engine.create_document("usersx", "other", b"x").unwrap();
+
+        let starts = engine
+            .partition_collection("users", 3, &Deadline::none())
+            .unwrap();
+        assert_eq!(starts, ["doc4", "doc8"]);
+
+        // the ranges cover the collection exactly once
+        let mut bounds: Vec<Option<&str>> = vec![None];
+        bounds.extend(starts.iter().map(|s| Some(s.as_str())));
+        bounds.push(None);
+        let mut seen = Vec::new();
+        for range in bounds.windows(2) {
+            engine
+                .scan_range(
+                    "users",
+                    range[0],
+                    range[1],
+                    &Deadline::none(),
+                    |doc_id, _| {
+                        seen.push(doc_id.to_string());
+                        ControlFlow::Continue(())
+                    },
+                )
+                .unwrap();
+        }
+        let expected: Vec<_> = (0..10).map(|i| format!("doc{i}")).collect();
+        assert_eq!(seen, expected);
+
+        assert!(
+            engine
+                .partition_collection("users", 1, &Deadline::none())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            engine
+                .partition_collection("empty", 4, &Deadline::none())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_scan_snapshot() {
+        let engine = test_engine();
+        engine.create_document("users", "b", b"1").unwrap();
+        engine.create_document("orders", "a", b"2").unwrap();
+        engine.create_document("users", "a", b"3").unwrap();
+        engine.create_document("items", "a", b"4").unwrap();
+
+        let scan = |collections: &[&str]| {
+            let mut seen = Vec::new();
+            engine
+                .scan_snapshot(
+                    collections,
+                    &Deadline::none(),
+                    |collection_id, doc_id, _| {
+                        seen.push(format!("{collection_id}/{doc_id}"));
+                        ControlFlow::Continue(())
+                    },
+                )
+                .unwrap();
+            seen
+        };
+
+        assert_eq!(scan(&[]), ["items/a", "orders/a", "users/a", "users/b"]);
+        assert_eq!(
+            scan(&["users", "orders", "users"]),
+            ["orders/a", "users/a", "users/b"]
+        );
+    }
+
+    #[test]
+    fn test_write_lock() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"1").unwrap();
+        assert_eq!(
+            engine.collection_config("users").unwrap(),
+            CollectionConfig::default()
+        );
+
+        let locked = CollectionConfig {
+            write_lock: true,
+            ..Default::default()
+        };
+        engine.set_collection_config("users", locked).unwrap();
+        assert_eq!(engine.collection_config("users").unwrap(), locked);
+
+        assert!(matches!(
+            engine.create_document("users", "b", b"2"),
+            Err(EngineError::WriteLocked(_))
+        ));
+        assert!(matches!(
+            engine.delete_document("users", "a"),
+            Err(EngineError::WriteLocked(_))
+        ));
+        let chunk = [import_doc("c", b"3")];
+        assert!(matches!(
+            engine.apply_import_chunk("job", 0, &chunk, ConflictPolicy::Skip, &Deadline::none()),
+            Err(EngineError::WriteLocked(_))
+        ));
+        // other collections are not affected
+        engine.create_document("orders", "a", b"1").unwrap();
+
+        engine
+            .set_collection_config("users", CollectionConfig::default())
+            .unwrap();
+        engine.create_document("users", "b", b"2").unwrap();
+    }
+
+    #[test]
+    fn test_strict_queries_config() {
+        let engine = test_engine();
+        let strict = CollectionConfig {
+            strict_queries: true,
+            ..Default::default()
+        };
+        engine.set_collection_config("users", strict).unwrap();
+        assert_eq!(engine.collection_config("users").unwrap(), strict);
+        // strictness does not lock writes
+        engine.create_document("users", "a", b"1").unwrap();
+    }
+
+    #[test]
+    fn test_read_only() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"1").unwrap();
+
+        assert!(!engine.set_read_only(true));
+        let handle = engine.acting_as("admin");
+        assert!(handle.is_read_only());
+        assert!(matches!(
+            handle.create_document("users", "b", b"2"),
+            Err(EngineError::ReadOnly)
+        ));
+        assert!(matches!(
+            engine.delete_document("users", "a"),
+            Err(EngineError::ReadOnly)
+        ));
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"1");
+
+        assert!(engine.set_read_only(false));
+        engine.create_document("users", "b", b"2").unwrap();
+    }
+
+    #[test]
+    fn test_import_unchecked() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"old").unwrap();
+
+        let chunk = [import_doc("a", b"new"), import_doc("b", b"2")];
+        let progress = engine
+            .apply_import_chunk(
+                "job",
+                0,
+                &chunk,
+                ConflictPolicy::Unchecked,
+                &Deadline::none(),
+            )
+            .unwrap();
+        assert_eq!(progress.written, 2);
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"new");
+
+        // the replaced document is counted twice
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!(stats.document_count, 3);
+        assert!(!stats.exact);
+    }
+
+    #[test]
+    fn test_copy_document() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("archive", "taken", b"x").unwrap();
+
+        let copied = engine
+            .copy_document(("users", "a"), ("archive", "a"), |data| {
+                Some([data, b"!"].concat())
+            })
+            .unwrap();
+        assert_eq!(copied, Some((b"alice!".to_vec(), 2)));
+        assert_eq!(engine.get_document("archive", "a").unwrap().data, b"alice!");
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice");
+        assert_eq!(
+            engine.collection_stats("archive").unwrap().document_count,
+            2
+        );
+
+        assert!(matches!(
+            engine.copy_document(("users", "a"), ("archive", "taken"), |d| Some(d.to_vec())),
+            Err(EngineError::AlreadyExists)
+        ));
+        assert!(matches!(
+            engine.copy_document(("users", "b"), ("archive", "b"), |d| Some(d.to_vec())),
+            Err(EngineError::NotFound)
+        ));
+        assert_eq!(
+            engine
+                .copy_document(("users", "a"), ("archive", "c"), |_| None)
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            engine.get_document("archive", "c"),
+            Err(EngineError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_move_document() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+
+        assert!(matches!(
+            engine.move_document(("users", "a"), ("users", "b"), |d| Some(d.to_vec())),
+            Err(EngineError::AlreadyExists)
+        ));
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice");
+
+        engine
+            .move_document(("users", "a"), ("archive", "a"), |d| Some(d.to_vec()))
+            .unwrap();
+        assert!(matches!(
+            engine.get_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+        assert_eq!(engine.get_document("archive", "a").unwrap().data, b"alice");
+
+        let users = engine.collection_stats("users").unwrap();
+        assert_eq!((users.document_count, users.size_bytes), (1, 3));
+        let archive = engine.collection_stats("archive").unwrap();
+        assert_eq!((archive.document_count, archive.size_bytes), (1, 5));
+    }
+
+    #[test]
+    fn test_update_document() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+
+        let updated = engine
+            .update_document("users", "a", |data| Some([data, b"!"].concat()))
+            .unwrap()
+            .unwrap();
+        assert_eq!((updated.0.data.as_slice(), updated.1), (&b"alice!"[..], 2));
+        let header = engine.document_header("users", "a").unwrap().unwrap();
+        assert_eq!(updated.0.sequence, header.sequence);
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice!");
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!(stats.document_count, 1);
+        assert_eq!(stats.size_bytes, 6);
+
+        assert_eq!(
+            engine.update_document("users", "a", |_| None).unwrap(),
+            None
+        );
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice!");
+        assert!(matches!(
+            engine.update_document("users", "b", |d| Some(d.to_vec())),
+            Err(EngineError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_update_document_if() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+        let written = engine.document_header("users", "a").unwrap().unwrap();
+        let replace = |_: &[u8]| Some(b"bob".to_vec());
+
+        let stale = Version::UpdateTime(written.write_time - Duration::from_millis(1));
+        assert!(matches!(
+            engine.update_document_if("users", "a", Some(stale), replace),
+            Err(EngineError::StaleDocument(header)) if header == written
+        ));
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice");
+
+        let updated = engine
+            .update_document_if(
+                "users",
+                "a",
+                Some(Version::UpdateTime(written.write_time)),
+                replace,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!((updated.0.data.as_slice(), updated.1), (&b"bob"[..], 2));
+
+        // the sequence number tells writes of the same millisecond apart
+        let expected = Some(Version::Sequence(written.sequence));
+        assert!(matches!(
+            engine.update_document_if("users", "a", expected, replace),
+            Err(EngineError::StaleDocument(_))
+        ));
+        let rewritten = engine.document_header("users", "a").unwrap().unwrap();
+        let expected = Some(Version::Sequence(rewritten.sequence));
+        assert!(
+            engine
+                .update_document_if("users", "a", expected, replace)
+                .unwrap()
+                .is_some()
+        );
+        assert!(matches!(
+            engine.update_document_if("users", "b", expected, replace),
+            Err(EngineError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_set_document() {
+        let engine = test_engine();
+        let created = engine.set_document("users", "a", b"alice").unwrap();
+        assert_eq!(created, (Action::Create, 1));
+        let replaced = engine.set_document("users", "a", b"bob").unwrap();
+        assert_eq!(replaced, (Action::Replace, 2));
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"bob");
+
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!((stats.document_count, stats.size_bytes), (1, 3));
+    }
+
+    #[test]
+    fn test_list_documents() {
+        let engine = test_engine();
+        for doc_id in ["a", "b", "c", "d"] {
+            engine.create_document("users", doc_id, b"x").unwrap();
+        }
+        engine.create_document("usersx", "a", b"other").unwrap();
+        let list = |after, descending, limit| {
+            let page = engine.list_documents("users", after, descending, limit, &Deadline::none());
+            let ids = page.unwrap().into_iter().map(|(doc_id, _)| doc_id);
+            ids.collect::<Vec<_>>()
+        };
+
+        assert_eq!(list(None, false, 2), ["a", "b"]);
+        assert_eq!(list(Some("b"), false, 2), ["c", "d"]);
+        assert!(list(Some("d"), false, 2).is_empty());
+        assert!(list(None, false, 0).is_empty());
+
+        assert_eq!(list(None, true, 3), ["d", "c", "b"]);
+        assert_eq!(list(Some("b"), true, 3), ["a"]);
+        assert!(list(Some("a"), true, 3).is_empty());
+
+        // the cursor document may have been deleted since
+        engine.delete_document("users", "b").unwrap();
+        assert_eq!(list(Some("b"), false, 10), ["c", "d"]);
+        assert_eq!(list(Some("b"), true, 10), ["a"]);
+    }
+
+    #[test]
+    fn test_run_transaction() {
+        let engine = test_engine();
+        engine.create_document("accounts", "a", b"10").unwrap();
+        let deadline = Deadline::none();
+
+        // a write racing the first run makes its commit conflict
+        let mut runs = 0;
+        let moved = engine
+            .run_transaction(&deadline, |tx| {
+                runs += 1;
+                let balance = tx.get("accounts", "a")?.unwrap();
+                if runs == 1 {
+                    engine.update_document("accounts", "a", |_| Some(b"7".to_vec()))?;
+                }
+                tx.set("accounts", "b", balance.clone())?;
+                tx.delete("accounts", "a")?;
+                assert_eq!(tx.get("accounts", "a")?, None);
+                Ok(balance)
+            })
+            .unwrap();
+        assert_eq!((runs, moved), (2, b"7".to_vec()));
+        assert_eq!(engine.get_document("accounts", "b").unwrap().data, b"7");
+        assert!(matches!(
+            engine.get_document("accounts", "a"),
+            Err(EngineError::NotFound)
+        ));
+        assert_eq!(
+            engine.collection_stats("accounts").unwrap().document_count,
+            1
+        );
+        assert_eq!(engine.collection_sequence("accounts").unwrap(), 3);
+
+        // a failing run writes nothing
+        let failed: Result<(), _> = engine.run_transaction(&deadline, |tx| {
+            tx.set("accounts", "c", b"1".to_vec())?;
+            Err(EngineError::NotFound)
+        });
+        assert!(matches!(failed, Err(EngineError::NotFound)));
+        assert!(engine.document_header("accounts", "c").unwrap().is_none());
+
+        // conflicting every time, it gives up
+        runs = 0;
+        let conflicting = engine.run_transaction(&deadline, |tx| {
+            runs += 1;
+            tx.get("accounts", "b")?;
+            engine.update_document("accounts", "b", |_| Some(b"8".to_vec()))?;
+            tx.set("accounts", "b", b"9".to_vec())
+        });
+        assert!(matches!(conflicting, Err(EngineError::TransactionConflict)));
+        assert_eq!(runs, TRANSACTION_ATTEMPTS);
+    }
+
+    #[test]
+    fn test_durability() {
+        let engine = test_engine();
+        assert_eq!(engine.durability(), Durability::Buffered);
+        let synced = engine.with_durability(Durability::Sync);
+        assert_eq!(synced.durability(), Durability::Sync);
+        assert_eq!(engine.durability(), Durability::Buffered);
+
+        synced.create_document("users", "a", b"alice").unwrap();
+        synced.set_document("users", "a", b"bob").unwrap();
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"bob");
+        engine.sync().unwrap();
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let engine = test_engine()
+            .with_group_commit_window(Duration::from_millis(1))
+            .with_durability(Durability::Sync);
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let engine = engine.clone();
+                std::thread::spawn(move || {
+                    engine.create_document("users", &format!("u{i}"), b"user")?;
+                    engine.delete_document("users", &format!("u{i}"))?;
+                    engine.create_document("users", &format!("v{i}"), b"user")
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+        let stats = engine.collection_stats("users").unwrap();
+        assert_eq!(stats.document_count, 8);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+        let snapshot = engine.snapshot();
+
+        engine
+            .update_document("users", "a", |_| Some(b"ann".to_vec()))
+            .unwrap();
+        engine.delete_document("users", "b").unwrap();
+        engine.create_document("users", "c", b"carol").unwrap();
+
+        assert_eq!(snapshot.get_document("users", "a").unwrap().data, b"alice");
+        let results = snapshot
+            .get_many(&[("users", "b"), ("users", "c")])
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap().data, b"bob");
+        assert!(matches!(results[1], Err(EngineError::NotFound)));
+        let listed = snapshot
+            .list_documents("users", None, false, 10, &Deadline::none())
+            .unwrap();
+        let ids: Vec<_> = listed.iter().map(|(doc_id, _)| doc_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        drop(snapshot);
+
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"ann");
+    }
+
+    #[test]
+    fn test_history() {
+        let engine = test_engine().with_history(Some(Duration::from_secs(3600)));
+        // write times have millisecond precision
+        let tick = || {
+            std::thread::sleep(Duration::from_millis(2));
+            let now = now_millis();
+            std::thread::sleep(Duration::from_millis(2));
+            now
+        };
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+        let before = tick();
+        engine
+            .update_document("users", "a", |_| Some(b"ann".to_vec()))
+            .unwrap();
+        engine.delete_document("users", "b").unwrap();
+        engine.create_document("users", "c", b"carol").unwrap();
+        let after = tick();
+
+        let get = |doc_id: &str, time| match engine.get_document_as_of("users", doc_id, time) {
+            Ok(document) => Some(document.data),
+            Err(EngineError::NotFound) => None,
+            Err(e) => panic!("{e}"),
+        };
+        assert_eq!(get("a", before).as_deref(), Some(&b"alice"[..]));
+        assert_eq!(get("a", after).as_deref(), Some(&b"ann"[..]));
+        assert_eq!(get("b", before).as_deref(), Some(&b"bob"[..]));
+        assert_eq!(get("b", after), None);
+        assert_eq!(get("c", before), None);
+
+        let scan = |time| {
+            let mut documents = Vec::new();
+            engine
+                .scan_as_of("users", time, &Deadline::none(), |doc_id, document| {
+                    documents.push((doc_id.to_string(), document.data));
+                    ControlFlow::Continue(())
+                })
+                .unwrap();
+            documents
+        };
+        let entry = |doc_id: &str, data: &[u8]| (doc_id.to_string(), data.to_vec());
+        assert_eq!(scan(before), [entry("a", b"alice"), entry("b", b"bob")]);
+        assert_eq!(scan(after), [entry("a", b"ann"), entry("c", b"carol")]);
+
+        // the window ends the history
+        let too_old = before - Duration::from_secs(7200);
+        assert!(matches!(
+            engine.get_document_as_of("users", "a", too_old),
+            Err(EngineError::HistoryUnavailable)
+        ));
+        let later = after + Duration::from_secs(7200);
+        assert_eq!(engine.prune_history(later, 1).unwrap(), 1);
+        assert_eq!(engine.prune_history(later, 10).unwrap(), 1);
+        assert_eq!(engine.prune_history(later, 10).unwrap(), 0);
+        assert!(matches!(
+            test_engine().get_document_as_of("users", "a", after),
+            Err(EngineError::HistoryUnavailable)
+        ));
+    }
+
+    #[test]
+    fn test_soft_delete() {
+        let engine = test_engine().with_soft_delete_retention(Duration::from_secs(3600));
+        let config = CollectionConfig {
+            soft_delete: true,
+            ..Default::default()
+        };
+        engine.set_collection_config("users", config).unwrap();
+        assert_eq!(engine.collection_config("users").unwrap(), config);
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+        engine.create_document("orders", "a", b"1").unwrap();
+
+        engine.delete_document("users", "a").unwrap();
+        engine.delete_document("orders", "a").unwrap();
+        engine
+            .rewrite_range("users", None, 10, &Deadline::none(), |_, _| {
+                Ok::<_, ()>(Rewrite::Delete)
+            })
+            .unwrap()
+            .unwrap();
+        // reads skip deleted documents
+        assert!(matches!(
+            engine.get_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+        let listed = engine
+            .list_documents("users", None, false, 10, &Deadline::none())
+            .unwrap();
+        assert!(listed.is_empty());
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 0);
+        let (deleted, _) = engine.get_deleted_document("users", "a").unwrap();
+        assert_eq!(deleted.data, b"alice");
+        // only soft deleting collections keep them
+        assert!(matches!(
+            engine.get_deleted_document("orders", "a"),
+            Err(EngineError::NotFound)
+        ));
+
+        let (restored, sequence) = engine.undelete_document("users", "a").unwrap();
+        assert_eq!(restored.data, b"alice");
+        assert_eq!(sequence, engine.collection_sequence("users").unwrap());
+        assert_eq!(engine.get_document("users", "a").unwrap(), restored);
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 1);
+        assert!(matches!(
+            engine.undelete_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+
+        engine.create_document("users", "b", b"bobby").unwrap();
+        assert!(matches!(
+            engine.undelete_document("users", "b"),
+            Err(EngineError::AlreadyExists)
+        ));
+        // synced and counted like any write
+        let buffered = engine.memory().memtable_bytes();
+        engine
+            .with_durability(Durability::Sync)
+            .purge_document("users", "b")
+            .unwrap();
+        assert!(engine.memory().memtable_bytes() > buffered);
+        assert!(matches!(
+            engine.purge_document("users", "b"),
+            Err(EngineError::NotFound)
+        ));
+
+        engine.delete_document("users", "a").unwrap();
+        engine.delete_document("users", "b").unwrap();
+        let now = now_millis();
+        assert_eq!(engine.purge_deleted(now, 10).unwrap(), 0);
+        let later = now + Duration::from_secs(7200);
+        assert_eq!(engine.purge_deleted(later, 1).unwrap(), 1);
+        assert_eq!(engine.purge_deleted(later, 10).unwrap(), 1);
+        assert_eq!(engine.purge_deleted(later, 10).unwrap(), 0);
+        assert!(matches!(
+            engine.get_deleted_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_delete_time() {
+        let engine = test_engine().with_audit_log(true);
+        let config = CollectionConfig {
+            soft_delete: true,
+            ..Default::default()
+        };
+        engine.set_collection_config("users", config).unwrap();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.delete_document("users", "a").unwrap();
+
+        // the tombstone and the audit entry are of the same write
+        let (deleted, deleted_at) = engine.get_deleted_document("users", "a").unwrap();
+        let entries = engine
+            .audit_entries(None, |_| true, 10, &Deadline::none())
+            .unwrap();
+        assert_eq!(entries[1].action, Action::Delete);
+        assert_eq!(entries[1].time, deleted_at);
+        assert!(deleted.write_time <= deleted_at);
+    }
+
+    #[test]
+    fn test_soft_delete_moves_and_jobs() {
+        let engine = test_engine();
+        let config = CollectionConfig {
+            soft_delete: true,
+            ..Default::default()
+        };
+        engine.set_collection_config("users", config).unwrap();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+
+        // moving a document out deletes it from its collection
+        engine
+            .move_document(("users", "a"), ("archive", "a"), |d| Some(d.to_vec()))
+            .unwrap()
+            .unwrap();
+        let (deleted, _) = engine.get_deleted_document("users", "a").unwrap();
+        assert_eq!(deleted.data, b"alice");
+        let (restored, _) = engine.undelete_document("users", "a").unwrap();
+        assert_eq!(restored.data, b"alice");
+
+        let delete = JobWrite::Delete {
+            collection_id: "users".to_string(),
+            doc_id: "b".to_string(),
+        };
+        assert!(
+            engine
+                .commit_job_step("cleanup", "j", 0, b"", &[delete])
+                .unwrap()
+        );
+        let (restored, _) = engine.undelete_document("users", "b").unwrap();
+        assert_eq!(restored.data, b"bob");
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 2);
+    }
+
+    #[test]
+    fn test_expiry() {
+        // payloads starting with a u64 BE expire at that millisecond
+        fn expiry_of(data: &[u8]) -> Option<SystemTime> {
+            let (millis, _) = data.split_first_chunk::<8>()?;
+            Some(SystemTime::UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(*millis)))
+        }
+        let expiring = |millis: u64| millis.to_be_bytes().to_vec();
+        let at = |millis| SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+        let engine = test_engine().with_expiry(expiry_of);
+        engine
+            .create_document("users", "a", &expiring(1000))
+            .unwrap();
+        engine
+            .create_document("users", "b", &expiring(5000))
+            .unwrap();
+        engine.create_document("users", "c", b"c").unwrap();
+        // the later expiry replaces the earlier one
+        engine
+            .update_document("users", "a", |_| Some(expiring(9000)))
+            .unwrap();
+
+        let deleted = |now, limit| engine.delete_expired(now, None, limit).unwrap().deleted;
+        assert_eq!(deleted(at(2000), 10), 0);
+        assert_eq!(deleted(at(6000), 10), 1);
+        assert!(matches!(
+            engine.get_document("users", "b"),
+            Err(EngineError::NotFound)
+        ));
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 2);
+
+        // write locked collections keep their documents
+        let locked = CollectionConfig {
+            write_lock: true,
+            ..Default::default()
+        };
+        engine.set_collection_config("users", locked).unwrap();
+        assert_eq!(deleted(at(10_000), 10), 0);
+        engine
+            .set_collection_config("users", CollectionConfig::default())
+            .unwrap();
+        assert_eq!(deleted(at(10_000), 10), 1);
+        assert_eq!(engine.get_document("users", "c").unwrap().data, b"c");
+
+        // in batches of `limit`
+        for doc_id in ["d", "e", "f"] {
+            engine
+                .create_document("orders", doc_id, &expiring(1000))
+                .unwrap();
+        }
+        assert_eq!(deleted(at(2000), 2), 2);
+        assert_eq!(deleted(at(2000), 2), 1);
+        assert_eq!(deleted(at(2000), 2), 0);
+
+        // entries of locked collections take their share of a batch, the
+        // next one starts past them
+        engine
+            .create_document("users", "g", &expiring(1000))
+            .unwrap();
+        engine
+            .create_document("users", "h", &expiring(1000))
+            .unwrap();
+        engine
+            .create_document("orders", "i", &expiring(3000))
+            .unwrap();
+        engine.set_collection_config("users", locked).unwrap();
+        let first = engine.delete_expired(at(4000), None, 2).unwrap();
+        assert_eq!(first.deleted, 0);
+        assert!(first.next.is_some());
+        let second = engine
+            .delete_expired(at(4000), first.next.as_deref(), 2)
+            .unwrap();
+        assert_eq!(
+            second,
+            ExpiryProgress {
+                deleted: 1,
+                next: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_counters() {
+        let engine = test_engine();
+        assert_eq!(engine.counter("visits").unwrap(), 0);
+        assert_eq!(
+            engine
+                .increment_counter("visits", 5, &Deadline::none())
+                .unwrap(),
+            5
+        );
+        assert_eq!(
+            engine
+                .increment_counter("visits", -7, &Deadline::none())
+                .unwrap(),
+            -2
+        );
+        assert_eq!(engine.counter("visits").unwrap(), -2);
+        assert_eq!(engine.counter("stock").unwrap(), 0);
+
+        engine
+            .increment_counter("max", i64::MAX, &Deadline::none())
+            .unwrap();
+        assert!(matches!(
+            engine.increment_counter("max", 1, &Deadline::none()),
+            Err(EngineError::CounterOverflow)
+        ));
+        assert_eq!(engine.counter("max").unwrap(), i64::MAX);
+
+        engine.set_read_only(true);
+        assert!(matches!(
+            engine.increment_counter("visits", 1, &Deadline::none()),
+            Err(EngineError::ReadOnly)
+        ));
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+        let mutation = |kind: &str, doc_id: &str, data: &[u8]| {
+            let (collection_id, doc_id, data) =
+                ("users".to_string(), doc_id.to_string(), data.to_vec());
+            match kind {
+                "create" => Mutation::Create {
+                    collection_id,
+                    doc_id,
+                    data,
+                },
+                "update" => Mutation::Update {
+                    collection_id,
+                    doc_id,
+                    data,
+                },
+                "set" => Mutation::Set {
+                    collection_id,
+                    doc_id,
+                    data,
+                },
+                _ => Mutation::Delete {
+                    collection_id,
+                    doc_id,
+                },
+            }
+        };
+
+        engine
+            .apply_batch(vec![
+                mutation("create", "c", b"carol"),
+                // sees the create before it
+                mutation("update", "c", b"carla"),
+                mutation("set", "a", b"ann"),
+                mutation("delete", "b", b""),
+                mutation("delete", "missing", b""),
+                Mutation::Set {
+                    collection_id: "orders".to_string(),
+                    doc_id: "o".to_string(),
+                    data: b"1".to_vec(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"ann");
+        assert_eq!(engine.get_document("users", "c").unwrap().data, b"carla");
+        assert!(engine.document_header("users", "b").unwrap().is_none());
+        assert_eq!(engine.get_document("orders", "o").unwrap().data, b"1");
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 2);
+
+        // one failing mutation fails the batch
+        let failed = engine.apply_batch(vec![
+            mutation("set", "d", b"dave"),
+            mutation("update", "b", b"bo"),
+        ]);
+        assert!(matches!(failed, Err(EngineError::NotFound)));
+        let failed = engine.apply_batch(vec![
+            mutation("delete", "a", b""),
+            mutation("create", "c", b"carol"),
+        ]);
+        assert!(matches!(failed, Err(EngineError::AlreadyExists)));
+        assert!(engine.document_header("users", "d").unwrap().is_none());
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"ann");
+    }
+
+    #[test]
+    fn test_collection_sequence() {
+        let engine = test_engine();
+        assert_eq!(engine.collection_sequence("users").unwrap(), 0);
+
+        assert_eq!(engine.create_document("users", "a", b"1").unwrap(), 1);
+        assert_eq!(engine.create_document("users", "b", b"2").unwrap(), 2);
+        assert_eq!(engine.create_document("orders", "a", b"1").unwrap(), 1);
+        assert_eq!(engine.delete_document("users", "b").unwrap().1, 3);
+        // failed writes do not use up a number
+        assert!(engine.delete_document("users", "b").is_err());
+
+        // a move between collections is a mutation of both
+        let moved = engine
+            .move_document(("users", "a"), ("orders", "b"), |d| Some(d.to_vec()))
+            .unwrap();
+        assert_eq!(moved, Some((b"1".to_vec(), 2)));
+        assert_eq!(engine.collection_sequence("users").unwrap(), 4);
+
+        // an import is one mutation per collection
+        let chunk = [import_doc("c", b"3"), import_doc("d", b"4")];
+        engine
+            .apply_import_chunk("job", 0, &chunk, ConflictPolicy::Fail, &Deadline::none())
+            .unwrap();
+        assert_eq!(engine.collection_sequence("users").unwrap(), 5);
+        assert_eq!(engine.collection_sequence("orders").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_collection_metadata() {
+        let engine = test_engine();
+        assert!(matches!(
+            engine.collection_metadata("users"),
+            Err(EngineError::NotFound)
+        ));
+
+        let before = now_millis();
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+        engine
+            .update_document("users", "a", |_| Some(b"ann".to_vec()))
+            .unwrap();
+        let users = engine.collection_metadata("users").unwrap();
+        assert!(users.created_at >= before);
+        assert_eq!((users.document_count, users.size_bytes), (2, 6));
+
+        engine
+            .move_document(("users", "b"), ("orders", "b"), |d| Some(d.to_vec()))
+            .unwrap();
+        engine.delete_document("users", "a").unwrap();
+        let users = engine.collection_metadata("users").unwrap();
+        assert_eq!((users.document_count, users.size_bytes), (0, 0));
+        let orders = engine.collection_metadata("orders").unwrap();
+        assert_eq!((orders.document_count, orders.size_bytes), (1, 3));
+
+        // the creation time stays, failed writes change nothing
+        assert!(engine.create_document("orders", "b", b"bob").is_err());
+        engine.create_document("users", "c", b"carol").unwrap();
+        let again = engine.collection_metadata("users").unwrap();
+        assert_eq!(again.created_at, users.created_at);
+        assert_eq!(engine.collection_metadata("orders").unwrap(), orders);
+
+        let chunk = [import_doc("d", b"dave")];
+        engine
+            .apply_import_chunk("job", 0, &chunk, ConflictPolicy::Fail, &Deadline::none())
+            .unwrap();
+        assert_eq!(
+            engine.collection_metadata("users").unwrap().document_count,
+            2
+        );
+        assert!(engine.collection_metadata("").is_err());
+    }
+
+    #[test]
+    fn test_drop_collection() {
+        let engine = test_engine().with_audit_log(true);
+        for i in 0..DROP_BATCH_SIZE + 5 {
+            engine
+                .create_document("users", &format!("{i:05}"), b"x")
+                .unwrap();
+        }
+        engine.create_document("orders", "a", b"1").unwrap();
+
+        let mut batches = Vec::new();
+        let deleted = engine
+            .drop_collection("users", &Deadline::none(), |n| batches.push(n))
+            .unwrap();
+        assert_eq!(deleted, DROP_BATCH_SIZE as u64 + 5);
+        assert_eq!(batches, [DROP_BATCH_SIZE as u64, 5]);
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 0);
+        assert!(matches!(
+            engine.collection_metadata("users"),
+            Err(EngineError::NotFound)
+        ));
+        assert!(engine.get_document("orders", "a").is_ok());
+
+        // dropping again, or an empty collection, deletes nothing
+        assert_eq!(
+            engine
+                .drop_collection("users", &Deadline::none(), |_| {})
+                .unwrap(),
+            0
+        );
+        engine.create_document("users", "a", b"1").unwrap();
+        assert_eq!(
+            engine.collection_metadata("users").unwrap().document_count,
+            1
+        );
+
+        let expired = Deadline::none();
+        expired.cancel();
+        assert!(matches!(
+            engine.drop_collection("users", &expired, |_| {}),
+            Err(EngineError::DeadlineExceeded)
+        ));
+        assert!(engine.get_document("users", "a").is_ok());
+    }
+
+    #[test]
+    fn test_value_compression() {
+        let plain = test_engine();
+        let compressed = plain.clone().with_value_compression(ValueCompression::Zstd);
+        let payload = b"compressible ".repeat(50);
+        plain.create_document("users", "a", &payload).unwrap();
+        compressed.create_document("users", "b", &payload).unwrap();
+
+        let stored = |doc_id| {
+            let key = keys::encode("users", doc_id).unwrap();
+            plain.primary.get(&key).unwrap().unwrap().len()
+        };
+        assert!(stored("a") > payload.len());
+        assert!(stored("b") < payload.len());
+        // records written either way are read alike
+        for engine in [&plain, &compressed] {
+            assert_eq!(engine.get_document("users", "a").unwrap().data, payload);
+            assert_eq!(engine.get_document("users", "b").unwrap().data, payload);
+        }
+        let stats = plain.collection_stats("users").unwrap();
+        assert_eq!(stats.size_bytes, 2 * payload.len() as u64);
+
+        compressed
+            .update_document("users", "a", |data| Some(data.to_vec()))
+            .unwrap();
+        assert!(stored("a") < payload.len());
+        plain.delete_document("users", "b").unwrap();
+        assert_eq!(
+            plain.collection_stats("users").unwrap().size_bytes,
+            payload.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_encryption() {
+        let plain = test_engine();
+        let old_keys = StaticKeys::new([(1, [1; KEY_LEN])]).unwrap();
+        let keys = StaticKeys::new([(1, [1; KEY_LEN]), (2, [2; KEY_LEN])]).unwrap();
+        let engine = plain.clone().with_encryption(Arc::new(old_keys));
+        plain.create_document("users", "a", b"alice").unwrap();
+        engine.create_document("users", "b", b"bob").unwrap();
+
+        let stored = |doc_id| {
+            let key = keys::encode("users", doc_id).unwrap();
+            plain.primary.get(&key).unwrap().unwrap().to_vec()
+        };
+        // sealed under key 1, right after the header
+        assert_eq!(stored("b")[17..21], 1u32.to_be_bytes());
+        assert!(!stored("b").windows(3).any(|w| w == b"bob"));
+        assert!(matches!(
+            plain.get_document("users", "b"),
+            Err(EngineError::Encryption(CipherError::NoKeys))
+        ));
+
+        // rotated, documents are sealed under key 2 as they are written
+        let rotated = plain.clone().with_encryption(Arc::new(keys));
+        assert_eq!(rotated.get_document("users", "a").unwrap().data, b"alice");
+        assert_eq!(rotated.get_document("users", "b").unwrap().data, b"bob");
+        rotated
+            .update_document("users", "b", |data| Some(data.to_vec()))
+            .unwrap();
+        assert_eq!(stored("b")[17..21], 2u32.to_be_bytes());
+        assert!(matches!(
+            engine.get_document("users", "b"),
+            Err(EngineError::Encryption(CipherError::UnknownKey(2)))
+        ));
+        let stats = rotated.collection_stats("users").unwrap();
+        assert_eq!(stats.size_bytes, 8);
+    }
+
+    #[test]
+    fn test_encrypted_audit_log() {
+        let plain = test_engine().with_audit_log(true);
+        let keys = StaticKeys::new([(1, [1; KEY_LEN])]).unwrap();
+        let engine = plain.clone().with_encryption(Arc::new(keys));
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine.delete_document("users", "a").unwrap();
+
+        let rtx = engine.db.read_tx();
+        let stored: Vec<_> = rtx
+            .range(
+                &engine.audit,
+                (Bound::<Vec<u8>>::Unbounded, Bound::Unbounded),
+            )
+            .map(|guard| guard.into_inner().unwrap().1.to_vec())
+            .collect();
+        assert_eq!(stored.len(), 2);
+        assert!(
+            !stored
+                .iter()
+                .any(|value| value.windows(5).any(|w| w == b"alice"))
+        );
+
+        let entries = engine
+            .audit_entries(None, |_| true, 10, &Deadline::none())
+            .unwrap();
+        assert_eq!(entries[0].data, b"alice");
+        assert_eq!(entries[1].data, b"");
+        assert!(matches!(
+            plain.audit_entries(None, |_| true, 10, &Deadline::none()),
+            Err(EngineError::Encryption(CipherError::NoKeys))
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_idempotency_keys() {
+        let plain = test_engine();
+        let provider = StaticKeys::new([(1, [1; KEY_LEN])]).unwrap();
+        let engine = plain.clone().with_encryption(Arc::new(provider));
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        engine
+            .create_document_once("users", "a", b"alice", "token", expires_at)
+            .unwrap();
+
+        let rtx = engine.db.read_tx();
+        let prefix = keys::system_prefix(IDEMPOTENCY_OPERATION);
+        let stored: Vec<_> = rtx
+            .prefix(&engine.operations, &prefix)
+            .map(|guard| guard.into_inner().unwrap().1.to_vec())
+            .collect();
+        assert_eq!(stored.len(), 1);
+        assert!(!stored[0].windows(5).any(|w| w == b"alice"));
+
+        let retry = engine
+            .create_document_once("users", "a", b"alice", "token", expires_at)
+            .unwrap();
+        assert_eq!(retry, (b"alice".to_vec(), None));
+        assert!(matches!(
+            plain.create_document_once("users", "a", b"alice", "token", expires_at),
+            Err(EngineError::Encryption(CipherError::NoKeys))
+        ));
+    }
+
+    #[test]
+    fn test_document_cache() {
+        let engine = test_engine().with_document_cache(1024 * 1024);
+        let cache = engine.cache.clone().unwrap();
+        engine.create_document("users", "a", b"alice").unwrap();
+        assert_eq!(cache.bytes(), 0);
+        let read = engine.get_document("users", "a").unwrap();
+        assert!(cache.bytes() > 0);
+        assert_eq!(engine.get_document("users", "a").unwrap(), read);
+
+        // writes through any handle drop the cached version
+        let other = engine.acting_as("bob");
+        other
+            .update_document("users", "a", |_| Some(b"alice2".to_vec()))
+            .unwrap();
+        assert_eq!(cache.bytes(), 0);
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"alice2");
+        other.delete_document("users", "a").unwrap();
+        assert!(matches!(
+            engine.get_document("users", "a"),
+            Err(EngineError::NotFound)
+        ));
+
+        // missing documents are cached until created
+        assert!(matches!(
+            engine.get_document("users", "b"),
+            Err(EngineError::NotFound)
+        ));
+        assert_eq!(engine.document_header("users", "b").unwrap(), None);
+        assert!(cache.bytes() > 0);
+        other.create_document("users", "b", b"bob").unwrap();
+        assert!(engine.document_header("users", "b").unwrap().is_some());
+        assert_eq!(engine.get_document("users", "b").unwrap().data, b"bob");
+
+        // off by default
+        assert!(test_engine().cache.is_none());
+        assert!(test_engine().with_document_cache(0).cache.is_none());
+    }
+
+    #[test]
+    fn test_document_header() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+
+        let header = engine.document_header("users", "a").unwrap().unwrap();
+        let stored = engine.get_document("users", "a").unwrap();
+        assert_eq!(header.sequence, stored.sequence);
+        assert_eq!(header.write_time, stored.write_time);
+
+        assert_eq!(engine.document_header("users", "b").unwrap(), None);
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let engine = test_engine().with_audit_log(true).acting_as("10.0.0.1");
+        engine.create_document("users", "a", b"alice").unwrap();
+        engine
+            .move_document(("users", "a"), ("archive", "a"), |d| Some(d.to_vec()))
+            .unwrap();
+        engine.delete_document("archive", "a").unwrap();
+
+        let all = engine
+            .audit_entries(None, |_| true, 10, &Deadline::none())
+            .unwrap();
+        let changes: Vec<_> = all
+            .iter()
+            .map(|e| (e.action, e.collection_id.as_str(), e.doc_id.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (Action::Create, "users", "a"),
+                (Action::Create, "archive", "a"),
+                (Action::Delete, "users", "a"),
+                (Action::Delete, "archive", "a"),
+            ]
+        );
+        assert!(all.iter().all(|e| e.actor == "10.0.0.1"));
+        assert_eq!(all[1].data, b"alice");
+        let sequences: Vec<_> = all.iter().map(|e| e.collection_sequence).collect();
+        assert_eq!(sequences, [1, 1, 2, 2]);
+
+        let after = (all[1].sequence, all[1].index);
+        let users = engine
+            .audit_entries(Some(after), |c| c == "users", 10, &Deadline::none())
+            .unwrap();
+        assert_eq!(users, [all[2].clone()]);
+    }
+
+    #[test]
+    fn test_audit_log_disabled() {
+        let engine = test_engine();
+        engine.create_document("users", "a", b"alice").unwrap();
+        assert!(
+            engine
+                .audit_entries(None, |_| true, 10, &Deadline::none())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_archive_collection() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(DirStore::new(dir.path()).unwrap());
+        let engine = test_engine();
+        assert!(matches!(
+            engine.archive_collection("users", None),
+            Err(EngineError::Archive(ArchiveError::Disabled))
+        ));
+
+        let engine = engine.with_archive(store, false);
+        engine.create_document("users", "a", b"1").unwrap();
+        engine.create_document("users", "b", b"2").unwrap();
+        engine.create_document("orders", "a", b"3").unwrap();
+        let written = engine.get_document("users", "a").unwrap().write_time;
+
+        // written since the cutoff
+        assert_eq!(
+            engine.archive_collection("users", Some(written)).unwrap(),
+            None
+        );
+        assert_eq!(engine.archive_collection("empty", None).unwrap(), None);
+        let stub = engine.archive_collection("users", None).unwrap().unwrap();
+        assert_eq!(stub.documents, 2);
+        assert_eq!(
+            engine.archived_collections().unwrap(),
+            [("users".to_string(), stub)]
+        );
+        assert_eq!(engine.collection_stats("users").unwrap().document_count, 2);
+
+        // point reads go to the segment, scans and writes are refused
+        let doc = engine.get_document("users", "b").unwrap();
+        assert_eq!(doc.data, b"2");
+        assert!(engine.document_header("users", "a").unwrap().is_some());
+        assert!(matches!(
+            engine.get_document("users", "c"),
+            Err(EngineError::NotFound)
+        ));
+        let results = engine
+            .get_many(&[
+                ("users", "a"),
+                ("orders", "a"),
+                ("users", "c"),
+                ("users", "b"),
+            ])
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap().data, b"1");
+        assert_eq!(results[1].as_ref().unwrap().data, b"3");
+        assert!(matches!(results[2], Err(EngineError::NotFound)));
+        assert_eq!(results[3].as_ref().unwrap().data, b"2");
+        let scan = engine.scan_range("users", None, None, &Deadline::none(), |_, _| {
+            ControlFlow::Continue(())
+        });
+        assert!(matches!(scan, Err(EngineError::Archived(_))));
+        assert!(matches!(
+            engine.create_document("users", "c", b"4"),
+            Err(EngineError::Archived(_))
+        ));
+        assert!(matches!(
+            engine.archive_collection("users", None),
+            Err(EngineError::Archived(_))
+        ));
+
+        assert_eq!(engine.restore_collection("users").unwrap(), 2);
+        assert_eq!(engine.restore_collection("users").unwrap(), 0);
+        assert!(engine.archived_collections().unwrap().is_empty());
+        let restored = engine.get_document("users", "b").unwrap();
+        assert_eq!(restored, doc);
+        engine.create_document("users", "c", b"4").unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_archive_written_meanwhile() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(DirStore::new(dir.path()).unwrap());
+        let engine = test_engine().with_archive(store, false);
+        engine.create_document("users", "a", b"1").unwrap();
+        let next_sequence = engine.collection_sequence("users").unwrap() + 1;
+        let doc_keys = [keys::encode("users", "a").unwrap()];
+        let stub = Stub {
+            segment: "stale.segment".to_string(),
+            documents: 1,
+            bytes: 0,
+            archived_at: now_millis(),
+        };
+
+        // a write between the snapshot and the commit keeps the collection
+        engine.create_document("users", "b", b"2").unwrap();
+        assert!(
+            !engine
+                .commit_archive("users", next_sequence, &doc_keys, &stub)
+                .unwrap()
+        );
+        assert!(engine.archived_collections().unwrap().is_empty());
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"1");
+    }
+
+    #[test]
+    fn test_archive_rehydrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(DirStore::new(dir.path()).unwrap());
+        let engine = test_engine().with_archive(store, true);
+        engine.create_document("users", "a", b"1").unwrap();
+        engine.create_document("orders", "a", b"2").unwrap();
+
+        let cutoff = now_millis() + Duration::from_secs(1);
+        let archived = engine.archive_idle_collections(cutoff).unwrap();
+        assert_eq!(archived.len(), 2);
+        assert_eq!(engine.get_document("users", "a").unwrap().data, b"1");
+        assert_eq!(engine.archived_collections().unwrap().len(), 1);
+
+        let mut seen = Vec::new();
+        engine
+            .scan_snapshot(&[], &Deadline::none(), |collection_id, doc_id, _| {
+                seen.push(format!("{collection_id}/{doc_id}"));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(seen, ["orders/a", "users/a"]);
+        assert!(engine.archived_collections().unwrap().is_empty());
     }
 }
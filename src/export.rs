@@ -0,0 +1,451 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Chunk framing for document exports and imports.
+//!
+//! Documents are packed into chunks bounded by a byte budget rather than a
+//! document count, so a handful of large documents cannot produce a huge
+//! message. Each chunk is optionally compressed, carries a sequence number and
+//! a CRC32 of its payload, and the stream ends with a manifest summarizing
+//! everything that was sent. A receiver can verify each chunk on arrival and
+//! resume an interrupted transfer from the last sequence number it applied.
+//!
+//! Chunk payload (before compression): `{len: u32 BE}{document}` repeated.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use flate2::Compression as GzipLevel;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+/// Default byte budget of a single chunk (uncompressed).
+pub const DEFAULT_CHUNK_BUDGET: usize = 1024 * 1024;
+
+/// Zstd level used for chunk compression.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression applied to chunk payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn compress(self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), GzipLevel::default());
+                encoder.write_all(&data)?;
+                encoder.finish()
+            }
+            Compression::Zstd => zstd::encode_all(data.as_slice(), ZSTD_LEVEL),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => {
+                let mut out = Vec::new();
+                GzDecoder::new(data).read_to_end(&mut out)?;
+                Ok(out)
+            }
+            Compression::Zstd => zstd::decode_all(data),
+        }
+    }
+}
+
+/// Errors that can occur while reading exported chunks.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The chunk payload does not match its checksum.
+    ChecksumMismatch { sequence: u64 },
+    /// A chunk arrived out of order.
+    OutOfSequence { expected: u64, got: u64 },
+    /// The chunk payload framing is invalid.
+    Malformed { sequence: u64 },
+    /// The received chunks do not add up to what the manifest declares.
+    ManifestMismatch,
+    /// Compression or decompression failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::ChecksumMismatch { sequence } => {
+                write!(f, "checksum mismatch in chunk {sequence}")
+            }
+            ExportError::OutOfSequence { expected, got } => {
+                write!(f, "expected chunk {expected}, got {got}")
+            }
+            ExportError::Malformed { sequence } => write!(f, "malformed chunk {sequence}"),
+            ExportError::ManifestMismatch => write!(f, "chunks do not match manifest"),
+            ExportError::Io(e) => write!(f, "io error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+/// A framed group of documents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Position of the chunk in the stream, starting at 0.
+    pub sequence: u64,
+    pub compression: Compression,
+    /// Number of documents in the chunk.
+    pub document_count: u64,
+    /// CRC32 of `payload`.
+    pub checksum: u32,
+    /// Framed documents, compressed with `compression`.
+    pub payload: Vec<u8>,
+}
+
+impl Chunk {
+    /// Verify the checksum and unpack the documents.
+    pub fn documents(&self) -> Result<Vec<Vec<u8>>, ExportError> {
+        if crc32fast::hash(&self.payload) != self.checksum {
+            return Err(ExportError::ChecksumMismatch {
+                sequence: self.sequence,
+            });
+        }
+
+        let raw = self.compression.decompress(&self.payload)?;
+        let malformed = || ExportError::Malformed {
+            sequence: self.sequence,
+        };
+
+        let mut documents = Vec::new();
+        let mut rest = raw.as_slice();
+        while !rest.is_empty() {
+            let (len, tail) = rest.split_first_chunk::<4>().ok_or_else(malformed)?;
+            let len = u32::from_be_bytes(*len) as usize;
+            if tail.len() < len {
+                return Err(malformed());
+            }
+            documents.push(tail[..len].to_vec());
+            rest = &tail[len..];
+        }
+
+        if documents.len() as u64 != self.document_count {
+            return Err(malformed());
+        }
+        Ok(documents)
+    }
+}
+
+/// Trailer sent after the last chunk of a stream.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub chunk_count: u64,
+    pub document_count: u64,
+    /// Total uncompressed size of all documents in bytes.
+    pub total_bytes: u64,
+    /// CRC32 over the checksums of all chunks, in order.
+    pub checksum: u32,
+}
+
+/// Running totals from which a [`Manifest`] is built.
+#[derive(Default)]
+struct Tally {
+    chunk_count: u64,
+    document_count: u64,
+    total_bytes: u64,
+    hasher: crc32fast::Hasher,
+}
+
+impl Tally {
+    fn add(&mut self, chunk: &Chunk, bytes: u64) {
+        self.chunk_count += 1;
+        self.document_count += chunk.document_count;
+        self.total_bytes += bytes;
+        self.hasher.update(&chunk.checksum.to_be_bytes());
+    }
+
+    fn manifest(self) -> Manifest {
+        Manifest {
+            chunk_count: self.chunk_count,
+            document_count: self.document_count,
+            total_bytes: self.total_bytes,
+            checksum: self.hasher.finalize(),
+        }
+    }
+}
+
+/// Packs documents into chunks bounded by a byte budget.
+pub struct ChunkWriter {
+    budget: usize,
+    compression: Compression,
+    buf: Vec<u8>,
+    documents: u64,
+    doc_bytes: u64,
+    next_sequence: u64,
+    tally: Tally,
+}
+
+impl ChunkWriter {
+    pub fn new(budget: usize, compression: Compression) -> Self {
+        ChunkWriter {
+            budget,
+            compression,
+            buf: Vec::new(),
+            documents: 0,
+            doc_bytes: 0,
+            next_sequence: 0,
+            tally: Tally::default(),
+        }
+    }
+
+    /// Add a document, returning a completed chunk once the budget is reached.
+    ///
+    /// A document larger than the budget is emitted as a chunk on its own.
+    pub fn push(&mut self, document: &[u8]) -> io::Result<Option<Chunk>> {
+        let framed_len = 4 + document.len();
+        let ready = if !self.buf.is_empty() && self.buf.len() + framed_len > self.budget {
+            Some(self.flush()?)
+        } else {
+            None
+        };
+
+        self.buf
+            .extend_from_slice(&(document.len() as u32).to_be_bytes());
+        self.buf.extend_from_slice(document);
+        self.documents += 1;
+        self.doc_bytes += document.len() as u64;
+        Ok(ready)
+    }
+
+    /// Emit the remaining documents, if any, and the manifest trailer.
+    pub fn finish(mut self) -> io::Result<(Option<Chunk>, Manifest)> {
+        let last = if self.buf.is_empty() {
+            None
+        } else {
+            Some(self.flush()?)
+        };
+        Ok((last, self.tally.manifest()))
+    }
+
+    fn flush(&mut self) -> io::Result<Chunk> {
+        let payload = self.compression.compress(std::mem::take(&mut self.buf))?;
+        let chunk = Chunk {
+            sequence: self.next_sequence,
+            compression: self.compression,
+            document_count: self.documents,
+            checksum: crc32fast::hash(&payload),
+            payload,
+        };
+
+        self.tally.add(&chunk, self.doc_bytes);
+        self.next_sequence += 1;
+        self.documents = 0;
+        self.doc_bytes = 0;
+        Ok(chunk)
+    }
+}
+
+/// Verifies a stream of chunks against its manifest.
+#[derive(Default)]
+pub struct ChunkReader {
+    next_sequence: u64,
+    tally: Tally,
+}
+
+impl ChunkReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sequence number of the next expected chunk.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Verify a chunk and unpack its documents.
+    pub fn read(&mut self, chunk: &Chunk) -> Result<Vec<Vec<u8>>, ExportError> {
+        if chunk.sequence != self.next_sequence {
+            return Err(ExportError::OutOfSequence {
+                expected: self.next_sequence,
+                got: chunk.sequence,
+            });
+        }
+
+        let documents = chunk.documents()?;
+        let bytes = documents.iter().map(|d| d.len() as u64).sum();
+        self.tally.add(chunk, bytes);
+        self.next_sequence += 1;
+        Ok(documents)
+    }
+
+    /// Check the received chunks against the manifest trailer.
+    pub fn finish(self, manifest: &Manifest) -> Result<(), ExportError> {
+        if self.tally.manifest() != *manifest {
+            return Err(ExportError::ManifestMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Read the next message of an export file, written prefixed with its
+/// length as a varint, `None` at the end of the file.
+pub fn read_delimited(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = 0u64;
+    // a u64 varint is at most 10 bytes
+    for i in 0..10 {
+        let mut byte = [0];
+        match reader.read_exact(&mut byte) {
+            Err(e) if i == 0 && e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        len |= u64::from(byte[0] & 0x7F) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            // read as it comes, a corrupted length must not allocate it
+            let mut message = Vec::new();
+            reader.take(len).read_to_end(&mut message)?;
+            if message.len() as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            return Ok(Some(message));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "invalid message length",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_all(
+        docs: &[&[u8]],
+        budget: usize,
+        compression: Compression,
+    ) -> (Vec<Chunk>, Manifest) {
+        let mut writer = ChunkWriter::new(budget, compression);
+        let mut chunks = Vec::new();
+        for doc in docs {
+            chunks.extend(writer.push(doc).unwrap());
+        }
+        let (last, manifest) = writer.finish().unwrap();
+        chunks.extend(last);
+        (chunks, manifest)
+    }
+
+    fn read_all(chunks: &[Chunk], manifest: &Manifest) -> Vec<Vec<u8>> {
+        let mut reader = ChunkReader::new();
+        let mut docs = Vec::new();
+        for chunk in chunks {
+            docs.extend(reader.read(chunk).unwrap());
+        }
+        reader.finish(manifest).unwrap();
+        docs
+    }
+
+    #[test]
+    fn test_roundtrip_all_compressions() {
+        let docs: Vec<&[u8]> = vec![b"alpha", b"beta", b"", b"gamma"];
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let (chunks, manifest) = write_all(&docs, 16, compression);
+            assert_eq!(read_all(&chunks, &manifest), docs);
+            assert_eq!(manifest.document_count, 4);
+            assert_eq!(manifest.total_bytes, 14);
+        }
+    }
+
+    #[test]
+    fn test_chunks_split_by_budget() {
+        let docs: Vec<&[u8]> = vec![&[1; 10], &[2; 10], &[3; 10]];
+        let (chunks, manifest) = write_all(&docs, 28, Compression::None);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].document_count, 2);
+        assert_eq!(chunks[1].document_count, 1);
+        assert_eq!(manifest.chunk_count, 2);
+    }
+
+    #[test]
+    fn test_oversized_document_gets_own_chunk() {
+        let docs: Vec<&[u8]> = vec![&[1; 4], &[2; 100], &[3; 4]];
+        let (chunks, _) = write_all(&docs, 16, Compression::None);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].document_count, 1);
+    }
+
+    #[test]
+    fn test_empty_export() {
+        let (chunks, manifest) = write_all(&[], 16, Compression::Gzip);
+
+        assert!(chunks.is_empty());
+        assert_eq!(manifest.chunk_count, 0);
+        assert!(read_all(&chunks, &manifest).is_empty());
+    }
+
+    #[test]
+    fn test_corrupted_chunk() {
+        let (mut chunks, _) = write_all(&[b"alpha"], 16, Compression::None);
+        chunks[0].payload[4] ^= 0xFF;
+
+        let err = ChunkReader::new().read(&chunks[0]).unwrap_err();
+        assert!(matches!(err, ExportError::ChecksumMismatch { sequence: 0 }));
+    }
+
+    #[test]
+    fn test_out_of_sequence() {
+        let docs: Vec<&[u8]> = vec![&[1; 10], &[2; 10]];
+        let (chunks, _) = write_all(&docs, 14, Compression::None);
+
+        let err = ChunkReader::new().read(&chunks[1]).unwrap_err();
+        assert!(matches!(
+            err,
+            ExportError::OutOfSequence {
+                expected: 0,
+                got: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn test_missing_chunk_fails_manifest() {
+        let docs: Vec<&[u8]> = vec![&[1; 10], &[2; 10]];
+        let (chunks, manifest) = write_all(&docs, 14, Compression::None);
+
+        let mut reader = ChunkReader::new();
+        reader.read(&chunks[0]).unwrap();
+        assert!(matches!(
+            reader.finish(&manifest),
+            Err(ExportError::ManifestMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_read_delimited() {
+        let mut file = vec![3, b'a', b'b', b'c', 0];
+        file.extend_from_slice(&[0x80, 0x01]);
+        file.extend_from_slice(&[7; 128]);
+        let mut reader = file.as_slice();
+        assert_eq!(read_delimited(&mut reader).unwrap().unwrap(), b"abc");
+        assert_eq!(read_delimited(&mut reader).unwrap().unwrap(), b"");
+        assert_eq!(read_delimited(&mut reader).unwrap().unwrap(), [7; 128]);
+        assert_eq!(read_delimited(&mut reader).unwrap(), None);
+
+        // truncated message, then truncated length
+        assert!(read_delimited(&mut &[3, b'a'][..]).is_err());
+        assert!(read_delimited(&mut &[0x80][..]).is_err());
+        assert!(read_delimited(&mut &[0xFF; 11][..]).is_err());
+    }
+}
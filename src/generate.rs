@@ -0,0 +1,638 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Synthetic data generator for demos and benchmarks.
+//!
+//! `zerotable generate --collection users --count 1M --schema users.json`
+//! synthesizes documents and bulk-loads them through an `ImportDocuments`
+//! stream in `IMPORT_MODE_BULK`. Document IDs are the zero-padded document
+//! numbers, so a second run with the same count replaces the same
+//! documents. `--database ID` loads them into a database other than the
+//! default one.
+//!
+//! A schema is a JSON object mapping field names to generators:
+//!
+//! - `"name"`, `"first_name"`, `"last_name"` and `"email"`, all describing
+//!   the same made-up person within a document
+//! - `"word"`, `"text"`, `"uuid"` and `"boolean"`
+//! - `"integer"` and `"double"`, optionally bounded as `"integer:18..90"`
+//! - `"timestamp"`, within the last five years
+//! - a nested object, generating a map with the same rules
+//! - an array of one generator, generating up to five values
+//!
+//! Without a schema, documents look like user profiles.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use prost::Message;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::zerotable_client::ZerotableClient;
+use crate::api::v1alpha1::{
+    ArrayValue, Document, ImportDocumentsRequest, ImportMode, MapValue, Value,
+};
+use crate::export::{ChunkWriter, Compression, DEFAULT_CHUNK_BUDGET};
+use crate::keys::{self, DEFAULT_DATABASE};
+use crate::service::chunk_to_proto;
+use crate::{conformance, generate_uuid_v7, name};
+
+/// Most values generated for an array field.
+const MAX_ARRAY_LEN: u64 = 5;
+
+/// Timestamps are generated within this long before now: about five years.
+const TIMESTAMP_RANGE: Duration = Duration::from_secs(5 * 365 * 24 * 60 * 60);
+
+/// Chunks queued for sending while the next ones are generated.
+const CHUNK_QUEUE: usize = 4;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Amara", "Bjarne", "Chen", "Dana", "Edsger", "Fatima", "Grace", "Hiro", "Ines",
+    "Jamal", "Ken", "Leila", "Linus", "Margaret", "Nia", "Omar", "Priya", "Radia", "Sofia", "Tim",
+    "Yuki", "Zoe",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Adeyemi", "Berg", "Costa", "Dijkstra", "Eriksen", "Fischer", "Garcia", "Hopper", "Ivanova",
+    "Kim", "Lovelace", "Moreau", "Nakamura", "Okafor", "Perlman", "Quinn", "Rossi", "Singh",
+    "Thompson", "Varga", "Wang", "Yilmaz",
+];
+
+const WORDS: &[&str] = &[
+    "alpha", "amber", "atlas", "beacon", "cedar", "comet", "delta", "ember", "falcon", "glacier",
+    "harbor", "indigo", "juniper", "kestrel", "lumen", "meadow", "nebula", "orchid", "prism",
+    "quartz", "river", "summit", "tundra", "willow",
+];
+
+const EMAIL_DOMAINS: &[&str] = &["example.com", "example.org", "example.net"];
+
+/// Schema used when none is given.
+const DEFAULT_SCHEMA: &str = r#"{
+    "name": "name",
+    "email": "email",
+    "age": "integer:18..90",
+    "active": "boolean",
+    "created_at": "timestamp",
+    "address": {"city": "word", "zip": "integer:10000..99999"},
+    "tags": ["word"]
+}"#;
+
+/// Errors that can occur while generating documents.
+#[derive(Debug)]
+pub enum GenerateError {
+    /// The command line is invalid.
+    Usage(String),
+    /// The schema file could not be read or is invalid.
+    Schema(String),
+    Connect(tonic::transport::Error),
+    Import(Status),
+    Io(io::Error),
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::Usage(message) => write!(f, "{message}"),
+            GenerateError::Schema(message) => write!(f, "invalid schema: {message}"),
+            GenerateError::Connect(e) => write!(f, "failed to connect: {e}"),
+            GenerateError::Import(status) => write!(f, "import failed: {}", status.message()),
+            GenerateError::Io(e) => write!(f, "failed to write chunk: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+/// Generator of one field value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Name,
+    FirstName,
+    LastName,
+    Email,
+    Word,
+    Text,
+    Uuid,
+    Boolean,
+    Integer { min: i64, max: i64 },
+    Double { min: f64, max: f64 },
+    Timestamp,
+    Map(Vec<(String, Field)>),
+    Array(Box<Field>),
+}
+
+impl Field {
+    fn parse(json: &serde_json::Value) -> Result<Self, String> {
+        match json {
+            serde_json::Value::String(kind) => Self::parse_kind(kind),
+            serde_json::Value::Object(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, field)| Ok((name.clone(), Field::parse(field)?)))
+                    .collect::<Result<_, String>>()?;
+                Ok(Field::Map(fields))
+            }
+            serde_json::Value::Array(items) => match items.as_slice() {
+                [item] => Ok(Field::Array(Box::new(Field::parse(item)?))),
+                _ => Err("an array must hold exactly one generator".to_string()),
+            },
+            _ => Err(format!("unknown generator {json}")),
+        }
+    }
+
+    fn parse_kind(kind: &str) -> Result<Self, String> {
+        let (kind, range) = match kind.split_once(':') {
+            Some((kind, range)) => (kind, Some(range)),
+            None => (kind, None),
+        };
+        let field = match kind {
+            "name" => Field::Name,
+            "first_name" => Field::FirstName,
+            "last_name" => Field::LastName,
+            "email" => Field::Email,
+            "word" => Field::Word,
+            "text" => Field::Text,
+            "uuid" => Field::Uuid,
+            "boolean" => Field::Boolean,
+            "timestamp" => Field::Timestamp,
+            "integer" => {
+                let (min, max) = parse_range(range, (0, 1_000_000))?;
+                Field::Integer { min, max }
+            }
+            "double" => {
+                let (min, max) = parse_range(range, (0.0, 1.0))?;
+                Field::Double { min, max }
+            }
+            _ => return Err(format!("unknown generator {kind:?}")),
+        };
+        if range.is_some() && !matches!(field, Field::Integer { .. } | Field::Double { .. }) {
+            return Err(format!("{kind} does not take a range"));
+        }
+        Ok(field)
+    }
+}
+
+/// Parse the `MIN..MAX` bounds of a number generator.
+fn parse_range<T: FromStr + PartialOrd>(
+    range: Option<&str>,
+    default: (T, T),
+) -> Result<(T, T), String> {
+    let Some(range) = range else {
+        return Ok(default);
+    };
+    let invalid = || format!("invalid range {range:?}, expected MIN..MAX");
+    let (min, max) = range.split_once("..").ok_or_else(invalid)?;
+    let min: T = min.parse().map_err(|_| invalid())?;
+    let max: T = max.parse().map_err(|_| invalid())?;
+    // NaN bounds are not ordered either
+    match min.partial_cmp(&max) {
+        Some(Ordering::Less | Ordering::Equal) => Ok((min, max)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Fields of the generated documents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    fields: Vec<(String, Field)>,
+}
+
+impl Schema {
+    /// Parse a schema from its JSON text.
+    pub fn parse(text: &str) -> Result<Self, GenerateError> {
+        let json: serde_json::Value =
+            serde_json::from_str(text).map_err(|e| GenerateError::Schema(e.to_string()))?;
+        match Field::parse(&json).map_err(GenerateError::Schema)? {
+            Field::Map(fields) => Ok(Schema { fields }),
+            _ => Err(GenerateError::Schema("must be a JSON object".to_string())),
+        }
+    }
+
+    /// Generate the fields of one document.
+    fn fields(&self, rng: &mut Rng, now: SystemTime) -> HashMap<String, Value> {
+        let person = Person::generate(rng);
+        generate_map(&self.fields, rng, &person, now)
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Schema::parse(DEFAULT_SCHEMA).expect("default schema is valid")
+    }
+}
+
+/// Made-up person the name fields of a document describe.
+struct Person {
+    first_name: &'static str,
+    last_name: &'static str,
+    email: String,
+}
+
+impl Person {
+    fn generate(rng: &mut Rng) -> Self {
+        let first_name = rng.pick(FIRST_NAMES);
+        let last_name = rng.pick(LAST_NAMES);
+        let email = format!(
+            "{}.{}{}@{}",
+            first_name.to_lowercase(),
+            last_name.to_lowercase(),
+            rng.below(1000),
+            rng.pick(EMAIL_DOMAINS)
+        );
+        Person {
+            first_name,
+            last_name,
+            email,
+        }
+    }
+}
+
+fn generate_map(
+    fields: &[(String, Field)],
+    rng: &mut Rng,
+    person: &Person,
+    now: SystemTime,
+) -> HashMap<String, Value> {
+    fields
+        .iter()
+        .map(|(name, field)| (name.clone(), generate(field, rng, person, now)))
+        .collect()
+}
+
+fn generate(field: &Field, rng: &mut Rng, person: &Person, now: SystemTime) -> Value {
+    let value = match field {
+        Field::Name => {
+            ValueType::StringValue(format!("{} {}", person.first_name, person.last_name))
+        }
+        Field::FirstName => ValueType::StringValue(person.first_name.to_string()),
+        Field::LastName => ValueType::StringValue(person.last_name.to_string()),
+        Field::Email => ValueType::StringValue(person.email.clone()),
+        Field::Word => ValueType::StringValue(rng.pick(WORDS).to_string()),
+        Field::Text => {
+            let words: Vec<_> = (0..5 + rng.below(20)).map(|_| rng.pick(WORDS)).collect();
+            ValueType::StringValue(words.join(" "))
+        }
+        Field::Uuid => {
+            let bytes = (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64());
+            ValueType::StringValue(
+                uuid::Builder::from_random_bytes(bytes.to_be_bytes())
+                    .into_uuid()
+                    .to_string(),
+            )
+        }
+        Field::Boolean => ValueType::BoolValue(rng.next_u64() & 1 == 1),
+        Field::Integer { min, max } => {
+            let span = max.abs_diff(*min);
+            let offset = if span == u64::MAX {
+                rng.next_u64()
+            } else {
+                rng.below(span + 1)
+            };
+            ValueType::IntValue(min.wrapping_add_unsigned(offset))
+        }
+        Field::Double { min, max } => ValueType::DoubleValue(min + (max - min) * rng.unit()),
+        Field::Timestamp => {
+            let ago = TIMESTAMP_RANGE.mul_f64(rng.unit());
+            let millis = Duration::from_millis(ago.as_millis() as u64);
+            ValueType::TimestampValue((now - millis).into())
+        }
+        Field::Map(fields) => ValueType::MapValue(MapValue {
+            fields: generate_map(fields, rng, person, now),
+        }),
+        Field::Array(item) => {
+            let values = (0..rng.below(MAX_ARRAY_LEN + 1))
+                .map(|_| generate(item, rng, person, now))
+                .collect();
+            ValueType::ArrayValue(ArrayValue { values })
+        }
+    };
+    Value {
+        value_type: Some(value),
+    }
+}
+
+/// Small deterministic random number generator, SplitMix64.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound`, `bound` must not be 0.
+    fn below(&mut self, bound: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(bound)) >> 64) as u64
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// What to generate and where to load it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub endpoint: String,
+    /// Collection ID as stored, see [`keys::qualify`].
+    pub collection: String,
+    pub count: u64,
+    pub schema: Schema,
+    /// Same seed, same documents, timestamps aside.
+    pub seed: u64,
+}
+
+impl Options {
+    /// Parse the arguments following `generate`: `--collection ID`,
+    /// `--count N` with an optional `k`, `M` or `G` suffix, and optionally
+    /// `--database ID`, `--schema FILE`, `--endpoint URL` and `--seed N`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, GenerateError> {
+        let usage = GenerateError::Usage;
+        let mut endpoint = conformance::DEFAULT_ENDPOINT.to_string();
+        let mut database = DEFAULT_DATABASE.to_string();
+        let mut collection = None;
+        let mut count = None;
+        let mut schema = Schema::default();
+        let mut seed = 0;
+
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| usage(format!("{flag} needs a value")))?;
+            match flag.as_str() {
+                "--endpoint" => endpoint = value,
+                "--database" => database = value,
+                "--collection" => collection = Some(value),
+                "--count" => {
+                    let parsed = parse_count(&value);
+                    count = Some(parsed.ok_or_else(|| usage(format!("invalid count {value:?}")))?);
+                }
+                "--schema" => {
+                    let text = std::fs::read_to_string(&value)
+                        .map_err(|e| GenerateError::Schema(format!("{value}: {e}")))?;
+                    schema = Schema::parse(&text)?;
+                }
+                "--seed" => {
+                    seed = value
+                        .parse()
+                        .map_err(|_| usage(format!("invalid seed {value:?}")))?;
+                }
+                _ => return Err(usage(format!("unknown flag {flag}"))),
+            }
+        }
+
+        let collection = collection
+            .filter(|collection| !collection.is_empty())
+            .ok_or_else(|| usage("--collection is required".to_string()))?;
+        Ok(Options {
+            endpoint,
+            collection: keys::qualify(&database, &collection)
+                .map_err(|e| usage(format!("invalid collection: {e}")))?,
+            count: count.ok_or_else(|| usage("--count is required".to_string()))?,
+            schema,
+            seed,
+        })
+    }
+}
+
+/// Parse a document count like `1000`, `50k` or `1M`.
+pub fn parse_count(value: &str) -> Option<u64> {
+    let (digits, multiplier) = match value.as_bytes().last()? {
+        b'k' | b'K' => (&value[..value.len() - 1], 1_000),
+        b'm' | b'M' => (&value[..value.len() - 1], 1_000_000),
+        b'g' | b'G' => (&value[..value.len() - 1], 1_000_000_000),
+        _ => (value, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Outcome of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub documents: u64,
+    pub chunks: u64,
+}
+
+/// Generate the documents and bulk-load them.
+pub async fn run(options: Options) -> Result<Summary, GenerateError> {
+    let mut client = ZerotableClient::connect(options.endpoint.clone())
+        .await
+        .map_err(GenerateError::Connect)?;
+    let job_id = format!("generate-{}", generate_uuid_v7().0);
+
+    // documents are generated on the blocking pool while chunks stream out
+    let (tx, rx) = mpsc::channel(CHUNK_QUEUE);
+    let producer = tokio::task::spawn_blocking(move || -> io::Result<()> {
+        let Options {
+            collection,
+            count,
+            schema,
+            seed,
+            ..
+        } = options;
+        let send = |chunk| {
+            let request = ImportDocumentsRequest {
+                job_id: job_id.clone(),
+                mode: ImportMode::Bulk as i32,
+                chunk: Some(chunk_to_proto(chunk)),
+            };
+            // the stream is gone once the import failed, which is reported
+            tx.blocking_send(request).is_ok()
+        };
+
+        let mut rng = Rng(seed);
+        let now = SystemTime::now();
+        let mut writer = ChunkWriter::new(DEFAULT_CHUNK_BUDGET, Compression::Zstd);
+        for i in 0..count {
+            let doc = Document {
+                name: name::format(&collection, &format!("{i:010}")),
+                fields: schema.fields(&mut rng, now),
+                create_time: Some(now.into()),
+                update_time: Some(now.into()),
+                version: 0,
+                expire_time: None,
+            };
+            if let Some(chunk) = writer.push(&doc.encode_to_vec())?
+                && !send(chunk)
+            {
+                return Ok(());
+            }
+        }
+        let (last, _) = writer.finish()?;
+        if let Some(chunk) = last {
+            send(chunk);
+        }
+        Ok(())
+    });
+
+    let response = client
+        .import_documents(ReceiverStream::new(rx))
+        .await
+        .map_err(GenerateError::Import)?
+        .into_inner();
+    producer
+        .await
+        .map_err(|e| GenerateError::Io(io::Error::other(e)))?
+        .map_err(GenerateError::Io)?;
+
+    Ok(Summary {
+        documents: response.results.iter().map(|r| r.written).sum(),
+        chunks: response.results.len() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+    use crate::api::v1alpha1::zerotable_server::ZerotableServer;
+    use crate::service::ZerotableService;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("1000"), Some(1000));
+        assert_eq!(parse_count("50k"), Some(50_000));
+        assert_eq!(parse_count("1M"), Some(1_000_000));
+        assert_eq!(parse_count("M"), None);
+        assert_eq!(parse_count("-1"), None);
+        assert_eq!(parse_count("99999999999G"), None);
+    }
+
+    #[test]
+    fn test_options() {
+        let options = Options::from_args(args(&[
+            "--collection",
+            "users",
+            "--count",
+            "2k",
+            "--seed",
+            "7",
+        ]))
+        .unwrap();
+        assert_eq!(options.collection, "users");
+        assert_eq!(options.count, 2000);
+        assert_eq!(options.seed, 7);
+        assert_eq!(options.endpoint, conformance::DEFAULT_ENDPOINT);
+
+        let options = Options::from_args(args(&[
+            "--database",
+            "acme",
+            "--collection",
+            "users",
+            "--count",
+            "1",
+        ]))
+        .unwrap();
+        assert_eq!(options.collection, "acme/users");
+
+        assert!(Options::from_args(args(&["--collection", "users"])).is_err());
+        assert!(Options::from_args(args(&["--count", "1", "--collection"])).is_err());
+        assert!(Options::from_args(args(&["--count", "1", "--collection", ""])).is_err());
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = Schema::parse(
+            r#"{"n": "integer:-5..5", "d": "double:1..2", "tags": ["uuid"], "x": {"y": "text"}}"#,
+        )
+        .unwrap();
+        let mut rng = Rng(1);
+        let now = SystemTime::now();
+        for _ in 0..100 {
+            let fields = schema.fields(&mut rng, now);
+            let Some(ValueType::IntValue(n)) = &fields["n"].value_type else {
+                panic!("not an integer: {:?}", fields["n"]);
+            };
+            assert!((-5..=5).contains(n));
+            let Some(ValueType::DoubleValue(d)) = &fields["d"].value_type else {
+                panic!("not a double: {:?}", fields["d"]);
+            };
+            assert!((1.0..2.0).contains(d));
+            assert!(matches!(
+                fields["x"].value_type,
+                Some(ValueType::MapValue(_))
+            ));
+        }
+
+        for invalid in [
+            r#"[]"#,
+            r#"{"a": "colour"}"#,
+            r#"{"a": "integer:5..1"}"#,
+            r#"{"a": "word:1..2"}"#,
+            r#"{"a": ["word", "word"]}"#,
+            r#"{"a": 1}"#,
+        ] {
+            assert!(Schema::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_documents() {
+        let schema = Schema::default();
+        let now = SystemTime::now();
+        let a = schema.fields(&mut Rng(42), now);
+        let b = schema.fields(&mut Rng(42), now);
+        assert_eq!(a, b);
+        assert_ne!(a, schema.fields(&mut Rng(43), now));
+
+        let Some(ValueType::StringValue(email)) = &a["email"].value_type else {
+            panic!("email is not a string");
+        };
+        let Some(ValueType::StringValue(name)) = &a["name"].value_type else {
+            panic!("name is not a string");
+        };
+        let first_name = name.split(' ').next().unwrap();
+        assert!(email.starts_with(&first_name.to_lowercase()));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Engine::open(dir.path()).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(ZerotableServer::new(ZerotableService::new(engine.clone())))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let options = Options {
+            endpoint: format!("http://{addr}"),
+            collection: "users".to_string(),
+            count: 500,
+            schema: Schema::default(),
+            seed: 0,
+        };
+        let summary = run(options).await.unwrap();
+        assert_eq!(summary.documents, 500);
+        assert!(summary.chunks >= 1);
+
+        let stored = engine.get_document("users", "0000000499").unwrap();
+        let doc = Document::decode(stored.data.as_slice()).unwrap();
+        assert!(doc.fields.contains_key("email"));
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Group commit of synced writes.
+//!
+//! With [`Durability::Sync`](crate::Durability::Sync) every write syncs the
+//! journal to disk once committed, and an fsync takes far longer than the
+//! commit. Writes committed at about the same time share a sync instead:
+//! the first to ask leads and syncs for every write committed by then, those
+//! committed while it syncs wait for it and share the next one.
+//!
+//! The leader may also wait a short window before syncing for more writes
+//! to join, trading latency for throughput when syncs are slow.
+//!
+//! Only synced writes are grouped. Writes with
+//! [`Durability::Buffered`](crate::Durability::Buffered) return once
+//! committed without syncing, there is no sync to share, and the window does
+//! not delay them.
+//!
+//! Transactions still commit one by one, they conflict on their own.
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Syncs shared by the writes of an engine.
+#[derive(Debug, Default)]
+pub struct GroupSync {
+    /// How long a leader waits for writes to join before syncing.
+    window: Duration,
+    state: Mutex<State>,
+    synced: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Writes committed and asking for a sync so far.
+    committed: u64,
+    /// Writes whose sync is done.
+    synced: u64,
+    /// Whether a leader is syncing.
+    syncing: bool,
+}
+
+impl GroupSync {
+    /// Syncs whose leader waits `window` before syncing.
+    pub fn new(window: Duration) -> Self {
+        GroupSync {
+            window,
+            ..Self::default()
+        }
+    }
+
+    /// Have a write committed just before synced, sharing the sync with
+    /// those committed about the same time. Runs `persist` when leading.
+    ///
+    /// A failed sync is returned to its leader only, the writes sharing it
+    /// try again.
+    pub fn sync<E>(&self, persist: impl FnOnce() -> Result<(), E>) -> Result<(), E> {
+        let mut state = self.lock();
+        state.committed += 1;
+        let ticket = state.committed;
+        loop {
+            if state.synced >= ticket {
+                return Ok(());
+            }
+            if !state.syncing {
+                break;
+            }
+            state = self.synced.wait(state).expect("group sync lock poisoned");
+        }
+        state.syncing = true;
+        drop(state);
+
+        if !self.window.is_zero() {
+            std::thread::sleep(self.window);
+        }
+        // every write counted by now committed before the sync starts
+        let through = self.lock().committed;
+        let result = persist();
+
+        let mut state = self.lock();
+        state.syncing = false;
+        if result.is_ok() {
+            state.synced = state.synced.max(through);
+        }
+        drop(state);
+        self.synced.notify_all();
+        result
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("group sync lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_shared_syncs() {
+        let group = Arc::new(GroupSync::new(Duration::from_millis(5)));
+        let syncs = Arc::new(AtomicUsize::new(0));
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let (group, syncs) = (group.clone(), syncs.clone());
+                std::thread::spawn(move || {
+                    group.sync(|| {
+                        syncs.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(5));
+                        Ok::<_, ()>(())
+                    })
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+        let syncs = syncs.load(Ordering::SeqCst);
+        assert!((1..8).contains(&syncs), "{syncs} syncs");
+    }
+
+    #[test]
+    fn test_failed_sync() {
+        let group = GroupSync::new(Duration::ZERO);
+        assert_eq!(group.sync(|| Err("disk full")), Err("disk full"));
+        // not counted as synced, the next write syncs again
+        let mut synced = false;
+        group
+            .sync(|| {
+                synced = true;
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert!(synced);
+    }
+}
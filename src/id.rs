@@ -7,14 +7,15 @@
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
+use crate::clock;
+
 /// Generate a new UUID v7 and extract its embedded timestamp.
 ///
 /// Returns `(uuid, timestamp)` where timestamp is extracted from the UUID itself,
 /// ensuring consistency between the ID and any create_time/update_time fields.
+/// Uses the process wide [`clock`](crate::clock::global).
 pub fn generate_uuid_v7() -> (Uuid, SystemTime) {
-    let uuid = Uuid::now_v7();
-    let timestamp = extract_timestamp(&uuid);
-    (uuid, timestamp)
+    clock::global().generate_uuid_v7()
 }
 
 /// Extract the timestamp embedded in a UUID v7.
@@ -29,12 +30,9 @@ pub fn extract_timestamp(uuid: &Uuid) -> SystemTime {
 /// Get current time truncated to millisecond precision.
 ///
 /// Ensures consistency with UUID v7 timestamps that are millisecond precision!
+/// Never returns a time earlier than a previous call, see [`crate::clock`].
 pub fn now_millis() -> SystemTime {
-    let millis = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64;
-    SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+    clock::global().now_millis()
 }
 
 #[cfg(test)]
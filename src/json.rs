@@ -0,0 +1,321 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Protobuf JSON mapping of documents.
+//!
+//! Follows the proto3 canonical JSON encoding: lowerCamelCase field names,
+//! int64 as strings, bytes as base64 and timestamps as RFC 3339.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use prost_types::Timestamp;
+use serde_json::{Map, Number, json};
+
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::{ArrayValue, Document, MapValue, Value};
+
+/// Error returned when a JSON payload does not describe a valid document.
+#[derive(Debug, PartialEq)]
+pub struct JsonError(String);
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid document json: {}", self.0)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+fn invalid(msg: impl Into<String>) -> JsonError {
+    JsonError(msg.into())
+}
+
+/// Encode a document as JSON.
+pub fn document_to_json(doc: &Document) -> serde_json::Value {
+    let mut obj = Map::new();
+    obj.insert("name".to_string(), json!(doc.name));
+    obj.insert("fields".to_string(), fields_to_json(&doc.fields));
+    if let Some(ts) = &doc.create_time {
+        obj.insert("createTime".to_string(), json!(ts.to_string()));
+    }
+    if let Some(ts) = &doc.update_time {
+        obj.insert("updateTime".to_string(), json!(ts.to_string()));
+    }
+    if doc.version != 0 {
+        obj.insert("version".to_string(), json!(doc.version.to_string()));
+    }
+    if let Some(ts) = &doc.expire_time {
+        obj.insert("expireTime".to_string(), json!(ts.to_string()));
+    }
+    serde_json::Value::Object(obj)
+}
+
+/// Decode a document from JSON.
+pub fn document_from_json(json: &serde_json::Value) -> Result<Document, JsonError> {
+    let obj = json
+        .as_object()
+        .ok_or_else(|| invalid("document must be an object"))?;
+
+    let mut doc = Document::default();
+    for (key, value) in obj {
+        match key.as_str() {
+            "name" => {
+                doc.name = value
+                    .as_str()
+                    .ok_or_else(|| invalid("name must be a string"))?
+                    .to_string();
+            }
+            "fields" => doc.fields = fields_from_json(value)?,
+            "createTime" => doc.create_time = Some(timestamp_from_json(value)?),
+            "updateTime" => doc.update_time = Some(timestamp_from_json(value)?),
+            "version" => doc.version = version_from_json(value)?,
+            "expireTime" => doc.expire_time = Some(timestamp_from_json(value)?),
+            _ => return Err(invalid(format!("unknown document field {key:?}"))),
+        }
+    }
+    Ok(doc)
+}
+
+fn fields_to_json(fields: &HashMap<String, Value>) -> serde_json::Value {
+    let obj = fields
+        .iter()
+        .map(|(k, v)| (k.clone(), value_to_json(v)))
+        .collect();
+    serde_json::Value::Object(obj)
+}
+
+fn fields_from_json(json: &serde_json::Value) -> Result<HashMap<String, Value>, JsonError> {
+    let obj = json
+        .as_object()
+        .ok_or_else(|| invalid("fields must be an object"))?;
+    obj.iter()
+        .map(|(k, v)| Ok((k.clone(), value_from_json(v)?)))
+        .collect()
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    let Some(value_type) = &value.value_type else {
+        return json!({});
+    };
+
+    match value_type {
+        ValueType::NullValue(_) => json!({ "nullValue": null }),
+        ValueType::BoolValue(b) => json!({ "boolValue": b }),
+        ValueType::IntValue(i) => json!({ "intValue": i.to_string() }),
+        ValueType::DoubleValue(d) => {
+            let encoded = match Number::from_f64(*d) {
+                Some(n) => serde_json::Value::Number(n),
+                None if d.is_nan() => json!("NaN"),
+                None if *d > 0.0 => json!("Infinity"),
+                None => json!("-Infinity"),
+            };
+            json!({ "doubleValue": encoded })
+        }
+        ValueType::StringValue(s) => json!({ "stringValue": s }),
+        ValueType::BytesValue(b) => json!({ "bytesValue": BASE64.encode(b) }),
+        ValueType::TimestampValue(ts) => json!({ "timestampValue": ts.to_string() }),
+        ValueType::MapValue(m) => json!({ "mapValue": { "fields": fields_to_json(&m.fields) } }),
+        ValueType::ArrayValue(a) => {
+            let values: Vec<_> = a.values.iter().map(value_to_json).collect();
+            json!({ "arrayValue": { "values": values } })
+        }
+    }
+}
+
+fn value_from_json(json: &serde_json::Value) -> Result<Value, JsonError> {
+    let obj = json
+        .as_object()
+        .ok_or_else(|| invalid("value must be an object"))?;
+
+    let mut entries = obj.iter();
+    let Some((kind, inner)) = entries.next() else {
+        return Ok(Value { value_type: None });
+    };
+    if entries.next().is_some() {
+        return Err(invalid("value must have exactly one type"));
+    }
+
+    let value_type = match kind.as_str() {
+        "nullValue" => ValueType::NullValue(prost_types::NullValue::NullValue as i32),
+        "boolValue" => ValueType::BoolValue(
+            inner
+                .as_bool()
+                .ok_or_else(|| invalid("boolValue must be a boolean"))?,
+        ),
+        "intValue" => ValueType::IntValue(int_from_json(inner)?),
+        "doubleValue" => ValueType::DoubleValue(double_from_json(inner)?),
+        "stringValue" => ValueType::StringValue(
+            inner
+                .as_str()
+                .ok_or_else(|| invalid("stringValue must be a string"))?
+                .to_string(),
+        ),
+        "bytesValue" => {
+            let encoded = inner
+                .as_str()
+                .ok_or_else(|| invalid("bytesValue must be a base64 string"))?;
+            ValueType::BytesValue(
+                BASE64
+                    .decode(encoded)
+                    .map_err(|e| invalid(format!("bytesValue: {e}")))?,
+            )
+        }
+        "timestampValue" => ValueType::TimestampValue(timestamp_from_json(inner)?),
+        "mapValue" => {
+            let fields = match inner.get("fields") {
+                Some(fields) => fields_from_json(fields)?,
+                None => HashMap::new(),
+            };
+            ValueType::MapValue(MapValue { fields })
+        }
+        "arrayValue" => {
+            let values = match inner.get("values") {
+                Some(values) => values
+                    .as_array()
+                    .ok_or_else(|| invalid("arrayValue.values must be an array"))?
+                    .iter()
+                    .map(value_from_json)
+                    .collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            };
+            ValueType::ArrayValue(ArrayValue { values })
+        }
+        _ => return Err(invalid(format!("unknown value type {kind:?}"))),
+    };
+
+    Ok(Value {
+        value_type: Some(value_type),
+    })
+}
+
+/// int64 is encoded as a string, but plain numbers are accepted too.
+fn int_from_json(json: &serde_json::Value) -> Result<i64, JsonError> {
+    let parsed = match json {
+        serde_json::Value::String(s) => s.parse().ok(),
+        serde_json::Value::Number(n) => n.as_i64(),
+        _ => None,
+    };
+    parsed.ok_or_else(|| invalid("intValue must be a 64-bit integer"))
+}
+
+/// uint64 is encoded as a string, but plain numbers are accepted too.
+fn version_from_json(json: &serde_json::Value) -> Result<u64, JsonError> {
+    let parsed = match json {
+        serde_json::Value::String(s) => s.parse().ok(),
+        serde_json::Value::Number(n) => n.as_u64(),
+        _ => None,
+    };
+    parsed.ok_or_else(|| invalid("version must be an unsigned 64-bit integer"))
+}
+
+fn double_from_json(json: &serde_json::Value) -> Result<f64, JsonError> {
+    let parsed = match json {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => match s.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            _ => s.parse().ok(),
+        },
+        _ => None,
+    };
+    parsed.ok_or_else(|| invalid("doubleValue must be a number"))
+}
+
+fn timestamp_from_json(json: &serde_json::Value) -> Result<Timestamp, JsonError> {
+    json.as_str()
+        .ok_or_else(|| invalid("timestamp must be an RFC 3339 string"))?
+        .parse()
+        .map_err(|e| invalid(format!("timestamp: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(value_type: ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut nested = HashMap::new();
+        nested.insert("flag".to_string(), value(ValueType::BoolValue(true)));
+
+        let mut fields = HashMap::new();
+        fields.insert("age".to_string(), value(ValueType::IntValue(i64::MAX)));
+        fields.insert("score".to_string(), value(ValueType::DoubleValue(1.5)));
+        fields.insert(
+            "name".to_string(),
+            value(ValueType::StringValue("ada".to_string())),
+        );
+        fields.insert(
+            "avatar".to_string(),
+            value(ValueType::BytesValue(vec![0, 1, 255])),
+        );
+        fields.insert(
+            "nested".to_string(),
+            value(ValueType::MapValue(MapValue { fields: nested })),
+        );
+        fields.insert(
+            "tags".to_string(),
+            value(ValueType::ArrayValue(ArrayValue {
+                values: vec![value(ValueType::NullValue(0))],
+            })),
+        );
+
+        let doc = Document {
+            name: "users/ada".to_string(),
+            fields,
+            create_time: Some(Timestamp {
+                seconds: 1_700_000_000,
+                nanos: 0,
+            }),
+            update_time: None,
+            version: 7,
+            expire_time: Some(Timestamp {
+                seconds: 1_800_000_000,
+                nanos: 0,
+            }),
+        };
+
+        let json = document_to_json(&doc);
+        assert_eq!(json["fields"]["age"]["intValue"], "9223372036854775807");
+        assert_eq!(json["fields"]["avatar"]["bytesValue"], "AAH/");
+        assert_eq!(json["version"], "7");
+        assert_eq!(document_from_json(&json).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_special_doubles() {
+        let json = value_to_json(&value(ValueType::DoubleValue(f64::INFINITY)));
+        assert_eq!(json["doubleValue"], "Infinity");
+        assert_eq!(
+            value_from_json(&json).unwrap(),
+            value(ValueType::DoubleValue(f64::INFINITY))
+        );
+    }
+
+    #[test]
+    fn test_int_accepts_number() {
+        let parsed = value_from_json(&json!({ "intValue": 42 })).unwrap();
+        assert_eq!(parsed, value(ValueType::IntValue(42)));
+    }
+
+    #[test]
+    fn test_rejects_multiple_types() {
+        let err = value_from_json(&json!({ "intValue": "1", "boolValue": true })).unwrap_err();
+        assert_eq!(err, invalid("value must have exactly one type"));
+    }
+
+    #[test]
+    fn test_rejects_unknown_document_field() {
+        assert!(document_from_json(&json!({ "nmae": "typo" })).is_err());
+    }
+}
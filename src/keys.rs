@@ -2,8 +2,59 @@
 // Use of this source code is governed by the Apache 2.0 license that can be
 // found in the LICENSE file.
 
+//! Storage key encoding.
+//!
+//! Every key starts with a [`Tag`] byte telling what kind of record it
+//! belongs to, so different kinds can share a keyspace and any key can be
+//! classified on its own. The layout of all keys is versioned by
+//! [`FORMAT_VERSION`].
+//!
+//! Databases share the keyspaces. The collections of a database other than
+//! the default one are stored under a `{database_id}/{collection_id}` ID,
+//! see [`qualify`], and since collection IDs cannot contain slashes no two
+//! databases share a collection key prefix.
+
 use std::fmt;
 
+/// Version of the key layout, bumped whenever existing keys would be read
+/// differently. Version 0 keys had no tag byte.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Kind of record a storage key belongs to, stored as its first byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Document = 0x01,
+    /// Reserved for secondary index entries.
+    Index = 0x02,
+    /// Engine bookkeeping: counters, configs, job journals and the like.
+    System = 0x03,
+    /// Audit log entries.
+    Changelog = 0x04,
+    /// Document expiry entries.
+    Ttl = 0x05,
+    /// Past revisions of documents.
+    History = 0x06,
+    /// Soft deleted documents, kept until restored or purged.
+    Deleted = 0x07,
+}
+
+impl Tag {
+    /// Kind of the record `key` belongs to, `None` if the key is empty or its
+    /// tag unknown, e.g. written by a newer version.
+    pub fn of(key: &[u8]) -> Option<Tag> {
+        match key.first()? {
+            0x01 => Some(Tag::Document),
+            0x02 => Some(Tag::Index),
+            0x03 => Some(Tag::System),
+            0x04 => Some(Tag::Changelog),
+            0x05 => Some(Tag::Ttl),
+            0x06 => Some(Tag::History),
+            0x07 => Some(Tag::Deleted),
+            _ => None,
+        }
+    }
+}
+
 /// Maximum length (bytes) for collection IDs and document IDs.
 // firestore-like limit. Fjall enforces 65536 bytes for keys.
 const MAX_ID_LENGTH: usize = 1500;
@@ -11,6 +62,13 @@ const MAX_ID_LENGTH: usize = 1500;
 /// Separator byte between collection ID and document ID in storage keys.
 const SEPARATOR: u8 = 0x00;
 
+/// Database of the collections stored under their plain IDs, addressed by
+/// requests that name no database.
+pub const DEFAULT_DATABASE: &str = "(default)";
+
+/// Maximum length (bytes) for database IDs.
+const MAX_DATABASE_ID_LENGTH: usize = 63;
+
 /// Errors that can occur during key encoding.
 #[derive(Debug, PartialEq)]
 pub enum KeyError {
@@ -18,6 +76,7 @@ pub enum KeyError {
     ContainsNullByte,
     ContainsSlash, 
     TooLong { len: usize, max: usize },
+    InvalidDatabaseId,
 }
 
 impl fmt::Display for KeyError {
@@ -29,6 +88,10 @@ impl fmt::Display for KeyError {
             KeyError::TooLong { len, max } => {
                 write!(f, "id too long: {len} bytes, max {max}")
             }
+            KeyError::InvalidDatabaseId => write!(
+                f,
+                "database id must be 1 to {MAX_DATABASE_ID_LENGTH} lowercase letters, digits or hyphens, starting with a letter"
+            ),
         }
     }
 }
@@ -45,9 +108,6 @@ fn validate(id: &str) -> Result<(), KeyError> {
     if id.as_bytes().contains(&SEPARATOR) {
         return Err(KeyError::ContainsNullByte);
     }
-    if id.contains('/') {
-        return Err(KeyError::ContainsSlash);
-    }
     if id.len() > MAX_ID_LENGTH {
         return Err(KeyError::TooLong {
             len: id.len(),
@@ -57,46 +117,263 @@ fn validate(id: &str) -> Result<(), KeyError> {
     Ok(())
 }
 
+/// Validate a collection ID, which unlike a document ID must not contain
+/// forward slashes.
+fn validate_collection_id(id: &str) -> Result<(), KeyError> {
+    validate(id)?;
+    if id.contains('/') {
+        return Err(KeyError::ContainsSlash);
+    }
+    Ok(())
+}
+
+/// Validate a collection ID as built by [`qualify`].
+fn validate_collection(collection: &str) -> Result<(), KeyError> {
+    if let Some((database_id, collection_id)) = collection.split_once('/') {
+        validate_database_id(database_id)?;
+        return validate_collection_id(collection_id);
+    }
+    validate_collection_id(collection)
+}
+
+/// Validate the ID of a database other than the default one.
+fn validate_database_id(id: &str) -> Result<(), KeyError> {
+    let valid = id.len() <= MAX_DATABASE_ID_LENGTH
+        && id.starts_with(|c: char| c.is_ascii_lowercase())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+    if !valid {
+        return Err(KeyError::InvalidDatabaseId);
+    }
+    Ok(())
+}
+
+/// Validate a database ID, the default database's included.
+pub fn validate_database(id: &str) -> Result<(), KeyError> {
+    if id == DEFAULT_DATABASE {
+        return Ok(());
+    }
+    validate_database_id(id)
+}
+
+/// Collection ID under which collection `collection_id` of `database_id` is
+/// stored: the plain ID in the default database, `{database_id}/{collection_id}`
+/// in others.
+///
+/// Returns an error if the database ID is invalid or the collection ID
+/// contains a forward slash, which would reach into another database. The
+/// rest of the collection ID is validated once used in a key.
+pub fn qualify(database_id: &str, collection_id: &str) -> Result<String, KeyError> {
+    if collection_id.contains('/') {
+        return Err(KeyError::ContainsSlash);
+    }
+    if database_id == DEFAULT_DATABASE {
+        return Ok(collection_id.to_string());
+    }
+    validate_database_id(database_id)?;
+    Ok(format!("{database_id}/{collection_id}"))
+}
+
+/// Split a collection ID built by [`qualify`] into (database_id,
+/// collection_id).
+pub fn unqualify(collection: &str) -> (&str, &str) {
+    collection
+        .split_once('/')
+        .unwrap_or((DEFAULT_DATABASE, collection))
+}
+
 /// Encode a collection id and document ID into a storage key.
 ///
-/// Key format: `{collection_id}\x00{doc_id}`
+/// Key format: `{Tag::Document}{collection_id}\x00{doc_id}`
 ///
-/// Returns an error if either id is empty, contains a null byte,
-/// contains a forward slash, or exceeds the maximum length.
+/// Returns an error if either id is empty, contains a null byte, or exceeds
+/// the maximum length, or if the collection id contains a forward slash
+/// other than the one after a database ID, see [`qualify`]. Document IDs may
+/// contain slashes, the API percent-encodes them in resource names.
 pub fn encode(collection_id: &str, doc_id: &str) -> Result<Vec<u8>, KeyError> {
-    // NOTE: should we have separate validation rules for collection vs doc ids?
     // NOTE: should we skip validation for server generated uuids? 
-    validate(collection_id)?;
+    validate_collection(collection_id)?;
     validate(doc_id)?;
-
-    let mut key = Vec::with_capacity(collection_id.len() + 1 + doc_id.len());
-    key.extend_from_slice(collection_id.as_bytes());
-    key.push(SEPARATOR);
-    key.extend_from_slice(doc_id.as_bytes());
-    Ok(key)
+    Ok(pair(Tag::Document, collection_id, doc_id))
 }
 
-/// Decode a storage key back into (collection_id, doc_id).
+/// Decode a document key back into (collection_id, doc_id).
 ///
-/// Returns `None` if the key has no separator or contains invalid UTF-8.
+/// Returns `None` if the key is not a document key, has no separator or
+/// contains invalid UTF-8.
 // NOTE: should this return Result instead of Option for better error info? 
 pub fn decode(key: &[u8]) -> Option<(&str, &str)> {
-    let pos = key.iter().position(|&b| b == SEPARATOR)?;
-    let collection_id = std::str::from_utf8(&key[..pos]).ok()?;
-    let doc_id = std::str::from_utf8(&key[pos + 1..]).ok()?;
-    Some((collection_id, doc_id))
+    split_pair(Tag::Document, key)
 }
 
 /// Build a prefix for scanning all documents in a collection.
 ///
 /// Use with `Keyspace::prefix()` to iterate over all documents in a collection.
 pub fn collection_prefix(collection_id: &str) -> Result<Vec<u8>, KeyError> {
-    validate(collection_id)?;
+    validate_collection(collection_id)?;
+    Ok(pair_prefix(Tag::Document, collection_id))
+}
+
+/// Encode the key of the revision of the document stored under
+/// `document_key` written with commit `sequence`.
+///
+/// Key format: `{Tag::History}{collection_id}\x00{doc_id}\x00{sequence: u64 BE}`,
+/// so the revisions of a document sort oldest first, in document ID order.
+pub fn history(document_key: &[u8], sequence: u64) -> Vec<u8> {
+    let mut key = document_history_prefix(document_key);
+    key.extend_from_slice(&sequence.to_be_bytes());
+    key
+}
+
+/// Decode a key built by [`history`] back into (collection_id, doc_id,
+/// sequence).
+pub fn decode_history(key: &[u8]) -> Option<(&str, &str, u64)> {
+    let (rest, sequence) = key.split_last_chunk::<8>()?;
+    let rest = rest.strip_suffix(&[SEPARATOR])?;
+    let (collection_id, doc_id) = split_pair(Tag::History, rest)?;
+    Some((collection_id, doc_id, u64::from_be_bytes(*sequence)))
+}
+
+/// Build a prefix for scanning the revisions of the document stored under
+/// `document_key`.
+pub fn document_history_prefix(document_key: &[u8]) -> Vec<u8> {
+    let rest = document_key.get(1..).unwrap_or_default();
+    let mut prefix = Vec::with_capacity(1 + rest.len() + 1 + 8);
+    prefix.push(Tag::History as u8);
+    prefix.extend_from_slice(rest);
+    prefix.push(SEPARATOR);
+    prefix
+}
+
+/// Build a prefix for scanning the revisions of all documents in a
+/// collection.
+pub fn history_prefix(collection_id: &str) -> Result<Vec<u8>, KeyError> {
+    validate_collection(collection_id)?;
+    Ok(pair_prefix(Tag::History, collection_id))
+}
+
+/// Encode the key of the soft deleted document stored under
+/// `document_key`.
+///
+/// Key format: `{Tag::Deleted}{collection_id}\x00{doc_id}`, the document key
+/// with another tag, so prefix scans of the collection skip it.
+pub fn deleted(document_key: &[u8]) -> Vec<u8> {
+    let mut key = document_key.to_vec();
+    if let Some(tag) = key.first_mut() {
+        *tag = Tag::Deleted as u8;
+    }
+    key
+}
+
+/// Decode a key built by [`deleted`] back into (collection_id, doc_id).
+pub fn decode_deleted(key: &[u8]) -> Option<(&str, &str)> {
+    split_pair(Tag::Deleted, key)
+}
+
+/// Build a prefix for scanning the soft deleted documents of a collection.
+pub fn deleted_prefix(collection_id: &str) -> Result<Vec<u8>, KeyError> {
+    validate_collection(collection_id)?;
+    Ok(pair_prefix(Tag::Deleted, collection_id))
+}
+
+/// Encode the key of the expiry entry of the document stored under
+/// `document_key`, expiring at `expire_at` milliseconds since the epoch.
+///
+/// Key format: `{Tag::Ttl}{expire_at: u64 BE}{collection_id}\x00{doc_id}`, so
+/// entries sort by expiry time.
+pub fn ttl(expire_at: u64, document_key: &[u8]) -> Vec<u8> {
+    let rest = document_key.get(1..).unwrap_or_default();
+    let mut key = ttl_prefix(expire_at);
+    key.extend_from_slice(rest);
+    key
+}
+
+/// Decode a key built by [`ttl`] back into (expire_at, collection_id,
+/// doc_id).
+pub fn decode_ttl(key: &[u8]) -> Option<(u64, &str, &str)> {
+    let (&tag, rest) = key.split_first()?;
+    if tag != Tag::Ttl as u8 {
+        return None;
+    }
+    let (expire_at, rest) = rest.split_first_chunk::<8>()?;
+    let pos = rest.iter().position(|&b| b == SEPARATOR)?;
+    let collection_id = std::str::from_utf8(&rest[..pos]).ok()?;
+    let doc_id = std::str::from_utf8(&rest[pos + 1..]).ok()?;
+    Some((u64::from_be_bytes(*expire_at), collection_id, doc_id))
+}
+
+/// Build a prefix for scanning the expiry entries of documents expiring at
+/// `expire_at` milliseconds since the epoch; entries expiring earlier sort
+/// before it.
+pub fn ttl_prefix(expire_at: u64) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(1 + 8);
+    prefix.push(Tag::Ttl as u8);
+    prefix.extend_from_slice(&expire_at.to_be_bytes());
+    prefix
+}
+
+/// Encode the key of entry `id` of an engine bookkeeping namespace, like
+/// the counters of a collection.
+///
+/// Key format: `{Tag::System}{namespace}\x00{id}`. `id` is validated like a
+/// document ID.
+pub fn system(namespace: &str, id: &str) -> Result<Vec<u8>, KeyError> {
+    validate(id)?;
+    Ok(pair(Tag::System, namespace, id))
+}
+
+/// Encode the key of entry `id` of an engine bookkeeping namespace kept
+/// apart for every `scope`, like the idempotency keys of a collection, so
+/// scopes never share an entry.
+///
+/// Key format: `{Tag::System}{namespace}\x00{scope}\x00{id}`. `scope` and
+/// `id` are validated like document IDs.
+pub fn scoped_system(namespace: &str, scope: &str, id: &str) -> Result<Vec<u8>, KeyError> {
+    validate(scope)?;
+    validate(id)?;
+    let mut key = pair(Tag::System, namespace, scope);
+    key.push(SEPARATOR);
+    key.extend_from_slice(id.as_bytes());
+    Ok(key)
+}
 
-    let mut prefix = Vec::with_capacity(collection_id.len() + 1);
-    prefix.extend_from_slice(collection_id.as_bytes());
+/// Decode a key built by [`system`] back into (namespace, id).
+pub fn decode_system(key: &[u8]) -> Option<(&str, &str)> {
+    split_pair(Tag::System, key)
+}
+
+/// Build a prefix for scanning all entries of a bookkeeping namespace.
+pub fn system_prefix(namespace: &str) -> Vec<u8> {
+    pair_prefix(Tag::System, namespace)
+}
+
+/// `{tag}{first}\x00{second}`
+fn pair(tag: Tag, first: &str, second: &str) -> Vec<u8> {
+    let mut key = pair_prefix(tag, first);
+    key.reserve_exact(second.len());
+    key.extend_from_slice(second.as_bytes());
+    key
+}
+
+/// `{tag}{first}\x00`
+fn pair_prefix(tag: Tag, first: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(1 + first.len() + 1);
+    prefix.push(tag as u8);
+    prefix.extend_from_slice(first.as_bytes());
     prefix.push(SEPARATOR);
-    Ok(prefix)
+    prefix
+}
+
+fn split_pair(tag: Tag, key: &[u8]) -> Option<(&str, &str)> {
+    let (&first, key) = key.split_first()?;
+    if first != tag as u8 {
+        return None;
+    }
+    let pos = key.iter().position(|&b| b == SEPARATOR)?;
+    let first = std::str::from_utf8(&key[..pos]).ok()?;
+    let second = std::str::from_utf8(&key[pos + 1..]).ok()?;
+    Some((first, second))
 }
 
 #[cfg(test)]
@@ -131,6 +408,47 @@ mod tests {
         assert_eq!(encode("users", "doc\x001"), Err(KeyError::ContainsNullByte));
     }
 
+    #[test]
+    fn test_encode_slash() {
+        assert_eq!(encode("a/us/ers", "doc1"), Err(KeyError::ContainsSlash));
+        let key = encode("users", "a/b").unwrap();
+        assert_eq!(decode(&key), Some(("users", "a/b")));
+    }
+
+    #[test]
+    fn test_qualify() {
+        assert_eq!(qualify(DEFAULT_DATABASE, "users"), Ok("users".to_string()));
+        assert_eq!(qualify("acme", "users"), Ok("acme/users".to_string()));
+        assert_eq!(unqualify("acme/users"), ("acme", "users"));
+        assert_eq!(unqualify("users"), (DEFAULT_DATABASE, "users"));
+
+        assert_eq!(qualify("acme", "a/b"), Err(KeyError::ContainsSlash));
+        let too_long = "a".repeat(64);
+        for database_id in ["", "Acme", "1acme", "ac me", too_long.as_str()] {
+            assert_eq!(
+                qualify(database_id, "users"),
+                Err(KeyError::InvalidDatabaseId),
+                "{database_id}"
+            );
+        }
+        assert_eq!(validate_database(DEFAULT_DATABASE), Ok(()));
+        assert_eq!(validate_database("acme-2"), Ok(()));
+        // the default database is only addressed by plain IDs
+        assert_eq!(
+            encode("(default)/users", "doc1"),
+            Err(KeyError::InvalidDatabaseId)
+        );
+        assert_eq!(encode("acme/", "doc1"), Err(KeyError::EmptyId));
+    }
+
+    #[test]
+    fn test_databases_do_not_share_prefixes() {
+        let key = encode(&qualify("acme", "users").unwrap(), "doc1").unwrap();
+        assert!(!key.starts_with(&collection_prefix("users").unwrap()));
+        assert!(!key.starts_with(&collection_prefix("acme").unwrap()));
+        assert_eq!(decode(&key), Some(("acme/users", "doc1")));
+    }
+
     #[test]
     fn test_encode_too_long_collection_id() {
         let long_name = "a".repeat(1501);
@@ -148,12 +466,12 @@ mod tests {
 
     #[test]
     fn test_decode_no_separator() {
-        assert_eq!(decode(b"noseparator"), None);
+        assert_eq!(decode(b"\x01noseparator"), None);
     }
 
     #[test]
     fn test_decode_invalid_utf8_collection() {
-        let mut key = Vec::new();
+        let mut key = vec![Tag::Document as u8];
         key.extend_from_slice(&[0xFF, 0xFE]); // invalid UTF-8
         key.push(SEPARATOR);
         key.extend_from_slice(b"doc1");
@@ -162,13 +480,86 @@ mod tests {
 
     #[test]
     fn test_decode_invalid_utf8_document() {
-        let mut key = Vec::new();
+        let mut key = vec![Tag::Document as u8];
         key.extend_from_slice(b"users");
         key.push(SEPARATOR);
         key.extend_from_slice(&[0xFF, 0xFE]); // invalid UTF-8
         assert_eq!(decode(&key), None);
     }
 
+    #[test]
+    fn test_tags() {
+        let document = encode("stats", "users").unwrap();
+        let system = system("stats", "users").unwrap();
+        assert_ne!(document, system);
+        assert_eq!(Tag::of(&document), Some(Tag::Document));
+        assert_eq!(Tag::of(&system), Some(Tag::System));
+        assert_eq!(Tag::of(b"users\x00doc1"), None);
+        assert_eq!(Tag::of(b""), None);
+
+        // each decoder only accepts its own kind of key
+        assert_eq!(decode(&system), None);
+        assert_eq!(decode_system(&system), Some(("stats", "users")));
+        assert_eq!(decode_system(&document), None);
+        assert!(system.starts_with(&system_prefix("stats")));
+        assert!(!document.starts_with(&system_prefix("stats")));
+    }
+
+    #[test]
+    fn test_scoped_system() {
+        let acme = scoped_system("idempotency", "acme/users", "token").unwrap();
+        let beta = scoped_system("idempotency", "beta/users", "token").unwrap();
+        assert_ne!(acme, beta);
+        assert_eq!(acme, b"\x03idempotency\x00acme/users\x00token");
+        assert!(acme.starts_with(&system_prefix("idempotency")));
+        assert_eq!(
+            scoped_system("idempotency", "users", "to\x00ken"),
+            Err(KeyError::ContainsNullByte)
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let document = encode("users", "a").unwrap();
+        let key = history(&document, 7);
+        assert_eq!(Tag::of(&key), Some(Tag::History));
+        assert_eq!(decode_history(&key), Some(("users", "a", 7)));
+        assert_eq!(decode(&key), None);
+        assert!(key.starts_with(&document_history_prefix(&document)));
+        assert!(key.starts_with(&history_prefix("users").unwrap()));
+
+        // revisions sort by document, then oldest first
+        let later = history(&document, 256);
+        let next = history(&encode("users", "a!").unwrap(), 1);
+        assert!(key < later && later < next);
+        assert!(!next.starts_with(&document_history_prefix(&document)));
+    }
+
+    #[test]
+    fn test_deleted() {
+        let document = encode("users", "a").unwrap();
+        let key = deleted(&document);
+        assert_eq!(Tag::of(&key), Some(Tag::Deleted));
+        assert_eq!(decode_deleted(&key), Some(("users", "a")));
+        assert_eq!(decode(&key), None);
+        assert!(key.starts_with(&deleted_prefix("users").unwrap()));
+        assert!(!key.starts_with(&collection_prefix("users").unwrap()));
+    }
+
+    #[test]
+    fn test_ttl() {
+        let document = encode("users", "a").unwrap();
+        let key = ttl(1_700_000_000_000, &document);
+        assert_eq!(Tag::of(&key), Some(Tag::Ttl));
+        assert_eq!(decode_ttl(&key), Some((1_700_000_000_000, "users", "a")));
+        assert_eq!(decode(&key), None);
+        assert!(key.starts_with(&ttl_prefix(1_700_000_000_000)));
+
+        // entries sort by expiry first
+        let later = ttl(1_700_000_000_001, &encode("aaa", "a").unwrap());
+        assert!(key < later && later < ttl_prefix(1_700_000_000_002));
+    }
+
     #[test]
     fn test_collection_prefix() {
         let prefix = collection_prefix("users").unwrap();
@@ -2,9 +2,53 @@
 // Use of this source code is governed by the Apache 2.0 license that can be
 // found in the LICENSE file.
 
+pub mod admin;
+pub mod aggregate;
+pub mod api;
+pub mod archive;
+pub mod async_engine;
+pub mod audit;
+pub mod cache;
+pub mod cipher;
+pub mod clock;
+pub mod config;
+pub mod conformance;
+pub mod contention;
+pub mod deadline;
 pub mod engine;
+pub mod export;
+pub mod generate;
+pub mod group_commit;
 pub mod id;
+pub mod json;
 pub mod keys;
+pub mod list;
+pub mod mask;
+pub mod memory;
+pub mod merge;
+pub mod mirror;
+pub mod name;
+pub mod operations;
+pub mod panic;
+pub mod payload;
+pub mod plan;
+pub mod rate_limit;
+pub mod record;
+pub mod reload;
+pub mod request_id;
+pub mod rest;
+pub mod service;
+pub mod slow_log;
+pub mod stats;
+pub mod telemetry;
+pub mod transform;
 
-pub use engine::{Engine, EngineError};
+pub use async_engine::AsyncEngine;
+pub use engine::{
+    CollectionConfig, CollectionMetadata, ConflictPolicy, Durability, Engine, EngineError,
+    EngineOptions, ExpiryProgress, ImportDocument, ImportProgress, JobCheckpoint, JobWrite,
+    KeyspaceStats, Mutation, Rewrite, RewriteProgress, Snapshot, StoredDocument, Transaction,
+    Version,
+};
 pub use id::{generate_uuid_v7, now_millis};
+pub use stats::{CollectionStats, DocumentSize};
@@ -0,0 +1,435 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Ordering and paging of document listings.
+//!
+//! Documents are listed in document ID order, or ordered by fields as in
+//! Firestore's `order_by`, like `age desc, name`: values of different types
+//! sort by type, null < bool < number < timestamp < string < bytes < array
+//! < map, ints and doubles compare by value, NaN before every other number,
+//! and documents missing any of the fields are left out. Ties keep document
+//! ID order.
+//!
+//! There are no secondary indexes yet, so an ordered listing reads the whole
+//! collection for every page, keeping no more than a page of it, and pages
+//! by the values and document ID of the last document listed: a document
+//! written to between two pages is listed at its new place, if that is past
+//! the last one. Only ordering by [`DOCUMENT_ID_FIELD`] alone, ascending or
+//! descending, reads no more than a page and pages by document ID.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use prost::Message;
+
+use crate::aggregate::lookup;
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::{ArrayValue, Document, Value};
+
+/// Maximum number of fields of an `order_by`.
+pub const MAX_ORDER_FIELDS: usize = 8;
+
+/// Field path of the document ID in an `order_by`, as in Firestore.
+pub const DOCUMENT_ID_FIELD: &str = "__name__";
+
+/// Error returned when an `order_by` or a page token is malformed.
+#[derive(Debug, PartialEq)]
+pub struct ListError(String);
+
+impl fmt::Display for ListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ListError {}
+
+fn invalid(msg: impl Into<String>) -> ListError {
+    ListError(msg.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Ascending,
+    Descending,
+}
+
+/// Fields a listing is ordered by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    fields: Vec<(String, Direction)>,
+}
+
+impl OrderBy {
+    /// Parse comma separated field paths, each optionally followed by `asc`
+    /// or `desc`.
+    pub fn parse(order_by: &str) -> Result<Self, ListError> {
+        let mut fields = Vec::new();
+        for clause in order_by.split(',') {
+            let mut words = clause.split_whitespace();
+            let Some(field) = words.next() else {
+                return Err(invalid(format!("empty clause in order_by '{order_by}'")));
+            };
+            if field.split('.').any(str::is_empty) {
+                return Err(invalid(format!("invalid field path '{field}'")));
+            }
+            let direction = match words.next() {
+                None | Some("asc") => Direction::Ascending,
+                Some("desc") => Direction::Descending,
+                Some(word) => {
+                    return Err(invalid(format!(
+                        "{field}: expected 'asc' or 'desc', got '{word}'"
+                    )));
+                }
+            };
+            if let Some(word) = words.next() {
+                return Err(invalid(format!("{field}: unexpected '{word}'")));
+            }
+            fields.push((field.to_string(), direction));
+        }
+        if fields.len() > MAX_ORDER_FIELDS {
+            return Err(invalid(format!(
+                "at most {MAX_ORDER_FIELDS} order_by fields are allowed"
+            )));
+        }
+        Ok(OrderBy { fields })
+    }
+
+    /// Field paths ordered by.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|(field, _)| field.as_str())
+    }
+
+    /// Whether a listing ordered by the document ID alone is descending,
+    /// `None` if it is ordered by other fields.
+    pub fn descending_id(&self) -> Option<bool> {
+        match &self.fields[..] {
+            [(field, direction)] if field == DOCUMENT_ID_FIELD => {
+                Some(*direction == Direction::Descending)
+            }
+            _ => None,
+        }
+    }
+
+    /// Values `doc` is ordered by, `None` if it misses any of the fields.
+    pub fn key(&self, doc: &Document) -> Option<Vec<Value>> {
+        self.fields
+            .iter()
+            .map(|(field, _)| lookup(&doc.fields, field).cloned())
+            .collect()
+    }
+
+    /// Order of two keys returned by [`OrderBy::key`].
+    pub fn compare(&self, a: &[Value], b: &[Value]) -> Ordering {
+        for (((_, direction), a), b) in self.fields.iter().zip(a).zip(b) {
+            let ordering = match direction {
+                Direction::Ascending => compare_values(a, b),
+                Direction::Descending => compare_values(b, a),
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Order of two documents by their keys, ties by document ID.
+    pub fn compare_documents(&self, a: (&[Value], &str), b: (&[Value], &str)) -> Ordering {
+        self.compare(a.0, b.0).then_with(|| a.1.cmp(b.1))
+    }
+}
+
+impl fmt::Display for OrderBy {
+    /// The normalized `order_by`, like `age desc, name`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (field, direction)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(field)?;
+            if *direction == Direction::Descending {
+                f.write_str(" desc")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rank of the type of a value in the order of values.
+fn type_rank(value: &Option<ValueType>) -> u8 {
+    match value {
+        None | Some(ValueType::NullValue(_)) => 0,
+        Some(ValueType::BoolValue(_)) => 1,
+        Some(ValueType::IntValue(_) | ValueType::DoubleValue(_)) => 2,
+        Some(ValueType::TimestampValue(_)) => 3,
+        Some(ValueType::StringValue(_)) => 4,
+        Some(ValueType::BytesValue(_)) => 5,
+        Some(ValueType::ArrayValue(_)) => 6,
+        Some(ValueType::MapValue(_)) => 7,
+    }
+}
+
+/// Order of two doubles, NaN before every other number.
+fn compare_doubles(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).expect("neither is NaN"),
+    }
+}
+
+/// Order of an int and a double, by value.
+fn compare_int_double(a: i64, b: f64) -> Ordering {
+    if b.is_nan() {
+        return Ordering::Greater;
+    }
+    // an int rounding to the double is compared to its integer part, which
+    // is within the i128 range as the int is within the i64 one
+    match compare_doubles(a as f64, b) {
+        Ordering::Equal => {
+            let truncated = b.trunc();
+            (a as i128)
+                .cmp(&(truncated as i128))
+                .then(compare_doubles(0.0, b - truncated))
+        }
+        ordering => ordering,
+    }
+}
+
+/// Order of two values, see the [module](self) documentation.
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+    let (a, b) = (&a.value_type, &b.value_type);
+    let by_type = type_rank(a).cmp(&type_rank(b));
+    if by_type.is_ne() {
+        return by_type;
+    }
+    match (a, b) {
+        (Some(ValueType::BoolValue(a)), Some(ValueType::BoolValue(b))) => a.cmp(b),
+        (Some(ValueType::IntValue(a)), Some(ValueType::IntValue(b))) => a.cmp(b),
+        (Some(ValueType::IntValue(a)), Some(ValueType::DoubleValue(b))) => {
+            compare_int_double(*a, *b)
+        }
+        (Some(ValueType::DoubleValue(a)), Some(ValueType::IntValue(b))) => {
+            compare_int_double(*b, *a).reverse()
+        }
+        (Some(ValueType::DoubleValue(a)), Some(ValueType::DoubleValue(b))) => {
+            compare_doubles(*a, *b)
+        }
+        (Some(ValueType::TimestampValue(a)), Some(ValueType::TimestampValue(b))) => {
+            (a.seconds, a.nanos).cmp(&(b.seconds, b.nanos))
+        }
+        (Some(ValueType::StringValue(a)), Some(ValueType::StringValue(b))) => a.cmp(b),
+        (Some(ValueType::BytesValue(a)), Some(ValueType::BytesValue(b))) => a.cmp(b),
+        (Some(ValueType::ArrayValue(a)), Some(ValueType::ArrayValue(b))) => a
+            .values
+            .iter()
+            .zip(&b.values)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.values.len().cmp(&b.values.len())),
+        (Some(ValueType::MapValue(a)), Some(ValueType::MapValue(b))) => {
+            let mut a: Vec<_> = a.fields.iter().collect();
+            let mut b: Vec<_> = b.fields.iter().collect();
+            a.sort_by(|x, y| x.0.cmp(y.0));
+            b.sort_by(|x, y| x.0.cmp(y.0));
+            a.iter()
+                .zip(&b)
+                .map(|((ak, av), (bk, bv))| ak.cmp(bk).then_with(|| compare_values(av, bv)))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        }
+        // both null
+        _ => Ordering::Equal,
+    }
+}
+
+/// Where the next page of a listing starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageToken {
+    /// After the document with this ID, in document ID order.
+    After(String),
+    /// Before the document with this ID, in descending document ID order.
+    Before(String),
+    /// After the document with this key and ID, in the listing ordered by
+    /// `order_by`.
+    Keyset {
+        order_by: String,
+        key: Vec<Value>,
+        doc_id: String,
+    },
+}
+
+impl PageToken {
+    /// The token as an opaque string for clients.
+    pub fn encode(&self) -> String {
+        let token = match self {
+            PageToken::After(doc_id) => format!("a:{doc_id}"),
+            PageToken::Before(doc_id) => format!("b:{doc_id}"),
+            // a normalized order_by has no newline, nor has base64, the
+            // document ID comes last as it may
+            PageToken::Keyset {
+                order_by,
+                key,
+                doc_id,
+            } => {
+                let key = ArrayValue {
+                    values: key.clone(),
+                };
+                let key = BASE64.encode(key.encode_to_vec());
+                format!("k:{order_by}\n{key}\n{doc_id}")
+            }
+        };
+        BASE64.encode(token)
+    }
+
+    /// Decode a token returned by [`PageToken::encode`].
+    pub fn decode(token: &str) -> Result<Self, ListError> {
+        let malformed = || invalid("malformed page token");
+        let token = BASE64.decode(token).map_err(|_| malformed())?;
+        let token = String::from_utf8(token).map_err(|_| malformed())?;
+        if let Some(doc_id) = token.strip_prefix("a:") {
+            return Ok(PageToken::After(doc_id.to_string()));
+        }
+        if let Some(doc_id) = token.strip_prefix("b:") {
+            return Ok(PageToken::Before(doc_id.to_string()));
+        }
+        let (order_by, token) = token
+            .strip_prefix("k:")
+            .and_then(|token| token.split_once('\n'))
+            .ok_or_else(malformed)?;
+        let (key, doc_id) = token.split_once('\n').ok_or_else(malformed)?;
+        let key = BASE64.decode(key).map_err(|_| malformed())?;
+        let key = ArrayValue::decode(key.as_slice()).map_err(|_| malformed())?;
+        Ok(PageToken::Keyset {
+            order_by: order_by.to_string(),
+            key: key.values,
+            doc_id: doc_id.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::v1alpha1::MapValue;
+
+    fn value(value_type: ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    #[test]
+    fn test_compare_values() {
+        let ascending = [
+            Value::default(),
+            value(ValueType::BoolValue(false)),
+            value(ValueType::BoolValue(true)),
+            value(ValueType::DoubleValue(f64::NAN)),
+            value(ValueType::DoubleValue(f64::NEG_INFINITY)),
+            value(ValueType::IntValue(-1)),
+            value(ValueType::DoubleValue(0.5)),
+            value(ValueType::IntValue(i64::MAX - 1)),
+            value(ValueType::IntValue(i64::MAX)),
+            value(ValueType::DoubleValue(9.3e18)),
+            value(ValueType::TimestampValue(prost_types::Timestamp {
+                seconds: 1,
+                nanos: 0,
+            })),
+            value(ValueType::StringValue("a".into())),
+            value(ValueType::StringValue("b".into())),
+            value(ValueType::BytesValue(vec![0])),
+            value(ValueType::ArrayValue(ArrayValue {
+                values: vec![value(ValueType::IntValue(1))],
+            })),
+            value(ValueType::ArrayValue(ArrayValue {
+                values: vec![value(ValueType::IntValue(1)), Value::default()],
+            })),
+            value(ValueType::MapValue(MapValue::default())),
+        ];
+        for (i, a) in ascending.iter().enumerate() {
+            for (j, b) in ascending.iter().enumerate() {
+                assert_eq!(compare_values(a, b), i.cmp(&j), "{a:?} and {b:?}");
+            }
+        }
+        assert_eq!(
+            compare_values(
+                &value(ValueType::IntValue(2)),
+                &value(ValueType::DoubleValue(2.0))
+            ),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_order_by() {
+        let order_by = OrderBy::parse("age  desc,address.city asc").unwrap();
+        assert_eq!(order_by.to_string(), "age desc, address.city");
+        assert_eq!(
+            order_by.fields().collect::<Vec<_>>(),
+            ["age", "address.city"]
+        );
+
+        let mut doc = Document::default();
+        doc.fields
+            .insert("age".to_string(), value(ValueType::IntValue(30)));
+        assert_eq!(order_by.key(&doc), None);
+        let mut address = MapValue::default();
+        address.fields.insert(
+            "city".to_string(),
+            value(ValueType::StringValue("Rome".into())),
+        );
+        doc.fields
+            .insert("address".to_string(), value(ValueType::MapValue(address)));
+        let older = order_by.key(&doc).unwrap();
+        doc.fields
+            .insert("age".to_string(), value(ValueType::IntValue(20)));
+        let younger = order_by.key(&doc).unwrap();
+        assert_eq!(order_by.compare(&older, &younger), Ordering::Less);
+        assert_eq!(
+            order_by.compare_documents((&younger, "a"), (&younger, "b")),
+            Ordering::Less
+        );
+        assert_eq!(
+            order_by.compare_documents((&older, "b"), (&younger, "a")),
+            Ordering::Less
+        );
+        assert_eq!(order_by.descending_id(), None);
+
+        let by_id = |order_by| OrderBy::parse(order_by).unwrap().descending_id();
+        assert_eq!(by_id("__name__"), Some(false));
+        assert_eq!(by_id("__name__ desc"), Some(true));
+        assert_eq!(by_id("__name__, age"), None);
+
+        assert!(OrderBy::parse("").is_err());
+        assert!(OrderBy::parse("age,").is_err());
+        assert!(OrderBy::parse("age up").is_err());
+        assert!(OrderBy::parse("age desc desc").is_err());
+        assert!(OrderBy::parse("a..b").is_err());
+    }
+
+    #[test]
+    fn test_page_token() {
+        for token in [
+            PageToken::After("a:b/c".to_string()),
+            PageToken::Before("users".to_string()),
+            PageToken::Keyset {
+                order_by: "age desc, name".to_string(),
+                key: vec![
+                    value(ValueType::IntValue(30)),
+                    value(ValueType::StringValue("a\nb".into())),
+                ],
+                doc_id: "x\ny".to_string(),
+            },
+        ] {
+            assert_eq!(PageToken::decode(&token.encode()).unwrap(), token);
+        }
+        assert!(PageToken::decode("not a token").is_err());
+        assert!(PageToken::decode(&BASE64.encode("k:age\nnot base64\nx")).is_err());
+        assert!(PageToken::decode(&BASE64.encode("o:200:age")).is_err());
+    }
+}
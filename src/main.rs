@@ -2,165 +2,484 @@
 // Use of this source code is governed by the Apache 2.0 license that can be
 // found in the LICENSE file.
 
-use prost::Message;
-use prost_types::Timestamp;
-use tonic::{Request, Response, Status, transport::Server};
-use zerotable::{Engine, EngineError, generate_uuid_v7, now_millis};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-pub mod api {
-    pub mod v1alpha1 {
-        tonic::include_proto!("api.v1alpha1");
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::Interval;
+#[cfg(unix)]
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::Request;
+use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic_web::GrpcWebLayer;
+use tower::util::option_layer;
+use zerotable::admin::{AdminAuth, AdminService};
+use zerotable::api::v1alpha1::admin_server::AdminServer;
+use zerotable::api::v1alpha1::zerotable_server::ZerotableServer;
+use zerotable::archive::DirStore;
+use zerotable::config::ServerConfig;
+use zerotable::mirror::Mirror;
+use zerotable::rate_limit::RateLimiter;
+use zerotable::reload::Reloader;
+use zerotable::service::{ZerotableService, document_expire_time};
+use zerotable::{AsyncEngine, Engine, EngineOptions};
+use zerotable::{conformance, deadline, generate, panic, request_id, rest, telemetry};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+const IDEMPOTENCY_PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const HISTORY_PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const DELETED_PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Expiry entries read per transaction.
+const EXPIRY_BATCH: usize = 500;
+/// Revisions dropped per transaction.
+const HISTORY_PRUNE_BATCH: usize = 1000;
+/// Soft deleted documents dropped per transaction.
+const DELETED_PURGE_BATCH: usize = 1000;
+/// Idempotency keys dropped per transaction.
+const IDEMPOTENCY_PURGE_BATCH: usize = 1000;
+
+#[tokio::main]
+async fn main() -> Result<(), BoxError> {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "conformance").is_some() {
+        let endpoint = args
+            .next()
+            .unwrap_or_else(|| conformance::DEFAULT_ENDPOINT.to_string());
+        return run_conformance(endpoint).await;
+    }
+    if args.next_if(|arg| arg == "generate").is_some() {
+        return run_generate(args).await;
     }
-}
 
-use api::v1alpha1::zerotable_server::{Zerotable, ZerotableServer};
-use api::v1alpha1::{
-    CreateDocumentRequest, DeleteDocumentRequest, Document, GetDocumentRequest,
-    UpdateDocumentRequest,
-};
+    let args: Vec<String> = args.collect();
+    let config = ServerConfig::load(args.clone())?;
+    let telemetry = telemetry::init(
+        config.log_format,
+        config.log_filter.as_deref(),
+        config.otlp_endpoint.as_deref(),
+    )?;
+    panic::install_hook();
 
-#[derive(Clone)]
-pub struct ZerotableService {
-    engine: Engine,
-}
+    let options = EngineOptions {
+        memory_budget: config.memory_budget,
+    };
+    let mut engine = Engine::open_with_options(&config.data_dir, options)?
+        .with_audit_log(config.audit_log)
+        .with_history(config.history_retention)
+        .with_soft_delete_retention(config.soft_delete_retention)
+        .with_expiry(document_expire_time)
+        .with_value_compression(config.value_compression)
+        .with_document_cache(config.document_cache)
+        .with_group_commit_window(config.group_commit_window)
+        .with_durability(config.durability);
+    if let Some(dir) = &config.archive_dir {
+        let store = DirStore::new(dir.clone())?;
+        engine = engine.with_archive(Arc::new(store), config.archive_rehydrate);
+    }
+    if let Some(keys) = &config.encryption_keys {
+        engine = engine.with_encryption(Arc::new(keys.clone()));
+    }
+    let workers = AsyncEngine::new(
+        engine.clone(),
+        config.engine_workers,
+        config.engine_queue_depth,
+    );
+    let mut service = ZerotableService::new(workers)
+        .with_retry_budget(config.retry_budget)
+        .with_rate_limiter(RateLimiter::new(
+            config.read_rate_limit,
+            config.write_rate_limit,
+        ))
+        .with_idempotency_ttl(config.idempotency_ttl)
+        .with_slow_query_threshold(config.slow_query_threshold)
+        .with_slow_request_threshold(config.slow_request_threshold)
+        .with_strict_databases(config.strict_databases.clone())
+        .with_max_document_size(config.max_document_size)
+        .with_stream_stall_timeout(config.stream_stall_timeout)
+        .with_long_running_tasks(config.long_running_tasks)
+        .with_export_dir(config.export_dir.clone());
+    if let Some(endpoint) = &config.mirror_endpoint {
+        let mirror = Mirror::new(endpoint, config.mirror_fraction, config.mirror_reads)?;
+        service = service.with_mirror(mirror);
+    }
+    let mut reloader = Reloader::new(args, telemetry.log_filter(), service.rate_limiter().clone());
 
-impl ZerotableService {
-    pub fn new(engine: Engine) -> Self {
-        Self { engine }
+    let mut server = ZerotableServer::new(service.clone())
+        .accept_compressed(CompressionEncoding::Gzip)
+        .accept_compressed(CompressionEncoding::Zstd)
+        .max_decoding_message_size(config.max_decoding_message_size)
+        .max_encoding_message_size(config.max_encoding_message_size);
+    if let Some(encoding) = config.compression {
+        server = server.send_compressed(encoding);
     }
-}
+    let server = InterceptedService::new(server, |request: Request<()>| {
+        deadline::intercept(request_id::intercept(request)?)
+    });
+    // the admin service is only served once a token is configured
+    let admin_auth = config.admin_token.clone().map(AdminAuth::new);
+    if let Some(auth) = &admin_auth {
+        reloader = reloader.with_admin_auth(auth.clone());
+    }
+    let admin = admin_auth.map(|auth| {
+        InterceptedService::new(
+            AdminServer::new(AdminService::new(engine.clone()).with_reloader(reloader.clone())),
+            move |request: Request<()>| auth.check(request_id::intercept(request)?),
+        )
+    });
 
-/// Convert EngineError to tonic Status.
-fn engine_err_to_status(err: EngineError) -> Status {
-    match err {
-        EngineError::AlreadyExists => Status::already_exists(err.to_string()),
-        EngineError::NotFound => Status::not_found(err.to_string()),
-        EngineError::InvalidKey(_) => Status::invalid_argument(err.to_string()),
-        EngineError::Storage(_) => Status::internal(err.to_string()),
-        EngineError::TransactionConflict => Status::aborted(err.to_string()),
+    // grpc-web lets browsers call the service directly, it needs HTTP/1.1.
+    let mut builder = Server::builder()
+        .trace_fn(telemetry::rpc_span)
+        .accept_http1(config.grpc_web)
+        .max_concurrent_streams(config.max_concurrent_streams)
+        .tcp_keepalive(config.tcp_keepalive)
+        .layer(option_layer(config.grpc_web.then(GrpcWebLayer::new)));
+    if let Some(limit) = config.concurrency_limit {
+        builder = builder.concurrency_limit_per_connection(limit);
     }
-}
+    // TLS is served on the TCP listener only, the unix socket is local.
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            let read = |path: &PathBuf| {
+                std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))
+            };
+            let identity = Identity::from_pem(read(cert)?, read(key)?);
+            Some(ServerTlsConfig::new().identity(identity))
+        }
+        _ => None,
+    };
+
+    // Every listener stops accepting new RPCs once `stop_tx` fires, and
+    // every background task once done with the pass it is at.
+    let (stop_tx, stop_rx) = watch::channel(false);
 
-/// Parse a resource name "collection_id/document_id" into parts.
-fn parse_name(name: &str) -> Result<(&str, &str), Status> {
-    let parts: Vec<&str> = name.splitn(2, '/').collect();
-    if parts.len() != 2 || parts[0].is_empty() || parts[1].is_empty() {
-        return Err(Status::invalid_argument(
-            "name must be in format 'collection_id/document_id'",
+    let mut background = JoinSet::new();
+    // Expired idempotency keys are dropped in the background.
+    background.spawn(purge_idempotency_keys(engine.clone(), stop_rx.clone()));
+    // So are the document revisions past the history retention.
+    if config.history_retention.is_some() {
+        background.spawn(prune_history(engine.clone(), stop_rx.clone()));
+    }
+    // And the soft deleted documents past their retention.
+    background.spawn(purge_deleted_documents(engine.clone(), stop_rx.clone()));
+    // And the documents past their expire time.
+    background.spawn(delete_expired_documents(engine.clone(), stop_rx.clone()));
+    // So are the collections idle for long enough archived.
+    if let Some(idle_after) = config.archive_idle_after {
+        background.spawn(archive_idle_collections(
+            engine.clone(),
+            idle_after,
+            stop_rx.clone(),
         ));
     }
-    Ok((parts[0], parts[1]))
-}
-
-#[tonic::async_trait]
-impl Zerotable for ZerotableService {
-    async fn get_document(
-        &self,
-        request: Request<GetDocumentRequest>,
-    ) -> Result<Response<Document>, Status> {
-        let req = request.into_inner();
-        let (collection_id, doc_id) = parse_name(&req.name)?;
+    // And with periodic durability, the storage synced to disk.
+    if let Some(interval) = config.sync_interval {
+        background.spawn(sync_periodically(engine.clone(), interval, stop_rx.clone()));
+    }
+    #[cfg(unix)]
+    let reload = tokio::spawn(reload_on_hangup(reloader));
 
-        let engine = self.engine.clone();
-        let collection_id = collection_id.to_string();
-        let doc_id = doc_id.to_string();
+    let stopped = move || {
+        let mut stop_rx = stop_rx.clone();
+        async move {
+            let _ = stop_rx.changed().await;
+        }
+    };
+    let mut listeners = JoinSet::<Result<(), BoxError>>::new();
 
-        let data = tokio::task::spawn_blocking(move || {
-            engine.get_document(&collection_id, &doc_id)
-        })
-        .await
-        .map_err(|e| Status::internal(format!("task failed: {e}")))?
-        .map_err(engine_err_to_status)?;
+    if let Some(addr) = config.addr {
+        tracing::info!(%addr, tls = tls.is_some(), "zerotable listening");
+        let mut tcp = builder.clone();
+        if let Some(tls) = tls {
+            tcp = tcp.tls_config(tls)?;
+        }
+        let serve = tcp
+            .add_service(server.clone())
+            .add_optional_service(admin.clone())
+            .serve_with_shutdown(addr, stopped());
+        listeners.spawn(async move { Ok(serve.await?) });
+    }
 
-        let doc = Document::decode(data.as_slice())
-            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+    if let Some(path) = &config.unix_socket {
+        #[cfg(unix)]
+        {
+            // a socket file left behind by a previous run would make bind fail
+            let _ = std::fs::remove_file(path);
+            let listener = tokio::net::UnixListener::bind(path)?;
+            tracing::info!(path = %path.display(), "zerotable listening on unix socket");
+            let serve = builder
+                .clone()
+                .add_service(server.clone())
+                .add_optional_service(admin.clone())
+                .serve_with_incoming_shutdown(UnixListenerStream::new(listener), stopped());
+            listeners.spawn(async move { Ok(serve.await?) });
+        }
+        #[cfg(not(unix))]
+        return Err(format!("unix socket {} is not supported here", path.display()).into());
+    }
 
-        Ok(Response::new(doc))
+    if let Some(addr) = config.rest_addr {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!(%addr, "HTTP/JSON gateway listening");
+        let app = rest::router(service.clone()).into_make_service_with_connect_info::<SocketAddr>();
+        let serve = axum::serve(listener, app).with_graceful_shutdown(stopped());
+        listeners.spawn(async move { Ok(serve.await?) });
     }
 
-    async fn create_document(
-        &self,
-        request: Request<CreateDocumentRequest>,
-    ) -> Result<Response<Document>, Status> {
-        let req = request.into_inner();
+    tokio::select! {
+        // a listener stopped on its own, most likely it failed to bind
+        Some(result) = listeners.join_next() => result??,
+        () = shutdown_signal() => {}
+    }
 
-        if req.collection_id.is_empty() {
-            return Err(Status::invalid_argument("collection_id is required"));
+    // Stop accepting new RPCs and give in-flight ones time to finish.
+    tracing::info!("shutting down, draining in-flight requests");
+    let _ = stop_tx.send(true);
+    let drain = async {
+        while let Some(result) = listeners.join_next().await {
+            result??;
         }
+        Ok::<_, BoxError>(())
+    };
+    match tokio::time::timeout(config.shutdown_timeout, drain).await {
+        Ok(result) => result?,
+        Err(_) => tracing::warn!(
+            timeout = ?config.shutdown_timeout,
+            "in-flight requests did not finish in time, closing anyway"
+        ),
+    }
 
-        let mut doc = req.document.ok_or_else(|| {
-            Status::invalid_argument("document is required")
-        })?;
+    // Refuse new writes, then wait for those under way: the engine calls
+    // and operations of the service, and the background tasks.
+    engine.set_read_only(true);
+    let settle = async {
+        service.drain().await;
+        while background.join_next().await.is_some() {}
+    };
+    let settled = tokio::time::timeout(config.shutdown_timeout, settle).await;
+    #[cfg(unix)]
+    reload.abort();
+    match settled {
+        Ok(()) => engine.close()?,
+        Err(_) => {
+            // the counters would miss the writes still under way
+            tracing::warn!(
+                timeout = ?config.shutdown_timeout,
+                "writes did not finish in time, closing without a clean shutdown"
+            );
+            engine.persist()?;
+        }
+    }
+    if let Some(path) = &config.unix_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    tracing::info!("zerotable stopped");
+    if let Err(e) = telemetry.shutdown() {
+        tracing::error!(error = %e, "failed to export the last traces");
+    }
 
-        let (doc_id, now) = if req.document_id.is_empty() {
-            let (uuid, ts) = generate_uuid_v7();
-            (uuid.to_string(), ts)
-        } else {
-            (req.document_id, now_millis())
-        };
+    Ok(())
+}
 
-        let prost_now: Timestamp = now.into();
-        doc.name = format!("{}/{}", req.collection_id, doc_id);
-        doc.create_time = Some(prost_now.clone());
-        doc.update_time = Some(prost_now);
+/// Check the server at `endpoint` against the API conformance suite.
+async fn run_conformance(endpoint: String) -> Result<(), BoxError> {
+    let report = conformance::run(endpoint).await?;
+    println!("{report}");
+    if !report.passed() {
+        return Err("conformance checks failed".into());
+    }
+    Ok(())
+}
 
-        let data = doc.encode_to_vec();
-        let engine = self.engine.clone();
-        let collection_id = req.collection_id;
-        let doc_id_clone = doc_id.clone();
+async fn run_generate(args: impl Iterator<Item = String>) -> Result<(), BoxError> {
+    let options = generate::Options::from_args(args)?;
+    let collection = options.collection.clone();
+    let summary = generate::run(options).await?;
+    println!(
+        "loaded {} documents into {collection} in {} chunks",
+        summary.documents, summary.chunks
+    );
+    Ok(())
+}
 
-        tokio::task::spawn_blocking(move || {
-            engine.create_document(&collection_id, &doc_id_clone, &data)
-        })
-        .await
-        .map_err(|e| Status::internal(format!("task failed: {e}")))?
-        .map_err(engine_err_to_status)?;
+/// Wait for the next tick of `interval`, false once `stop` fires instead.
+async fn tick(interval: &mut Interval, stop: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        biased;
+        _ = stop.wait_for(|&stop| stop) => false,
+        _ = interval.tick() => true,
+    }
+}
 
-        Ok(Response::new(doc))
+/// Periodically drop the idempotency keys past their TTL, batch by batch
+/// until none are left, until `stop` fires.
+async fn purge_idempotency_keys(engine: Engine, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(IDEMPOTENCY_PURGE_INTERVAL);
+    while tick(&mut interval, &mut stop).await {
+        while !*stop.borrow() {
+            let engine = engine.clone();
+            let purged = tokio::task::spawn_blocking(move || {
+                engine.purge_idempotency_keys(SystemTime::now(), IDEMPOTENCY_PURGE_BATCH)
+            })
+            .await;
+            match purged {
+                Ok(Ok(purged)) if purged < IDEMPOTENCY_PURGE_BATCH => break,
+                Err(_) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    tracing::error!(error = %e, "failed to purge idempotency keys");
+                    break;
+                }
+            }
+        }
     }
+}
 
-    async fn update_document(
-        &self,
-        _request: Request<UpdateDocumentRequest>,
-    ) -> Result<Response<Document>, Status> {
-        Err(Status::unimplemented("not yet implemented"))
+/// Periodically drop the document revisions past the history retention,
+/// batch by batch until none are left, until `stop` fires.
+async fn prune_history(engine: Engine, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(HISTORY_PRUNE_INTERVAL);
+    while tick(&mut interval, &mut stop).await {
+        while !*stop.borrow() {
+            let engine = engine.clone();
+            let pruned = tokio::task::spawn_blocking(move || {
+                engine.prune_history(SystemTime::now(), HISTORY_PRUNE_BATCH)
+            })
+            .await;
+            match pruned {
+                Ok(Ok(pruned)) if pruned < HISTORY_PRUNE_BATCH => break,
+                Err(_) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    tracing::error!(error = %e, "failed to prune document history");
+                    break;
+                }
+            }
+        }
     }
+}
 
-    async fn delete_document(
-        &self,
-        request: Request<DeleteDocumentRequest>,
-    ) -> Result<Response<()>, Status> {
-        let req = request.into_inner();
-        let (collection, doc_id) = parse_name(&req.name)?;
+/// Periodically drop the soft deleted documents past their retention,
+/// batch by batch until none are left, until `stop` fires.
+async fn purge_deleted_documents(engine: Engine, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(DELETED_PURGE_INTERVAL);
+    while tick(&mut interval, &mut stop).await {
+        while !*stop.borrow() {
+            let engine = engine.clone();
+            let purged = tokio::task::spawn_blocking(move || {
+                engine.purge_deleted(SystemTime::now(), DELETED_PURGE_BATCH)
+            })
+            .await;
+            match purged {
+                Ok(Ok(purged)) if purged < DELETED_PURGE_BATCH => break,
+                Err(_) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    tracing::error!(error = %e, "failed to purge deleted documents");
+                    break;
+                }
+            }
+        }
+    }
+}
 
-        let engine = self.engine.clone();
-        let collection = collection.to_string();
-        let doc_id = doc_id.to_string();
+/// Periodically delete the documents past their expire time, batch by
+/// batch until every expiry entry due was read, until `stop` fires.
+async fn delete_expired_documents(engine: Engine, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(EXPIRY_INTERVAL);
+    while tick(&mut interval, &mut stop).await {
+        let mut start = None;
+        while !*stop.borrow() {
+            let engine = engine.clone();
+            let progress = tokio::task::spawn_blocking(move || {
+                engine.delete_expired(SystemTime::now(), start.as_deref(), EXPIRY_BATCH)
+            })
+            .await;
+            match progress {
+                Ok(Ok(progress)) if progress.next.is_some() => start = progress.next,
+                Ok(Ok(_)) | Err(_) => break,
+                Ok(Err(e)) => {
+                    tracing::error!(error = %e, "failed to delete expired documents");
+                    break;
+                }
+            }
+        }
+    }
+}
 
-        tokio::task::spawn_blocking(move || {
-            engine.delete_document(&collection, &doc_id)
-        })
-        .await
-        .map_err(|e| Status::internal(format!("task failed: {e}")))?
-        .map_err(engine_err_to_status)?;
+/// Periodically archive the collections not written for `idle_after`, until
+/// `stop` fires.
+async fn archive_idle_collections(
+    engine: Engine,
+    idle_after: Duration,
+    mut stop: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(ARCHIVE_INTERVAL);
+    while tick(&mut interval, &mut stop).await {
+        let written_before = SystemTime::now()
+            .checked_sub(idle_after)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let engine = engine.clone();
+        let archived =
+            tokio::task::spawn_blocking(move || engine.archive_idle_collections(written_before))
+                .await;
+        if let Ok(Err(e)) = archived {
+            tracing::error!(error = %e, "failed to archive idle collections");
+        }
+    }
+}
 
-        Ok(Response::new(()))
+/// Sync the storage to disk every `interval`, until `stop` fires.
+async fn sync_periodically(engine: Engine, interval: Duration, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(interval);
+    while tick(&mut interval, &mut stop).await {
+        let engine = engine.clone();
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || engine.sync()).await {
+            tracing::error!(error = %e, "failed to sync storage");
+        }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::1]:50051".parse()?;
+/// Reload the configuration every time the process receives SIGHUP.
+#[cfg(unix)]
+async fn reload_on_hangup(reloader: Reloader) {
+    use tokio::signal::unix::{SignalKind, signal};
 
-    let engine = Engine::open(".zerotable_data")?;
-    let service = ZerotableService::new(engine);
+    let mut hangup = signal(SignalKind::hangup()).expect("failed to install SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        let reloader = reloader.clone();
+        match tokio::task::spawn_blocking(move || reloader.reload()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!(error = %e, "failed to reload the configuration"),
+            Err(e) => tracing::error!(error = %e, "configuration reload task failed"),
+        }
+    }
+}
 
-    println!("Zerotable listening on {}", addr);
+/// Resolve when the process receives SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let interrupt = tokio::signal::ctrl_c();
 
-    Server::builder()
-        .add_service(ZerotableServer::new(service))
-        .serve(addr)
-        .await?;
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    Ok(())
+    tokio::select! {
+        _ = interrupt => {}
+        () = terminate => {}
+    }
 }
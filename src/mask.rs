@@ -0,0 +1,175 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Field masks of partial updates.
+//!
+//! An UpdateDocument with a mask only writes the fields the mask names:
+//! each takes its value in the update, or is deleted if the update lacks
+//! it, and every other field of the stored document is left as is. Fields
+//! of the update outside the mask are ignored.
+//!
+//! Since a masked update only depends on the fields it names, it can be
+//! applied again to a document written concurrently, as long as that write
+//! left those fields alone, see [`FieldMask::changed`].
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::aggregate::lookup;
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::{Document, Value};
+use crate::transform::{field_mut, overlaps};
+
+/// Maximum number of field paths in a mask.
+pub const MAX_FIELD_PATHS: usize = 100;
+
+/// Error returned when a mask is malformed.
+#[derive(Debug, PartialEq)]
+pub struct MaskError(String);
+
+impl fmt::Display for MaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid field mask: {}", self.0)
+    }
+}
+
+impl std::error::Error for MaskError {}
+
+fn invalid(msg: impl Into<String>) -> MaskError {
+    MaskError(msg.into())
+}
+
+/// Validated field paths of a mask.
+#[derive(Debug, Clone)]
+pub struct FieldMask {
+    paths: Vec<String>,
+}
+
+impl FieldMask {
+    pub fn new(paths: &[String]) -> Result<Self, MaskError> {
+        if paths.is_empty() {
+            return Err(invalid("at least one field path is required"));
+        }
+        if paths.len() > MAX_FIELD_PATHS {
+            return Err(invalid(format!(
+                "at most {MAX_FIELD_PATHS} field paths are allowed"
+            )));
+        }
+        for (i, path) in paths.iter().enumerate() {
+            if path.split('.').any(str::is_empty) {
+                return Err(invalid(format!("invalid field path '{path}'")));
+            }
+            if let Some(other) = paths[..i].iter().find(|other| overlaps(path, other)) {
+                return Err(invalid(format!("'{path}' overlaps '{other}'")));
+            }
+        }
+        Ok(FieldMask {
+            paths: paths.to_vec(),
+        })
+    }
+
+    /// Write the masked fields of `update` to `doc`, deleting those
+    /// `update` lacks.
+    pub fn apply(&self, doc: &mut Document, update: &Document) {
+        for path in &self.paths {
+            match lookup(&update.fields, path) {
+                Some(value) => *field_mut(&mut doc.fields, path) = value.clone(),
+                None => remove(&mut doc.fields, path),
+            }
+        }
+    }
+
+    /// First masked field whose value differs between `before` and
+    /// `after`, `None` if the mask is disjoint from what changed.
+    pub fn changed(&self, before: &Document, after: &Document) -> Option<&str> {
+        self.paths
+            .iter()
+            .find(|path| lookup(&before.fields, path) != lookup(&after.fields, path))
+            .map(String::as_str)
+    }
+}
+
+/// Delete the value at a dot separated field path, if any.
+fn remove(fields: &mut HashMap<String, Value>, path: &str) {
+    let Some((first, rest)) = path.split_once('.') else {
+        fields.remove(path);
+        return;
+    };
+    if let Some(Value {
+        value_type: Some(ValueType::MapValue(map)),
+    }) = fields.get_mut(first)
+    {
+        remove(&mut map.fields, rest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::v1alpha1::MapValue;
+
+    fn int(i: i64) -> Value {
+        Value {
+            value_type: Some(ValueType::IntValue(i)),
+        }
+    }
+
+    fn doc(fields: &[(&str, Value)]) -> Document {
+        Document {
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn mask(paths: &[&str]) -> Result<FieldMask, MaskError> {
+        let paths: Vec<_> = paths.iter().map(|path| path.to_string()).collect();
+        FieldMask::new(&paths)
+    }
+
+    #[test]
+    fn test_apply() {
+        let address = Value {
+            value_type: Some(ValueType::MapValue(MapValue {
+                fields: doc(&[("zip", int(1)), ("floor", int(2))]).fields,
+            })),
+        };
+        let mut stored = doc(&[("a", int(1)), ("b", int(2)), ("address", address)]);
+        let update = doc(&[("a", int(10)), ("c", int(30)), ("d", int(40))]);
+
+        mask(&["a", "b", "c", "address.floor"])
+            .unwrap()
+            .apply(&mut stored, &update);
+        assert_eq!(stored.fields["a"], int(10));
+        assert!(!stored.fields.contains_key("b"));
+        assert_eq!(stored.fields["c"], int(30));
+        assert!(!stored.fields.contains_key("d"));
+        assert_eq!(lookup(&stored.fields, "address.zip"), Some(&int(1)));
+        assert_eq!(lookup(&stored.fields, "address.floor"), None);
+    }
+
+    #[test]
+    fn test_changed() {
+        let before = doc(&[("a", int(1)), ("b", int(2))]);
+        let after = doc(&[("a", int(1)), ("b", int(3)), ("c", int(4))]);
+        assert_eq!(mask(&["a", "d.e"]).unwrap().changed(&before, &after), None);
+        assert_eq!(
+            mask(&["a", "c"]).unwrap().changed(&before, &after),
+            Some("c")
+        );
+        assert_eq!(mask(&["b.x", "a"]).unwrap().changed(&before, &after), None);
+        assert_eq!(mask(&["b"]).unwrap().changed(&before, &after), Some("b"));
+    }
+
+    #[test]
+    fn test_invalid_masks() {
+        assert!(mask(&[]).is_err());
+        assert!(mask(&["a..b"]).is_err());
+        assert!(mask(&["a", "a.b"]).is_err());
+        assert!(mask(&["a.b", "a"]).is_err());
+        assert!(mask(&["a", "ab"]).is_ok());
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Memory budget of the engine.
+//!
+//! A single budget is split between the memtables buffering recent writes and
+//! the block cache, so the storage stays within a known size in memory
+//! constrained containers. Memtables are flushed early once the writes they
+//! buffer approach their share, instead of waiting for the storage limits.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Memory used by the engine when no budget is given: 128 MiB.
+pub const DEFAULT_MEMORY_BUDGET: u64 = 128 * 1024 * 1024;
+
+/// Percentage of the budget given to memtables, the rest goes to the block
+/// cache.
+const MEMTABLE_SHARE_PERCENT: u64 = 25;
+
+/// Memtables are flushed once they buffer this percentage of their share.
+const FLUSH_THRESHOLD_PERCENT: u64 = 80;
+
+/// How a memory budget is split between the pools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    /// Bytes of writes buffered in memtables before they are flushed.
+    pub memtables: u64,
+    /// Bytes of the block cache.
+    pub block_cache: u64,
+}
+
+impl MemoryBudget {
+    /// Split a total budget in bytes.
+    pub fn split(total: u64) -> Self {
+        let memtables = percent(total, MEMTABLE_SHARE_PERCENT);
+        MemoryBudget {
+            memtables,
+            block_cache: total - memtables,
+        }
+    }
+
+    /// Bytes buffered in memtables at which they are flushed.
+    fn flush_threshold(&self) -> u64 {
+        percent(self.memtables, FLUSH_THRESHOLD_PERCENT)
+    }
+}
+
+/// `share` percent of `bytes`, without overflowing.
+fn percent(bytes: u64, share: u64) -> u64 {
+    (u128::from(bytes) * u128::from(share) / 100) as u64
+}
+
+/// Tracks the writes buffered in memtables against the budget.
+///
+/// Usage is estimated from the size of the committed keys and values, which
+/// is an upper bound: overwritten entries take no extra room.
+pub struct MemoryTracker {
+    budget: MemoryBudget,
+    unflushed: AtomicU64,
+    flushes: AtomicU64,
+}
+
+impl MemoryTracker {
+    pub fn new(budget: MemoryBudget) -> Self {
+        MemoryTracker {
+            budget,
+            unflushed: AtomicU64::new(0),
+            flushes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn budget(&self) -> MemoryBudget {
+        self.budget
+    }
+
+    /// Estimated bytes buffered in memtables.
+    pub fn memtable_bytes(&self) -> u64 {
+        self.unflushed.load(Ordering::Relaxed)
+    }
+
+    /// Account for `bytes` of committed writes. Returns true for the write
+    /// crossing the flush threshold, whose caller should flush.
+    pub fn record_write(&self, bytes: u64) -> bool {
+        let before = self.unflushed.fetch_add(bytes, Ordering::Relaxed);
+        let threshold = self.budget.flush_threshold();
+        before < threshold && before + bytes >= threshold
+    }
+
+    /// The memtables were handed over to be flushed.
+    pub fn flushed(&self) {
+        self.unflushed.store(0, Ordering::Relaxed);
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_memory_budget_bytes Memory budget of each pool."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_memory_budget_bytes gauge");
+        let _ = writeln!(
+            out,
+            "zerotable_memory_budget_bytes{{pool=\"memtables\"}} {}",
+            self.budget.memtables
+        );
+        let _ = writeln!(
+            out,
+            "zerotable_memory_budget_bytes{{pool=\"block_cache\"}} {}",
+            self.budget.block_cache
+        );
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_memtable_bytes Estimated bytes of writes buffered in memtables."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_memtable_bytes gauge");
+        let _ = writeln!(out, "zerotable_memtable_bytes {}", self.memtable_bytes());
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_memtable_flushes_total Memtable flushes started by the memory budget."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_memtable_flushes_total counter");
+        let _ = writeln!(
+            out,
+            "zerotable_memtable_flushes_total {}",
+            self.flushes.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let budget = MemoryBudget::split(DEFAULT_MEMORY_BUDGET);
+        assert_eq!(budget.memtables + budget.block_cache, DEFAULT_MEMORY_BUDGET);
+        assert_eq!(budget.memtables, DEFAULT_MEMORY_BUDGET / 4);
+    }
+
+    #[test]
+    fn test_flush_once_per_crossing() {
+        let tracker = MemoryTracker::new(MemoryBudget {
+            memtables: 1000,
+            block_cache: 0,
+        });
+        assert!(!tracker.record_write(500));
+        assert!(tracker.record_write(300));
+        // past the threshold, the flush is already under way
+        assert!(!tracker.record_write(300));
+        assert_eq!(tracker.memtable_bytes(), 1100);
+
+        tracker.flushed();
+        assert_eq!(tracker.memtable_bytes(), 0);
+        assert!(!tracker.record_write(100));
+        assert!(
+            tracker
+                .render()
+                .contains("zerotable_memtable_flushes_total 1\n")
+        );
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! K-way merge of sorted scans.
+//!
+//! Used to read several key ranges, e.g. the same document IDs spread over
+//! many collections, as a single ordered stream. Only the head of each source
+//! is held in memory.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Head of one source, ordered by its sort key then by source index so that
+/// equal keys come out in the order the sources were given.
+struct Head<K, T> {
+    key: K,
+    source: usize,
+    item: T,
+}
+
+impl<K: Ord, T> PartialEq for Head<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, T> Eq for Head<K, T> {}
+
+impl<K: Ord, T> PartialOrd for Head<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, T> Ord for Head<K, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key
+            .cmp(&other.key)
+            .then(self.source.cmp(&other.source))
+    }
+}
+
+/// Iterator merging sources that are each sorted by `key`.
+///
+/// An error is yielded right after the item that preceded it in its source.
+pub struct MergeBy<I, F, K, T, E> {
+    sources: Vec<I>,
+    heap: BinaryHeap<Reverse<Head<K, T>>>,
+    key: F,
+    started: bool,
+    error: Option<E>,
+}
+
+impl<I, F, K, T, E> MergeBy<I, F, K, T, E>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: FnMut(&T) -> K,
+    K: Ord,
+{
+    pub fn new(sources: Vec<I>, key: F) -> Self {
+        MergeBy {
+            heap: BinaryHeap::with_capacity(sources.len()),
+            sources,
+            key,
+            started: false,
+            error: None,
+        }
+    }
+
+    /// Pull the next item of `source` into the heap.
+    fn advance(&mut self, source: usize) -> Result<(), E> {
+        if let Some(item) = self.sources[source].next() {
+            let item = item?;
+            let key = (self.key)(&item);
+            self.heap.push(Reverse(Head { key, source, item }));
+        }
+        Ok(())
+    }
+}
+
+impl<I, F, K, T, E> Iterator for MergeBy<I, F, K, T, E>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: FnMut(&T) -> K,
+    K: Ord,
+{
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                if let Err(e) = self.advance(source) {
+                    self.error.get_or_insert(e);
+                }
+            }
+        }
+
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+
+        let Reverse(head) = self.heap.pop()?;
+        if let Err(e) = self.advance(head.source) {
+            self.error = Some(e);
+        }
+        Some(Ok(head.item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(
+        items: &[(&'static str, u32)],
+    ) -> std::vec::IntoIter<Result<(&'static str, u32), ()>> {
+        items
+            .iter()
+            .copied()
+            .map(Ok)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_merge_order() {
+        let merged: Vec<_> = MergeBy::new(
+            vec![
+                source(&[("a", 1), ("c", 1), ("e", 1)]),
+                source(&[]),
+                source(&[("b", 3), ("c", 3), ("d", 3)]),
+            ],
+            |(key, _)| *key,
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+        assert_eq!(
+            merged,
+            [("a", 1), ("b", 3), ("c", 1), ("c", 3), ("d", 3), ("e", 1)]
+        );
+    }
+
+    #[test]
+    fn test_merge_error() {
+        let failing = vec![Ok(("b", 2)), Err(())].into_iter();
+        let mut merged = MergeBy::new(vec![source(&[("a", 1), ("c", 1)]), failing], |(key, _)| {
+            *key
+        });
+
+        assert_eq!(merged.next(), Some(Ok(("a", 1))));
+        assert_eq!(merged.next(), Some(Ok(("b", 2))));
+        assert_eq!(merged.next(), Some(Err(())));
+    }
+}
@@ -0,0 +1,385 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Request mirroring.
+//!
+//! A sampled fraction of the unary document writes, and optionally reads, a
+//! server serves is sent again to a secondary server, once the client has
+//! its response, and the two outcomes are compared: a write diverges if it
+//! fails on one server only or with another code, a read if its response
+//! differs too. Divergences are counted per method, so an upgrade can be
+//! checked against real traffic before the cutover. A read only matches on
+//! a secondary holding the same documents, e.g. restored from an export,
+//! timestamps included.
+//!
+//! Mirroring never slows down or fails a client: requests are sent in the
+//! background, and dropped while [`MAX_IN_FLIGHT`] are waiting for the
+//! secondary. Streaming RPCs are not mirrored, nor requests the primary
+//! refused because of its own load or the client's deadline.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use prost::Message;
+use serde_json::json;
+use tokio::sync::Semaphore;
+use tonic::client::Grpc;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status};
+use tonic_prost::ProstCodec;
+
+use crate::api::v1alpha1::zerotable_server::SERVICE_NAME;
+use crate::rate_limit::Operation;
+
+/// Mirrored requests waiting for the secondary at most, more are dropped.
+pub const MAX_IN_FLIGHT: usize = 64;
+
+/// How long the secondary has to answer a mirrored request.
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Kind of the unary RPC `method`, `None` if it is not mirrored.
+fn operation(method: &str) -> Option<Operation> {
+    match method {
+        "CreateDocument" | "CreateDocumentTree" | "UpdateDocument" | "DeleteDocument"
+        | "TransformDocument" | "UpdateWhere" | "DeleteWhere" | "CopyDocument" | "MoveDocument"
+        | "UndeleteDocument" | "PurgeDocument" | "IncrementCounter" => Some(Operation::Write),
+        "GetDocument"
+        | "DocumentExists"
+        | "BatchGetDocuments"
+        | "ListDocuments"
+        | "RunAggregationQuery"
+        | "GetCounter" => Some(Operation::Read),
+        _ => None,
+    }
+}
+
+/// Picks an evenly spread `fraction` of the requests.
+struct Sampler {
+    fraction: f64,
+    seen: AtomicU64,
+}
+
+impl Sampler {
+    fn new(fraction: f64) -> Self {
+        Sampler {
+            fraction,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether to pick the next request: the one taking the count of those
+    /// picked to the next integer.
+    fn sample(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((seen + 1.0) * self.fraction).floor() > (seen * self.fraction).floor()
+    }
+}
+
+/// How a mirrored request compares to the primary's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Matched,
+    Diverged,
+    /// The secondary could not be reached, or was too loaded to answer.
+    Failed,
+}
+
+/// Compare the outcome on the secondary to that on the `primary`, whose
+/// response is only given for reads.
+fn compare<Res: PartialEq>(
+    primary: &Result<Option<Res>, Code>,
+    secondary: &Result<Res, Status>,
+) -> Outcome {
+    match (primary, secondary) {
+        (Ok(Some(primary)), Ok(secondary)) if primary != secondary => Outcome::Diverged,
+        (Ok(_), Ok(_)) => Outcome::Matched,
+        (_, Err(status))
+            if matches!(
+                status.code(),
+                Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted
+            ) && primary.as_ref().err() != Some(&status.code()) =>
+        {
+            Outcome::Failed
+        }
+        (Err(primary), Err(secondary)) if *primary == secondary.code() => Outcome::Matched,
+        _ => Outcome::Diverged,
+    }
+}
+
+/// Mirrors requests to a secondary server.
+pub struct Mirror {
+    grpc: Grpc<Channel>,
+    writes: Sampler,
+    reads: Option<Sampler>,
+    in_flight: Arc<Semaphore>,
+    metrics: Arc<MirrorMetrics>,
+}
+
+impl Mirror {
+    /// Mirror `fraction` of the writes, and of the reads if `reads`, to the
+    /// server at `endpoint`, connected to on first use.
+    pub fn new(
+        endpoint: &str,
+        fraction: f64,
+        reads: bool,
+    ) -> Result<Self, tonic::transport::Error> {
+        let channel = Endpoint::from_shared(endpoint.to_string())?
+            .timeout(MIRROR_TIMEOUT)
+            .connect_lazy();
+        Ok(Mirror {
+            grpc: Grpc::new(channel),
+            writes: Sampler::new(fraction),
+            reads: reads.then(|| Sampler::new(fraction)),
+            in_flight: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+            metrics: Arc::default(),
+        })
+    }
+
+    /// Whether to mirror the next request of `method`.
+    pub fn sample(&self, method: &str) -> bool {
+        match operation(method) {
+            Some(Operation::Write) => self.writes.sample(),
+            Some(Operation::Read) => self.reads.as_ref().is_some_and(Sampler::sample),
+            None => false,
+        }
+    }
+
+    /// Send `message` of `method`, with the `metadata` of the client, to the
+    /// secondary in the background, and compare its outcome to `primary`.
+    pub fn send<Req, Res>(
+        &self,
+        method: &'static str,
+        message: Req,
+        mut metadata: MetadataMap,
+        primary: Result<&Res, Code>,
+    ) where
+        Req: Message + 'static,
+        Res: Message + Default + PartialEq + Clone + 'static,
+    {
+        if let Err(Code::Cancelled | Code::DeadlineExceeded | Code::ResourceExhausted) = primary {
+            return;
+        }
+        let read = operation(method) == Some(Operation::Read);
+        let primary = primary.map(|response| read.then(|| response.clone()));
+        let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        // the deadline of the client is not the secondary's
+        metadata.remove("grpc-timeout");
+        let mut request = Request::new(message);
+        *request.metadata_mut() = metadata;
+        request.set_timeout(MIRROR_TIMEOUT);
+        let path = PathAndQuery::try_from(format!("/{SERVICE_NAME}/{method}"))
+            .expect("method names are valid paths");
+        let mut grpc = self.grpc.clone();
+        let metrics = self.metrics.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let secondary = match grpc.ready().await {
+                Ok(()) => grpc
+                    .unary(request, path, ProstCodec::<Req, Res>::default())
+                    .await
+                    .map(Response::into_inner),
+                Err(e) => Err(Status::unavailable(e.to_string())),
+            };
+            let outcome = compare(&primary, &secondary);
+            if outcome != Outcome::Matched {
+                tracing::debug!(
+                    method,
+                    ?outcome,
+                    primary = ?primary.as_ref().err(),
+                    secondary = ?secondary.as_ref().err().map(Status::code),
+                    "mirrored request did not match"
+                );
+            }
+            metrics.record(method, outcome);
+        });
+    }
+
+    /// Mirroring counters.
+    pub fn metrics(&self) -> &MirrorMetrics {
+        &self.metrics
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct MethodCounts {
+    mirrored: u64,
+    diverged: u64,
+    failed: u64,
+}
+
+/// Counters of the mirrored requests.
+#[derive(Default)]
+pub struct MirrorMetrics {
+    methods: Mutex<BTreeMap<&'static str, MethodCounts>>,
+    dropped: AtomicU64,
+}
+
+impl MirrorMetrics {
+    fn record(&self, method: &'static str, outcome: Outcome) {
+        let mut methods = self.methods.lock().expect("mirror metrics lock poisoned");
+        let counts = methods.entry(method).or_default();
+        counts.mirrored += 1;
+        match outcome {
+            Outcome::Matched => {}
+            Outcome::Diverged => counts.diverged += 1,
+            Outcome::Failed => counts.failed += 1,
+        }
+    }
+
+    /// Point in time copy of the metrics, for stats dumps.
+    pub fn to_json(&self) -> serde_json::Value {
+        let methods = self.methods.lock().expect("mirror metrics lock poisoned");
+        let methods: serde_json::Map<_, _> = methods
+            .iter()
+            .map(|(method, counts)| {
+                let counts = json!({
+                    "mirrored": counts.mirrored,
+                    "diverged": counts.diverged,
+                    "failed": counts.failed,
+                });
+                (method.to_string(), counts)
+            })
+            .collect();
+        json!({
+            "methods": methods,
+            "dropped": self.dropped.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let methods = self.methods.lock().expect("mirror metrics lock poisoned");
+        let mut out = String::new();
+        let counters: [(&str, &str, fn(&MethodCounts) -> u64); 3] = [
+            (
+                "zerotable_mirrored_requests_total",
+                "Requests mirrored to the secondary.",
+                |counts| counts.mirrored,
+            ),
+            (
+                "zerotable_mirror_divergences_total",
+                "Mirrored requests whose outcome differed on the secondary.",
+                |counts| counts.diverged,
+            ),
+            (
+                "zerotable_mirror_failures_total",
+                "Mirrored requests the secondary did not answer.",
+                |counts| counts.failed,
+            ),
+        ];
+        for (name, help, count) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (method, counts) in methods.iter() {
+                let _ = writeln!(out, "{name}{{method=\"{method}\"}} {}", count(counts));
+            }
+        }
+        let _ = writeln!(
+            out,
+            "# HELP zerotable_mirror_dropped_total Requests not mirrored because too many were in flight."
+        );
+        let _ = writeln!(out, "# TYPE zerotable_mirror_dropped_total counter");
+        let _ = writeln!(
+            out,
+            "zerotable_mirror_dropped_total {}",
+            self.dropped.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampler() {
+        let picked = |fraction| {
+            let sampler = Sampler::new(fraction);
+            (0..1000).filter(|_| sampler.sample()).count()
+        };
+        assert_eq!(picked(1.0), 1000);
+        assert_eq!(picked(0.1), 100);
+        assert_eq!(picked(0.25), 250);
+        assert_eq!(picked(0.0), 0);
+
+        // evenly spread
+        let sampler = Sampler::new(0.5);
+        let picks: Vec<_> = (0..4).map(|_| sampler.sample()).collect();
+        assert_eq!(picks, [false, true, false, true]);
+    }
+
+    #[test]
+    fn test_operation() {
+        assert_eq!(operation("UpdateDocument"), Some(Operation::Write));
+        assert_eq!(operation("GetDocument"), Some(Operation::Read));
+        assert_eq!(operation("GetServerInfo"), None);
+        assert_eq!(operation("BulkWrite"), None);
+    }
+
+    #[test]
+    fn test_compare() {
+        let ok = |value: u32| -> Result<u32, Status> { Ok(value) };
+        let err = |code| -> Result<u32, Status> { Err(Status::new(code, "")) };
+
+        // writes, compared by code
+        assert_eq!(compare(&Ok(None), &ok(2)), Outcome::Matched);
+        assert_eq!(compare(&Ok(None), &err(Code::NotFound)), Outcome::Diverged);
+        assert_eq!(
+            compare(&Err(Code::NotFound), &err(Code::NotFound)),
+            Outcome::Matched
+        );
+        assert_eq!(
+            compare(&Err(Code::NotFound), &err(Code::AlreadyExists)),
+            Outcome::Diverged
+        );
+        assert_eq!(compare(&Err(Code::NotFound), &ok(1)), Outcome::Diverged);
+
+        // reads, compared by response too
+        assert_eq!(compare(&Ok(Some(1)), &ok(1)), Outcome::Matched);
+        assert_eq!(compare(&Ok(Some(1)), &ok(2)), Outcome::Diverged);
+
+        // unreachable or loaded secondary
+        assert_eq!(
+            compare(&Ok(Some(1)), &err(Code::Unavailable)),
+            Outcome::Failed
+        );
+        assert_eq!(
+            compare(&Err(Code::NotFound), &err(Code::DeadlineExceeded)),
+            Outcome::Failed
+        );
+        assert_eq!(
+            compare(&Err(Code::Unavailable), &err(Code::Unavailable)),
+            Outcome::Matched
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metrics = MirrorMetrics::default();
+        metrics.record("GetDocument", Outcome::Matched);
+        metrics.record("GetDocument", Outcome::Diverged);
+        metrics.record("UpdateDocument", Outcome::Failed);
+        metrics.dropped.fetch_add(3, Ordering::Relaxed);
+
+        let text = metrics.render();
+        assert!(text.contains("zerotable_mirrored_requests_total{method=\"GetDocument\"} 2\n"));
+        assert!(text.contains("zerotable_mirror_divergences_total{method=\"GetDocument\"} 1\n"));
+        assert!(text.contains("zerotable_mirror_failures_total{method=\"UpdateDocument\"} 1\n"));
+        assert!(text.contains("zerotable_mirror_dropped_total 3\n"));
+
+        let json = metrics.to_json();
+        assert_eq!(json["methods"]["GetDocument"]["diverged"], 1);
+        assert_eq!(json["dropped"], 3);
+    }
+}
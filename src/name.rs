@@ -0,0 +1,223 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Resource names of documents, like `collection_id/document_id` in the
+//! default database and
+//! `databases/{database_id}/collections/{collection_id}/documents/{document_id}`
+//! in others. Parsing accepts the long form for the default database too.
+//!
+//! Document IDs may contain forward slashes, e.g. `a/b@example.com`, which
+//! would be ambiguous in a name. Collection and document IDs are
+//! percent-encoded: `/` becomes `%2F` and `%` becomes `%25`, every other
+//! character is kept as is. Parsing accepts any `%XX` escape.
+//!
+//! Names map to the collection IDs the engine stores, see
+//! [`keys::qualify`].
+
+use std::fmt;
+
+use crate::keys::{self, DEFAULT_DATABASE, KeyError};
+
+/// Error returned when a resource name cannot be parsed.
+#[derive(Debug, PartialEq)]
+pub enum NameError {
+    /// Not two non-empty parts separated by a single slash.
+    Malformed,
+    /// A `%` not followed by two hex digits.
+    InvalidEscape,
+    /// The decoded part is not valid UTF-8.
+    InvalidUtf8,
+    /// The database ID is invalid or the decoded collection ID contains a
+    /// slash.
+    InvalidId(KeyError),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Malformed => write!(
+                f,
+                "name must be in format 'collection_id/document_id' or \
+                 'databases/{{database_id}}/collections/{{collection_id}}/documents/{{document_id}}'"
+            ),
+            NameError::InvalidEscape => write!(f, "name contains an invalid percent escape"),
+            NameError::InvalidUtf8 => write!(f, "name is not valid UTF-8 once decoded"),
+            NameError::InvalidId(e) => write!(f, "invalid name: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Build the resource name of a collection as stored: its escaped ID in the
+/// default database, `databases/{database_id}/collections/{collection_id}`
+/// in others.
+pub fn format_collection(collection: &str) -> String {
+    let (database_id, collection_id) = keys::unqualify(collection);
+    let mut name = String::with_capacity(collection.len() + 24);
+    if database_id != DEFAULT_DATABASE {
+        name.push_str("databases/");
+        name.push_str(database_id);
+        name.push_str("/collections/");
+    }
+    escape_into(&mut name, collection_id);
+    name
+}
+
+/// Build the resource name of a document of a collection as stored.
+pub fn format(collection: &str, doc_id: &str) -> String {
+    let mut name = format_collection(collection);
+    if keys::unqualify(collection).0 == DEFAULT_DATABASE {
+        name.push('/');
+    } else {
+        name.push_str("/documents/");
+    }
+    escape_into(&mut name, doc_id);
+    name
+}
+
+/// Split a resource name into the collection ID as stored and the decoded
+/// document ID.
+pub fn parse(name: &str) -> Result<(String, String), NameError> {
+    let parts: Vec<&str> = name.split('/').collect();
+    let (database_id, collection_id, doc_id) = match parts.as_slice() {
+        [collection_id, doc_id] => (DEFAULT_DATABASE, *collection_id, *doc_id),
+        [
+            "databases",
+            database_id,
+            "collections",
+            collection_id,
+            "documents",
+            doc_id,
+        ] => (*database_id, *collection_id, *doc_id),
+        _ => return Err(NameError::Malformed),
+    };
+    if collection_id.is_empty() || doc_id.is_empty() {
+        return Err(NameError::Malformed);
+    }
+    let collection =
+        keys::qualify(database_id, &unescape(collection_id)?).map_err(NameError::InvalidId)?;
+    Ok((collection, unescape(doc_id)?))
+}
+
+fn escape_into(out: &mut String, part: &str) {
+    for c in part.chars() {
+        match c {
+            '/' => out.push_str("%2F"),
+            '%' => out.push_str("%25"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn unescape(part: &str) -> Result<String, NameError> {
+    if !part.contains('%') {
+        return Ok(part.to_string());
+    }
+
+    let bytes = part.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex_digit = |j: usize| {
+            let digit = char::from(*bytes.get(j)?).to_digit(16)?;
+            Some(digit as u8)
+        };
+        match (hex_digit(i + 1), hex_digit(i + 2)) {
+            (Some(high), Some(low)) => out.push(high << 4 | low),
+            _ => return Err(NameError::InvalidEscape),
+        }
+        i += 3;
+    }
+    String::from_utf8(out).map_err(|_| NameError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for (collection_id, doc_id) in [
+            ("users", "alice"),
+            ("users", "a/b@example.com"),
+            ("users", "100%"),
+            ("users", "%2F"),
+            ("città", "über/straße"),
+        ] {
+            let name = format(collection_id, doc_id);
+            assert_eq!(
+                parse(&name),
+                Ok((collection_id.to_string(), doc_id.to_string()))
+            );
+        }
+        assert_eq!(format("users", "a/b%"), "users/a%2Fb%25");
+    }
+
+    #[test]
+    fn test_databases() {
+        let name = format("acme/users", "a/b");
+        assert_eq!(name, "databases/acme/collections/users/documents/a%2Fb");
+        assert_eq!(
+            format_collection("acme/users"),
+            "databases/acme/collections/users"
+        );
+        assert_eq!(format_collection("a%b"), "a%25b");
+        assert_eq!(
+            parse(&name),
+            Ok(("acme/users".to_string(), "a/b".to_string()))
+        );
+        assert_eq!(
+            parse("databases/(default)/collections/users/documents/alice"),
+            Ok(("users".to_string(), "alice".to_string()))
+        );
+        // a short name with two parts is a collection named 'databases'
+        assert_eq!(
+            parse("databases/acme"),
+            Ok(("databases".to_string(), "acme".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        assert_eq!(
+            parse("users/a%2fb%40c"),
+            Ok(("users".to_string(), "a/b@c".to_string()))
+        );
+        assert_eq!(
+            parse("users/%C3%A9"),
+            Ok(("users".to_string(), "é".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("users"), Err(NameError::Malformed));
+        assert_eq!(parse("/doc"), Err(NameError::Malformed));
+        assert_eq!(parse("users/"), Err(NameError::Malformed));
+        assert_eq!(parse("users/a/b"), Err(NameError::Malformed));
+        assert_eq!(parse("users/a%2"), Err(NameError::InvalidEscape));
+        assert_eq!(parse("users/a%zz"), Err(NameError::InvalidEscape));
+        assert_eq!(parse("users/%+1"), Err(NameError::InvalidEscape));
+        assert_eq!(parse("users/%FF"), Err(NameError::InvalidUtf8));
+        // an escaped slash must not reach into another database
+        assert_eq!(
+            parse("acme%2Fusers/doc"),
+            Err(NameError::InvalidId(KeyError::ContainsSlash))
+        );
+        assert_eq!(
+            parse("databases/ACME/collections/users/documents/doc"),
+            Err(NameError::InvalidId(KeyError::InvalidDatabaseId))
+        );
+        assert_eq!(
+            parse("databases/acme/collections/users/doc"),
+            Err(NameError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Long-running operations.
+//!
+//! Work too slow for a single RPC, like exporting a database to a file, runs
+//! in the background as an operation: the RPC starting it returns at once
+//! with its ID, the client polls it until it is done and may cancel it, in
+//! the spirit of google.longrunning.
+//!
+//! Operations live in memory only, a restart forgets them and stops those
+//! running. Finished operations can be polled for
+//! [`FINISHED_OPERATION_TTL`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use tonic::{Code, Status};
+
+use crate::deadline::Deadline;
+use crate::id::{generate_uuid_v7, now_millis};
+
+/// How long a finished operation is remembered.
+pub const FINISHED_OPERATION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where an operation is at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationState {
+    Running,
+    Succeeded,
+    Failed {
+        code: Code,
+        message: String,
+    },
+    /// Stopped by [`Operations::cancel`] before it was done.
+    Cancelled,
+}
+
+/// What is known of an operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationInfo {
+    pub id: String,
+    /// What the operation does, e.g. `EXPORT`.
+    pub kind: &'static str,
+    /// What it works on, e.g. the export file.
+    pub target: String,
+    pub state: OperationState,
+    /// Documents processed so far.
+    pub documents: u64,
+    pub start_time: SystemTime,
+    /// `None` while running.
+    pub end_time: Option<SystemTime>,
+}
+
+/// Handle of the work of an operation, to report progress and notice
+/// cancellation.
+#[derive(Debug, Clone)]
+pub struct OperationHandle {
+    id: String,
+    documents: Arc<AtomicU64>,
+    deadline: Deadline,
+}
+
+impl OperationHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Count `documents` more processed.
+    pub fn add_documents(&self, documents: u64) {
+        self.documents.fetch_add(documents, Ordering::Relaxed);
+    }
+
+    /// Expires once the operation is cancelled, never otherwise.
+    pub fn deadline(&self) -> &Deadline {
+        &self.deadline
+    }
+}
+
+struct Entry {
+    info: OperationInfo,
+    documents: Arc<AtomicU64>,
+    deadline: Deadline,
+    cancel_requested: bool,
+}
+
+impl Entry {
+    fn info(&self) -> OperationInfo {
+        OperationInfo {
+            documents: self.documents.load(Ordering::Relaxed),
+            ..self.info.clone()
+        }
+    }
+
+    fn cancel(&mut self) {
+        if self.info.state == OperationState::Running {
+            self.cancel_requested = true;
+            self.deadline.cancel();
+        }
+    }
+}
+
+/// Operations of a server, shared by clones.
+#[derive(Clone, Default)]
+pub struct Operations {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl Operations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a running operation of `kind` on `target`, returns it and the
+    /// handle of its work.
+    pub fn start(&self, kind: &'static str, target: &str) -> (OperationInfo, OperationHandle) {
+        let (id, start_time) = generate_uuid_v7();
+        let handle = OperationHandle {
+            id: id.to_string(),
+            documents: Arc::default(),
+            deadline: Deadline::none(),
+        };
+        let info = OperationInfo {
+            id: handle.id.clone(),
+            kind,
+            target: target.to_string(),
+            state: OperationState::Running,
+            documents: 0,
+            start_time,
+            end_time: None,
+        };
+
+        let mut entries = self.lock();
+        prune(&mut entries, start_time);
+        entries.insert(
+            handle.id.clone(),
+            Entry {
+                info: info.clone(),
+                documents: handle.documents.clone(),
+                deadline: handle.deadline.clone(),
+                cancel_requested: false,
+            },
+        );
+        (info, handle)
+    }
+
+    /// Record the outcome of the work of operation `id`. A failure after
+    /// the operation was cancelled is its cancellation.
+    pub fn finish(&self, id: &str, result: Result<(), Status>) {
+        let mut entries = self.lock();
+        let Some(entry) = entries.get_mut(id) else {
+            return;
+        };
+        entry.info.state = match result {
+            Ok(()) => OperationState::Succeeded,
+            Err(_) if entry.cancel_requested => OperationState::Cancelled,
+            Err(status) => OperationState::Failed {
+                code: status.code(),
+                message: status.message().to_string(),
+            },
+        };
+        entry.info.end_time = Some(now_millis());
+    }
+
+    /// Operation `id`, `None` if unknown or forgotten.
+    pub fn get(&self, id: &str) -> Option<OperationInfo> {
+        self.lock().get(id).map(Entry::info)
+    }
+
+    /// Every operation remembered, oldest first.
+    pub fn list(&self) -> Vec<OperationInfo> {
+        let mut all: Vec<_> = self.lock().values().map(Entry::info).collect();
+        // version 7 UUIDs sort by creation time
+        all.sort_by(|a, b| a.id.cmp(&b.id));
+        all
+    }
+
+    /// Ask operation `id` to stop, its work stops the next time it checks.
+    /// Finished operations are left as they are. Returns `None` if unknown.
+    pub fn cancel(&self, id: &str) -> Option<OperationInfo> {
+        let mut entries = self.lock();
+        let entry = entries.get_mut(id)?;
+        entry.cancel();
+        Some(entry.info())
+    }
+
+    /// Ask every running operation to stop, e.g. before shutting down.
+    pub fn cancel_all(&self) {
+        self.lock().values_mut().for_each(Entry::cancel);
+    }
+
+    /// Number of operations whose work is still running.
+    pub fn running(&self) -> usize {
+        self.lock()
+            .values()
+            .filter(|entry| entry.info.state == OperationState::Running)
+            .count()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().expect("operations lock poisoned")
+    }
+}
+
+/// Forget the operations finished for [`FINISHED_OPERATION_TTL`] at `now`.
+fn prune(entries: &mut HashMap<String, Entry>, now: SystemTime) {
+    entries.retain(|_, entry| match entry.info.end_time {
+        Some(end_time) => now.duration_since(end_time).unwrap_or_default() < FINISHED_OPERATION_TTL,
+        None => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle() {
+        let operations = Operations::new();
+        let (info, handle) = operations.start("EXPORT", "users.export");
+        assert_eq!(info.state, OperationState::Running);
+        assert_eq!(info.id, handle.id());
+
+        handle.add_documents(3);
+        handle.add_documents(2);
+        assert_eq!(operations.get(handle.id()).unwrap().documents, 5);
+
+        operations.finish(handle.id(), Ok(()));
+        let done = operations.get(handle.id()).unwrap();
+        assert_eq!(done.state, OperationState::Succeeded);
+        assert!(done.end_time.is_some());
+
+        // cancelling a finished operation changes nothing
+        assert_eq!(operations.cancel(handle.id()).unwrap(), done);
+        assert!(!handle.deadline().is_expired());
+        assert_eq!(operations.get("missing"), None);
+        assert_eq!(operations.cancel("missing"), None);
+    }
+
+    #[test]
+    fn test_cancel_and_fail() {
+        let operations = Operations::new();
+        let (_, cancelled) = operations.start("EXPORT", "a");
+        let (_, failed) = operations.start("IMPORT", "b");
+
+        operations.cancel(cancelled.id()).unwrap();
+        assert!(cancelled.deadline().is_expired());
+        assert!(!failed.deadline().is_expired());
+        assert_eq!(operations.running(), 2);
+        operations.finish(cancelled.id(), Err(Status::deadline_exceeded("stop")));
+        operations.finish(failed.id(), Err(Status::not_found("no such file")));
+
+        let states: Vec<_> = operations
+            .list()
+            .into_iter()
+            .map(|info| (info.target, info.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("a".to_string(), OperationState::Cancelled),
+                (
+                    "b".to_string(),
+                    OperationState::Failed {
+                        code: Code::NotFound,
+                        message: "no such file".to_string(),
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancel_all() {
+        let operations = Operations::new();
+        let (_, running) = operations.start("EXPORT", "a");
+        let (_, done) = operations.start("IMPORT", "b");
+        operations.finish(done.id(), Ok(()));
+
+        operations.cancel_all();
+        assert!(running.deadline().is_expired());
+        assert!(!done.deadline().is_expired());
+        assert_eq!(operations.running(), 1);
+        operations.finish(running.id(), Err(Status::deadline_exceeded("stop")));
+        assert_eq!(operations.running(), 0);
+        let info = operations.get(running.id()).unwrap();
+        assert_eq!(info.state, OperationState::Cancelled);
+    }
+
+    #[test]
+    fn test_prune() {
+        let operations = Operations::new();
+        let (_, finished) = operations.start("EXPORT", "a");
+        let (_, running) = operations.start("EXPORT", "b");
+        operations.finish(finished.id(), Ok(()));
+
+        let later = now_millis() + FINISHED_OPERATION_TTL;
+        prune(&mut operations.lock(), later);
+        assert_eq!(operations.get(finished.id()), None);
+        assert!(operations.get(running.id()).is_some());
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Panics in engine work.
+//!
+//! Engine work run for a request, see [`AsyncEngine`](crate::AsyncEngine),
+//! is wrapped in [`catch`], so a panic fails the request with `INTERNAL`
+//! rather than a vague failed task, and is logged with its backtrace and
+//! the request ID by the service. Panics are counted process wide.
+//!
+//! Backtraces are captured by the hook [`install_hook`] installs, whatever
+//! `RUST_BACKTRACE` says. Panics outside [`catch`] are left to the previous
+//! hook.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static PANICS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Whether the thread runs in [`catch`].
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Backtrace of the panic being caught on the thread.
+    static BACKTRACE: Cell<Option<Backtrace>> = const { Cell::new(None) };
+}
+
+/// Capture the backtraces of the panics [`catch`] catches. Call once at
+/// startup.
+pub fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CATCHING.get() {
+            BACKTRACE.set(Some(Backtrace::force_capture()));
+        } else {
+            previous(info);
+        }
+    }));
+}
+
+/// A panic caught by [`catch`].
+#[derive(Debug)]
+pub struct Panic {
+    pub message: String,
+    /// `None` unless [`install_hook`] was called.
+    pub backtrace: Option<Backtrace>,
+}
+
+/// Run `f`, returning its panic if it panics.
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Panic> {
+    let catching = CATCHING.replace(true);
+    // engine state is transactional, a panic drops the open transaction
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.set(catching);
+
+    result.map_err(|payload| {
+        PANICS.fetch_add(1, Ordering::Relaxed);
+        Panic {
+            message: message(payload.as_ref()),
+            backtrace: BACKTRACE.take(),
+        }
+    })
+}
+
+/// Message of a panic payload, as the default hook prints it.
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Number of panics caught so far.
+pub fn count() -> u64 {
+    PANICS.load(Ordering::Relaxed)
+}
+
+/// Render the panic counter in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP zerotable_panics_total Panics caught in engine work."
+    );
+    let _ = writeln!(out, "# TYPE zerotable_panics_total counter");
+    let _ = writeln!(out, "zerotable_panics_total {}", count());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch() {
+        assert_eq!(catch(|| 1).unwrap(), 1);
+
+        install_hook();
+        let before = count();
+        let n = 1;
+        let panic = catch(|| -> u32 { panic!("boom {n}") }).unwrap_err();
+        assert_eq!(panic.message, "boom 1");
+        assert!(panic.backtrace.is_some());
+        let panic = catch(|| std::panic::panic_any(7)).unwrap_err();
+        assert_eq!(panic.message, "Box<dyn Any>");
+        assert!(count() >= before + 2);
+        assert!(render().contains("# TYPE zerotable_panics_total counter\n"));
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Payload size metrics.
+//!
+//! Encoded sizes of the request and response messages of every unary RPC,
+//! per method, rendered in the Prometheus text format. Large payloads are the
+//! usual suspects when latency or memory use climbs. Streaming RPCs are not
+//! measured, their messages are export chunks and import batches.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde_json::json;
+
+use crate::contention::{Histogram, render_header};
+
+/// Upper bounds of the payload size buckets, in bytes.
+const SIZE_BUCKETS: [u64; 9] = [64, 256, 1024, 4096, 16384, 65536, 262144, 1048576, 4194304];
+
+type SizeHistogram = Histogram<{ SIZE_BUCKETS.len() }>;
+
+/// Sizes of the messages of one method.
+struct MethodSizes {
+    requests: SizeHistogram,
+    responses: SizeHistogram,
+}
+
+/// Payload sizes shared by every clone of the service.
+#[derive(Default)]
+pub struct PayloadMetrics {
+    methods: Mutex<BTreeMap<&'static str, MethodSizes>>,
+}
+
+impl PayloadMetrics {
+    fn with_method(&self, method: &'static str, f: impl FnOnce(&MethodSizes)) {
+        let mut methods = self.methods.lock().expect("payload metrics lock poisoned");
+        let sizes = methods.entry(method).or_insert_with(|| MethodSizes {
+            requests: Histogram::new(SIZE_BUCKETS),
+            responses: Histogram::new(SIZE_BUCKETS),
+        });
+        f(sizes);
+    }
+
+    /// Record a request of `method` of `size` bytes.
+    pub fn record_request(&self, method: &'static str, size: usize) {
+        self.with_method(method, |sizes| sizes.requests.observe(size as u64));
+    }
+
+    /// Record a response of `method` of `size` bytes.
+    pub fn record_response(&self, method: &'static str, size: usize) {
+        self.with_method(method, |sizes| sizes.responses.observe(size as u64));
+    }
+
+    /// Point in time copy of the metrics, for stats dumps.
+    pub fn to_json(&self) -> serde_json::Value {
+        let methods = self.methods.lock().expect("payload metrics lock poisoned");
+        let methods: serde_json::Map<_, _> = methods
+            .iter()
+            .map(|(method, sizes)| {
+                let sizes = json!({
+                    "request_bytes": sizes.requests.to_json(),
+                    "response_bytes": sizes.responses.to_json(),
+                });
+                (method.to_string(), sizes)
+            })
+            .collect();
+        serde_json::Value::Object(methods)
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let methods = self.methods.lock().expect("payload metrics lock poisoned");
+        let mut out = String::new();
+        render_header(
+            &mut out,
+            "zerotable_request_bytes",
+            "Encoded size of RPC requests.",
+        );
+        for (method, sizes) in methods.iter() {
+            let labels = format!("method=\"{method}\"");
+            sizes
+                .requests
+                .render_series(&mut out, "zerotable_request_bytes", &labels);
+        }
+        render_header(
+            &mut out,
+            "zerotable_response_bytes",
+            "Encoded size of RPC responses.",
+        );
+        for (method, sizes) in methods.iter() {
+            let labels = format!("method=\"{method}\"");
+            sizes
+                .responses
+                .render_series(&mut out, "zerotable_response_bytes", &labels);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let metrics = PayloadMetrics::default();
+        metrics.record_request("GetDocument", 40);
+        metrics.record_response("GetDocument", 5000);
+        metrics.record_request("CreateDocument", 5000);
+
+        let text = metrics.render();
+        assert!(
+            text.contains("zerotable_request_bytes_bucket{method=\"GetDocument\",le=\"64\"} 1\n")
+        );
+        assert!(text.contains("zerotable_request_bytes_sum{method=\"CreateDocument\"} 5000\n"));
+        assert!(text.contains("zerotable_response_bytes_count{method=\"GetDocument\"} 1\n"));
+        assert!(text.contains("zerotable_response_bytes_count{method=\"CreateDocument\"} 0\n"));
+
+        let json = metrics.to_json();
+        assert_eq!(json["GetDocument"]["response_bytes"]["sum"], 5000);
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Query plans.
+//!
+//! How a query is run, as a JSON document returned by ExplainQuery and
+//! written by the slow-query log, for tools to check, e.g. a CI job refusing
+//! full scans. The layout is versioned by [`PLAN_VERSION`]: within a version
+//! fields are only ever added. Filter values are left out, plans end up in
+//! logs.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "database": "(default)",
+//!   "collection": "users",
+//!   "stages": [
+//!     {"stage": "SCAN", "access": "FULL_SCAN", "parallelism": 4},
+//!     {"stage": "FILTER", "conditions": [{"field": "address.city", "op": "EQUAL"}]},
+//!     {"stage": "AGGREGATE", "aggregations": [{"alias": "n", "op": "COUNT"}]}
+//!   ],
+//!   "estimated_cost": {"documents_read": 1200, "bytes_read": 96000, "exact": false}
+//! }
+//! ```
+//!
+//! There are no secondary indexes yet, so every query scans its whole
+//! collection and filters the documents it reads. The estimated cost is
+//! the size of the collection, exact only if its stats are. Scans split in
+//! ranges read at once say how many, see [`QueryPlan::with_parallelism`].
+//!
+//! [`QueryPlan::to_text`] gives the query itself as normalized text, for the
+//! slow request log.
+
+use serde_json::json;
+
+use crate::aggregate::Aggregator;
+use crate::api::v1alpha1::FieldFilter;
+use crate::keys;
+use crate::stats::CollectionStats;
+
+/// Version of the JSON layout of plans.
+pub const PLAN_VERSION: u32 = 1;
+
+/// How the documents of a query are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Every document of the collection.
+    FullScan,
+}
+
+impl Access {
+    fn as_str(self) -> &'static str {
+        match self {
+            Access::FullScan => "FULL_SCAN",
+        }
+    }
+}
+
+/// Estimated cost of running a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cost {
+    pub documents_read: u64,
+    pub bytes_read: u64,
+    /// Whether the numbers are exact or an estimate.
+    pub exact: bool,
+}
+
+/// Plan of an aggregation query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    /// Collection ID as stored, see [`keys::qualify`].
+    pub collection: String,
+    pub access: Access,
+    /// Field paths of the equality filters.
+    pub filters: Vec<String>,
+    /// Alias, operator and field of every aggregation.
+    pub aggregations: Vec<(String, &'static str, Option<String>)>,
+    /// Number of ranges of the collection scanned at once.
+    pub parallelism: usize,
+    /// `None` unless estimated with [`QueryPlan::with_estimated_cost`].
+    pub cost: Option<Cost>,
+}
+
+impl QueryPlan {
+    /// Plan of reading the documents of `collection` matching `filters`,
+    /// e.g. to update or delete them.
+    pub fn scan(collection: &str, filters: &[FieldFilter]) -> Self {
+        QueryPlan {
+            collection: collection.to_string(),
+            access: Access::FullScan,
+            filters: filters.iter().map(|filter| filter.field.clone()).collect(),
+            aggregations: Vec::new(),
+            parallelism: 1,
+            cost: None,
+        }
+    }
+
+    /// Plan of aggregating the documents of `collection` matching `filters`.
+    pub fn aggregation(collection: &str, filters: &[FieldFilter], aggregator: &Aggregator) -> Self {
+        QueryPlan {
+            aggregations: aggregator
+                .describe()
+                .map(|(alias, op, field)| (alias.to_string(), op, field.map(str::to_string)))
+                .collect(),
+            ..QueryPlan::scan(collection, filters)
+        }
+    }
+
+    /// The plan scanning `parallelism` ranges of the collection at once.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    /// The plan with its cost estimated from `stats`, those of its
+    /// collection.
+    pub fn with_estimated_cost(mut self, stats: &CollectionStats) -> Self {
+        // a full scan reads every document, whatever the filters
+        self.cost = Some(Cost {
+            documents_read: stats.document_count,
+            bytes_read: stats.size_bytes,
+            exact: stats.exact,
+        });
+        self
+    }
+
+    /// Whether the query reads every document of its collection.
+    pub fn is_full_scan(&self) -> bool {
+        self.access == Access::FullScan
+    }
+
+    /// The index the filters of the query need and do not have, like
+    /// `users (address.city, age)`, `None` without filters.
+    pub fn missing_index(&self) -> Option<String> {
+        if !self.is_full_scan() || self.filters.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{} ({})",
+            keys::unqualify(&self.collection).1,
+            self.filters.join(", ")
+        ))
+    }
+
+    /// The plan in its stable JSON layout.
+    pub fn to_json(&self) -> serde_json::Value {
+        let (database, collection) = keys::unqualify(&self.collection);
+        let mut scan = json!({ "stage": "SCAN", "access": self.access.as_str() });
+        if self.parallelism > 1 {
+            scan["parallelism"] = json!(self.parallelism);
+        }
+        let mut stages = vec![scan];
+        if !self.filters.is_empty() {
+            let conditions: Vec<_> = self
+                .filters
+                .iter()
+                .map(|field| json!({ "field": field, "op": "EQUAL" }))
+                .collect();
+            stages.push(json!({ "stage": "FILTER", "conditions": conditions }));
+        }
+        let aggregations: Vec<_> = self
+            .aggregations
+            .iter()
+            .map(|(alias, op, field)| match field {
+                Some(field) => json!({ "alias": alias, "op": op, "field": field }),
+                None => json!({ "alias": alias, "op": op }),
+            })
+            .collect();
+        stages.push(json!({ "stage": "AGGREGATE", "aggregations": aggregations }));
+
+        let mut plan = json!({
+            "version": PLAN_VERSION,
+            "database": database,
+            "collection": collection,
+            "stages": stages,
+        });
+        if let Some(cost) = self.cost {
+            plan["estimated_cost"] = json!({
+                "documents_read": cost.documents_read,
+                "bytes_read": cost.bytes_read,
+                "exact": cost.exact,
+            });
+        }
+        plan
+    }
+
+    /// The query as normalized text, filter values replaced by `?`, e.g.
+    /// `SELECT COUNT(*) AS n FROM users WHERE address.city = ?`, so the
+    /// same query with other values reads the same. The database is left
+    /// out.
+    pub fn to_text(&self) -> String {
+        let aggregations: Vec<_> = self
+            .aggregations
+            .iter()
+            .map(|(alias, op, field)| {
+                format!("{op}({}) AS {alias}", field.as_deref().unwrap_or("*"))
+            })
+            .collect();
+        let mut text = format!(
+            "SELECT {} FROM {}",
+            aggregations.join(", "),
+            keys::unqualify(&self.collection).1
+        );
+        for (i, field) in self.filters.iter().enumerate() {
+            text.push_str(if i == 0 { " WHERE " } else { " AND " });
+            text.push_str(field);
+            text.push_str(" = ?");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::v1alpha1::Aggregation;
+    use crate::api::v1alpha1::aggregation::{Count, Operator, Sum};
+
+    #[test]
+    fn test_to_json() {
+        let aggregator = Aggregator::new(&[
+            Aggregation {
+                alias: "n".to_string(),
+                operator: Some(Operator::Count(Count {})),
+            },
+            Aggregation {
+                alias: String::new(),
+                operator: Some(Operator::Sum(Sum {
+                    field: "age".to_string(),
+                })),
+            },
+        ])
+        .unwrap();
+        let filters = [FieldFilter {
+            field: "address.city".to_string(),
+            ..Default::default()
+        }];
+        let plan = QueryPlan::aggregation("acme/users", &filters, &aggregator);
+        assert!(plan.is_full_scan());
+        assert_eq!(plan.missing_index().unwrap(), "users (address.city)");
+
+        assert_eq!(
+            plan.to_json(),
+            json!({
+                "version": 1,
+                "database": "acme",
+                "collection": "users",
+                "stages": [
+                    { "stage": "SCAN", "access": "FULL_SCAN" },
+                    {
+                        "stage": "FILTER",
+                        "conditions": [{ "field": "address.city", "op": "EQUAL" }],
+                    },
+                    {
+                        "stage": "AGGREGATE",
+                        "aggregations": [
+                            { "alias": "n", "op": "COUNT" },
+                            { "alias": "field_2", "op": "SUM", "field": "age" },
+                        ],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_estimated_cost() {
+        let aggregator = Aggregator::new(&[Aggregation {
+            alias: String::new(),
+            operator: Some(Operator::Count(Count {})),
+        }])
+        .unwrap();
+        let plan = QueryPlan::aggregation("users", &[], &aggregator);
+        assert!(plan.to_json().get("estimated_cost").is_none());
+
+        let stats = CollectionStats {
+            document_count: 3,
+            size_bytes: 120,
+            exact: true,
+            disk_bytes: 0,
+        };
+        let plan = plan.with_estimated_cost(&stats);
+        assert_eq!(
+            plan.to_json()["estimated_cost"],
+            json!({ "documents_read": 3, "bytes_read": 120, "exact": true })
+        );
+    }
+
+    #[test]
+    fn test_no_filter_stage_without_filters() {
+        let aggregator = Aggregator::new(&[Aggregation {
+            alias: String::new(),
+            operator: Some(Operator::Count(Count {})),
+        }])
+        .unwrap();
+        let plan = QueryPlan::aggregation("users", &[], &aggregator);
+        assert_eq!(plan.missing_index(), None);
+        assert!(plan.to_json()["stages"][0].get("parallelism").is_none());
+        let plan = plan.with_parallelism(4).to_json();
+        assert_eq!(plan["stages"][0]["parallelism"], 4);
+        assert_eq!(plan["database"], keys::DEFAULT_DATABASE);
+        let stages: Vec<_> = plan["stages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|stage| stage["stage"].as_str().unwrap())
+            .collect();
+        assert_eq!(stages, ["SCAN", "AGGREGATE"]);
+    }
+
+    #[test]
+    fn test_to_text() {
+        let aggregator = Aggregator::new(&[
+            Aggregation {
+                alias: "n".to_string(),
+                operator: Some(Operator::Count(Count {})),
+            },
+            Aggregation {
+                alias: String::new(),
+                operator: Some(Operator::Sum(Sum {
+                    field: "age".to_string(),
+                })),
+            },
+        ])
+        .unwrap();
+        let filter = |field: &str| FieldFilter {
+            field: field.to_string(),
+            ..Default::default()
+        };
+
+        let plan = QueryPlan::aggregation("acme/users", &[], &aggregator);
+        assert_eq!(
+            plan.to_text(),
+            "SELECT COUNT(*) AS n, SUM(age) AS field_2 FROM users"
+        );
+        let plan = QueryPlan::aggregation("users", &[filter("city"), filter("age")], &aggregator);
+        assert_eq!(
+            plan.to_text(),
+            "SELECT COUNT(*) AS n, SUM(age) AS field_2 FROM users WHERE city = ? AND age = ?"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Per-client rate limiting.
+//!
+//! Every client gets a token bucket per kind of operation, refilled at the
+//! configured rate and holding up to one second worth of requests. Clients
+//! are identified by their IP address; requests without a peer address,
+//! like those over the unix socket, share a single bucket.
+//!
+//! The limits can be changed while serving, see [`RateLimiter::set_limits`].
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+/// Number of tracked buckets above which full buckets are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Kind of operation, each one is limited separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Read,
+    Write,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Add the tokens earned since the last update.
+    fn refill(&mut self, rate: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.updated = now;
+    }
+}
+
+/// Token buckets of every client.
+#[derive(Default)]
+pub struct RateLimiter {
+    /// Requests per second, 0 when unlimited.
+    reads: AtomicU32,
+    writes: AtomicU32,
+    buckets: Mutex<HashMap<(Option<IpAddr>, Operation), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Limit each client to `reads` and `writes` requests per second,
+    /// `None` leaves that kind of operation unlimited.
+    pub fn new(reads: Option<NonZeroU32>, writes: Option<NonZeroU32>) -> Self {
+        let limiter = RateLimiter::default();
+        limiter.set_limits(reads, writes);
+        limiter
+    }
+
+    /// Change the limits, buckets of clients keep their tokens up to the new
+    /// rate.
+    pub fn set_limits(&self, reads: Option<NonZeroU32>, writes: Option<NonZeroU32>) {
+        let rate = |limit: Option<NonZeroU32>| limit.map_or(0, NonZeroU32::get);
+        self.reads.store(rate(reads), Ordering::Relaxed);
+        self.writes.store(rate(writes), Ordering::Relaxed);
+    }
+
+    /// Requests per second each client may make for `operation`, `None` if
+    /// unlimited.
+    pub fn limit(&self, operation: Operation) -> Option<NonZeroU32> {
+        let limit = match operation {
+            Operation::Read => &self.reads,
+            Operation::Write => &self.writes,
+        };
+        NonZeroU32::new(limit.load(Ordering::Relaxed))
+    }
+
+    /// Take a token for `client`, returns false if its bucket is empty.
+    pub fn try_acquire(&self, client: Option<IpAddr>, operation: Operation) -> bool {
+        self.acquire_at(client, operation, Instant::now())
+    }
+
+    /// Requests per second allowed for `operation`, `None` if unlimited.
+    fn rate(&self, operation: Operation) -> Option<f64> {
+        self.limit(operation).map(|limit| f64::from(limit.get()))
+    }
+
+    fn acquire_at(&self, client: Option<IpAddr>, operation: Operation, now: Instant) -> bool {
+        let Some(rate) = self.rate(operation) else {
+            return true;
+        };
+
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+        if buckets.len() >= MAX_BUCKETS {
+            // a full bucket behaves exactly like a missing one
+            buckets.retain(|(_, op), bucket| {
+                let rate = self.rate(*op).unwrap_or(0.0);
+                bucket.refill(rate, now);
+                bucket.tokens < rate
+            });
+        }
+
+        let bucket = buckets.entry((client, operation)).or_insert(Bucket {
+            tokens: rate,
+            updated: now,
+        });
+        bucket.refill(rate, now);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(reads: u32, writes: u32) -> RateLimiter {
+        RateLimiter::new(NonZeroU32::new(reads), NonZeroU32::new(writes))
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = limiter(2, 0);
+        let client = Some("10.0.0.1".parse().unwrap());
+        let now = Instant::now();
+
+        assert!(limiter.acquire_at(client, Operation::Read, now));
+        assert!(limiter.acquire_at(client, Operation::Read, now));
+        assert!(!limiter.acquire_at(client, Operation::Read, now));
+
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.acquire_at(client, Operation::Read, later));
+        assert!(!limiter.acquire_at(client, Operation::Read, later));
+    }
+
+    #[test]
+    fn test_clients_and_operations_are_separate() {
+        let limiter = limiter(1, 1);
+        let a = Some("10.0.0.1".parse().unwrap());
+        let b = Some("10.0.0.2".parse().unwrap());
+        let now = Instant::now();
+
+        assert!(limiter.acquire_at(a, Operation::Read, now));
+        assert!(!limiter.acquire_at(a, Operation::Read, now));
+        assert!(limiter.acquire_at(a, Operation::Write, now));
+        assert!(limiter.acquire_at(b, Operation::Read, now));
+    }
+
+    #[test]
+    fn test_set_limits() {
+        let limiter = limiter(4, 0);
+        let now = Instant::now();
+        assert!(limiter.acquire_at(None, Operation::Read, now));
+
+        limiter.set_limits(NonZeroU32::new(1), None);
+        assert_eq!(limiter.limit(Operation::Read), NonZeroU32::new(1));
+        assert_eq!(limiter.limit(Operation::Write), None);
+        // the bucket is cut down to the new rate
+        assert!(limiter.acquire_at(None, Operation::Read, now));
+        assert!(!limiter.acquire_at(None, Operation::Read, now));
+
+        limiter.set_limits(None, None);
+        assert!(limiter.acquire_at(None, Operation::Read, now));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = limiter(0, 1);
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert!(limiter.acquire_at(None, Operation::Read, now));
+        }
+    }
+}
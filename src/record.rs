@@ -0,0 +1,314 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Storage record envelope wrapped around every document value.
+//!
+//! Record format: `{version: u8}{sequence: u64 BE}{write_time_millis: u64 BE}{payload}`
+//!
+//! The version also says how the payload is stored: as is in version 1
+//! records, zstd compressed in version 2 ones. Records written with and
+//! without [`ValueCompression`] coexist and decode alike, turning it on or
+//! off rewrites nothing.
+//!
+//! Version 3 records carry their payload sealed, see [`cipher`]: once
+//! opened it is `{version: u8}{payload}`, the payload stored as in a record
+//! of that version. The header is left in the clear, bound to the payload.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::cipher::{self, CipherError, KeyProvider};
+
+/// Current record format version.
+const FORMAT_VERSION: u8 = 1;
+
+/// Version of the records whose payload is zstd compressed.
+const ZSTD_VERSION: u8 = 2;
+
+/// Version of the records whose payload is encrypted.
+const SEALED_VERSION: u8 = 3;
+
+/// Payloads shorter than this are stored as is, compressing them saves too
+/// little to pay for itself.
+const MIN_COMPRESSED_LEN: usize = 64;
+
+/// Zstd level of compressed payloads, favouring write speed.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Size of the fixed header that precedes the payload.
+const HEADER_LEN: usize = 1 + 8 + 8;
+
+/// Largest payload a record may carry. fjall values are limited to 4 GiB,
+/// the margin leaves room for the header and for the audit entries that
+/// carry payloads with their names.
+pub const MAX_PAYLOAD_LEN: usize = u32::MAX as usize - 64 * 1024;
+
+/// Errors that can occur while sealing or decoding a stored record.
+#[derive(Debug, PartialEq)]
+pub enum RecordError {
+    Truncated { len: usize },
+    UnknownVersion(u8),
+    Decompress(String),
+    Cipher(CipherError),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Truncated { len } => {
+                write!(f, "record truncated: {len} bytes, header is {HEADER_LEN}")
+            }
+            RecordError::UnknownVersion(v) => write!(f, "unknown record version {v}"),
+            RecordError::Decompress(e) => write!(f, "record payload does not decompress: {e}"),
+            RecordError::Cipher(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<CipherError> for RecordError {
+    fn from(e: CipherError) -> Self {
+        RecordError::Cipher(e)
+    }
+}
+
+/// Metadata stored alongside a document payload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordHeader {
+    /// Commit sequence number assigned by the engine when the record was written.
+    pub sequence: u64,
+    /// Wall-clock time of the write, millisecond precision.
+    pub write_time: SystemTime,
+}
+
+/// How record payloads are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValueCompression {
+    /// As is.
+    #[default]
+    None,
+    /// Zstd compressed, unless that does not make them smaller.
+    Zstd,
+}
+
+/// Encode a payload into a storage record, as is.
+pub fn encode(header: &RecordHeader, payload: &[u8]) -> Vec<u8> {
+    encode_with(header, payload, ValueCompression::None)
+}
+
+/// Encode a payload into a storage record, compressed as `compression`
+/// says.
+pub fn encode_with(
+    header: &RecordHeader,
+    payload: &[u8],
+    compression: ValueCompression,
+) -> Vec<u8> {
+    let compressed = match compression {
+        ValueCompression::Zstd if payload.len() >= MIN_COMPRESSED_LEN => {
+            zstd::bulk::compress(payload, ZSTD_LEVEL)
+                .ok()
+                .filter(|compressed| compressed.len() < payload.len())
+        }
+        _ => None,
+    };
+    let (version, payload) = match &compressed {
+        Some(compressed) => (ZSTD_VERSION, compressed.as_slice()),
+        None => (FORMAT_VERSION, payload),
+    };
+
+    let millis = header
+        .write_time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+    record.push(version);
+    record.extend_from_slice(&header.sequence.to_be_bytes());
+    record.extend_from_slice(&millis.to_be_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+/// Encode a payload into a storage record, compressed as `compression`
+/// says then sealed under the current key of `keys`.
+pub fn encode_sealed(
+    header: &RecordHeader,
+    payload: &[u8],
+    compression: ValueCompression,
+    keys: &dyn KeyProvider,
+) -> Result<Vec<u8>, RecordError> {
+    let inner = encode_with(header, payload, compression);
+    let mut record = inner[..HEADER_LEN].to_vec();
+    record[0] = SEALED_VERSION;
+    let mut plaintext = Vec::with_capacity(1 + inner.len() - HEADER_LEN);
+    plaintext.push(inner[0]);
+    plaintext.extend_from_slice(&inner[HEADER_LEN..]);
+    let sealed = cipher::seal(keys, &record, &plaintext)?;
+    record.extend_from_slice(&sealed);
+    Ok(record)
+}
+
+/// Decode a storage record into its header and payload, decompressed if it
+/// was compressed. Fails on sealed records, see [`decode_with`].
+pub fn decode(record: &[u8]) -> Result<(RecordHeader, Cow<'_, [u8]>), RecordError> {
+    decode_with(record, None)
+}
+
+/// Decode a storage record like [`decode`], opening sealed payloads with
+/// `keys`.
+pub fn decode_with<'a>(
+    record: &'a [u8],
+    keys: Option<&dyn KeyProvider>,
+) -> Result<(RecordHeader, Cow<'a, [u8]>), RecordError> {
+    let header = decode_header(record)?;
+    let payload = &record[HEADER_LEN..];
+    if record[0] != SEALED_VERSION {
+        return Ok((header, unpack(record[0], payload)?));
+    }
+    let keys = keys.ok_or(CipherError::NoKeys)?;
+    let plaintext = cipher::open(keys, &record[..HEADER_LEN], payload)?;
+    let (&version, payload) = plaintext.split_first().ok_or(CipherError::Invalid)?;
+    let payload = unpack(version, payload)?.into_owned();
+    Ok((header, Cow::Owned(payload)))
+}
+
+/// Payload as stored in a record of `version`, decompressed.
+fn unpack(version: u8, payload: &[u8]) -> Result<Cow<'_, [u8]>, RecordError> {
+    match version {
+        FORMAT_VERSION => Ok(Cow::Borrowed(payload)),
+        ZSTD_VERSION => zstd::decode_all(payload)
+            .map(Cow::Owned)
+            .map_err(|e| RecordError::Decompress(e.to_string())),
+        version => Err(RecordError::UnknownVersion(version)),
+    }
+}
+
+/// Decode the header of a storage record, leaving its payload alone.
+pub fn decode_header(record: &[u8]) -> Result<RecordHeader, RecordError> {
+    if record.len() < HEADER_LEN {
+        return Err(RecordError::Truncated { len: record.len() });
+    }
+    if ![FORMAT_VERSION, ZSTD_VERSION, SEALED_VERSION].contains(&record[0]) {
+        return Err(RecordError::UnknownVersion(record[0]));
+    }
+
+    let sequence = u64::from_be_bytes(record[1..9].try_into().expect("slice is 8 bytes"));
+    let millis = u64::from_be_bytes(record[9..17].try_into().expect("slice is 8 bytes"));
+
+    Ok(RecordHeader {
+        sequence,
+        write_time: SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::{KEY_LEN, StaticKeys};
+    use crate::id::now_millis;
+
+    #[test]
+    fn test_encode_decode() {
+        let header = RecordHeader {
+            sequence: 42,
+            write_time: now_millis(),
+        };
+        let record = encode(&header, b"payload");
+        let (decoded, payload) = decode(&record).unwrap();
+
+        assert_eq!(decoded, header);
+        assert_eq!(&*payload, b"payload");
+    }
+
+    #[test]
+    fn test_compression() {
+        let header = RecordHeader {
+            sequence: 7,
+            write_time: now_millis(),
+        };
+        let payload = b"abcd".repeat(100);
+        let compressed = encode_with(&header, &payload, ValueCompression::Zstd);
+        assert_eq!(compressed[0], ZSTD_VERSION);
+        assert!(compressed.len() < HEADER_LEN + payload.len());
+        let (decoded, decompressed) = decode(&compressed).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(&*decompressed, payload.as_slice());
+        assert_eq!(decode_header(&compressed).unwrap(), header);
+
+        // short payloads are left as is
+        let short = encode_with(&header, b"short", ValueCompression::Zstd);
+        assert_eq!(short, encode(&header, b"short"));
+
+        // not a zstd frame
+        let mut corrupted = compressed.clone();
+        corrupted[HEADER_LEN] ^= 0xFF;
+        assert!(matches!(
+            decode(&corrupted),
+            Err(RecordError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn test_sealed() {
+        let keys = StaticKeys::new([(1, [9; KEY_LEN])]).unwrap();
+        let header = RecordHeader {
+            sequence: 3,
+            write_time: now_millis(),
+        };
+        let payload = b"abcd".repeat(100);
+        for compression in [ValueCompression::None, ValueCompression::Zstd] {
+            let record = encode_sealed(&header, &payload, compression, &keys).unwrap();
+            assert_eq!(record[0], SEALED_VERSION);
+            assert_eq!(decode_header(&record).unwrap(), header);
+            let (decoded, opened) = decode_with(&record, Some(&keys)).unwrap();
+            assert_eq!(decoded, header);
+            assert_eq!(&*opened, payload.as_slice());
+            assert_eq!(
+                decode(&record),
+                Err(RecordError::Cipher(CipherError::NoKeys))
+            );
+        }
+
+        // the header is bound to the payload
+        let mut record = encode_sealed(&header, b"payload", ValueCompression::None, &keys).unwrap();
+        record[1] ^= 1;
+        assert_eq!(
+            decode_with(&record, Some(&keys)),
+            Err(RecordError::Cipher(CipherError::Invalid))
+        );
+        // plain records decode with keys too
+        let plain = encode(&header, b"payload");
+        assert_eq!(&*decode_with(&plain, Some(&keys)).unwrap().1, b"payload");
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let header = RecordHeader {
+            sequence: 1,
+            write_time: SystemTime::UNIX_EPOCH,
+        };
+        let record = encode(&header, b"");
+        let (_, payload) = decode(&record).unwrap();
+
+        assert!(payload.is_empty());
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        assert_eq!(
+            decode(&[FORMAT_VERSION, 0, 0]),
+            Err(RecordError::Truncated { len: 3 })
+        );
+    }
+
+    #[test]
+    fn test_decode_unknown_version() {
+        let mut record = vec![0u8; HEADER_LEN];
+        record[0] = 0xFF;
+        assert_eq!(decode(&record), Err(RecordError::UnknownVersion(0xFF)));
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Reloading part of the configuration while serving.
+//!
+//! On SIGHUP or an Admin `ReloadConfig` call the configuration is loaded
+//! again the way it was at startup, and the log filter, the rate limits and
+//! the admin token are applied without dropping connections. Other settings
+//! take a restart.
+
+use std::sync::Arc;
+
+use crate::admin::AdminAuth;
+use crate::config::{ConfigError, ServerConfig};
+use crate::rate_limit::RateLimiter;
+use crate::telemetry::LogFilter;
+
+/// Applies a reloaded configuration to the running server.
+#[derive(Clone)]
+pub struct Reloader {
+    args: Arc<[String]>,
+    log_filter: LogFilter,
+    rate_limiter: Arc<RateLimiter>,
+    admin_auth: Option<AdminAuth>,
+}
+
+impl Reloader {
+    /// `args` are the command line flags the server was started with.
+    pub fn new(args: Vec<String>, log_filter: LogFilter, rate_limiter: Arc<RateLimiter>) -> Self {
+        Reloader {
+            args: args.into(),
+            log_filter,
+            rate_limiter,
+            admin_auth: None,
+        }
+    }
+
+    /// Replace the token checked by `auth` on reload.
+    pub fn with_admin_auth(mut self, auth: AdminAuth) -> Self {
+        self.admin_auth = Some(auth);
+        self
+    }
+
+    /// Load the configuration again and apply it. Nothing changes if it is
+    /// invalid.
+    pub fn reload(&self) -> Result<(), ConfigError> {
+        let config = ServerConfig::load(self.args.iter().cloned())?;
+        self.apply(&config);
+        tracing::info!("configuration reloaded");
+        Ok(())
+    }
+
+    fn apply(&self, config: &ServerConfig) {
+        if let Err(e) = self.log_filter.set(config.log_filter.as_deref()) {
+            tracing::warn!(error = %e, "failed to replace the log filter");
+        }
+        self.rate_limiter
+            .set_limits(config.read_rate_limit, config.write_rate_limit);
+        match (&self.admin_auth, &config.admin_token) {
+            (Some(auth), Some(token)) => auth.set_token(token.as_str()),
+            (Some(_), None) => {
+                tracing::warn!("disabling the admin service takes a restart, keeping its token")
+            }
+            (None, Some(_)) => {
+                tracing::warn!("enabling the admin service takes a restart")
+            }
+            (None, None) => {}
+        }
+    }
+}
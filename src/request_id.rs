@@ -0,0 +1,184 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Request ID propagation.
+//!
+//! Every RPC carries an `x-request-id`: either the one sent by the client or a
+//! freshly generated UUID v7. The ID is logged, appended to error messages and
+//! echoed back in the response metadata so clients can correlate failures with
+//! server-side logs.
+
+use std::fmt;
+
+use tonic::metadata::{AsciiMetadataValue, MetadataMap};
+use tonic::{Code, Request, Response, Status};
+
+use crate::id::generate_uuid_v7;
+
+/// Metadata key carrying the request ID.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Maximum accepted length of a client supplied request ID.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Identifier attached to a single RPC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Generate a new request ID.
+    pub fn generate() -> Self {
+        RequestId(generate_uuid_v7().0.to_string())
+    }
+
+    /// Accept a client supplied request ID if it is short printable ASCII.
+    fn accept(value: &str) -> Option<Self> {
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LENGTH
+            && value.bytes().all(|b| b.is_ascii_graphic());
+        valid.then(|| RequestId(value.to_string()))
+    }
+
+    /// Read the request ID from the client metadata, generating one if absent or invalid.
+    pub fn from_metadata(metadata: &MetadataMap) -> Self {
+        metadata
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(RequestId::accept)
+            .unwrap_or_else(RequestId::generate)
+    }
+
+    /// Get the request ID assigned by [`intercept`].
+    ///
+    /// Falls back to reading the metadata if the interceptor is not installed.
+    pub fn of<T>(request: &Request<T>) -> Self {
+        match request.extensions().get::<RequestId>() {
+            Some(id) => id.clone(),
+            None => RequestId::from_metadata(request.metadata()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn metadata_value(&self) -> AsciiMetadataValue {
+        // accepted and generated ids are always visible ASCII
+        self.0.parse().expect("request id is valid ASCII metadata")
+    }
+
+    /// Log the outcome of an RPC and stamp the request ID on the result.
+    ///
+    /// Successful responses get the ID in their metadata, errors get it in both
+    /// the metadata and the message.
+    pub fn finish<T>(
+        &self,
+        method: &str,
+        result: Result<Response<T>, Status>,
+    ) -> Result<Response<T>, Status> {
+        match result {
+            Ok(mut response) => {
+                tracing::info!(request_id = %self, method, "ok");
+                response
+                    .metadata_mut()
+                    .insert(REQUEST_ID_HEADER, self.metadata_value());
+                Ok(response)
+            }
+            Err(status) => {
+                // only failures on our side are errors, the rest are answers
+                match status.code() {
+                    Code::Internal | Code::DataLoss | Code::Unknown => tracing::error!(
+                        request_id = %self,
+                        method,
+                        code = ?status.code(),
+                        message = status.message(),
+                        "failed"
+                    ),
+                    code => tracing::info!(
+                        request_id = %self,
+                        method,
+                        ?code,
+                        message = status.message(),
+                        "failed"
+                    ),
+                }
+                let mut metadata = status.metadata().clone();
+                metadata.insert(REQUEST_ID_HEADER, self.metadata_value());
+                Err(Status::with_details_and_metadata(
+                    status.code(),
+                    format!("{} (request_id: {self})", status.message()),
+                    status.details().to_vec().into(),
+                    metadata,
+                ))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Interceptor assigning a [`RequestId`] to every incoming request.
+pub fn intercept(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let id = RequestId::from_metadata(request.metadata());
+    request.extensions_mut().insert(id);
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_client_id() {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(REQUEST_ID_HEADER, "abc-123".parse().unwrap());
+
+        let request = intercept(request).unwrap();
+        assert_eq!(RequestId::of(&request).as_str(), "abc-123");
+    }
+
+    #[test]
+    fn test_generates_missing_id() {
+        let request = intercept(Request::new(())).unwrap();
+        assert!(uuid::Uuid::parse_str(RequestId::of(&request).as_str()).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_oversized_id() {
+        let mut request = Request::new(());
+        let long_id = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+        request
+            .metadata_mut()
+            .insert(REQUEST_ID_HEADER, long_id.parse().unwrap());
+
+        assert_ne!(RequestId::of(&request).as_str(), long_id);
+    }
+
+    #[test]
+    fn test_finish_stamps_response() {
+        let id = RequestId::generate();
+        let response = id.finish("Test", Ok(Response::new(()))).unwrap();
+
+        let value = response.metadata().get(REQUEST_ID_HEADER).unwrap();
+        assert_eq!(value.to_str().unwrap(), id.as_str());
+    }
+
+    #[test]
+    fn test_finish_stamps_error() {
+        let id = RequestId::generate();
+        let status = id
+            .finish::<()>("Test", Err(Status::not_found("missing")))
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert!(status.message().contains(id.as_str()));
+        assert!(status.metadata().get(REQUEST_ID_HEADER).is_some());
+    }
+}
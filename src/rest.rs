@@ -0,0 +1,403 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! HTTP/JSON gateway.
+//!
+//! Every route calls the gRPC service, so both front ends share validation,
+//! error handling and request IDs. Documents use the protobuf JSON encoding.
+//!
+//! | Method   | Path                                          | RPC            |
+//! |----------|-----------------------------------------------|----------------|
+//! | `GET`    | `/v1alpha1/{collection_id}/{document_id}`     | GetDocument    |
+//! | `GET`    | `/v1alpha1/{collection_id}`                   | ListDocuments  |
+//! | `POST`   | `/v1alpha1/{collection_id}?documentId={id}`   | CreateDocument |
+//! | `PATCH`  | `/v1alpha1/{collection_id}/{document_id}`     | UpdateDocument |
+//! | `DELETE` | `/v1alpha1/{collection_id}/{document_id}`     | DeleteDocument |
+//!
+//! GetDocument takes a `readTime` query parameter, an RFC 3339 timestamp.
+//! ListDocuments takes `pageSize`, `pageToken`, `orderBy`, `showMissing` and
+//! `readTime` query parameters and returns
+//! `{"documents": [...], "nextPageToken": ...}`.
+//! UpdateDocument takes `updateMask`, comma separated field paths,
+//! `mergeDisjointFields` and `expectedVersion`, the `version` of the
+//! document as read. DeleteDocument returns `{}`, or the deleted
+//! document with `returnPrevious=true`.
+//!
+//! Every path also exists under `/v1alpha1/databases/{database_id}/` for
+//! the collections of databases other than the default one.
+//!
+//! Path segments are percent-decoded, so a document ID containing a slash is
+//! addressed as `a%2Fb`.
+//!
+//! `GET /metrics` serves the service metrics in the Prometheus text format.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use prost_types::Timestamp;
+use serde_json::json;
+use tonic::metadata::MetadataMap;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::{Code, Extensions, Request, Status};
+
+use crate::api::v1alpha1::zerotable_server::Zerotable;
+use crate::api::v1alpha1::{
+    CreateDocumentRequest, DeleteDocumentRequest, Document, DocumentMask, GetDocumentRequest,
+    ListDocumentsRequest, UpdateDocumentRequest,
+};
+use crate::json::{document_from_json, document_to_json};
+use crate::keys::DEFAULT_DATABASE;
+use crate::request_id::RequestId;
+use crate::service::ZerotableService;
+use crate::{clock, keys, name, panic};
+
+/// Build the gateway routes on top of `service`.
+///
+/// Must be served with [`ConnectInfo<SocketAddr>`], see
+/// [`Router::into_make_service_with_connect_info`].
+pub fn router(service: ZerotableService) -> Router {
+    let mut router = Router::new().route("/metrics", get(metrics));
+    for prefix in ["/v1alpha1", "/v1alpha1/databases/{database_id}"] {
+        router = router
+            .route(
+                &format!("{prefix}/{{collection_id}}"),
+                get(list_documents).post(create_document),
+            )
+            .route(
+                &format!("{prefix}/{{collection_id}}/{{document_id}}"),
+                get(get_document)
+                    .patch(update_document)
+                    .delete(delete_document),
+            );
+    }
+    router.with_state(service)
+}
+
+/// Path parameters of a route, by name.
+type Params = HashMap<String, String>;
+
+fn param<'a>(params: &'a Params, name: &str) -> &'a str {
+    params.get(name).map_or("", String::as_str)
+}
+
+/// The `readTime` query parameter, `None` if absent.
+fn read_time(query: &HashMap<String, String>) -> Result<Option<Timestamp>, Status> {
+    query
+        .get("readTime")
+        .map(|time| time.parse())
+        .transpose()
+        .map_err(|_| Status::invalid_argument("readTime must be an RFC 3339 timestamp"))
+}
+
+/// Resource name of the document the route parameters point at.
+fn document_name(params: &Params) -> Result<String, Status> {
+    let database_id = params
+        .get("database_id")
+        .map_or(DEFAULT_DATABASE, String::as_str);
+    let collection = keys::qualify(database_id, param(params, "collection_id"))
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    Ok(name::format(&collection, param(params, "document_id")))
+}
+
+async fn get_document(
+    State(service): State<ZerotableService>,
+    Path(params): Path<Params>,
+    Query(query): Query<HashMap<String, String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    let name = match document_name(&params) {
+        Ok(name) => name,
+        Err(status) => return error_response(status),
+    };
+    let read_time = match read_time(&query) {
+        Ok(read_time) => read_time,
+        Err(status) => return error_response(status),
+    };
+    let request = grpc_request(peer, headers, GetDocumentRequest { name, read_time });
+    reply(service.get_document(request).await, |doc| {
+        document_to_json(&doc)
+    })
+}
+
+async fn create_document(
+    State(service): State<ZerotableService>,
+    Path(params): Path<Params>,
+    Query(query): Query<HashMap<String, String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let document = match parse_document(&body) {
+        Ok(document) => document,
+        Err(status) => return error_response(status),
+    };
+    let request = grpc_request(
+        peer,
+        headers,
+        CreateDocumentRequest {
+            collection_id: param(&params, "collection_id").to_string(),
+            document_id: query.get("documentId").cloned().unwrap_or_default(),
+            document: Some(document),
+            database_id: param(&params, "database_id").to_string(),
+        },
+    );
+    reply(service.create_document(request).await, |doc| {
+        document_to_json(&doc)
+    })
+}
+
+async fn list_documents(
+    State(service): State<ZerotableService>,
+    Path(params): Path<Params>,
+    Query(query): Query<HashMap<String, String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    let page_size = match query.get("pageSize").map(|size| size.parse()) {
+        None => 0,
+        Some(Ok(size)) => size,
+        Some(Err(_)) => {
+            return error_response(Status::invalid_argument("pageSize must be an integer"));
+        }
+    };
+    let read_time = match read_time(&query) {
+        Ok(read_time) => read_time,
+        Err(status) => return error_response(status),
+    };
+    let request = grpc_request(
+        peer,
+        headers,
+        ListDocumentsRequest {
+            collection_id: param(&params, "collection_id").to_string(),
+            database_id: param(&params, "database_id").to_string(),
+            page_size,
+            page_token: query.get("pageToken").cloned().unwrap_or_default(),
+            order_by: query.get("orderBy").cloned().unwrap_or_default(),
+            show_missing: query.get("showMissing").is_some_and(|show| show == "true"),
+            read_time,
+        },
+    );
+    reply(service.list_documents(request).await, |page| {
+        let documents: Vec<_> = page.documents.iter().map(document_to_json).collect();
+        let mut body = json!({ "documents": documents });
+        if !page.next_page_token.is_empty() {
+            body["nextPageToken"] = json!(page.next_page_token);
+        }
+        body
+    })
+}
+
+async fn update_document(
+    State(service): State<ZerotableService>,
+    Path(params): Path<Params>,
+    Query(query): Query<HashMap<String, String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut document = match parse_document(&body) {
+        Ok(document) => document,
+        Err(status) => return error_response(status),
+    };
+    document.name = match document_name(&params) {
+        Ok(name) => name,
+        Err(status) => return error_response(status),
+    };
+    let expected_version = match query.get("expectedVersion").map(|version| version.parse()) {
+        None => 0,
+        Some(Ok(version)) => version,
+        Some(Err(_)) => {
+            return error_response(Status::invalid_argument(
+                "expectedVersion must be an unsigned integer",
+            ));
+        }
+    };
+    let request = grpc_request(
+        peer,
+        headers,
+        UpdateDocumentRequest {
+            document: Some(document),
+            update_mask: query.get("updateMask").map(|mask| DocumentMask {
+                field_paths: mask.split(',').map(str::to_string).collect(),
+            }),
+            merge_disjoint_fields: query
+                .get("mergeDisjointFields")
+                .is_some_and(|merge| merge == "true"),
+            return_previous: false,
+            expected_version,
+        },
+    );
+    reply(service.update_document(request).await, |updated| {
+        document_to_json(&updated.document.unwrap_or_default())
+    })
+}
+
+async fn delete_document(
+    State(service): State<ZerotableService>,
+    Path(params): Path<Params>,
+    Query(query): Query<HashMap<String, String>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    let name = match document_name(&params) {
+        Ok(name) => name,
+        Err(status) => return error_response(status),
+    };
+    let request = grpc_request(
+        peer,
+        headers,
+        DeleteDocumentRequest {
+            name,
+            return_previous: query
+                .get("returnPrevious")
+                .is_some_and(|previous| previous == "true"),
+        },
+    );
+    reply(service.delete_document(request).await, |deleted| {
+        let previous = deleted.previous_document;
+        previous.map_or_else(|| json!({}), |previous| document_to_json(&previous))
+    })
+}
+
+async fn metrics(State(service): State<ZerotableService>) -> Response {
+    let mut body = service.contention().render();
+    body.push_str(&clock::global().render());
+    body.push_str(&service.memory().render());
+    body.push_str(&service.payloads().render());
+    if let Some(mirror) = service.mirror() {
+        body.push_str(&mirror.metrics().render());
+    }
+    body.push_str(&panic::render());
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+fn parse_document(body: &[u8]) -> Result<Document, Status> {
+    let json: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| Status::invalid_argument(format!("invalid json: {e}")))?;
+    document_from_json(&json).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+/// Wrap a message into a gRPC request carrying the HTTP headers as metadata.
+fn grpc_request<T>(peer: SocketAddr, headers: HeaderMap, message: T) -> Request<T> {
+    let metadata = MetadataMap::from_headers(headers);
+    let mut extensions = Extensions::default();
+    extensions.insert(RequestId::from_metadata(&metadata));
+    // lets the service see the client address, e.g. for rate limiting
+    extensions.insert(TcpConnectInfo {
+        local_addr: None,
+        remote_addr: Some(peer),
+    });
+    Request::from_parts(metadata, extensions, message)
+}
+
+fn reply<T>(
+    result: Result<tonic::Response<T>, Status>,
+    encode: impl FnOnce(T) -> serde_json::Value,
+) -> Response {
+    match result {
+        Ok(response) => {
+            let (metadata, message, _) = response.into_parts();
+            let mut response = Json(encode(message)).into_response();
+            response.headers_mut().extend(metadata.into_headers());
+            response
+        }
+        Err(status) => error_response(status),
+    }
+}
+
+fn error_response(status: Status) -> Response {
+    let body = json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+    });
+    let mut response = (http_status(status.code()), Json(body)).into_response();
+    response
+        .headers_mut()
+        .extend(status.metadata().clone().into_headers());
+    response
+}
+
+/// Map a gRPC code to the HTTP status used by grpc-gateway.
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::from_u16(499).expect("499 is a valid status code"),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request_id::REQUEST_ID_HEADER;
+
+    #[test]
+    fn test_http_status() {
+        assert_eq!(http_status(Code::NotFound), StatusCode::NOT_FOUND);
+        assert_eq!(http_status(Code::AlreadyExists), StatusCode::CONFLICT);
+        assert_eq!(http_status(Code::InvalidArgument), StatusCode::BAD_REQUEST);
+        assert_eq!(http_status(Code::Cancelled).as_u16(), 499);
+    }
+
+    #[test]
+    fn test_grpc_request_keeps_request_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, "abc".parse().unwrap());
+
+        let peer = "10.0.0.1:1234".parse().unwrap();
+        let request = grpc_request(peer, headers, ());
+        assert_eq!(RequestId::of(&request).as_str(), "abc");
+        assert_eq!(request.remote_addr(), Some(peer));
+    }
+
+    #[test]
+    fn test_error_response() {
+        let response = error_response(Status::not_found("document not found"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_document_name() {
+        let mut params = Params::new();
+        params.insert("collection_id".to_string(), "users".to_string());
+        params.insert("document_id".to_string(), "a/b".to_string());
+        assert_eq!(document_name(&params).unwrap(), "users/a%2Fb");
+
+        params.insert("database_id".to_string(), "acme".to_string());
+        assert_eq!(
+            document_name(&params).unwrap(),
+            "databases/acme/collections/users/documents/a%2Fb"
+        );
+        params.insert("collection_id".to_string(), "a/b".to_string());
+        assert_eq!(
+            document_name(&params).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_parse_document_rejects_bad_json() {
+        let status = parse_document(b"{not json").unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,3598 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! gRPC implementation of the Zerotable service.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use prost::Message;
+use prost_types::Timestamp;
+use serde_json::json;
+use tokio::sync::Semaphore;
+use tokio::sync::mpsc::{self, error::SendTimeoutError};
+use tokio::task::JoinSet;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Code, Request, Response, Status, Streaming};
+use tonic_types::{ErrorDetails, StatusExt};
+
+use crate::aggregate::{self, Aggregator, matches};
+use crate::api::v1alpha1::zerotable_server::Zerotable;
+use crate::api::v1alpha1::batch_get_result::Result as BatchResult;
+use crate::api::v1alpha1::bulk_write_request::Operation as BulkOperation;
+use crate::api::v1alpha1::export_documents_response::Item as ExportItem;
+use crate::api::v1alpha1::{
+    AuditAction, AuditEntry, BatchGetDocumentsRequest, BatchGetDocumentsResponse, BatchGetResult,
+    CancelOperationRequest, Collection, CollectionConfig, CollectionStats, Compression,
+    CopyDocumentRequest, Counter, CreateDocumentRequest, CreateDocumentTreeRequest,
+    CreateDocumentTreeResponse, DatabaseStats, DeleteDocumentRequest, DeleteDocumentResponse,
+    DeleteWhereRequest, DeleteWhereResponse, Document, DocumentExistsRequest,
+    DocumentExistsResponse, DocumentSize, ExplainQueryRequest, ExplainQueryResponse, ExportChunk,
+    ExportDocumentsRequest, ExportDocumentsResponse, ExportHeartbeat, ExportManifest, FieldFilter,
+    GetCollectionConfigRequest, GetCollectionRequest, GetCollectionStatsRequest, GetCounterRequest,
+    GetDatabaseStatsRequest, GetDocumentRequest, GetOperationRequest, GetServerInfoRequest,
+    ImportChunkResult, ImportDocumentsRequest, ImportDocumentsResponse, ImportMode,
+    IncrementCounterRequest, ListAuditEntriesRequest, ListAuditEntriesResponse,
+    ListDocumentsRequest, ListDocumentsResponse, ListOperationsRequest, ListOperationsResponse,
+    MoveDocumentRequest, Operation as LongRunningOperation, OperationState, Partition,
+    PartitionQueryRequest, PartitionQueryResponse, PurgeDocumentRequest, PurgeDocumentResponse,
+    RunAggregationQueryRequest, RunAggregationQueryResponse, ServerInfo, ServerLimits,
+    StartDropCollectionRequest, StartExportRequest, StartImportRequest, TransformDocumentRequest,
+    UndeleteDocumentRequest, UpdateCollectionConfigRequest, UpdateDocumentRequest,
+    UpdateDocumentResponse, UpdateWhereRequest, UpdateWhereResponse, Value,
+};
+use crate::archive::ArchiveError;
+use crate::async_engine::AsyncEngine;
+use crate::audit;
+use crate::cipher::CipherError;
+use crate::config::{
+    DEFAULT_IDEMPOTENCY_TTL, DEFAULT_LONG_RUNNING_TASKS, DEFAULT_MAX_DOCUMENT_SIZE,
+    DEFAULT_STREAM_STALL_TIMEOUT,
+};
+use crate::contention::ContentionMetrics;
+use crate::deadline::Deadline;
+use crate::export::{self, Chunk, ChunkReader, ChunkWriter, DEFAULT_CHUNK_BUDGET, Manifest};
+use crate::keys::DEFAULT_DATABASE;
+use crate::list::{MAX_ORDER_FIELDS, OrderBy, PageToken};
+use crate::mask::{FieldMask, MAX_FIELD_PATHS};
+use crate::memory::MemoryTracker;
+use crate::mirror::Mirror;
+use crate::operations::{self, OperationHandle, OperationInfo, Operations};
+use crate::panic::{self, Panic};
+use crate::payload::PayloadMetrics;
+use crate::plan::QueryPlan;
+use crate::rate_limit::{Operation, RateLimiter};
+use crate::request_id::RequestId;
+use crate::slow_log::{Described, SlowRequestTimer};
+use crate::transform::{MAX_TRANSFORMS, Transforms};
+use crate::{
+    ConflictPolicy, Durability, Engine, EngineError, ImportDocument, Rewrite, StoredDocument,
+    Version, clock, generate_uuid_v7, keys, name, now_millis,
+};
+
+/// Maximum number of partitions a PartitionQuery may ask for.
+const MAX_PARTITIONS: i32 = 1024;
+
+/// Maximum number of documents, the parent included, a CreateDocumentTree
+/// may create.
+const MAX_TREE_DOCUMENTS: usize = 500;
+
+/// Attempts a TransformDocument conflicting with concurrent writes gets,
+/// whatever the retry budget.
+const TRANSFORM_ATTEMPTS: u32 = 10;
+
+/// Maximum number of ranges an aggregation query may scan at once.
+const MAX_QUERY_PARALLELISM: u32 = 16;
+
+/// Attempts an UpdateDocument merging disjoint fields gets on conflicts
+/// with concurrent writes, whatever the retry budget.
+const MERGE_ATTEMPTS: u32 = 10;
+
+/// Documents an UpdateWhere or DeleteWhere, and writes a BulkWrite, writes
+/// per transaction.
+const BULK_BATCH_SIZE: usize = 500;
+
+/// Attempts a batch of an UpdateWhere, DeleteWhere or BulkWrite conflicting
+/// with concurrent writes gets, whatever the retry budget.
+const BULK_BATCH_ATTEMPTS: u32 = 10;
+
+/// Documents returned by ListDocuments when no page size is given.
+const DEFAULT_LIST_PAGE_SIZE: i32 = 100;
+
+/// Maximum number of documents a ListDocuments may ask for.
+const MAX_LIST_PAGE_SIZE: i32 = 1000;
+
+/// Audit entries returned by ListAuditEntries when no page size is given.
+const DEFAULT_AUDIT_PAGE_SIZE: i32 = 100;
+
+/// Maximum number of audit entries a ListAuditEntries may ask for.
+const MAX_AUDIT_PAGE_SIZE: i32 = 1000;
+
+/// Attempts a chunk of an import operation conflicting with concurrent
+/// writes gets.
+const IMPORT_CHUNK_ATTEMPTS: u32 = 10;
+
+/// Export chunks buffered ahead of a slow client.
+const EXPORT_BUFFERED_CHUNKS: usize = 4;
+
+/// BulkWrite responses buffered ahead of a slow client.
+const BULK_WRITE_BUFFERED_RESPONSES: usize = 4;
+
+/// Interval of the heartbeats of an export while no chunk is ready.
+const EXPORT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval of the checks for operations still running while draining.
+const OPERATION_DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Metadata key of the client supplied token making a create idempotent.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response metadata key carrying the mutation number a write got in its
+/// collection, see [`Engine::collection_sequence`].
+pub const COLLECTION_SEQUENCE_HEADER: &str = "x-collection-sequence";
+
+/// Metadata key overriding the durability of the writes of a request,
+/// `sync` or `buffered`, see [`Durability`]. Other values are ignored.
+pub const DURABILITY_HEADER: &str = "x-durability";
+
+#[derive(Clone)]
+pub struct ZerotableService {
+    engine: AsyncEngine,
+    retry_budget: Duration,
+    contention: Arc<ContentionMetrics>,
+    payloads: Arc<PayloadMetrics>,
+    rate_limiter: Arc<RateLimiter>,
+    idempotency_ttl: Duration,
+    slow_query_threshold: Option<Duration>,
+    slow_request_threshold: Option<Duration>,
+    strict_databases: Arc<[String]>,
+    max_document_size: usize,
+    stream_stall_timeout: Duration,
+    operations: Operations,
+    /// Slots of the operations and exports running on the blocking pool.
+    long_running: Arc<Semaphore>,
+    export_dir: Option<PathBuf>,
+    mirror: Option<Arc<Mirror>>,
+}
+
+/// Per-request state kept once the message is taken out of the request.
+#[derive(Clone)]
+struct Call {
+    request_id: RequestId,
+    deadline: Deadline,
+    /// Who writes are attributed to in the audit log.
+    actor: String,
+    /// Durability of the writes asked for by the client, the server's if
+    /// `None`.
+    durability: Option<Durability>,
+}
+
+impl Call {
+    fn of<T>(request: &Request<T>) -> Self {
+        let durability = request.metadata().get(DURABILITY_HEADER);
+        let durability = match durability.map(|value| value.to_str()) {
+            Some(Ok("sync")) => Some(Durability::Sync),
+            Some(Ok("buffered")) => Some(Durability::Buffered),
+            _ => None,
+        };
+        Call {
+            request_id: RequestId::of(request),
+            deadline: Deadline::of(request),
+            actor: match request.remote_addr() {
+                Some(addr) => addr.ip().to_string(),
+                None => "local".to_string(),
+            },
+            durability,
+        }
+    }
+}
+
+impl ZerotableService {
+    /// Create a service that does not retry conflicting transactions.
+    pub fn new(engine: AsyncEngine) -> Self {
+        Self {
+            engine,
+            retry_budget: Duration::ZERO,
+            contention: Arc::default(),
+            payloads: Arc::default(),
+            rate_limiter: Arc::default(),
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            slow_query_threshold: None,
+            slow_request_threshold: None,
+            strict_databases: Arc::new([]),
+            max_document_size: DEFAULT_MAX_DOCUMENT_SIZE,
+            stream_stall_timeout: DEFAULT_STREAM_STALL_TIMEOUT,
+            operations: Operations::new(),
+            long_running: Arc::new(Semaphore::new(DEFAULT_LONG_RUNNING_TASKS.get())),
+            export_dir: None,
+            mirror: None,
+        }
+    }
+
+    /// Remember idempotency keys of created documents for `ttl`.
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        self
+    }
+
+    /// Limit the request rate of every client.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Arc::new(rate_limiter);
+        self
+    }
+
+    /// Log the queries running at least `threshold` with their plan, none if
+    /// `None`.
+    pub fn with_slow_query_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_query_threshold = threshold;
+        self
+    }
+
+    /// Log the requests taking at least `threshold`, none if `None`, see
+    /// [`slow_log`](crate::slow_log).
+    pub fn with_slow_request_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_request_threshold = threshold;
+        self
+    }
+
+    /// Refuse writes of documents larger than `size` encoded bytes. Copies
+    /// and moves are not checked, they write documents already accepted.
+    pub fn with_max_document_size(mut self, size: usize) -> Self {
+        self.max_document_size = size;
+        self
+    }
+
+    /// Drop the clients of response streams that do not read for `timeout`,
+    /// releasing the snapshot the stream reads from.
+    pub fn with_stream_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_stall_timeout = timeout;
+        self
+    }
+
+    /// Run exports and imports as operations on files of `dir`, none are if
+    /// `None`.
+    pub fn with_export_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.export_dir = dir;
+        self
+    }
+
+    /// Run up to `limit` operations and exports at once. Operations started
+    /// beyond it wait for one to end, exports are refused.
+    pub fn with_long_running_tasks(mut self, limit: NonZeroUsize) -> Self {
+        self.long_running = Arc::new(Semaphore::new(limit.get()));
+        self
+    }
+
+    /// Mirror requests to a secondary server, see [`mirror`](crate::mirror).
+    pub fn with_mirror(mut self, mirror: Mirror) -> Self {
+        self.mirror = Some(Arc::new(mirror));
+        self
+    }
+
+    /// Refuse the queries no index serves in every collection of
+    /// `databases`, not only in those configured so.
+    pub fn with_strict_databases(mut self, databases: Vec<String>) -> Self {
+        self.strict_databases = databases.into();
+        self
+    }
+
+    /// Retry conflicting transactions for up to `budget` per request.
+    pub fn with_retry_budget(mut self, budget: Duration) -> Self {
+        self.retry_budget = budget;
+        self
+    }
+
+    /// Rate limiter of this service, whose limits can be changed while
+    /// serving.
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+
+    /// Wait for the work under way to finish, before the engine closes:
+    /// the engine calls queued or running and the operations, cancelled
+    /// first, a restart stops them anyway.
+    pub async fn drain(&self) {
+        self.operations.cancel_all();
+        self.engine.drain().await;
+        while self.operations.running() > 0 {
+            tokio::time::sleep(OPERATION_DRAIN_INTERVAL).await;
+        }
+    }
+
+    /// Transaction contention metrics of this service.
+    pub fn contention(&self) -> &ContentionMetrics {
+        &self.contention
+    }
+
+    /// Payload size metrics of this service.
+    pub fn payloads(&self) -> &PayloadMetrics {
+        &self.payloads
+    }
+
+    /// Memory budget and usage of the storage engine.
+    pub fn memory(&self) -> &MemoryTracker {
+        self.engine.engine().memory()
+    }
+
+    /// Request mirror of this service, if any.
+    pub fn mirror(&self) -> Option<&Mirror> {
+        self.mirror.as_deref()
+    }
+
+    /// Serve a unary RPC of `method` with `handle`, recording its payload
+    /// sizes, mirroring it if sampled and stamping the request ID on the
+    /// result.
+    async fn unary<Req, Res, F>(
+        &self,
+        method: &'static str,
+        request: Request<Req>,
+        handle: impl FnOnce(Request<Req>) -> F,
+    ) -> Result<Response<Res>, Status>
+    where
+        Req: Message + Described + Clone + 'static,
+        Res: Message + Default + PartialEq + Clone + 'static,
+        F: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let request_id = RequestId::of(&request);
+        self.payloads
+            .record_request(method, request.get_ref().encoded_len());
+        let timer = self
+            .slow_request_threshold
+            .map(|threshold| SlowRequestTimer::start(threshold, request.get_ref()));
+        let mirrored = match &self.mirror {
+            Some(mirror) if mirror.sample(method) => {
+                Some((request.get_ref().clone(), request.metadata().clone()))
+            }
+            _ => None,
+        };
+        let result = handle(request).await;
+        if let Ok(response) = &result {
+            self.payloads
+                .record_response(method, response.get_ref().encoded_len());
+        }
+        if let (Some(mirror), Some((message, metadata))) = (&self.mirror, mirrored) {
+            let primary = result.as_ref().map(Response::get_ref).map_err(Status::code);
+            mirror.send(method, message, metadata, primary);
+        }
+        if let Some(timer) = timer {
+            let code = result.as_ref().map_or_else(Status::code, |_| Code::Ok);
+            timer.finish(method, &request_id, code);
+        }
+        request_id.finish(method, result)
+    }
+
+    /// Whether queries of `collection`, as stored, must be served by an
+    /// index.
+    fn strict_queries(&self, collection: &str) -> Result<bool, Status> {
+        let database_id = keys::unqualify(collection).0;
+        if self
+            .strict_databases
+            .iter()
+            .any(|strict| strict == database_id)
+        {
+            return Ok(true);
+        }
+        let config = self
+            .engine
+            .engine()
+            .collection_config(collection)
+            .map_err(engine_err_to_status)?;
+        Ok(config.strict_queries)
+    }
+
+    /// Reject `what`, a document of `size` encoded bytes, if it is over the
+    /// size limit.
+    fn check_document_size(&self, what: &str, size: usize) -> Result<(), Status> {
+        if size > self.max_document_size {
+            return Err(invalid_field(
+                "document",
+                &format!(
+                    "{what} is {size} bytes, at most {} are allowed",
+                    self.max_document_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Path of export file `file` in the export directory.
+    fn export_file(&self, file: &str) -> Result<PathBuf, Status> {
+        let Some(dir) = &self.export_dir else {
+            return Err(Status::failed_precondition(
+                "no export directory is configured",
+            ));
+        };
+        if file.is_empty() {
+            return Err(invalid_field("file", "file is required"));
+        }
+        if file == "." || file == ".." || file.contains(['/', '\\', '\0']) {
+            return Err(invalid_field(
+                "file",
+                "file must be a file name, not a path",
+            ));
+        }
+        Ok(dir.join(file))
+    }
+
+    /// Write the export file at `path` to the database with `engine`, see
+    /// [`StartImportRequest`]. Chunks are verified as they are read and
+    /// each is applied in a transaction of its own, the manifest is checked
+    /// at the end of the file.
+    fn import_file(
+        &self,
+        engine: &Engine,
+        path: &Path,
+        policy: ConflictPolicy,
+        operation: &OperationHandle,
+    ) -> Result<(), Status> {
+        let mut file = BufReader::new(File::open(path).map_err(export_file_error)?);
+        let mut reader = ChunkReader::new();
+        while let Some(message) = export::read_delimited(&mut file).map_err(export_file_error)? {
+            let message = ExportDocumentsResponse::decode(message.as_slice())
+                .map_err(|e| corrupted_export_file(&e))?;
+            let chunk = match message.item {
+                Some(ExportItem::Chunk(chunk)) => chunk_from_proto(chunk),
+                Some(ExportItem::Manifest(manifest)) => {
+                    return reader
+                        .finish(&manifest_from_proto(&manifest))
+                        .map_err(|e| corrupted_export_file(&e));
+                }
+                Some(ExportItem::Heartbeat(_)) | None => continue,
+            };
+            let documents = reader.read(&chunk).map_err(|e| corrupted_export_file(&e))?;
+            let documents = import_documents(documents)?;
+            for doc in &documents {
+                let what = format!("document {}", name::format(&doc.collection_id, &doc.doc_id));
+                self.check_document_size(&what, doc.data.len())?;
+            }
+
+            let mut attempts = 1;
+            loop {
+                let applied = engine.apply_import_chunk(
+                    operation.id(),
+                    chunk.sequence,
+                    &documents,
+                    policy,
+                    operation.deadline(),
+                );
+                match applied {
+                    Err(EngineError::TransactionConflict) if attempts < IMPORT_CHUNK_ATTEMPTS => {
+                        attempts += 1;
+                    }
+                    applied => {
+                        applied.map_err(engine_err_to_status)?;
+                        break;
+                    }
+                }
+            }
+            operation.add_documents(documents.len() as u64);
+        }
+        Err(Status::data_loss(
+            "export file is truncated, its manifest is missing",
+        ))
+    }
+
+    /// Run `work` of `operation` on the blocking pool once a long-running
+    /// slot is free, and record how it ended.
+    fn spawn_operation<F>(&self, request_id: RequestId, operation: OperationHandle, work: F)
+    where
+        F: FnOnce(&OperationHandle) -> Result<(), Status> + Send + 'static,
+    {
+        let operations = self.operations.clone();
+        let long_running = self.long_running.clone();
+        tokio::spawn(async move {
+            let permit = long_running
+                .acquire_owned()
+                .await
+                .expect("long-running semaphore is never closed");
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let result = panic::catch(|| work(&operation))
+                    .unwrap_or_else(|panic| Err(panic_to_status(&request_id, panic)));
+                if let Err(status) = &result {
+                    tracing::warn!(
+                        %request_id,
+                        operation = operation.id(),
+                        message = status.message(),
+                        "operation failed"
+                    );
+                }
+                operations.finish(operation.id(), result);
+            });
+        });
+    }
+
+    /// Aggregate the documents of the collection of `plan` matching
+    /// `filters`. The collection is split in up to `plan.parallelism`
+    /// ranges scanned at once by the engine workers, each retried on its
+    /// own, and what each found is merged in range order.
+    async fn aggregate(
+        &self,
+        call: Call,
+        plan: &QueryPlan,
+        aggregator: Aggregator,
+        filters: Vec<FieldFilter>,
+    ) -> Result<Result<Aggregator, prost::DecodeError>, Status> {
+        let collection = plan.collection.clone();
+        let starts = if plan.parallelism > 1 {
+            let collection = collection.clone();
+            let partitions = plan.parallelism;
+            self.run(call.clone(), move |engine, deadline| {
+                engine.partition_collection(&collection, partitions, deadline)
+            })
+            .await?
+        } else {
+            Vec::new()
+        };
+
+        let mut ranges = Vec::with_capacity(starts.len() + 1);
+        let mut start = None;
+        for end in starts {
+            ranges.push((start, Some(end.clone())));
+            start = Some(end);
+        }
+        ranges.push((start, None));
+
+        let filters = Arc::new(filters);
+        let mut scans = JoinSet::new();
+        for (i, (start, end)) in ranges.into_iter().enumerate() {
+            let service = self.clone();
+            let call = call.clone();
+            let collection = collection.clone();
+            let filters = filters.clone();
+            let aggregator = aggregator.clone();
+            scans.spawn(async move {
+                let scanned = service
+                    .run(call, move |engine, deadline| {
+                        aggregate_range(
+                            engine,
+                            &collection,
+                            start.as_deref(),
+                            end.as_deref(),
+                            &filters,
+                            &aggregator,
+                            deadline,
+                        )
+                    })
+                    .await;
+                (i, scanned)
+            });
+        }
+
+        let mut partials = Vec::with_capacity(scans.len());
+        while let Some(joined) = scans.join_next().await {
+            let (i, scanned) =
+                joined.map_err(|e| Status::internal(format!("range scan failed: {e}")))?;
+            match scanned? {
+                Ok(partial) => partials.push((i, partial)),
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+        // merged in range order, so sums of doubles add up the same way
+        // whatever range finishes first
+        partials.sort_by_key(|(i, _)| *i);
+        let mut aggregator = aggregator;
+        for (_, partial) in &partials {
+            aggregator.merge(partial);
+        }
+        Ok(Ok(aggregator))
+    }
+
+    /// Collection as stored and bound filters of an UpdateWhere or
+    /// DeleteWhere, refused in strict collections when the filters need an
+    /// index.
+    fn plan_bulk(
+        &self,
+        database_id: &str,
+        collection_id: &str,
+        filters: &[FieldFilter],
+        parameters: &HashMap<String, Value>,
+    ) -> Result<(String, Vec<FieldFilter>), Status> {
+        if collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+        let filters = bind_filters(filters, parameters)?;
+        let collection = qualify(database_id, collection_id)?;
+        if let Some(index) = QueryPlan::scan(&collection, &filters).missing_index()
+            && self.strict_queries(&collection)?
+        {
+            return Err(missing_index(&index));
+        }
+        Ok((collection, filters))
+    }
+
+    /// Rewrite every document of `collection`, as stored, batch by batch,
+    /// see [`Engine::rewrite_range`]. Each batch is retried on conflicts,
+    /// the batches before a failure stay written. Returns the number of
+    /// documents changed.
+    async fn rewrite_collection<F>(
+        &self,
+        call: Call,
+        collection: String,
+        rewrite: F,
+    ) -> Result<u64, Status>
+    where
+        F: Fn(&str, &[u8]) -> Result<Rewrite, Status> + Send + Sync + 'static,
+    {
+        let rewrite = Arc::new(rewrite);
+        let mut start = None;
+        let mut changed = 0;
+        loop {
+            let call = call.clone();
+            let collection = collection.clone();
+            let rewrite = rewrite.clone();
+            let batch_start = start.clone();
+            let progress = self
+                .run_retrying(call, BULK_BATCH_ATTEMPTS, move |engine, deadline| {
+                    engine.rewrite_range(
+                        &collection,
+                        batch_start.as_deref(),
+                        BULK_BATCH_SIZE,
+                        deadline,
+                        &*rewrite,
+                    )
+                })
+                .await??;
+            changed += progress.changed;
+            match progress.next {
+                Some(next) => start = Some(next),
+                None => return Ok(changed),
+            }
+        }
+    }
+
+    /// Commit `writes` of a BulkWrite, the first at position `index` in the
+    /// stream, in one transaction retried on conflicts. Returns the result
+    /// of every write, those of the whole batch if it failed.
+    async fn bulk_write_batch(
+        &self,
+        call: Call,
+        index: u64,
+        writes: Vec<BulkWriteRequest>,
+    ) -> Vec<BulkWriteResult> {
+        // `None` for the writes left to the transaction
+        let mut invalid = Vec::with_capacity(writes.len());
+        let mut valid = Vec::with_capacity(writes.len());
+        for write in writes {
+            match bulk_op(write) {
+                Ok(write) => {
+                    valid.push(write);
+                    invalid.push(None);
+                }
+                Err(status) => invalid.push(Some(status)),
+            }
+        }
+
+        let count = valid.len();
+        let max_document_size = self.max_document_size;
+        let committed = if valid.is_empty() {
+            Ok(Vec::new())
+        } else {
+            self.run_retrying(call, BULK_BATCH_ATTEMPTS, move |engine, _| {
+                let documents: Vec<_> = valid
+                    .iter()
+                    .map(|(collection, doc_id, _)| (collection.as_str(), doc_id.as_str()))
+                    .collect();
+                engine.write_batch(&documents, |i, current| {
+                    let (collection, doc_id, op) = &valid[i];
+                    let name = name::format(collection, doc_id);
+                    bulk_rewrite(op, name, current, max_document_size)
+                })
+            })
+            .await
+        };
+        let mut committed = committed
+            .unwrap_or_else(|status| vec![Err(status); count])
+            .into_iter();
+
+        invalid
+            .into_iter()
+            .zip(index..)
+            .map(|(invalid, index)| {
+                let outcome = match invalid {
+                    Some(status) => Err(status),
+                    None => committed.next().expect("one outcome per valid write"),
+                };
+                bulk_write_result(index, outcome)
+            })
+            .collect()
+    }
+
+    /// Commit the writes received on `writes` batch by batch, sending the
+    /// results of every batch on `responses`. A batch takes the writes
+    /// received while the previous one committed. Stops at the first error
+    /// of the request stream, once sent, or when the client goes away or
+    /// stalls.
+    async fn commit_bulk_writes(
+        self,
+        call: Call,
+        mut writes: mpsc::Receiver<Result<BulkWriteRequest, Status>>,
+        responses: mpsc::Sender<Result<BulkWriteResponse, Status>>,
+    ) {
+        let stall_timeout = self.stream_stall_timeout;
+        let mut index = 0;
+        while let Some(mut message) = writes.recv().await {
+            let mut batch = Vec::new();
+            let failed = loop {
+                match message {
+                    Ok(write) => batch.push(write),
+                    Err(status) => break Some(status),
+                }
+                if batch.len() == BULK_BATCH_SIZE {
+                    break None;
+                }
+                match writes.try_recv() {
+                    Ok(next) => message = next,
+                    Err(_) => break None,
+                }
+            };
+
+            if !batch.is_empty() {
+                let count = batch.len() as u64;
+                let results = self.bulk_write_batch(call.clone(), index, batch).await;
+                index += count;
+                let response = Ok(BulkWriteResponse { results });
+                if !send_response(&responses, response, stall_timeout, &call.request_id).await {
+                    return;
+                }
+            }
+            if let Some(status) = failed {
+                send_response(&responses, Err(status), stall_timeout, &call.request_id).await;
+                return;
+            }
+        }
+    }
+
+    /// `plan` with its cost estimated from the stats of its collection.
+    fn estimate_cost(&self, plan: QueryPlan) -> Result<QueryPlan, Status> {
+        let stats = self
+            .engine
+            .engine()
+            .collection_stats(&plan.collection)
+            .map_err(engine_err_to_status)?;
+        Ok(plan.with_estimated_cost(&stats))
+    }
+
+    /// Reject the request if its client is over the rate limit.
+    fn check_rate_limit<T>(
+        &self,
+        request: &Request<T>,
+        operation: Operation,
+    ) -> Result<(), Status> {
+        let client = request.remote_addr().map(|addr| addr.ip());
+        if !self.rate_limiter.try_acquire(client, operation) {
+            return Err(Status::resource_exhausted("rate limit exceeded"));
+        }
+        Ok(())
+    }
+
+    /// Run engine work on the engine workers within the request deadline.
+    ///
+    /// The work is handed the deadline, which is also cancelled if the RPC
+    /// is dropped, e.g. because the client went away. Work failing with a
+    /// transaction conflict is run again, with a short backoff, until it
+    /// succeeds or the retry budget is spent.
+    async fn run<T, F>(&self, call: Call, work: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: Fn(&Engine, &Deadline) -> Result<T, EngineError> + Send + 'static,
+    {
+        self.run_retrying(call, 1, work).await
+    }
+
+    /// Like [`run`](Self::run), but work failing with a transaction conflict
+    /// is run at least `attempts` times, even past the retry budget, while
+    /// the deadline allows. Only for work that does not depend on what the
+    /// client read, like server-side transforms.
+    async fn run_retrying<T, F>(&self, call: Call, attempts: u32, work: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: Fn(&Engine, &Deadline) -> Result<T, EngineError> + Send + 'static,
+    {
+        let Call {
+            request_id,
+            deadline,
+            actor,
+            durability,
+        } = call;
+        let panic_request_id = request_id.clone();
+        let cancel = deadline.cancel_on_drop();
+        let mut engine = self.engine.acting_as(&actor);
+        if let Some(durability) = durability {
+            engine = engine.with_durability(durability);
+        }
+        let retry_budget = self.retry_budget;
+        let contention = self.contention.clone();
+        let remaining = deadline.remaining();
+        // the workers do not inherit the span of the RPC
+        let span = tracing::info_span!("engine", retries = tracing::field::Empty);
+
+        let task = engine.run(move |engine| {
+            let _entered = span.enter();
+            let mut retries = 0;
+            let mut first_conflict: Option<Instant> = None;
+            loop {
+                let result = work(engine, &deadline);
+                let conflict = matches!(result, Err(EngineError::TransactionConflict));
+                let retry_time = first_conflict.map_or(Duration::ZERO, |t| t.elapsed());
+                let budgeted = retry_time < retry_budget;
+                let retry = budgeted || retries + 1 < attempts;
+                if conflict && retry && !deadline.is_expired() {
+                    first_conflict.get_or_insert_with(Instant::now);
+                    retries += 1;
+                    let backoff = Duration::from_millis(1 << retries.min(5));
+                    std::thread::sleep(if budgeted {
+                        backoff.min(retry_budget - retry_time)
+                    } else {
+                        backoff
+                    });
+                    continue;
+                }
+
+                contention.record(retries, retry_time, conflict);
+                span.record("retries", retries);
+                if retries > 0 {
+                    tracing::info!(
+                        %request_id,
+                        retries,
+                        ?retry_time,
+                        "transaction retried"
+                    );
+                }
+                return result;
+            }
+        });
+
+        let joined = match remaining {
+            Some(remaining) => tokio::time::timeout(remaining, task)
+                .await
+                .map_err(|_| engine_err_to_status(EngineError::DeadlineExceeded))?,
+            None => task.await,
+        };
+        // the work is done, later work of the same request, e.g. the next
+        // chunk of a stream, keeps the deadline
+        cancel.disarm();
+        joined
+            .map_err(|panic| panic_to_status(&panic_request_id, panic))?
+            .map_err(engine_err_to_status)
+    }
+}
+
+/// Log a panic of engine work run for the request `request_id` and fail
+/// the request.
+pub(crate) fn panic_to_status(request_id: &RequestId, panic: Panic) -> Status {
+    let backtrace = panic.backtrace.map_or_else(
+        || "unavailable".to_string(),
+        |backtrace| backtrace.to_string(),
+    );
+    tracing::error!(
+        %request_id,
+        message = panic.message,
+        %backtrace,
+        "engine work panicked"
+    );
+    let details = ErrorDetails::with_error_info("PANIC", ERROR_DOMAIN, HashMap::new());
+    Status::with_error_details(
+        Code::Internal,
+        "internal error, the server logged its cause",
+        details,
+    )
+}
+
+/// Domain reported in the `ErrorInfo` details of failed requests.
+const ERROR_DOMAIN: &str = "zerotable.io";
+
+/// Convert EngineError to tonic Status.
+///
+/// The status carries an `ErrorInfo` with a machine readable reason, and a
+/// `PreconditionFailure` when an import chunk is out of sequence.
+pub(crate) fn engine_err_to_status(err: EngineError) -> Status {
+    let (code, reason) = match &err {
+        EngineError::AlreadyExists => (Code::AlreadyExists, "ALREADY_EXISTS"),
+        EngineError::NotFound => (Code::NotFound, "NOT_FOUND"),
+        EngineError::InvalidKey(_) => (Code::InvalidArgument, "INVALID_KEY"),
+        EngineError::Storage(_) => (Code::Internal, "STORAGE_ERROR"),
+        EngineError::Corrupted(_) => (Code::DataLoss, "CORRUPTED_RECORD"),
+        EngineError::TransactionConflict => (Code::Aborted, "TRANSACTION_CONFLICT"),
+        EngineError::OutOfSequence { .. } => (Code::FailedPrecondition, "OUT_OF_SEQUENCE"),
+        EngineError::DeadlineExceeded => (Code::DeadlineExceeded, "DEADLINE_EXCEEDED"),
+        EngineError::WriteLocked(_) => (Code::FailedPrecondition, "WRITE_LOCKED"),
+        EngineError::CorruptedAudit(_) => (Code::DataLoss, "CORRUPTED_AUDIT_ENTRY"),
+        EngineError::ReadOnly => (Code::FailedPrecondition, "READ_ONLY"),
+        EngineError::UnsupportedKeyFormat(_) => (Code::Internal, "UNSUPPORTED_KEY_FORMAT"),
+        EngineError::DocumentTooLarge { .. } => (Code::InvalidArgument, "DOCUMENT_TOO_LARGE"),
+        EngineError::Archived(_) => (Code::FailedPrecondition, "COLLECTION_ARCHIVED"),
+        EngineError::Archive(ArchiveError::Disabled) => {
+            (Code::FailedPrecondition, "ARCHIVE_DISABLED")
+        }
+        EngineError::Archive(ArchiveError::Io(_)) => (Code::Unavailable, "ARCHIVE_UNAVAILABLE"),
+        EngineError::Archive(ArchiveError::Corrupted(_)) => (Code::DataLoss, "CORRUPTED_SEGMENT"),
+        EngineError::StaleDocument(_) => (Code::FailedPrecondition, "STALE_DOCUMENT"),
+        EngineError::HistoryUnavailable => (Code::FailedPrecondition, "HISTORY_UNAVAILABLE"),
+        EngineError::CounterOverflow => (Code::OutOfRange, "COUNTER_OVERFLOW"),
+        EngineError::Encryption(CipherError::Provider(_)) => {
+            (Code::Unavailable, "ENCRYPTION_KEY_UNAVAILABLE")
+        }
+        EngineError::Encryption(CipherError::Invalid) => (Code::DataLoss, "CORRUPTED_RECORD"),
+        EngineError::Encryption(_) => (Code::Internal, "ENCRYPTION_ERROR"),
+    };
+
+    let mut metadata = HashMap::new();
+    let mut details = match &err {
+        EngineError::OutOfSequence { expected, got } => {
+            metadata.insert("expected_sequence".to_string(), expected.to_string());
+            metadata.insert("sequence".to_string(), got.to_string());
+            ErrorDetails::with_precondition_failure_violation(
+                "IMPORT_SEQUENCE",
+                format!("chunk {got}"),
+                err.to_string(),
+            )
+        }
+        EngineError::WriteLocked(collection_id) => {
+            metadata.insert("collection_id".to_string(), collection_id.clone());
+            ErrorDetails::with_precondition_failure_violation(
+                "COLLECTION_WRITE_LOCK",
+                collection_id.clone(),
+                err.to_string(),
+            )
+        }
+        EngineError::Archived(collection_id) => {
+            metadata.insert("collection_id".to_string(), collection_id.clone());
+            ErrorDetails::with_precondition_failure_violation(
+                "COLLECTION_ARCHIVED",
+                collection_id.clone(),
+                err.to_string(),
+            )
+        }
+        EngineError::StaleDocument(header) => {
+            metadata.insert("sequence".to_string(), header.sequence.to_string());
+            ErrorDetails::with_precondition_failure_violation(
+                "DOCUMENT_VERSION",
+                header.sequence.to_string(),
+                err.to_string(),
+            )
+        }
+        _ => ErrorDetails::new(),
+    };
+    details.set_error_info(reason, ERROR_DOMAIN, metadata);
+
+    Status::with_error_details(code, err.to_string(), details)
+}
+
+/// INVALID_ARGUMENT status pointing at the offending request field.
+fn invalid_field(field: &str, description: &str) -> Status {
+    Status::with_error_details(
+        Code::InvalidArgument,
+        description,
+        ErrorDetails::with_bad_request_violation(field, description),
+    )
+}
+
+/// Parse the resource name in request `field`, "collection_id/document_id",
+/// into the collection ID as stored and the decoded document ID, see
+/// [`name`].
+fn parse_name(field: &str, name: &str) -> Result<(String, String), Status> {
+    name::parse(name).map_err(|e| invalid_field(field, &e.to_string()))
+}
+
+/// Database in request field `database_id`, the default one if empty.
+fn database(database_id: &str) -> Result<&str, Status> {
+    if database_id.is_empty() {
+        return Ok(DEFAULT_DATABASE);
+    }
+    keys::validate_database(database_id)
+        .map_err(|e| invalid_field("database_id", &e.to_string()))?;
+    Ok(database_id)
+}
+
+/// Collection ID as stored of `collection_id` in the database in request
+/// field `database_id`, see [`keys::qualify`].
+pub(crate) fn qualify(database_id: &str, collection_id: &str) -> Result<String, Status> {
+    keys::qualify(database(database_id)?, collection_id).map_err(|e| engine_err_to_status(e.into()))
+}
+
+/// Validate the filters of a request and bind their parameters.
+fn bind_filters(
+    filters: &[FieldFilter],
+    parameters: &HashMap<String, Value>,
+) -> Result<Vec<FieldFilter>, Status> {
+    for (i, filter) in filters.iter().enumerate() {
+        if filter.field.is_empty() {
+            return Err(invalid_field(
+                &format!("filters[{i}]"),
+                "filters need a field",
+            ));
+        }
+    }
+    aggregate::bind(filters, parameters).map_err(|e| invalid_field("filters", &e.to_string()))
+}
+
+/// Validate an aggregation query and plan it. Returns the filters with
+/// their parameters bound.
+fn plan_aggregation(
+    req: &RunAggregationQueryRequest,
+) -> Result<(QueryPlan, Aggregator, Vec<FieldFilter>), Status> {
+    if req.collection_id.is_empty() {
+        return Err(invalid_field("collection_id", "collection_id is required"));
+    }
+    let filters = bind_filters(&req.filters, &req.parameters)?;
+    let aggregator = Aggregator::new(&req.aggregations)
+        .map_err(|e| invalid_field("aggregations", &e.to_string()))?;
+
+    if req.parallelism > MAX_QUERY_PARALLELISM {
+        return Err(invalid_field(
+            "parallelism",
+            &format!("parallelism must be at most {MAX_QUERY_PARALLELISM}"),
+        ));
+    }
+
+    let collection_id = qualify(&req.database_id, &req.collection_id)?;
+    let plan = QueryPlan::aggregation(&collection_id, &filters, &aggregator)
+        .with_parallelism(req.parallelism.max(1) as usize);
+    Ok((plan, aggregator, filters))
+}
+
+/// Aggregate the documents of `collection` from `start` to `end` matching
+/// `filters`, starting from `aggregator`.
+fn aggregate_range(
+    engine: &Engine,
+    collection: &str,
+    start: Option<&str>,
+    end: Option<&str>,
+    filters: &[FieldFilter],
+    aggregator: &Aggregator,
+    deadline: &Deadline,
+) -> Result<Result<Aggregator, prost::DecodeError>, EngineError> {
+    // start over if the scan is retried
+    let mut aggregator = aggregator.clone();
+    let mut corrupted = None;
+    engine.scan_range(collection, start, end, deadline, |_, stored| {
+        let doc = match Document::decode(stored.data.as_slice()) {
+            Ok(doc) => doc,
+            Err(e) => {
+                corrupted = Some(e);
+                return ControlFlow::Break(());
+            }
+        };
+        if matches(&doc, filters) {
+            aggregator.add(&doc);
+        }
+        ControlFlow::Continue(())
+    })?;
+    Ok(corrupted.map_or(Ok(aggregator), Err))
+}
+
+/// Fail a query of a strict collection that needs `index`.
+fn missing_index(index: &str) -> Status {
+    let message =
+        format!("the query needs an index on {index}, strict mode refuses full collection scans");
+    let mut details =
+        ErrorDetails::with_precondition_failure_violation("MISSING_INDEX", index, &message);
+    details.set_error_info("MISSING_INDEX", ERROR_DOMAIN, HashMap::new());
+    Status::with_error_details(Code::FailedPrecondition, message, details)
+}
+
+/// Fail an update merging disjoint fields whose `field` was written
+/// concurrently.
+fn field_conflict(field: &str) -> Status {
+    let message = format!("field '{field}' was written concurrently");
+    let mut metadata = HashMap::new();
+    metadata.insert("field_path".to_string(), field.to_string());
+    let details = ErrorDetails::with_error_info("FIELD_CONFLICT", ERROR_DOMAIN, metadata);
+    Status::with_error_details(Code::Aborted, message, details)
+}
+
+/// Refuse a bulk delete of `collection`, which has delete protection.
+fn delete_protected(collection: &str) -> Status {
+    let name = name::format_collection(collection);
+    let message = format!("collection {name} has delete protection");
+    let mut details =
+        ErrorDetails::with_precondition_failure_violation("DELETE_PROTECTION", &name, &message);
+    details.set_error_info("DELETE_PROTECTION", ERROR_DOMAIN, HashMap::new());
+    Status::with_error_details(Code::FailedPrecondition, message, details)
+}
+
+/// Reject a request whose resource names are in different databases.
+fn check_same_database(field: &str, collection: &str, other: &str) -> Result<(), Status> {
+    if keys::unqualify(collection).0 != keys::unqualify(other).0 {
+        return Err(invalid_field(field, "names must be in the same database"));
+    }
+    Ok(())
+}
+
+/// The `read_time` of a read, `None` if unset.
+fn read_time(read_time: Option<Timestamp>) -> Result<Option<SystemTime>, Status> {
+    let Some(read_time) = read_time else {
+        return Ok(None);
+    };
+    let read_time = SystemTime::try_from(read_time)
+        .map_err(|_| invalid_field("read_time", "read_time is not a valid time"))?;
+    if read_time > SystemTime::now() {
+        return Err(invalid_field("read_time", "read_time is in the future"));
+    }
+    Ok(Some(read_time))
+}
+
+/// A page of a listing and where the next one starts, if there is one.
+type Page = (Vec<Document>, Option<PageToken>);
+
+/// Visit the documents of `collection` in document ID order, as they were
+/// at `read_time` if set.
+fn scan_collection(
+    engine: &Engine,
+    collection: &str,
+    read_time: Option<SystemTime>,
+    deadline: &Deadline,
+    visit: impl FnMut(&str, StoredDocument) -> ControlFlow<()>,
+) -> Result<(), EngineError> {
+    match read_time {
+        Some(time) => engine.scan_as_of(collection, time, deadline, visit),
+        None => engine.scan_range(collection, None, None, deadline, visit),
+    }
+}
+
+/// Like [`Engine::list_documents`], as of `time`. Revisions are only read
+/// in document ID order, so a descending page reads every document before
+/// its cursor.
+fn list_documents_as_of(
+    engine: &Engine,
+    collection: &str,
+    after: Option<&str>,
+    descending: bool,
+    limit: usize,
+    time: SystemTime,
+    deadline: &Deadline,
+) -> Result<Vec<(String, StoredDocument)>, EngineError> {
+    let mut listed = VecDeque::new();
+    engine.scan_as_of(collection, time, deadline, |doc_id, stored| {
+        match (after, descending) {
+            (Some(after), false) if doc_id <= after => return ControlFlow::Continue(()),
+            (Some(before), true) if doc_id >= before => return ControlFlow::Break(()),
+            _ => {}
+        }
+        listed.push_back((doc_id.to_string(), stored));
+        if !descending && listed.len() == limit {
+            return ControlFlow::Break(());
+        }
+        // the last ones before the cursor
+        if listed.len() > limit {
+            listed.pop_front();
+        }
+        ControlFlow::Continue(())
+    })?;
+    let mut listed = Vec::from(listed);
+    if descending {
+        listed.reverse();
+    }
+    Ok(listed)
+}
+
+/// List the documents of `collection` after the document `after`, in
+/// document ID order, or reverse order if `descending`, as they were at
+/// `read_time` if set.
+fn list_by_id(
+    engine: &Engine,
+    collection: &str,
+    after: Option<&str>,
+    descending: bool,
+    page_size: usize,
+    read_time: Option<SystemTime>,
+    deadline: &Deadline,
+) -> Result<Result<Page, prost::DecodeError>, EngineError> {
+    // one more tells whether there is a next page
+    let mut listed = match read_time {
+        Some(time) => list_documents_as_of(
+            engine,
+            collection,
+            after,
+            descending,
+            page_size + 1,
+            time,
+            deadline,
+        )?,
+        None => engine.list_documents(collection, after, descending, page_size + 1, deadline)?,
+    };
+    let more = listed.len() > page_size;
+    listed.truncate(page_size);
+
+    let mut documents = Vec::with_capacity(listed.len());
+    for (_, stored) in &listed {
+        match decode_stored(stored) {
+            Ok(doc) => documents.push(doc),
+            Err(e) => return Ok(Err(e)),
+        }
+    }
+    let next = listed
+        .pop()
+        .filter(|_| more)
+        .map(|(doc_id, _)| match descending {
+            true => PageToken::Before(doc_id),
+            false => PageToken::After(doc_id),
+        });
+    Ok(Ok((documents, next)))
+}
+
+/// List the documents of `collection` ordered by `order_by`, after the
+/// document with the key and ID `after`, as they were at `read_time` if set.
+/// Reads the whole collection but keeps no more than two pages of it.
+fn list_ordered(
+    engine: &Engine,
+    collection: &str,
+    order_by: &OrderBy,
+    after: Option<(&[Value], &str)>,
+    page_size: usize,
+    read_time: Option<SystemTime>,
+    deadline: &Deadline,
+) -> Result<Result<Page, prost::DecodeError>, EngineError> {
+    let compare = |(a_key, a_id, _): &(Vec<Value>, String, Document),
+                   (b_key, b_id, _): &(Vec<Value>, String, Document)| {
+        order_by.compare_documents((a_key, a_id), (b_key, b_id))
+    };
+    // one more tells whether there is a next page
+    let keep = page_size + 1;
+    let mut keyed = Vec::new();
+    let mut corrupted = None;
+    scan_collection(
+        engine,
+        collection,
+        read_time,
+        deadline,
+        |doc_id, stored| match decode_stored(&stored) {
+            Ok(doc) => {
+                if let Some(key) = order_by.key(&doc)
+                    && after.is_none_or(|after| {
+                        order_by.compare_documents((&key, doc_id), after).is_gt()
+                    })
+                {
+                    keyed.push((key, doc_id.to_string(), doc));
+                    if keyed.len() >= 2 * keep {
+                        keyed.sort_unstable_by(compare);
+                        keyed.truncate(keep);
+                    }
+                }
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                corrupted = Some(e);
+                ControlFlow::Break(())
+            }
+        },
+    )?;
+    if let Some(e) = corrupted {
+        return Ok(Err(e));
+    }
+
+    keyed.sort_unstable_by(compare);
+    let more = keyed.len() > page_size;
+    keyed.truncate(page_size);
+    let next = keyed
+        .last()
+        .filter(|_| more)
+        .map(|(key, doc_id, _)| PageToken::Keyset {
+            order_by: order_by.to_string(),
+            key: key.clone(),
+            doc_id: doc_id.clone(),
+        });
+    let documents = keyed.into_iter().map(|(_, _, doc)| doc).collect();
+    Ok(Ok((documents, next)))
+}
+
+/// Sends the items of an export from the blocking pool.
+struct ExportSender {
+    tx: mpsc::Sender<Result<ExportDocumentsResponse, Status>>,
+    runtime: tokio::runtime::Handle,
+    request_id: RequestId,
+    stall_timeout: Duration,
+    last_sent: Instant,
+    documents_scanned: u64,
+}
+
+impl ExportSender {
+    /// Send `message`, waiting at most the stall timeout for the client to
+    /// make room for it. Returns false if the client went away or stalled.
+    fn send(&mut self, message: Result<ExportDocumentsResponse, Status>) -> bool {
+        let sent = self
+            .runtime
+            .block_on(self.tx.send_timeout(message, self.stall_timeout));
+        match sent {
+            Ok(()) => {
+                self.last_sent = Instant::now();
+                true
+            }
+            Err(SendTimeoutError::Timeout(_)) => {
+                tracing::warn!(
+                    request_id = %self.request_id,
+                    stall_timeout = ?self.stall_timeout,
+                    "export client stopped reading, dropping it"
+                );
+                false
+            }
+            Err(SendTimeoutError::Closed(_)) => false,
+        }
+    }
+}
+
+/// Where [`export_snapshot`] sends the items of an export.
+trait ExportSink {
+    /// Send `item`, returns false if the export must stop.
+    fn send_item(&mut self, item: ExportItem) -> bool;
+
+    /// Count a document read from the snapshot.
+    fn scanned(&mut self) {}
+}
+
+impl ExportSink for ExportSender {
+    fn send_item(&mut self, item: ExportItem) -> bool {
+        self.send(Ok(ExportDocumentsResponse { item: Some(item) }))
+    }
+
+    // sends a heartbeat if nothing was sent for a while
+    fn scanned(&mut self) {
+        self.documents_scanned += 1;
+        if self.last_sent.elapsed() < EXPORT_HEARTBEAT_INTERVAL {
+            return;
+        }
+        let heartbeat = ExportItem::Heartbeat(ExportHeartbeat {
+            documents_scanned: self.documents_scanned,
+        });
+        // a full buffer holds items the client has yet to read anyway
+        let message = Ok(ExportDocumentsResponse {
+            item: Some(heartbeat),
+        });
+        if self.tx.try_send(message).is_ok() {
+            self.last_sent = Instant::now();
+        }
+    }
+}
+
+/// Writes the items of an export to an export file, see
+/// [`StartExportRequest`].
+struct FileSink {
+    file: BufWriter<File>,
+    operation: OperationHandle,
+    failed: Option<io::Error>,
+}
+
+impl ExportSink for FileSink {
+    fn send_item(&mut self, item: ExportItem) -> bool {
+        let documents = match &item {
+            ExportItem::Chunk(chunk) => chunk.document_count,
+            _ => 0,
+        };
+        let message = ExportDocumentsResponse { item: Some(item) };
+        match self
+            .file
+            .write_all(&message.encode_length_delimited_to_vec())
+        {
+            Ok(()) => {
+                self.operation.add_documents(documents);
+                true
+            }
+            Err(e) => {
+                self.failed = Some(e);
+                false
+            }
+        }
+    }
+}
+
+impl FileSink {
+    /// Flush the file to disk, or return the error writing it failed with.
+    fn finish(self) -> io::Result<()> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        self.file
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()
+    }
+}
+
+/// Read a snapshot of `collections`, or of every collection of `database`
+/// if empty, and send it as chunks followed by the manifest.
+///
+/// Stops quietly if `sender` refuses an item, e.g. because the client went
+/// away or stalled.
+fn export_snapshot(
+    engine: &Engine,
+    database: &str,
+    collections: &[String],
+    compression: export::Compression,
+    deadline: &Deadline,
+    sender: &mut impl ExportSink,
+) -> Result<(), Status> {
+    let collections: Vec<&str> = collections.iter().map(String::as_str).collect();
+    let mut writer = ChunkWriter::new(DEFAULT_CHUNK_BUDGET, compression);
+    let mut failed = None;
+    let mut disconnected = false;
+
+    let snapshot_time = now_millis();
+    engine
+        .scan_snapshot(&collections, deadline, |collection_id, _, stored| {
+            sender.scanned();
+            // NOTE: exporting a whole database scans the other ones too
+            if keys::unqualify(collection_id).0 != database {
+                return ControlFlow::Continue(());
+            }
+            match writer.push(&stored.data) {
+                Ok(None) => ControlFlow::Continue(()),
+                Ok(Some(chunk)) => {
+                    if sender.send_item(chunk_item(chunk)) {
+                        ControlFlow::Continue(())
+                    } else {
+                        disconnected = true;
+                        ControlFlow::Break(())
+                    }
+                }
+                Err(e) => {
+                    failed = Some(e);
+                    ControlFlow::Break(())
+                }
+            }
+        })
+        .map_err(engine_err_to_status)?;
+    if let Some(e) = failed {
+        return Err(Status::internal(format!("failed to compress chunk: {e}")));
+    }
+    if disconnected {
+        return Ok(());
+    }
+
+    let (last, manifest) = writer
+        .finish()
+        .map_err(|e| Status::internal(format!("failed to compress chunk: {e}")))?;
+    if let Some(chunk) = last
+        && !sender.send_item(chunk_item(chunk))
+    {
+        return Ok(());
+    }
+    sender.send_item(manifest_item(manifest, snapshot_time));
+    Ok(())
+}
+
+/// Export a snapshot, as [`export_snapshot`] does, to the export file at
+/// `path`. The file is written as `{path}.partial` and renamed once
+/// complete, so an export file is always whole.
+fn export_to_file(
+    engine: &Engine,
+    path: &Path,
+    database: &str,
+    collections: &[String],
+    compression: export::Compression,
+    operation: &OperationHandle,
+) -> Result<(), Status> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let file = File::create_new(&partial).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => Status::already_exists(
+            "another export to this file is running, or one was interrupted and left its \
+             .partial file",
+        ),
+        _ => export_file_error(e),
+    })?;
+
+    let mut sink = FileSink {
+        file: BufWriter::new(file),
+        operation: operation.clone(),
+        failed: None,
+    };
+    let result = export_snapshot(
+        engine,
+        database,
+        collections,
+        compression,
+        operation.deadline(),
+        &mut sink,
+    )
+    .and_then(|()| {
+        sink.finish()
+            .and_then(|()| fs::rename(&partial, path))
+            .map_err(export_file_error)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Status of a failure to read or write an export file.
+fn export_file_error(e: io::Error) -> Status {
+    match e.kind() {
+        io::ErrorKind::NotFound => Status::not_found("export file not found"),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => corrupted_export_file(&e),
+        _ => Status::internal(format!("export file error: {e}")),
+    }
+}
+
+fn corrupted_export_file(e: &dyn std::error::Error) -> Status {
+    Status::data_loss(format!("corrupted export file: {e}"))
+}
+
+/// Collections as stored of ExportDocuments or StartExport field
+/// `collection_ids`.
+fn export_collections(database: &str, collection_ids: &[String]) -> Result<Vec<String>, Status> {
+    collection_ids
+        .iter()
+        .enumerate()
+        .map(|(i, collection_id)| {
+            let collection = keys::qualify(database, collection_id)
+                .and_then(|collection| keys::collection_prefix(&collection).map(|_| collection));
+            collection.map_err(|e| invalid_field(&format!("collection_ids[{i}]"), &e.to_string()))
+        })
+        .collect()
+}
+
+fn conflict_policy(mode: ImportMode) -> ConflictPolicy {
+    match mode {
+        ImportMode::SkipExisting => ConflictPolicy::Skip,
+        ImportMode::Overwrite => ConflictPolicy::Overwrite,
+        ImportMode::FailExisting => ConflictPolicy::Fail,
+        ImportMode::Bulk => ConflictPolicy::Unchecked,
+    }
+}
+
+fn operation_to_proto(info: OperationInfo) -> LongRunningOperation {
+    let (state, error_code, error_message) = match info.state {
+        operations::OperationState::Running => (OperationState::Running, 0, String::new()),
+        operations::OperationState::Succeeded => (OperationState::Succeeded, 0, String::new()),
+        operations::OperationState::Failed { code, message } => {
+            (OperationState::Failed, code as i32, message)
+        }
+        operations::OperationState::Cancelled => (OperationState::Cancelled, 0, String::new()),
+    };
+    LongRunningOperation {
+        id: info.id,
+        kind: info.kind.to_string(),
+        file: info.target,
+        state: state as i32,
+        documents: info.documents,
+        error_code,
+        error_message,
+        start_time: Some(info.start_time.into()),
+        end_time: info.end_time.map(Into::into),
+    }
+}
+
+fn compression_from_proto(compression: Compression) -> export::Compression {
+    match compression {
+        Compression::None => export::Compression::None,
+        Compression::Gzip => export::Compression::Gzip,
+        Compression::Zstd => export::Compression::Zstd,
+    }
+}
+
+fn compression_to_proto(compression: export::Compression) -> Compression {
+    match compression {
+        export::Compression::None => Compression::None,
+        export::Compression::Gzip => Compression::Gzip,
+        export::Compression::Zstd => Compression::Zstd,
+    }
+}
+
+pub(crate) fn chunk_item(chunk: Chunk) -> ExportItem {
+    ExportItem::Chunk(chunk_to_proto(chunk))
+}
+
+pub(crate) fn chunk_to_proto(chunk: Chunk) -> ExportChunk {
+    ExportChunk {
+        sequence: chunk.sequence,
+        compression: compression_to_proto(chunk.compression) as i32,
+        document_count: chunk.document_count,
+        checksum: chunk.checksum,
+        payload: chunk.payload,
+    }
+}
+
+/// Decode a document as read, with the version it was read at.
+fn decode_stored(stored: &StoredDocument) -> Result<Document, prost::DecodeError> {
+    let mut doc = Document::decode(stored.data.as_slice())?;
+    doc.version = stored.sequence;
+    Ok(doc)
+}
+
+/// Re-encode a stored document under a new name, overriding the given
+/// timestamps. Returns `None` if the document cannot be decoded.
+fn renamed(
+    data: &[u8],
+    name: &str,
+    create_time: Option<SystemTime>,
+    update_time: Option<SystemTime>,
+) -> Option<Vec<u8>> {
+    let mut doc = Document::decode(data).ok()?;
+    doc.name = name.to_string();
+    if let Some(time) = create_time {
+        doc.create_time = Some(time.into());
+    }
+    if let Some(time) = update_time {
+        doc.update_time = Some(time.into());
+    }
+    Some(doc.encode_to_vec())
+}
+
+/// Name and timestamp a new document of a collection as stored, with
+/// `document_id` or a generated ID if empty. Returns the document ID.
+fn stamp_new_document(doc: &mut Document, collection_id: &str, document_id: String) -> String {
+    let (doc_id, now) = if document_id.is_empty() {
+        let (uuid, ts) = generate_uuid_v7();
+        (uuid.to_string(), ts)
+    } else {
+        (document_id, now_millis())
+    };
+
+    let prost_now: Timestamp = now.into();
+    doc.name = name::format(collection_id, &doc_id);
+    doc.create_time = Some(prost_now.clone());
+    doc.update_time = Some(prost_now);
+    // known once read
+    doc.version = 0;
+    doc_id
+}
+
+/// When the document stored as `data` expires, `None` if it does not, see
+/// [`Engine::with_expiry`].
+pub fn document_expire_time(data: &[u8]) -> Option<SystemTime> {
+    let expire_time = Document::decode(data).ok()?.expire_time?;
+    SystemTime::try_from(expire_time).ok()
+}
+
+/// Wrap a write's reply, reporting the mutation number the write got in its
+/// collection, if any.
+fn with_collection_sequence<T>(message: T, sequence: Option<u64>) -> Response<T> {
+    let mut response = Response::new(message);
+    if let Some(sequence) = sequence {
+        response
+            .metadata_mut()
+            .insert(COLLECTION_SEQUENCE_HEADER, sequence.into());
+    }
+    response
+}
+
+pub(crate) fn chunk_from_proto(chunk: ExportChunk) -> Chunk {
+    Chunk {
+        sequence: chunk.sequence,
+        compression: compression_from_proto(chunk.compression()),
+        document_count: chunk.document_count,
+        checksum: chunk.checksum,
+        payload: chunk.payload,
+    }
+}
+
+pub(crate) fn manifest_from_proto(manifest: &ExportManifest) -> Manifest {
+    Manifest {
+        chunk_count: manifest.chunk_count,
+        document_count: manifest.document_count,
+        total_bytes: manifest.total_bytes,
+        checksum: manifest.checksum,
+    }
+}
+
+/// Verify an imported chunk and split it into documents to write.
+fn chunk_documents(chunk: ExportChunk) -> Result<Vec<ImportDocument>, Status> {
+    let documents = chunk_from_proto(chunk)
+        .documents()
+        .map_err(|e| invalid_field("chunk", &e.to_string()))?;
+    import_documents(documents)
+}
+
+/// Name the encoded documents of a verified chunk by where they are
+/// imported.
+fn import_documents(documents: Vec<Vec<u8>>) -> Result<Vec<ImportDocument>, Status> {
+    documents
+        .into_iter()
+        .map(|data| {
+            let doc = Document::decode(data.as_slice())
+                .map_err(|e| invalid_field("chunk", &format!("invalid document: {e}")))?;
+            let (collection_id, doc_id) = parse_name("chunk", &doc.name)?;
+            Ok(ImportDocument {
+                collection_id,
+                doc_id,
+                data,
+            })
+        })
+        .collect()
+}
+
+/// A write of a BulkWrite, validated.
+enum BulkOp {
+    Create(Document),
+    Set(Document),
+    Update(Document, Option<FieldMask>),
+    Delete,
+}
+
+/// Collection as stored, document ID and operation of a write of a
+/// BulkWrite.
+fn bulk_op(write: BulkWriteRequest) -> Result<(String, String, BulkOp), Status> {
+    let (name, op) = match write.operation {
+        Some(BulkOperation::Create(doc)) => (doc.name.clone(), BulkOp::Create(doc)),
+        Some(BulkOperation::Set(doc)) => (doc.name.clone(), BulkOp::Set(doc)),
+        Some(BulkOperation::Update(doc)) => {
+            let mask = match &write.update_mask {
+                Some(mask) => Some(
+                    FieldMask::new(&mask.field_paths)
+                        .map_err(|e| invalid_field("update_mask", &e.to_string()))?,
+                ),
+                None => None,
+            };
+            (doc.name.clone(), BulkOp::Update(doc, mask))
+        }
+        Some(BulkOperation::Delete(name)) => (name, BulkOp::Delete),
+        None => return Err(invalid_field("operation", "operation is required")),
+    };
+    if write.update_mask.is_some() && !matches!(op, BulkOp::Update(..)) {
+        return Err(invalid_field(
+            "update_mask",
+            "update_mask is only for updates",
+        ));
+    }
+    let (collection, doc_id) = parse_name("name", &name)?;
+    Ok((collection, doc_id, op))
+}
+
+/// What a write of a BulkWrite does to the document named `name`, whose
+/// current payload is `current`, `None` if it does not exist.
+fn bulk_rewrite(
+    op: &BulkOp,
+    name: String,
+    current: Option<&[u8]>,
+    max_document_size: usize,
+) -> Result<Rewrite, Status> {
+    let current = match current {
+        Some(data) => Some(
+            Document::decode(data)
+                .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?,
+        ),
+        None => None,
+    };
+    let now: Timestamp = now_millis().into();
+    let mut doc = match (op, current) {
+        (BulkOp::Create(_), Some(_)) => {
+            return Err(engine_err_to_status(EngineError::AlreadyExists));
+        }
+        (BulkOp::Update(..) | BulkOp::Delete, None) => {
+            return Err(engine_err_to_status(EngineError::NotFound));
+        }
+        (BulkOp::Delete, Some(_)) => return Ok(Rewrite::Delete),
+        (BulkOp::Create(doc) | BulkOp::Set(doc), None) => Document {
+            create_time: Some(now.clone()),
+            ..doc.clone()
+        },
+        (BulkOp::Set(doc), Some(current)) => Document {
+            create_time: current.create_time,
+            ..doc.clone()
+        },
+        (BulkOp::Update(update, mask), Some(mut doc)) => {
+            match mask {
+                Some(mask) => mask.apply(&mut doc, update),
+                None => {
+                    doc.fields = update.fields.clone();
+                    doc.expire_time = update.expire_time.clone();
+                }
+            }
+            doc
+        }
+    };
+    doc.name = name;
+    doc.update_time = Some(now);
+    let data = doc.encode_to_vec();
+    if data.len() > max_document_size {
+        return Err(engine_err_to_status(EngineError::DocumentTooLarge {
+            size: data.len(),
+            max: max_document_size,
+        }));
+    }
+    Ok(Rewrite::Replace(data))
+}
+
+/// Send `message` on a response stream, waiting at most `stall_timeout` for
+/// the client to make room for it. Returns false if the client went away or
+/// stalled.
+async fn send_response<T>(
+    tx: &mpsc::Sender<Result<T, Status>>,
+    message: Result<T, Status>,
+    stall_timeout: Duration,
+    request_id: &RequestId,
+) -> bool {
+    match tx.send_timeout(message, stall_timeout).await {
+        Ok(()) => true,
+        Err(SendTimeoutError::Timeout(_)) => {
+            tracing::warn!(
+                %request_id,
+                ?stall_timeout,
+                "client stopped reading, dropping it"
+            );
+            false
+        }
+        Err(SendTimeoutError::Closed(_)) => false,
+    }
+}
+
+/// Result of the write at position `index` of a BulkWrite.
+fn bulk_write_result(index: u64, outcome: Result<Option<u64>, Status>) -> BulkWriteResult {
+    match outcome {
+        Ok(sequence) => BulkWriteResult {
+            index,
+            collection_sequence: sequence.unwrap_or_default(),
+            ..Default::default()
+        },
+        Err(status) => BulkWriteResult {
+            index,
+            code: status.code() as i32,
+            message: status.message().to_string(),
+            ..Default::default()
+        },
+    }
+}
+
+fn audit_entry_to_proto(entry: audit::AuditEntry) -> Result<AuditEntry, Status> {
+    let action = match entry.action {
+        audit::Action::Create => AuditAction::Create,
+        audit::Action::Replace => AuditAction::Replace,
+        audit::Action::Delete => AuditAction::Delete,
+    };
+    let document = match entry.action {
+        audit::Action::Delete => None,
+        _ => Some(
+            Document::decode(entry.data.as_slice())
+                .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?,
+        ),
+    };
+    Ok(AuditEntry {
+        sequence: entry.sequence,
+        index: entry.index,
+        time: Some(entry.time.into()),
+        actor: entry.actor,
+        action: action as i32,
+        name: name::format(&entry.collection_id, &entry.doc_id),
+        document,
+        collection_sequence: entry.collection_sequence,
+    })
+}
+
+pub(crate) fn manifest_item(manifest: Manifest, snapshot_time: SystemTime) -> ExportItem {
+    ExportItem::Manifest(ExportManifest {
+        chunk_count: manifest.chunk_count,
+        document_count: manifest.document_count,
+        total_bytes: manifest.total_bytes,
+        checksum: manifest.checksum,
+        snapshot_time: Some(snapshot_time.into()),
+    })
+}
+
+impl ZerotableService {
+    async fn handle_get_document(
+        &self,
+        request: Request<GetDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection_id, doc_id) = parse_name("name", &req.name)?;
+        let read_time = read_time(req.read_time)?;
+
+        let stored = self
+            .run(call, move |engine, _| match read_time {
+                Some(time) => engine.get_document_as_of(&collection_id, &doc_id, time),
+                None => engine.get_document(&collection_id, &doc_id),
+            })
+            .await?;
+
+        let doc = decode_stored(&stored)
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        Ok(Response::new(doc))
+    }
+
+    async fn handle_create_document(
+        &self,
+        request: Request<CreateDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let idempotency_key = match request.metadata().get(IDEMPOTENCY_KEY_HEADER) {
+            Some(value) => Some(value.to_str().map(str::to_string).map_err(|_| {
+                invalid_field(
+                    IDEMPOTENCY_KEY_HEADER,
+                    "idempotency key must be printable ASCII",
+                )
+            })?),
+            None => None,
+        };
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+
+        let mut doc = req
+            .document
+            .ok_or_else(|| invalid_field("document", "document is required"))?;
+
+        let doc_id = stamp_new_document(&mut doc, &collection_id, req.document_id);
+
+        let data = doc.encode_to_vec();
+        self.check_document_size("document", data.len())?;
+        let doc_id_clone = doc_id.clone();
+
+        let Some(idempotency_key) = idempotency_key else {
+            let sequence = self
+                .run(call, move |engine, _| {
+                    engine.create_document(&collection_id, &doc_id_clone, &data)
+                })
+                .await?;
+            return Ok(with_collection_sequence(doc, Some(sequence)));
+        };
+
+        // a retry gets the document created by the first attempt
+        let expires_at = SystemTime::now() + self.idempotency_ttl;
+        let (created, sequence) = self
+            .run(call, move |engine, _| {
+                engine.create_document_once(
+                    &collection_id,
+                    &doc_id_clone,
+                    &data,
+                    &idempotency_key,
+                    expires_at,
+                )
+            })
+            .await?;
+        let doc = Document::decode(created.as_slice())
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        // a retry made no write, so there is no number to report
+        Ok(with_collection_sequence(doc, sequence))
+    }
+
+    async fn handle_create_document_tree(
+        &self,
+        request: Request<CreateDocumentTreeRequest>,
+    ) -> Result<Response<CreateDocumentTreeResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        let parent = req
+            .parent
+            .ok_or_else(|| invalid_field("parent", "parent is required"))?;
+        if req.children.len() >= MAX_TREE_DOCUMENTS {
+            return Err(invalid_field(
+                "children",
+                &format!("at most {} children are allowed", MAX_TREE_DOCUMENTS - 1),
+            ));
+        }
+        let children = req
+            .children
+            .into_iter()
+            .map(|child| (child.collection_id, child.document_id, child.document));
+        let tree = std::iter::once((parent.collection_id, parent.document_id, parent.document))
+            .chain(children);
+
+        let mut docs = Vec::with_capacity(MAX_TREE_DOCUMENTS);
+        let mut writes = Vec::with_capacity(MAX_TREE_DOCUMENTS);
+        for (i, (collection_id, document_id, doc)) in tree.enumerate() {
+            let field = match i {
+                0 => "parent".to_string(),
+                i => format!("children[{}]", i - 1),
+            };
+            if collection_id.is_empty() {
+                return Err(invalid_field(
+                    &format!("{field}.collection_id"),
+                    "collection_id is required",
+                ));
+            }
+            // without subcollections children name a top-level collection of
+            // the parent's database, not one under the parent's path
+            let collection_id = qualify(&parent.database_id, &collection_id)?;
+            let mut doc = doc.ok_or_else(|| {
+                invalid_field(&format!("{field}.document"), "document is required")
+            })?;
+
+            let doc_id = stamp_new_document(&mut doc, &collection_id, document_id);
+            let data = doc.encode_to_vec();
+            self.check_document_size(&format!("{field}.document"), data.len())?;
+            writes.push((collection_id, doc_id, data));
+            docs.push(doc);
+        }
+
+        let sequences = self
+            .run(call, move |engine, _| {
+                let documents: Vec<_> = writes
+                    .iter()
+                    .map(|(collection_id, doc_id, data)| {
+                        (collection_id.as_str(), doc_id.as_str(), data.as_slice())
+                    })
+                    .collect();
+                engine.create_documents(&documents)
+            })
+            .await?;
+
+        let mut docs = docs.into_iter();
+        let response = CreateDocumentTreeResponse {
+            parent: docs.next(),
+            children: docs.collect(),
+        };
+        Ok(with_collection_sequence(
+            response,
+            sequences.first().copied(),
+        ))
+    }
+
+    async fn handle_update_document(
+        &self,
+        request: Request<UpdateDocumentRequest>,
+    ) -> Result<Response<UpdateDocumentResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let update = req
+            .document
+            .ok_or_else(|| invalid_field("document", "document is required"))?;
+        let (collection, doc_id) = parse_name("document.name", &update.name)?;
+        let mask = match &req.update_mask {
+            Some(mask) => Some(
+                FieldMask::new(&mask.field_paths)
+                    .map_err(|e| invalid_field("update_mask", &e.to_string()))?,
+            ),
+            None => None,
+        };
+        let merge = req.merge_disjoint_fields;
+        if merge && mask.is_none() {
+            return Err(invalid_field(
+                "merge_disjoint_fields",
+                "merge_disjoint_fields needs an update_mask",
+            ));
+        }
+        let attempts = if merge { MERGE_ATTEMPTS } else { 1 };
+        let version = match req.expected_version {
+            0 => None,
+            version => Some(Version::Sequence(version)),
+        };
+        let max_document_size = self.max_document_size;
+        // the document as first read, later attempts check that concurrent
+        // writes left the masked fields alone
+        let first_read = Mutex::new(None);
+
+        let return_previous = req.return_previous;
+
+        let (updated, sequence, previous) = self
+            .run_retrying(call, attempts, move |engine, _| {
+                let mut conflict = None;
+                let mut too_large = None;
+                let mut previous = None;
+                let written = engine.update_document_if(&collection, &doc_id, version, |data| {
+                    let mut doc = Document::decode(data).ok()?;
+                    if return_previous {
+                        previous = Some(doc.clone());
+                    }
+                    if let Some(mask) = mask.as_ref().filter(|_| merge) {
+                        let mut first_read = first_read.lock().expect("first read lock poisoned");
+                        let first_read = first_read.get_or_insert_with(|| doc.clone());
+                        if let Some(field) = mask.changed(first_read, &doc) {
+                            conflict = Some(field.to_string());
+                            return None;
+                        }
+                    }
+                    match &mask {
+                        Some(mask) => mask.apply(&mut doc, &update),
+                        None => {
+                            doc.fields = update.fields.clone();
+                            doc.expire_time = update.expire_time.clone();
+                        }
+                    }
+                    doc.update_time = Some(now_millis().into());
+                    let data = doc.encode_to_vec();
+                    if data.len() > max_document_size {
+                        too_large = Some(data.len());
+                        return None;
+                    }
+                    Some(data)
+                })?;
+                match (written, conflict, too_large) {
+                    (None, Some(field), _) => Ok(Err(field)),
+                    (None, _, Some(size)) => Err(EngineError::DocumentTooLarge {
+                        size,
+                        max: max_document_size,
+                    }),
+                    (written, _, _) => Ok(Ok(
+                        written.map(|(data, sequence)| (data, sequence, previous))
+                    )),
+                }
+            })
+            .await?
+            .map_err(|field| field_conflict(&field))?
+            .ok_or_else(|| Status::internal("failed to decode document"))?;
+        let doc = decode_stored(&updated)
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        let response = UpdateDocumentResponse {
+            document: Some(doc),
+            previous_document: previous,
+        };
+        Ok(with_collection_sequence(response, Some(sequence)))
+    }
+
+    async fn handle_delete_document(
+        &self,
+        request: Request<DeleteDocumentRequest>,
+    ) -> Result<Response<DeleteDocumentResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection, doc_id) = parse_name("name", &req.name)?;
+
+        let (previous, sequence) = self
+            .run(call, move |engine, _| {
+                engine.delete_document(&collection, &doc_id)
+            })
+            .await?;
+        let previous_document = if req.return_previous {
+            let doc = Document::decode(previous.as_slice())
+                .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+            Some(doc)
+        } else {
+            None
+        };
+
+        let response = DeleteDocumentResponse { previous_document };
+        Ok(with_collection_sequence(response, Some(sequence)))
+    }
+
+    async fn handle_transform_document(
+        &self,
+        request: Request<TransformDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection, doc_id) = parse_name("name", &req.name)?;
+        let transforms = Transforms::new(&req.transforms)
+            .map_err(|e| invalid_field("transforms", &e.to_string()))?;
+        let max_document_size = self.max_document_size;
+
+        let (transformed, sequence) = self
+            .run_retrying(call, TRANSFORM_ATTEMPTS, move |engine, _| {
+                let mut too_large = None;
+                let written = engine.update_document(&collection, &doc_id, |data| {
+                    let mut doc = Document::decode(data).ok()?;
+                    transforms.apply(&mut doc);
+                    doc.update_time = Some(now_millis().into());
+                    let data = doc.encode_to_vec();
+                    if data.len() > max_document_size {
+                        too_large = Some(data.len());
+                        return None;
+                    }
+                    Some(data)
+                })?;
+                match (written, too_large) {
+                    (None, Some(size)) => Err(EngineError::DocumentTooLarge {
+                        size,
+                        max: max_document_size,
+                    }),
+                    (written, _) => Ok(written),
+                }
+            })
+            .await?
+            .ok_or_else(|| Status::internal("failed to decode document"))?;
+        let doc = decode_stored(&transformed)
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        Ok(with_collection_sequence(doc, Some(sequence)))
+    }
+
+    async fn handle_update_where(
+        &self,
+        request: Request<UpdateWhereRequest>,
+    ) -> Result<Response<UpdateWhereResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection, filters) = self.plan_bulk(
+            &req.database_id,
+            &req.collection_id,
+            &req.filters,
+            &req.parameters,
+        )?;
+        let update = req
+            .update
+            .ok_or_else(|| invalid_field("update", "update is required"))?;
+        let mask = req
+            .update_mask
+            .ok_or_else(|| invalid_field("update_mask", "update_mask is required"))?;
+        let mask = FieldMask::new(&mask.field_paths)
+            .map_err(|e| invalid_field("update_mask", &e.to_string()))?;
+        let max_document_size = self.max_document_size;
+
+        let updated = self
+            .rewrite_collection(call, collection, move |_, data| {
+                let mut doc = Document::decode(data)
+                    .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+                if !matches(&doc, &filters) {
+                    return Ok(Rewrite::Keep);
+                }
+                mask.apply(&mut doc, &update);
+                doc.update_time = Some(now_millis().into());
+                let data = doc.encode_to_vec();
+                if data.len() > max_document_size {
+                    return Err(engine_err_to_status(EngineError::DocumentTooLarge {
+                        size: data.len(),
+                        max: max_document_size,
+                    }));
+                }
+                Ok(Rewrite::Replace(data))
+            })
+            .await?;
+
+        Ok(Response::new(UpdateWhereResponse { updated }))
+    }
+
+    async fn handle_delete_where(
+        &self,
+        request: Request<DeleteWhereRequest>,
+    ) -> Result<Response<DeleteWhereResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection, filters) = self.plan_bulk(
+            &req.database_id,
+            &req.collection_id,
+            &req.filters,
+            &req.parameters,
+        )?;
+        let config = self
+            .engine
+            .engine()
+            .collection_config(&collection)
+            .map_err(engine_err_to_status)?;
+        if config.delete_protection {
+            return Err(delete_protected(&collection));
+        }
+
+        let deleted = self
+            .rewrite_collection(call, collection, move |_, data| {
+                let doc = Document::decode(data)
+                    .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+                if matches(&doc, &filters) {
+                    Ok(Rewrite::Delete)
+                } else {
+                    Ok(Rewrite::Keep)
+                }
+            })
+            .await?;
+
+        Ok(Response::new(DeleteWhereResponse { deleted }))
+    }
+
+    async fn handle_get_server_info(
+        &self,
+        request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+
+        let mut features = Vec::new();
+        if self.engine.engine().audit_log_enabled() {
+            features.push("audit_log".to_string());
+        }
+        if self.engine.engine().is_read_only() {
+            features.push("read_only".to_string());
+        }
+        if self.engine.engine().archive_enabled() {
+            features.push("archive".to_string());
+        }
+        if self.export_dir.is_some() {
+            features.push("operations".to_string());
+        }
+        let rate = |operation| {
+            self.rate_limiter
+                .limit(operation)
+                .map_or(0, NonZeroU32::get)
+        };
+        let limits = ServerLimits {
+            max_document_size: self.max_document_size as u64,
+            default_list_page_size: DEFAULT_LIST_PAGE_SIZE,
+            max_list_page_size: MAX_LIST_PAGE_SIZE,
+            max_audit_page_size: MAX_AUDIT_PAGE_SIZE,
+            max_partition_count: MAX_PARTITIONS,
+            max_tree_documents: MAX_TREE_DOCUMENTS as u32,
+            max_aggregations: aggregate::MAX_AGGREGATIONS as u32,
+            max_query_parallelism: MAX_QUERY_PARALLELISM,
+            max_transforms: MAX_TRANSFORMS as u32,
+            max_update_mask_fields: MAX_FIELD_PATHS as u32,
+            max_order_by_fields: MAX_ORDER_FIELDS as u32,
+            bulk_batch_size: BULK_BATCH_SIZE as u32,
+            max_reads_per_second: rate(Operation::Read),
+            max_writes_per_second: rate(Operation::Write),
+            retry_budget_millis: self.retry_budget.as_millis() as u64,
+            stream_stall_timeout_millis: self.stream_stall_timeout.as_millis() as u64,
+        };
+
+        Ok(Response::new(ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features,
+            strict_databases: self.strict_databases.to_vec(),
+            limits: Some(limits),
+        }))
+    }
+
+    async fn handle_document_exists(
+        &self,
+        request: Request<DocumentExistsRequest>,
+    ) -> Result<Response<DocumentExistsResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection_id, doc_id) = parse_name("name", &req.name)?;
+
+        let header = self
+            .run(call, move |engine, _| {
+                engine.document_header(&collection_id, &doc_id)
+            })
+            .await?;
+
+        Ok(Response::new(DocumentExistsResponse {
+            exists: header.is_some(),
+            update_time: header.map(|header| header.write_time.into()),
+        }))
+    }
+
+    async fn handle_copy_document(
+        &self,
+        request: Request<CopyDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let from = parse_name("name", &req.name)?;
+        let to = parse_name("destination", &req.destination)?;
+        check_same_database("destination", &from.0, &to.0)?;
+        let destination = name::format(&to.0, &to.1);
+        let preserve_timestamps = req.preserve_timestamps;
+
+        let (copied, sequence) = self
+            .run(call, move |engine, _| {
+                engine.copy_document((&from.0, &from.1), (&to.0, &to.1), |data| {
+                    let now = (!preserve_timestamps).then(now_millis);
+                    renamed(data, &destination, now, now)
+                })
+            })
+            .await?
+            .ok_or_else(|| Status::internal("failed to decode document"))?;
+        let doc = Document::decode(copied.as_slice())
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        Ok(with_collection_sequence(doc, Some(sequence)))
+    }
+
+    async fn handle_move_document(
+        &self,
+        request: Request<MoveDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let from = parse_name("name", &req.name)?;
+        let to = parse_name("destination", &req.destination)?;
+        check_same_database("destination", &from.0, &to.0)?;
+        let destination = name::format(&to.0, &to.1);
+
+        let (moved, sequence) = self
+            .run(call, move |engine, _| {
+                engine.move_document((&from.0, &from.1), (&to.0, &to.1), |data| {
+                    renamed(data, &destination, None, Some(now_millis()))
+                })
+            })
+            .await?
+            .ok_or_else(|| Status::internal("failed to decode document"))?;
+        let doc = Document::decode(moved.as_slice())
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        Ok(with_collection_sequence(doc, Some(sequence)))
+    }
+
+    async fn handle_undelete_document(
+        &self,
+        request: Request<UndeleteDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection, doc_id) = parse_name("name", &req.name)?;
+
+        let (restored, sequence) = self
+            .run(call, move |engine, _| {
+                engine.undelete_document(&collection, &doc_id)
+            })
+            .await?;
+        let doc = decode_stored(&restored)
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        Ok(with_collection_sequence(doc, Some(sequence)))
+    }
+
+    async fn handle_purge_document(
+        &self,
+        request: Request<PurgeDocumentRequest>,
+    ) -> Result<Response<PurgeDocumentResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+        let (collection, doc_id) = parse_name("name", &req.name)?;
+
+        self.run(call, move |engine, _| {
+            engine.purge_document(&collection, &doc_id)
+        })
+        .await?;
+
+        Ok(Response::new(PurgeDocumentResponse {}))
+    }
+
+    async fn handle_increment_counter(
+        &self,
+        request: Request<IncrementCounterRequest>,
+    ) -> Result<Response<Counter>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        if req.name.is_empty() {
+            return Err(invalid_field("name", "name is required"));
+        }
+        let counter_id = qualify(&req.database_id, &req.name)?;
+        let delta = req.delta;
+
+        let value = self
+            .run(call, move |engine, deadline| {
+                engine.increment_counter(&counter_id, delta, deadline)
+            })
+            .await?;
+
+        Ok(Response::new(Counter {
+            name: req.name,
+            value,
+        }))
+    }
+
+    async fn handle_get_counter(
+        &self,
+        request: Request<GetCounterRequest>,
+    ) -> Result<Response<Counter>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        if req.name.is_empty() {
+            return Err(invalid_field("name", "name is required"));
+        }
+        let counter_id = qualify(&req.database_id, &req.name)?;
+
+        let value = self
+            .run(call, move |engine, _| engine.counter(&counter_id))
+            .await?;
+
+        Ok(Response::new(Counter {
+            name: req.name,
+            value,
+        }))
+    }
+
+    async fn handle_batch_get_documents(
+        &self,
+        request: Request<BatchGetDocumentsRequest>,
+    ) -> Result<Response<BatchGetDocumentsResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        let documents = req
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| parse_name(&format!("names[{i}]"), name))
+            .collect::<Result<Vec<_>, Status>>()?;
+        if let Some((first, _)) = documents.first() {
+            for (i, (collection, _)) in documents.iter().enumerate() {
+                check_same_database(&format!("names[{i}]"), first, collection)?;
+            }
+        }
+
+        let stored = self
+            .run(call, move |engine, _| {
+                let keys: Vec<(&str, &str)> = documents
+                    .iter()
+                    .map(|(c, d)| (c.as_str(), d.as_str()))
+                    .collect();
+                engine.get_many(&keys)
+            })
+            .await?;
+
+        let mut results = Vec::with_capacity(stored.len());
+        for (name, stored) in req.names.into_iter().zip(stored) {
+            let result = match stored {
+                Ok(stored) => BatchResult::Found(
+                    decode_stored(&stored)
+                        .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?,
+                ),
+                Err(EngineError::NotFound) => BatchResult::Missing(name),
+                Err(e) => return Err(engine_err_to_status(e)),
+            };
+            results.push(BatchGetResult {
+                result: Some(result),
+            });
+        }
+
+        Ok(Response::new(BatchGetDocumentsResponse { results }))
+    }
+
+    async fn handle_list_documents(
+        &self,
+        request: Request<ListDocumentsRequest>,
+    ) -> Result<Response<ListDocumentsResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+        let page_size = match req.page_size {
+            0 => DEFAULT_LIST_PAGE_SIZE,
+            1..=MAX_LIST_PAGE_SIZE => req.page_size,
+            _ => {
+                return Err(invalid_field(
+                    "page_size",
+                    &format!("page_size must be between 1 and {MAX_LIST_PAGE_SIZE}"),
+                ));
+            }
+        } as usize;
+        let read_time = read_time(req.read_time)?;
+        let order_by = match req.order_by.as_str() {
+            "" => None,
+            order_by => Some(
+                OrderBy::parse(order_by).map_err(|e| invalid_field("order_by", &e.to_string()))?,
+            ),
+        };
+        // ordered by document ID alone, the listing pages by document ID
+        let descending = order_by.as_ref().and_then(OrderBy::descending_id);
+        let order_by = order_by.filter(|_| descending.is_none());
+        let descending = descending.unwrap_or(false);
+        let start = match req.page_token.as_str() {
+            "" => None,
+            token => Some(
+                PageToken::decode(token)
+                    .map_err(|e| invalid_field("page_token", &e.to_string()))?,
+            ),
+        };
+        let (after, after_key) = match (start, &order_by) {
+            (None, _) => (None, None),
+            (Some(PageToken::After(doc_id)), None) if !descending => (Some(doc_id), None),
+            (Some(PageToken::Before(doc_id)), None) if descending => (Some(doc_id), None),
+            (
+                Some(PageToken::Keyset {
+                    order_by,
+                    key,
+                    doc_id,
+                }),
+                Some(ordered),
+            ) if order_by == ordered.to_string() && key.len() == ordered.fields().count() => {
+                (Some(doc_id), Some(key))
+            }
+            _ => {
+                return Err(invalid_field(
+                    "page_token",
+                    "the page token is of a listing with another order_by",
+                ));
+            }
+        };
+        if let Some(order_by) = &order_by
+            && self.strict_queries(&collection_id)?
+        {
+            let fields: Vec<_> = order_by.fields().collect();
+            let index = format!("{} ({})", req.collection_id, fields.join(", "));
+            return Err(missing_index(&index));
+        }
+        // show_missing lists nothing more, without subcollections no
+        // document is missing
+
+        let (documents, next) = self
+            .run(call, move |engine, deadline| match &order_by {
+                None => list_by_id(
+                    engine,
+                    &collection_id,
+                    after.as_deref(),
+                    descending,
+                    page_size,
+                    read_time,
+                    deadline,
+                ),
+                Some(order_by) => list_ordered(
+                    engine,
+                    &collection_id,
+                    order_by,
+                    after_key.as_deref().zip(after.as_deref()),
+                    page_size,
+                    read_time,
+                    deadline,
+                ),
+            })
+            .await?
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?;
+
+        Ok(Response::new(ListDocumentsResponse {
+            documents,
+            next_page_token: next.map(|token| token.encode()).unwrap_or_default(),
+        }))
+    }
+
+    async fn handle_run_aggregation_query(
+        &self,
+        request: Request<RunAggregationQueryRequest>,
+    ) -> Result<Response<RunAggregationQueryResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let request_id = call.request_id.clone();
+        let req = request.into_inner();
+        let (plan, aggregator, filters) = plan_aggregation(&req)?;
+        let plan = self.estimate_cost(plan)?;
+        if let Some(index) = plan.missing_index()
+            && self.strict_queries(&plan.collection)?
+        {
+            return Err(missing_index(&index));
+        }
+
+        let started = Instant::now();
+        let scanned = self.aggregate(call, &plan, aggregator, filters).await;
+        let elapsed = started.elapsed();
+        if self
+            .slow_query_threshold
+            .is_some_and(|threshold| elapsed >= threshold)
+        {
+            tracing::warn!(
+                %request_id,
+                elapsed_ms = elapsed.as_millis() as u64,
+                plan = %plan.to_json(),
+                "slow query"
+            );
+        }
+
+        let result = scanned?
+            .map_err(|e| Status::internal(format!("failed to decode document: {e}")))?
+            .finish()
+            .map_err(|e| Status::out_of_range(e.to_string()))?;
+
+        Ok(Response::new(RunAggregationQueryResponse { result }))
+    }
+
+    async fn handle_explain_query(
+        &self,
+        request: Request<ExplainQueryRequest>,
+    ) -> Result<Response<ExplainQueryResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let query = request
+            .into_inner()
+            .query
+            .ok_or_else(|| invalid_field("query", "query is required"))?;
+        let (plan, _, _) = plan_aggregation(&query)?;
+        let plan = self.estimate_cost(plan)?;
+
+        Ok(Response::new(ExplainQueryResponse {
+            plan_json: plan.to_json().to_string(),
+        }))
+    }
+
+    async fn handle_export_documents(
+        &self,
+        request: Request<ExportDocumentsRequest>,
+    ) -> Result<Response<ReceiverStream<Result<ExportDocumentsResponse, Status>>>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let Call {
+            request_id,
+            deadline,
+            ..
+        } = Call::of(&request);
+        let req = request.into_inner();
+
+        let database = database(&req.database_id)?.to_string();
+        let collections = export_collections(&database, &req.collection_ids)?;
+        let compression = compression_from_proto(req.compression());
+        let permit = self.long_running.clone().try_acquire_owned().map_err(|_| {
+            Status::resource_exhausted("too many exports and operations running, retry later")
+        })?;
+
+        // the snapshot is read on the blocking pool while the chunks stream out
+        let (tx, rx) = mpsc::channel(EXPORT_BUFFERED_CHUNKS);
+        let mut sender = ExportSender {
+            tx,
+            runtime: tokio::runtime::Handle::current(),
+            request_id: request_id.clone(),
+            stall_timeout: self.stream_stall_timeout,
+            last_sent: Instant::now(),
+            documents_scanned: 0,
+        };
+        let engine = self.engine.engine().clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let result = panic::catch(|| {
+                export_snapshot(
+                    &engine,
+                    &database,
+                    &collections,
+                    compression,
+                    &deadline,
+                    &mut sender,
+                )
+            })
+            .unwrap_or_else(|panic| Err(panic_to_status(&request_id, panic)));
+            if let Err(status) = result {
+                tracing::warn!(%request_id, message = status.message(), "export failed");
+                sender.send(Err(status));
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn handle_import_documents(
+        &self,
+        request: Request<Streaming<ImportDocumentsRequest>>,
+    ) -> Result<Response<ImportDocumentsResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let mut stream = request.into_inner();
+
+        let mut job: Option<(String, ImportMode)> = None;
+        let mut results = Vec::new();
+        let mut next_sequence = 0;
+        while let Some(req) = stream.message().await? {
+            if req.job_id.is_empty() {
+                return Err(invalid_field("job_id", "job_id is required"));
+            }
+            let mode = req.mode();
+            let (job_id, job_mode) = job.get_or_insert_with(|| (req.job_id.clone(), mode));
+            if req.job_id != *job_id || mode != *job_mode {
+                return Err(invalid_field(
+                    "job_id",
+                    "job_id and mode must not change within a stream",
+                ));
+            }
+            let policy = conflict_policy(mode);
+            let chunk = req
+                .chunk
+                .ok_or_else(|| invalid_field("chunk", "chunk is required"))?;
+            let sequence = chunk.sequence;
+            let documents = chunk_documents(chunk)?;
+            for doc in &documents {
+                let what = format!("document {}", name::format(&doc.collection_id, &doc.doc_id));
+                self.check_document_size(&what, doc.data.len())?;
+            }
+
+            let job_id = job_id.clone();
+            let progress = self
+                .run(call.clone(), move |engine, deadline| {
+                    engine.apply_import_chunk(&job_id, sequence, &documents, policy, deadline)
+                })
+                .await?;
+            next_sequence = progress.next_sequence;
+            results.push(ImportChunkResult {
+                sequence,
+                written: progress.written,
+                skipped: progress.skipped,
+            });
+        }
+
+        Ok(Response::new(ImportDocumentsResponse {
+            results,
+            next_sequence,
+        }))
+    }
+
+    async fn handle_bulk_write(
+        &self,
+        request: Request<Streaming<BulkWriteRequest>>,
+    ) -> Result<Response<ReceiverStream<Result<BulkWriteResponse, Status>>>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let mut stream = request.into_inner();
+
+        // writes are read on while a batch commits, up to a batch ahead
+        let (writes_tx, writes_rx) = mpsc::channel(BULK_BATCH_SIZE);
+        tokio::spawn(async move {
+            while let Some(message) = stream.message().await.transpose() {
+                let failed = message.is_err();
+                if writes_tx.send(message).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        let (tx, rx) = mpsc::channel(BULK_WRITE_BUFFERED_RESPONSES);
+        tokio::spawn(self.clone().commit_bulk_writes(call, writes_rx, tx));
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn handle_start_export(
+        &self,
+        request: Request<StartExportRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let request_id = RequestId::of(&request);
+        let req = request.into_inner();
+
+        let path = self.export_file(&req.file)?;
+        if path.exists() {
+            return Err(Status::already_exists(format!(
+                "export file '{}' exists",
+                req.file
+            )));
+        }
+        let database = database(&req.database_id)?.to_string();
+        let collections = export_collections(&database, &req.collection_ids)?;
+        let compression = compression_from_proto(req.compression());
+
+        let (info, operation) = self.operations.start("EXPORT", &req.file);
+        let engine = self.engine.engine().clone();
+        self.spawn_operation(request_id, operation, move |operation| {
+            export_to_file(
+                &engine,
+                &path,
+                &database,
+                &collections,
+                compression,
+                operation,
+            )
+        });
+        Ok(Response::new(operation_to_proto(info)))
+    }
+
+    async fn handle_start_import(
+        &self,
+        request: Request<StartImportRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let Call {
+            request_id, actor, ..
+        } = Call::of(&request);
+        let req = request.into_inner();
+
+        let path = self.export_file(&req.file)?;
+        if !path.is_file() {
+            return Err(Status::not_found(format!(
+                "export file '{}' not found",
+                req.file
+            )));
+        }
+        let policy = conflict_policy(req.mode());
+
+        let (info, operation) = self.operations.start("IMPORT", &req.file);
+        let service = self.clone();
+        let engine = self.engine.engine().acting_as(&actor);
+        self.spawn_operation(request_id, operation, move |operation| {
+            service.import_file(&engine, &path, policy, operation)
+        });
+        Ok(Response::new(operation_to_proto(info)))
+    }
+
+    async fn handle_start_drop_collection(
+        &self,
+        request: Request<StartDropCollectionRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let Call {
+            request_id, actor, ..
+        } = Call::of(&request);
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+        let collection = qualify(&req.database_id, &req.collection_id)?;
+        let config = self
+            .engine
+            .engine()
+            .collection_config(&collection)
+            .map_err(engine_err_to_status)?;
+        if config.delete_protection {
+            return Err(delete_protected(&collection));
+        }
+
+        let target = name::format_collection(&collection);
+        let (info, operation) = self.operations.start("DROP_COLLECTION", &target);
+        let engine = self.engine.engine().acting_as(&actor);
+        self.spawn_operation(request_id, operation, move |operation| {
+            engine
+                .drop_collection(&collection, operation.deadline(), |deleted| {
+                    operation.add_documents(deleted)
+                })
+                .map_err(engine_err_to_status)?;
+            Ok(())
+        });
+        Ok(Response::new(operation_to_proto(info)))
+    }
+
+    async fn handle_get_operation(
+        &self,
+        request: Request<GetOperationRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let req = request.into_inner();
+
+        match self.operations.get(&req.id) {
+            Some(info) => Ok(Response::new(operation_to_proto(info))),
+            None => Err(Status::not_found(format!(
+                "operation '{}' not found",
+                req.id
+            ))),
+        }
+    }
+
+    async fn handle_list_operations(
+        &self,
+        request: Request<ListOperationsRequest>,
+    ) -> Result<Response<ListOperationsResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+
+        let operations = self
+            .operations
+            .list()
+            .into_iter()
+            .map(operation_to_proto)
+            .collect();
+        Ok(Response::new(ListOperationsResponse { operations }))
+    }
+
+    async fn handle_cancel_operation(
+        &self,
+        request: Request<CancelOperationRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let req = request.into_inner();
+
+        match self.operations.cancel(&req.id) {
+            Some(info) => Ok(Response::new(operation_to_proto(info))),
+            None => Err(Status::not_found(format!(
+                "operation '{}' not found",
+                req.id
+            ))),
+        }
+    }
+
+    async fn handle_partition_query(
+        &self,
+        request: Request<PartitionQueryRequest>,
+    ) -> Result<Response<PartitionQueryResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+        if !(1..=MAX_PARTITIONS).contains(&req.partition_count) {
+            return Err(invalid_field(
+                "partition_count",
+                &format!("partition_count must be between 1 and {MAX_PARTITIONS}"),
+            ));
+        }
+
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+        let partition_count = req.partition_count as usize;
+        let starts = self
+            .run(call, move |engine, deadline| {
+                engine.partition_collection(&collection_id, partition_count, deadline)
+            })
+            .await?;
+
+        let mut partitions = Vec::with_capacity(starts.len() + 1);
+        let mut start = String::new();
+        for end in starts {
+            partitions.push(Partition {
+                start_doc_id: start,
+                end_doc_id: end.clone(),
+            });
+            start = end;
+        }
+        partitions.push(Partition {
+            start_doc_id: start,
+            end_doc_id: String::new(),
+        });
+
+        Ok(Response::new(PartitionQueryResponse { partitions }))
+    }
+
+    async fn handle_get_collection_stats(
+        &self,
+        request: Request<GetCollectionStatsRequest>,
+    ) -> Result<Response<CollectionStats>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+        let stats = self
+            .engine
+            .engine()
+            .collection_stats(&collection_id)
+            .map_err(engine_err_to_status)?;
+        let largest_documents = self
+            .engine
+            .engine()
+            .largest_documents(&collection_id)
+            .map_err(engine_err_to_status)?
+            .into_iter()
+            .map(|doc| DocumentSize {
+                document_id: doc.doc_id,
+                size_bytes: doc.size_bytes as i64,
+            })
+            .collect();
+
+        Ok(Response::new(CollectionStats {
+            document_count: stats.document_count as i64,
+            size_bytes: stats.size_bytes as i64,
+            exact: stats.exact,
+            largest_documents,
+            disk_bytes: stats.disk_bytes as i64,
+        }))
+    }
+
+    async fn handle_get_collection(
+        &self,
+        request: Request<GetCollectionRequest>,
+    ) -> Result<Response<Collection>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+        let metadata = self
+            .engine
+            .engine()
+            .collection_metadata(&collection_id)
+            .map_err(engine_err_to_status)?;
+
+        Ok(Response::new(Collection {
+            name: name::format_collection(&collection_id),
+            create_time: Some(metadata.created_at.into()),
+            document_count: metadata.document_count as i64,
+            size_bytes: metadata.size_bytes as i64,
+        }))
+    }
+
+    async fn handle_get_collection_config(
+        &self,
+        request: Request<GetCollectionConfigRequest>,
+    ) -> Result<Response<CollectionConfig>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+        let config = self
+            .engine
+            .engine()
+            .collection_config(&collection_id)
+            .map_err(engine_err_to_status)?;
+
+        Ok(Response::new(CollectionConfig {
+            delete_protection: config.delete_protection,
+            write_lock: config.write_lock,
+            strict_queries: config.strict_queries,
+            soft_delete: config.soft_delete,
+        }))
+    }
+
+    async fn handle_update_collection_config(
+        &self,
+        request: Request<UpdateCollectionConfigRequest>,
+    ) -> Result<Response<CollectionConfig>, Status> {
+        self.check_rate_limit(&request, Operation::Write)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        if req.collection_id.is_empty() {
+            return Err(invalid_field("collection_id", "collection_id is required"));
+        }
+        let config = req
+            .config
+            .ok_or_else(|| invalid_field("config", "config is required"))?;
+
+        let collection_id = qualify(&req.database_id, &req.collection_id)?;
+        let engine_config = crate::CollectionConfig {
+            delete_protection: config.delete_protection,
+            write_lock: config.write_lock,
+            strict_queries: config.strict_queries,
+            soft_delete: config.soft_delete,
+        };
+        self.run(call, move |engine, _| {
+            engine.set_collection_config(&collection_id, engine_config)
+        })
+        .await?;
+
+        Ok(Response::new(config))
+    }
+
+    async fn handle_get_database_stats(
+        &self,
+        request: Request<GetDatabaseStatsRequest>,
+    ) -> Result<Response<DatabaseStats>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let req = request.into_inner();
+        let database = database(&req.database_id)?;
+
+        let now = now_millis();
+        let collections: serde_json::Map<_, _> = self
+            .engine
+            .engine()
+            .all_collection_stats()
+            .into_iter()
+            .filter(|(collection, _)| keys::unqualify(collection).0 == database)
+            .map(|(collection, stats)| {
+                let largest: Vec<_> = self
+                    .engine
+                    .engine()
+                    .largest_documents(&collection)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|doc| json!({ "doc_id": doc.doc_id, "size_bytes": doc.size_bytes }))
+                    .collect();
+                let stats = json!({
+                    "document_count": stats.document_count,
+                    "size_bytes": stats.size_bytes,
+                    "exact": stats.exact,
+                    "disk_bytes": stats.disk_bytes,
+                    "largest_documents": largest,
+                });
+                (keys::unqualify(&collection).1.to_string(), stats)
+            })
+            .collect();
+        let snapshot = json!({
+            "snapshot_time_millis": now
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            "version": env!("CARGO_PKG_VERSION"),
+            "database": database,
+            "collections": collections,
+            "transactions": self.contention.to_json(),
+            "payloads": self.payloads.to_json(),
+            "mirror": self.mirror.as_ref().map(|mirror| mirror.metrics().to_json()),
+            "clock": { "regressions": clock::global().regressions() },
+            "panics": panic::count(),
+        });
+
+        Ok(Response::new(DatabaseStats {
+            snapshot_time: Some(now.into()),
+            json: snapshot.to_string(),
+        }))
+    }
+
+    async fn handle_list_audit_entries(
+        &self,
+        request: Request<ListAuditEntriesRequest>,
+    ) -> Result<Response<ListAuditEntriesResponse>, Status> {
+        self.check_rate_limit(&request, Operation::Read)?;
+        let call = Call::of(&request);
+        let req = request.into_inner();
+
+        let page_size = match req.page_size {
+            0 => DEFAULT_AUDIT_PAGE_SIZE,
+            1..=MAX_AUDIT_PAGE_SIZE => req.page_size,
+            _ => {
+                return Err(invalid_field(
+                    "page_size",
+                    &format!("page_size must be between 1 and {MAX_AUDIT_PAGE_SIZE}"),
+                ));
+            }
+        } as usize;
+        let database = database(&req.database_id)?.to_string();
+        let collection_id = if req.collection_id.is_empty() {
+            None
+        } else {
+            let collection_id = qualify(&database, &req.collection_id)?;
+            keys::collection_prefix(&collection_id)
+                .map_err(|e| invalid_field("collection_id", &e.to_string()))?;
+            Some(collection_id)
+        };
+
+        let after = (req.after_sequence, req.after_index);
+        let entries = self
+            .run(call, move |engine, deadline| {
+                let include = |collection: &str| match &collection_id {
+                    Some(collection_id) => collection == collection_id.as_str(),
+                    None => keys::unqualify(collection).0 == database,
+                };
+                engine.audit_entries(Some(after), include, page_size, deadline)
+            })
+            .await?;
+
+        let entries = entries
+            .into_iter()
+            .map(audit_entry_to_proto)
+            .collect::<Result<_, _>>()?;
+        Ok(Response::new(ListAuditEntriesResponse { entries }))
+    }
+}
+
+#[tonic::async_trait]
+impl Zerotable for ZerotableService {
+    async fn get_document(
+        &self,
+        request: Request<GetDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.unary("GetDocument", request, |request| {
+            self.handle_get_document(request)
+        })
+        .await
+    }
+
+    async fn create_document(
+        &self,
+        request: Request<CreateDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.unary("CreateDocument", request, |request| {
+            self.handle_create_document(request)
+        })
+        .await
+    }
+
+    async fn create_document_tree(
+        &self,
+        request: Request<CreateDocumentTreeRequest>,
+    ) -> Result<Response<CreateDocumentTreeResponse>, Status> {
+        self.unary("CreateDocumentTree", request, |request| {
+            self.handle_create_document_tree(request)
+        })
+        .await
+    }
+
+    async fn update_document(
+        &self,
+        request: Request<UpdateDocumentRequest>,
+    ) -> Result<Response<UpdateDocumentResponse>, Status> {
+        self.unary("UpdateDocument", request, |request| {
+            self.handle_update_document(request)
+        })
+        .await
+    }
+
+    async fn delete_document(
+        &self,
+        request: Request<DeleteDocumentRequest>,
+    ) -> Result<Response<DeleteDocumentResponse>, Status> {
+        self.unary("DeleteDocument", request, |request| {
+            self.handle_delete_document(request)
+        })
+        .await
+    }
+
+    async fn transform_document(
+        &self,
+        request: Request<TransformDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.unary("TransformDocument", request, |request| {
+            self.handle_transform_document(request)
+        })
+        .await
+    }
+
+    async fn update_where(
+        &self,
+        request: Request<UpdateWhereRequest>,
+    ) -> Result<Response<UpdateWhereResponse>, Status> {
+        self.unary("UpdateWhere", request, |request| {
+            self.handle_update_where(request)
+        })
+        .await
+    }
+
+    async fn delete_where(
+        &self,
+        request: Request<DeleteWhereRequest>,
+    ) -> Result<Response<DeleteWhereResponse>, Status> {
+        self.unary("DeleteWhere", request, |request| {
+            self.handle_delete_where(request)
+        })
+        .await
+    }
+
+    async fn get_server_info(
+        &self,
+        request: Request<GetServerInfoRequest>,
+    ) -> Result<Response<ServerInfo>, Status> {
+        self.unary("GetServerInfo", request, |request| {
+            self.handle_get_server_info(request)
+        })
+        .await
+    }
+
+    async fn document_exists(
+        &self,
+        request: Request<DocumentExistsRequest>,
+    ) -> Result<Response<DocumentExistsResponse>, Status> {
+        self.unary("DocumentExists", request, |request| {
+            self.handle_document_exists(request)
+        })
+        .await
+    }
+
+    async fn copy_document(
+        &self,
+        request: Request<CopyDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.unary("CopyDocument", request, |request| {
+            self.handle_copy_document(request)
+        })
+        .await
+    }
+
+    async fn move_document(
+        &self,
+        request: Request<MoveDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.unary("MoveDocument", request, |request| {
+            self.handle_move_document(request)
+        })
+        .await
+    }
+
+    async fn undelete_document(
+        &self,
+        request: Request<UndeleteDocumentRequest>,
+    ) -> Result<Response<Document>, Status> {
+        self.unary("UndeleteDocument", request, |request| {
+            self.handle_undelete_document(request)
+        })
+        .await
+    }
+
+    async fn purge_document(
+        &self,
+        request: Request<PurgeDocumentRequest>,
+    ) -> Result<Response<PurgeDocumentResponse>, Status> {
+        self.unary("PurgeDocument", request, |request| {
+            self.handle_purge_document(request)
+        })
+        .await
+    }
+
+    async fn increment_counter(
+        &self,
+        request: Request<IncrementCounterRequest>,
+    ) -> Result<Response<Counter>, Status> {
+        self.unary("IncrementCounter", request, |request| {
+            self.handle_increment_counter(request)
+        })
+        .await
+    }
+
+    async fn get_counter(
+        &self,
+        request: Request<GetCounterRequest>,
+    ) -> Result<Response<Counter>, Status> {
+        self.unary("GetCounter", request, |request| {
+            self.handle_get_counter(request)
+        })
+        .await
+    }
+
+    async fn batch_get_documents(
+        &self,
+        request: Request<BatchGetDocumentsRequest>,
+    ) -> Result<Response<BatchGetDocumentsResponse>, Status> {
+        self.unary("BatchGetDocuments", request, |request| {
+            self.handle_batch_get_documents(request)
+        })
+        .await
+    }
+
+    async fn list_documents(
+        &self,
+        request: Request<ListDocumentsRequest>,
+    ) -> Result<Response<ListDocumentsResponse>, Status> {
+        self.unary("ListDocuments", request, |request| {
+            self.handle_list_documents(request)
+        })
+        .await
+    }
+
+    async fn run_aggregation_query(
+        &self,
+        request: Request<RunAggregationQueryRequest>,
+    ) -> Result<Response<RunAggregationQueryResponse>, Status> {
+        self.unary("RunAggregationQuery", request, |request| {
+            self.handle_run_aggregation_query(request)
+        })
+        .await
+    }
+
+    async fn explain_query(
+        &self,
+        request: Request<ExplainQueryRequest>,
+    ) -> Result<Response<ExplainQueryResponse>, Status> {
+        self.unary("ExplainQuery", request, |request| {
+            self.handle_explain_query(request)
+        })
+        .await
+    }
+
+    type ExportDocumentsStream = ReceiverStream<Result<ExportDocumentsResponse, Status>>;
+
+    async fn export_documents(
+        &self,
+        request: Request<ExportDocumentsRequest>,
+    ) -> Result<Response<Self::ExportDocumentsStream>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish(
+            "ExportDocuments",
+            self.handle_export_documents(request).await,
+        )
+    }
+
+    async fn import_documents(
+        &self,
+        request: Request<Streaming<ImportDocumentsRequest>>,
+    ) -> Result<Response<ImportDocumentsResponse>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish(
+            "ImportDocuments",
+            self.handle_import_documents(request).await,
+        )
+    }
+
+    type BulkWriteStream = ReceiverStream<Result<BulkWriteResponse, Status>>;
+
+    async fn bulk_write(
+        &self,
+        request: Request<Streaming<BulkWriteRequest>>,
+    ) -> Result<Response<Self::BulkWriteStream>, Status> {
+        let request_id = RequestId::of(&request);
+        request_id.finish("BulkWrite", self.handle_bulk_write(request).await)
+    }
+
+    async fn start_export(
+        &self,
+        request: Request<StartExportRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.unary("StartExport", request, |request| {
+            self.handle_start_export(request)
+        })
+        .await
+    }
+
+    async fn start_import(
+        &self,
+        request: Request<StartImportRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.unary("StartImport", request, |request| {
+            self.handle_start_import(request)
+        })
+        .await
+    }
+
+    async fn start_drop_collection(
+        &self,
+        request: Request<StartDropCollectionRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.unary("StartDropCollection", request, |request| {
+            self.handle_start_drop_collection(request)
+        })
+        .await
+    }
+
+    async fn get_operation(
+        &self,
+        request: Request<GetOperationRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.unary("GetOperation", request, |request| {
+            self.handle_get_operation(request)
+        })
+        .await
+    }
+
+    async fn list_operations(
+        &self,
+        request: Request<ListOperationsRequest>,
+    ) -> Result<Response<ListOperationsResponse>, Status> {
+        self.unary("ListOperations", request, |request| {
+            self.handle_list_operations(request)
+        })
+        .await
+    }
+
+    async fn cancel_operation(
+        &self,
+        request: Request<CancelOperationRequest>,
+    ) -> Result<Response<LongRunningOperation>, Status> {
+        self.unary("CancelOperation", request, |request| {
+            self.handle_cancel_operation(request)
+        })
+        .await
+    }
+
+    async fn partition_query(
+        &self,
+        request: Request<PartitionQueryRequest>,
+    ) -> Result<Response<PartitionQueryResponse>, Status> {
+        self.unary("PartitionQuery", request, |request| {
+            self.handle_partition_query(request)
+        })
+        .await
+    }
+
+    async fn get_collection_stats(
+        &self,
+        request: Request<GetCollectionStatsRequest>,
+    ) -> Result<Response<CollectionStats>, Status> {
+        self.unary("GetCollectionStats", request, |request| {
+            self.handle_get_collection_stats(request)
+        })
+        .await
+    }
+
+    async fn get_collection(
+        &self,
+        request: Request<GetCollectionRequest>,
+    ) -> Result<Response<Collection>, Status> {
+        self.unary("GetCollection", request, |request| {
+            self.handle_get_collection(request)
+        })
+        .await
+    }
+
+    async fn get_collection_config(
+        &self,
+        request: Request<GetCollectionConfigRequest>,
+    ) -> Result<Response<CollectionConfig>, Status> {
+        self.unary("GetCollectionConfig", request, |request| {
+            self.handle_get_collection_config(request)
+        })
+        .await
+    }
+
+    async fn update_collection_config(
+        &self,
+        request: Request<UpdateCollectionConfigRequest>,
+    ) -> Result<Response<CollectionConfig>, Status> {
+        self.unary("UpdateCollectionConfig", request, |request| {
+            self.handle_update_collection_config(request)
+        })
+        .await
+    }
+
+    async fn get_database_stats(
+        &self,
+        request: Request<GetDatabaseStatsRequest>,
+    ) -> Result<Response<DatabaseStats>, Status> {
+        self.unary("GetDatabaseStats", request, |request| {
+            self.handle_get_database_stats(request)
+        })
+        .await
+    }
+
+    async fn list_audit_entries(
+        &self,
+        request: Request<ListAuditEntriesRequest>,
+    ) -> Result<Response<ListAuditEntriesResponse>, Status> {
+        self.unary("ListAuditEntries", request, |request| {
+            self.handle_list_audit_entries(request)
+        })
+        .await
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Slow request log.
+//!
+//! Unary RPCs taking at least the configured threshold are logged at `warn`
+//! with the document or collection they are about, by its canonical resource
+//! name, and queries with their normalized text, see [`QueryPlan::to_text`].
+//! Filter values are never logged. Streaming RPCs are not timed, exports and
+//! imports take as long as the data they move.
+
+use std::time::{Duration, Instant};
+
+use tonic::Code;
+
+use crate::aggregate::Aggregator;
+use crate::api::v1alpha1::{
+    BatchGetDocumentsRequest, CancelOperationRequest, CopyDocumentRequest, CreateDocumentRequest,
+    CreateDocumentTreeRequest, DeleteDocumentRequest, DeleteWhereRequest, DocumentExistsRequest,
+    ExplainQueryRequest, GetCollectionConfigRequest, GetCollectionRequest,
+    GetCollectionStatsRequest, GetCounterRequest, GetDatabaseStatsRequest, GetDocumentRequest,
+    GetOperationRequest, GetServerInfoRequest, IncrementCounterRequest, ListAuditEntriesRequest,
+    ListDocumentsRequest, ListOperationsRequest, MoveDocumentRequest, PartitionQueryRequest,
+    PurgeDocumentRequest, RunAggregationQueryRequest, StartDropCollectionRequest,
+    StartExportRequest, StartImportRequest, TransformDocumentRequest, UndeleteDocumentRequest,
+    UpdateCollectionConfigRequest, UpdateDocumentRequest, UpdateWhereRequest,
+};
+use crate::keys::{self, DEFAULT_DATABASE};
+use crate::name;
+use crate::plan::QueryPlan;
+use crate::request_id::RequestId;
+
+/// What a request message is about.
+pub trait Described {
+    /// Resource name of the document or collection the request is about.
+    fn resource(&self) -> Option<String> {
+        None
+    }
+
+    /// Normalized text of the query of the request.
+    fn query(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Times one request.
+pub struct SlowRequestTimer {
+    threshold: Duration,
+    started: Instant,
+    resource: Option<String>,
+    query: Option<String>,
+}
+
+impl SlowRequestTimer {
+    /// Start timing the request of `message`.
+    pub fn start(threshold: Duration, message: &impl Described) -> Self {
+        SlowRequestTimer {
+            threshold,
+            started: Instant::now(),
+            resource: message.resource(),
+            query: message.query(),
+        }
+    }
+
+    /// Log the request if it took at least the threshold.
+    pub fn finish(self, method: &str, request_id: &RequestId, code: Code) {
+        let elapsed = self.started.elapsed();
+        if elapsed < self.threshold {
+            return;
+        }
+        tracing::warn!(
+            %request_id,
+            method,
+            ?code,
+            elapsed_ms = elapsed.as_millis() as u64,
+            resource = self.resource.as_deref(),
+            query = self.query.as_deref(),
+            "slow request"
+        );
+    }
+}
+
+/// Canonical name of the document named `name`, `name` as is if it does not
+/// parse.
+fn document(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return None;
+    }
+    Some(match name::parse(name) {
+        Ok((collection, doc_id)) => name::format(&collection, &doc_id),
+        Err(_) => name.to_string(),
+    })
+}
+
+/// Collection as stored of request fields `database_id` and `collection_id`.
+fn qualify(database_id: &str, collection_id: &str) -> Option<String> {
+    let database_id = match database_id {
+        "" => DEFAULT_DATABASE,
+        database_id => database_id,
+    };
+    keys::qualify(database_id, collection_id).ok()
+}
+
+/// Canonical name of a collection, `collection_id` as is if it is invalid.
+fn collection(database_id: &str, collection_id: &str) -> Option<String> {
+    if collection_id.is_empty() {
+        return None;
+    }
+    Some(match qualify(database_id, collection_id) {
+        Some(collection) => name::format_collection(&collection),
+        None => collection_id.to_string(),
+    })
+}
+
+impl Described for GetDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for CreateDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        if self.document_id.is_empty() {
+            return collection(&self.database_id, &self.collection_id);
+        }
+        match qualify(&self.database_id, &self.collection_id) {
+            Some(collection) => Some(name::format(&collection, &self.document_id)),
+            None => collection(&self.database_id, &self.collection_id),
+        }
+    }
+}
+
+impl Described for CreateDocumentTreeRequest {
+    fn resource(&self) -> Option<String> {
+        self.parent.as_ref()?.resource()
+    }
+}
+
+impl Described for UpdateDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.document.as_ref()?.name)
+    }
+}
+
+impl Described for DeleteDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for TransformDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for UpdateWhereRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for DeleteWhereRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for DocumentExistsRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for CopyDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for MoveDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for UndeleteDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+impl Described for PurgeDocumentRequest {
+    fn resource(&self) -> Option<String> {
+        document(&self.name)
+    }
+}
+
+// counters are neither documents nor collections
+impl Described for IncrementCounterRequest {}
+
+impl Described for GetCounterRequest {}
+
+impl Described for ListDocumentsRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+// about as many documents as it names
+impl Described for BatchGetDocumentsRequest {}
+
+impl Described for RunAggregationQueryRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+
+    fn query(&self) -> Option<String> {
+        let collection = qualify(&self.database_id, &self.collection_id)?;
+        let aggregator = Aggregator::new(&self.aggregations).ok()?;
+        Some(QueryPlan::aggregation(&collection, &self.filters, &aggregator).to_text())
+    }
+}
+
+impl Described for ExplainQueryRequest {
+    fn resource(&self) -> Option<String> {
+        self.query.as_ref()?.resource()
+    }
+
+    fn query(&self) -> Option<String> {
+        self.query.as_ref()?.query()
+    }
+}
+
+impl Described for PartitionQueryRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for GetCollectionStatsRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for GetCollectionRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for GetCollectionConfigRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for UpdateCollectionConfigRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+impl Described for GetDatabaseStatsRequest {}
+
+impl Described for GetServerInfoRequest {}
+
+impl Described for ListAuditEntriesRequest {
+    fn resource(&self) -> Option<String> {
+        collection(&self.database_id, &self.collection_id)
+    }
+}
+
+// operations return at once, whatever they are about
+impl Described for StartExportRequest {}
+
+impl Described for StartImportRequest {}
+
+impl Described for StartDropCollectionRequest {}
+
+impl Described for GetOperationRequest {}
+
+impl Described for ListOperationsRequest {}
+
+impl Described for CancelOperationRequest {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::v1alpha1::Aggregation;
+    use crate::api::v1alpha1::aggregation::{Count, Operator};
+
+    #[test]
+    fn test_resource() {
+        let get = |name: &str| GetDocumentRequest {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(get("users/a%2fb").resource().unwrap(), "users/a%2Fb");
+        assert_eq!(
+            get("databases/(default)/collections/users/documents/alice")
+                .resource()
+                .unwrap(),
+            "users/alice"
+        );
+        assert_eq!(get("users").resource().unwrap(), "users");
+        assert_eq!(get("").resource(), None);
+
+        let create = CreateDocumentRequest {
+            collection_id: "users".to_string(),
+            database_id: "acme".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            create.resource().unwrap(),
+            "databases/acme/collections/users"
+        );
+        let create = CreateDocumentRequest {
+            document_id: "alice".to_string(),
+            ..create
+        };
+        assert_eq!(
+            create.resource().unwrap(),
+            "databases/acme/collections/users/documents/alice"
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let query = RunAggregationQueryRequest {
+            collection_id: "users".to_string(),
+            database_id: "acme".to_string(),
+            aggregations: vec![Aggregation {
+                alias: "n".to_string(),
+                operator: Some(Operator::Count(Count {})),
+            }],
+            ..Default::default()
+        };
+        let explain = ExplainQueryRequest {
+            query: Some(query.clone()),
+        };
+        assert_eq!(
+            explain.resource().unwrap(),
+            "databases/acme/collections/users"
+        );
+        assert_eq!(explain.query().unwrap(), "SELECT COUNT(*) AS n FROM users");
+
+        let invalid = RunAggregationQueryRequest {
+            aggregations: vec![Aggregation::default()],
+            ..query
+        };
+        assert_eq!(invalid.query(), None);
+    }
+}
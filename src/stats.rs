@@ -0,0 +1,231 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Per-collection document counters.
+//!
+//! Counters are kept in memory, updated after every committed write and
+//! checkpointed to the meta keyspace by the engine. They are exact only if
+//! the last run ended with a clean shutdown; after a crash the writes since
+//! the last checkpoint are missing and the counters are flagged as estimates.
+//! Writes that skip reading the previous version, like bulk imports, also
+//! turn the counters into estimates.
+//!
+//! The largest documents of every collection are tracked as well, to find
+//! the outliers slowing down reads. That list is not checkpointed: it holds
+//! the largest documents written since the server started, and shrinks when
+//! they are deleted rather than looking for the next largest ones.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Approximate size of a collection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectionStats {
+    pub document_count: u64,
+    /// Total size of the document payloads in bytes.
+    pub size_bytes: u64,
+    /// Whether the numbers are exact or an estimate.
+    pub exact: bool,
+    /// Approximate space the documents take on disk, filled in by the
+    /// engine.
+    pub disk_bytes: u64,
+}
+
+/// Number of largest documents tracked per collection.
+pub const LARGEST_DOCUMENTS: usize = 10;
+
+/// Size of one document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSize {
+    pub doc_id: String,
+    /// Size of the document payload in bytes.
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Counters {
+    pub(crate) documents: u64,
+    pub(crate) bytes: u64,
+}
+
+impl Counters {
+    pub(crate) fn encode(&self) -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        buf[..8].copy_from_slice(&self.documents.to_be_bytes());
+        buf[8..].copy_from_slice(&self.bytes.to_be_bytes());
+        buf
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; 16] = bytes.try_into().ok()?;
+        Some(Counters {
+            documents: u64::from_be_bytes(bytes[..8].try_into().ok()?),
+            bytes: u64::from_be_bytes(bytes[8..].try_into().ok()?),
+        })
+    }
+}
+
+/// In-memory per-collection counters shared by all engine clones.
+pub(crate) struct StatsTracker {
+    counters: Mutex<HashMap<String, Counters>>,
+    exact: AtomicBool,
+    /// Changes recorded so far.
+    changes: AtomicU64,
+    /// Largest documents of every collection, by decreasing size.
+    largest: Mutex<HashMap<String, Vec<DocumentSize>>>,
+}
+
+impl StatsTracker {
+    pub(crate) fn new(counters: HashMap<String, Counters>, exact: bool) -> Self {
+        StatsTracker {
+            counters: Mutex::new(counters),
+            exact: AtomicBool::new(exact),
+            changes: AtomicU64::new(0),
+            largest: Mutex::default(),
+        }
+    }
+
+    /// Apply a committed change to a collection.
+    pub(crate) fn record(&self, collection_id: &str, documents: i64, bytes: i64) {
+        let mut counters = self.counters.lock().expect("stats lock poisoned");
+        let entry = counters.entry(collection_id.to_string()).or_default();
+        entry.documents = entry.documents.saturating_add_signed(documents);
+        entry.bytes = entry.bytes.saturating_add_signed(bytes);
+        self.changes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Apply a committed write of a document of `size` bytes, `None` if it
+    /// was deleted, to the largest documents of its collection.
+    pub(crate) fn record_document(&self, collection_id: &str, doc_id: &str, size: Option<u64>) {
+        let mut largest = self.largest.lock().expect("stats lock poisoned");
+        let Some(size_bytes) = size else {
+            if let Some(top) = largest.get_mut(collection_id) {
+                top.retain(|doc| doc.doc_id != doc_id);
+            }
+            return;
+        };
+        let top = largest.entry(collection_id.to_string()).or_default();
+        top.retain(|doc| doc.doc_id != doc_id);
+        let at = top.partition_point(|doc| doc.size_bytes >= size_bytes);
+        if at < LARGEST_DOCUMENTS {
+            let doc_id = doc_id.to_string();
+            top.insert(at, DocumentSize { doc_id, size_bytes });
+            top.truncate(LARGEST_DOCUMENTS);
+        }
+    }
+
+    /// Largest documents of a collection, by decreasing size.
+    pub(crate) fn largest(&self, collection_id: &str) -> Vec<DocumentSize> {
+        let largest = self.largest.lock().expect("stats lock poisoned");
+        largest.get(collection_id).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn get(&self, collection_id: &str) -> CollectionStats {
+        let counters = self.counters.lock().expect("stats lock poisoned");
+        let entry = counters.get(collection_id).copied().unwrap_or_default();
+        CollectionStats {
+            document_count: entry.documents,
+            size_bytes: entry.bytes,
+            exact: self.exact.load(Ordering::Relaxed),
+            disk_bytes: 0,
+        }
+    }
+
+    /// Total size of the document payloads of every collection.
+    pub(crate) fn total_bytes(&self) -> u64 {
+        let counters = self.counters.lock().expect("stats lock poisoned");
+        counters.values().map(|c| c.bytes).sum()
+    }
+
+    /// Whether the counters are still exact.
+    pub(crate) fn is_exact(&self) -> bool {
+        self.exact.load(Ordering::Relaxed)
+    }
+
+    /// Flag the counters as estimates, e.g. after writes of unknown size.
+    pub(crate) fn mark_estimate(&self) {
+        self.exact.store(false, Ordering::Relaxed);
+    }
+
+    /// Number of changes recorded so far, taken before a
+    /// [`snapshot`](Self::snapshot) to tell whether any was recorded since.
+    pub(crate) fn changes(&self) -> u64 {
+        self.changes.load(Ordering::SeqCst)
+    }
+
+    /// Copy of all counters, for checkpointing.
+    pub(crate) fn snapshot(&self) -> Vec<(String, Counters)> {
+        let counters = self.counters.lock().expect("stats lock poisoned");
+        counters.iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_roundtrip() {
+        let counters = Counters {
+            documents: 3,
+            bytes: 1024,
+        };
+        assert_eq!(Counters::decode(&counters.encode()), Some(counters));
+        assert_eq!(Counters::decode(b"short"), None);
+    }
+
+    #[test]
+    fn test_record_and_get() {
+        let tracker = StatsTracker::new(HashMap::new(), true);
+        tracker.record("users", 1, 10);
+        tracker.record("users", 1, 20);
+        tracker.record("users", -1, -10);
+
+        let stats = tracker.get("users");
+        assert_eq!(stats.document_count, 1);
+        assert_eq!(stats.size_bytes, 20);
+        assert!(stats.exact);
+
+        assert_eq!(tracker.get("orders").document_count, 0);
+        tracker.record("orders", 1, 5);
+        assert_eq!(tracker.total_bytes(), 25);
+        assert_eq!(tracker.changes(), 4);
+    }
+
+    #[test]
+    fn test_largest_documents() {
+        let tracker = StatsTracker::new(HashMap::new(), true);
+        for i in 0..20 {
+            tracker.record_document("users", &format!("user-{i}"), Some(i));
+        }
+        tracker.record_document("users", "user-3", Some(100));
+        tracker.record_document("users", "user-19", None);
+        tracker.record_document("orders", "missing", None);
+
+        let largest = tracker.largest("users");
+        assert_eq!(largest.len(), LARGEST_DOCUMENTS - 1);
+        assert_eq!(
+            largest[0],
+            DocumentSize {
+                doc_id: "user-3".to_string(),
+                size_bytes: 100
+            }
+        );
+        let sizes: Vec<_> = largest.iter().map(|doc| doc.size_bytes).collect();
+        assert_eq!(sizes, [100, 18, 17, 16, 15, 14, 13, 12, 11]);
+        assert!(tracker.largest("orders").is_empty());
+    }
+
+    #[test]
+    fn test_record_saturates_at_zero() {
+        let tracker = StatsTracker::new(HashMap::new(), false);
+        tracker.record("users", -1, -10);
+
+        let stats = tracker.get("users");
+        assert_eq!(stats.document_count, 0);
+        assert_eq!(stats.size_bytes, 0);
+        assert!(!stats.exact);
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Logging and distributed tracing.
+//!
+//! Logs are written to stderr as text or JSON lines, filtered by the
+//! configured directives, else by `RUST_LOG` (`info` by default). The filter
+//! can be replaced while serving, see [`LogFilter`].
+//!
+//! Every RPC gets a span, see [`rpc_span`], the engine work it runs on the
+//! engine workers a child span, and the storage operations of that work
+//! children of their own, so a trace shows where the latency of a request
+//! goes. Spans are exported over OTLP/gRPC when a collector is configured,
+//! and cost next to nothing otherwise. Log lines carry the spans they were
+//! written in.
+//!
+//! Clients may send a W3C `traceparent` header, the RPC span then joins their
+//! trace.
+
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry::{Context, global};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkError;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tonic::codegen::http;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, reload};
+
+use crate::config::LogFormat;
+
+/// Service name reported with every span.
+const SERVICE_NAME: &str = "zerotable";
+
+/// Log filter used when `RUST_LOG` is not set.
+const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Exports spans until shut down.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    log_filter: LogFilter,
+}
+
+impl Telemetry {
+    /// Handle on the filter of the logs.
+    pub fn log_filter(&self) -> LogFilter {
+        self.log_filter.clone()
+    }
+
+    /// Export the spans still buffered and stop.
+    pub fn shutdown(self) -> Result<(), OTelSdkError> {
+        match self.provider {
+            Some(provider) => provider.shutdown(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Replaces the filter of the logs.
+#[derive(Clone)]
+pub struct LogFilter(reload::Handle<EnvFilter, Registry>);
+
+impl LogFilter {
+    /// Filter logs by `directives`, e.g. `info,zerotable=debug`, or as at
+    /// startup without any.
+    pub fn set(&self, directives: Option<&str>) -> Result<(), reload::Error> {
+        self.0.reload(env_filter(directives))
+    }
+}
+
+/// Filter of `directives`, else of `RUST_LOG`, else the default one.
+fn env_filter(directives: Option<&str>) -> EnvFilter {
+    match directives {
+        Some(directives) => EnvFilter::new(directives),
+        None => {
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER))
+        }
+    }
+}
+
+/// Start logging in `format`, filtered by `log_filter` directives if set, and
+/// export spans to the OTLP/gRPC collector at `otlp_endpoint`, e.g.
+/// `http://localhost:4317`, if set.
+///
+/// Must be called from within the tokio runtime, at most once.
+pub fn init(
+    format: LogFormat,
+    log_filter: Option<&str>,
+    otlp_endpoint: Option<&str>,
+) -> Result<Telemetry, ExporterBuildError> {
+    let (filter, handle) = reload::Layer::new(env_filter(log_filter));
+    let logs = match format {
+        LogFormat::Text => fmt::layer().with_writer(std::io::stderr).boxed(),
+        LogFormat::Json => fmt::layer().json().with_writer(std::io::stderr).boxed(),
+    };
+
+    let provider = match otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+            let provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                .build();
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            Some(provider)
+        }
+        None => None,
+    };
+    // the filter only applies to logs, every span is exported
+    let spans = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+    // a second call would keep the first subscriber
+    let _ = tracing_subscriber::registry()
+        .with(logs.with_filter(filter))
+        .with(spans)
+        .try_init();
+    Ok(Telemetry {
+        provider,
+        log_filter: LogFilter(handle),
+    })
+}
+
+/// Span of one RPC, the parent of every span recorded while serving it.
+pub fn rpc_span(request: &http::Request<()>) -> Span {
+    let span = tracing::info_span!("rpc", otel.name = request.uri().path(), rpc.system = "grpc");
+    let parent: Context = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&Headers(request.headers()))
+    });
+    let _ = span.set_parent(parent);
+    span
+}
+
+/// Reads trace context from request headers.
+struct Headers<'a>(&'a http::HeaderMap);
+
+impl Extractor for Headers<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(http::HeaderName::as_str).collect()
+    }
+}
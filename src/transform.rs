@@ -0,0 +1,267 @@
+// Copyright 2026 zerotable.
+// Use of this source code is governed by the Apache 2.0 license that can be
+// found in the LICENSE file.
+
+//! Server-side field transforms, as in Firestore.
+//!
+//! A transform computes the new value of a field from its current one on
+//! the server: an increment adds to a number, appending missing elements
+//! adds to an array the values it lacks. The result does not depend on
+//! anything the client read, so a transform losing a conflict with another
+//! write can simply be applied again to the new document.
+//!
+//! Int increments saturate at the i64 bounds rather than wrap, an increment
+//! with a double operand or of a double field gives a double.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::api::v1alpha1::field_transform::TransformType;
+use crate::api::v1alpha1::value::ValueType;
+use crate::api::v1alpha1::{ArrayValue, Document, FieldTransform, MapValue, Value};
+
+/// Maximum number of transforms in a single request.
+pub const MAX_TRANSFORMS: usize = 20;
+
+/// Error returned when a transform is malformed.
+#[derive(Debug, PartialEq)]
+pub struct TransformError(String);
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid transform: {}", self.0)
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+fn invalid(msg: impl Into<String>) -> TransformError {
+    TransformError(msg.into())
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Increment(ValueType),
+    AppendMissingElements(Vec<Value>),
+}
+
+/// Validated transforms of a request, applied in order.
+#[derive(Debug, Clone)]
+pub struct Transforms {
+    transforms: Vec<(String, Kind)>,
+}
+
+impl Transforms {
+    pub fn new(transforms: &[FieldTransform]) -> Result<Self, TransformError> {
+        if transforms.is_empty() {
+            return Err(invalid("at least one transform is required"));
+        }
+        if transforms.len() > MAX_TRANSFORMS {
+            return Err(invalid(format!(
+                "at most {MAX_TRANSFORMS} transforms are allowed"
+            )));
+        }
+
+        let mut validated: Vec<(String, Kind)> = Vec::with_capacity(transforms.len());
+        for transform in transforms {
+            let path = &transform.field_path;
+            if path.split('.').any(str::is_empty) {
+                return Err(invalid(format!("invalid field path '{path}'")));
+            }
+            if validated.iter().any(|(other, _)| overlaps(path, other)) {
+                return Err(invalid(format!("field '{path}' is transformed twice")));
+            }
+            let kind = match &transform.transform_type {
+                Some(TransformType::Increment(operand)) => match &operand.value_type {
+                    Some(operand @ (ValueType::IntValue(_) | ValueType::DoubleValue(_))) => {
+                        Kind::Increment(operand.clone())
+                    }
+                    _ => {
+                        return Err(invalid(format!(
+                            "{path}: increment needs an int or double operand"
+                        )));
+                    }
+                },
+                Some(TransformType::AppendMissingElements(array)) => {
+                    Kind::AppendMissingElements(array.values.clone())
+                }
+                None => return Err(invalid(format!("{path}: transform type is required"))),
+            };
+            validated.push((path.clone(), kind));
+        }
+        Ok(Transforms {
+            transforms: validated,
+        })
+    }
+
+    /// Apply every transform to the fields of `doc`.
+    pub fn apply(&self, doc: &mut Document) {
+        for (path, kind) in &self.transforms {
+            let field = field_mut(&mut doc.fields, path);
+            field.value_type = Some(match (kind, field.value_type.take()) {
+                (Kind::Increment(operand), current) => increment(current, operand),
+                (Kind::AppendMissingElements(elements), current) => {
+                    let mut values = match current {
+                        Some(ValueType::ArrayValue(array)) => array.values,
+                        _ => Vec::new(),
+                    };
+                    for element in elements {
+                        if !values.contains(element) {
+                            values.push(element.clone());
+                        }
+                    }
+                    ValueType::ArrayValue(ArrayValue { values })
+                }
+            });
+        }
+    }
+}
+
+/// Whether one of two field paths is the other or a field inside it.
+pub(crate) fn overlaps(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    long.strip_prefix(short)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// The value at a dot separated field path, created, along with the maps
+/// leading to it, if missing. Values in the way that are not maps are
+/// replaced by maps.
+pub(crate) fn field_mut<'a>(fields: &'a mut HashMap<String, Value>, path: &str) -> &'a mut Value {
+    let (parent, last) = match path.rsplit_once('.') {
+        Some((parent, last)) => (Some(parent), last),
+        None => (None, path),
+    };
+    let mut fields = fields;
+    for segment in parent.into_iter().flat_map(|parent| parent.split('.')) {
+        let value = fields.entry(segment.to_string()).or_default();
+        if !matches!(value.value_type, Some(ValueType::MapValue(_))) {
+            value.value_type = Some(ValueType::MapValue(MapValue::default()));
+        }
+        let Some(ValueType::MapValue(map)) = &mut value.value_type else {
+            unreachable!("the value was just made a map");
+        };
+        fields = &mut map.fields;
+    }
+    fields.entry(last.to_string()).or_default()
+}
+
+/// `current` plus `operand`, `operand` if `current` is not a number.
+fn increment(current: Option<ValueType>, operand: &ValueType) -> ValueType {
+    match (current, operand) {
+        (Some(ValueType::IntValue(a)), ValueType::IntValue(b)) => {
+            ValueType::IntValue(a.saturating_add(*b))
+        }
+        (Some(ValueType::IntValue(a)), ValueType::DoubleValue(b)) => {
+            ValueType::DoubleValue(a as f64 + b)
+        }
+        (Some(ValueType::DoubleValue(a)), ValueType::IntValue(b)) => {
+            ValueType::DoubleValue(a + *b as f64)
+        }
+        (Some(ValueType::DoubleValue(a)), ValueType::DoubleValue(b)) => {
+            ValueType::DoubleValue(a + b)
+        }
+        (_, operand) => operand.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(path: &str, transform_type: TransformType) -> FieldTransform {
+        FieldTransform {
+            field_path: path.to_string(),
+            transform_type: Some(transform_type),
+        }
+    }
+
+    fn value(value_type: ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    fn increment_by(path: &str, operand: ValueType) -> FieldTransform {
+        transform(path, TransformType::Increment(value(operand)))
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut doc = Document::default();
+        doc.fields
+            .insert("n".to_string(), value(ValueType::IntValue(i64::MAX - 1)));
+        doc.fields.insert(
+            "name".to_string(),
+            value(ValueType::StringValue("a".into())),
+        );
+
+        let transforms = Transforms::new(&[
+            increment_by("n", ValueType::IntValue(5)),
+            increment_by("name", ValueType::IntValue(2)),
+            increment_by("stats.views", ValueType::IntValue(1)),
+            increment_by("stats.score", ValueType::DoubleValue(0.5)),
+        ])
+        .unwrap();
+        transforms.apply(&mut doc);
+        transforms.apply(&mut doc);
+
+        assert_eq!(doc.fields["n"], value(ValueType::IntValue(i64::MAX)));
+        assert_eq!(doc.fields["name"], value(ValueType::IntValue(4)));
+        let Some(ValueType::MapValue(stats)) = &doc.fields["stats"].value_type else {
+            panic!("stats is not a map");
+        };
+        assert_eq!(stats.fields["views"], value(ValueType::IntValue(2)));
+        assert_eq!(stats.fields["score"], value(ValueType::DoubleValue(1.0)));
+
+        let transforms = Transforms::new(&[increment_by("n", ValueType::DoubleValue(0.5))]);
+        transforms.unwrap().apply(&mut doc);
+        assert_eq!(
+            doc.fields["n"],
+            value(ValueType::DoubleValue(i64::MAX as f64 + 0.5))
+        );
+    }
+
+    #[test]
+    fn test_append_missing_elements() {
+        let int = |i| value(ValueType::IntValue(i));
+        let append = |values: Vec<Value>| {
+            transform(
+                "tags",
+                TransformType::AppendMissingElements(ArrayValue { values }),
+            )
+        };
+        let mut doc = Document::default();
+
+        Transforms::new(&[append(vec![int(1), int(2), int(1)])])
+            .unwrap()
+            .apply(&mut doc);
+        Transforms::new(&[append(vec![int(3), int(2)])])
+            .unwrap()
+            .apply(&mut doc);
+        assert_eq!(
+            doc.fields["tags"],
+            value(ValueType::ArrayValue(ArrayValue {
+                values: vec![int(1), int(2), int(3)],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_invalid_transforms() {
+        let one = || ValueType::IntValue(1);
+        assert!(Transforms::new(&[]).is_err());
+        assert!(Transforms::new(&[increment_by("a..b", one())]).is_err());
+        assert!(Transforms::new(&[increment_by("", one())]).is_err());
+        assert!(Transforms::new(&[increment_by("a", ValueType::StringValue("1".into()))]).is_err());
+        assert!(
+            Transforms::new(&[FieldTransform {
+                field_path: "a".to_string(),
+                transform_type: None,
+            }])
+            .is_err()
+        );
+        assert!(Transforms::new(&[increment_by("a", one()), increment_by("a.b", one())]).is_err());
+        assert!(Transforms::new(&[increment_by("a", one()), increment_by("ab", one())]).is_ok());
+    }
+}